@@ -0,0 +1,60 @@
+-- PostgreSQL databases start from the schema that SQLite databases have been migrated up to.
+CREATE TABLE posted_urls (
+    id BIGSERIAL NOT NULL,
+    url TEXT PRIMARY KEY NOT NULL,
+    status TEXT NOT NULL DEFAULT 'posted',
+    source_id TEXT,
+    fingerprint TEXT,
+    content_hash TEXT,
+    record_uri TEXT,
+    record_cid TEXT,
+    posted_at TEXT,
+    deleted_at TEXT
+);
+
+CREATE INDEX posted_urls_fingerprint ON posted_urls (fingerprint);
+CREATE INDEX posted_urls_source_id ON posted_urls (source_id);
+CREATE INDEX posted_urls_posted_at ON posted_urls (posted_at);
+
+CREATE TABLE state (
+    key TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL
+);
+
+CREATE TABLE fetch_history (
+    id BIGSERIAL PRIMARY KEY,
+    feed TEXT NOT NULL,
+    fetched_at TEXT NOT NULL,
+    error_class TEXT,
+    error TEXT,
+    item_count BIGINT NOT NULL DEFAULT 0,
+    duration_ms BIGINT NOT NULL
+);
+
+CREATE INDEX fetch_history_feed ON fetch_history (feed, id);
+
+CREATE TABLE article_post_counts (
+    article TEXT PRIMARY KEY NOT NULL,
+    count BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE daily_post_counts (
+    day TEXT PRIMARY KEY NOT NULL,
+    count BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE failed_posts (
+    url TEXT PRIMARY KEY NOT NULL,
+    source_id TEXT,
+    fingerprint TEXT,
+    payload TEXT NOT NULL,
+    error TEXT NOT NULL,
+    attempts BIGINT NOT NULL DEFAULT 1,
+    last_attempt_at TEXT NOT NULL
+);
+
+CREATE TABLE canonical_urls (
+    url TEXT PRIMARY KEY NOT NULL,
+    canonical_url TEXT NOT NULL,
+    resolved_at TEXT NOT NULL
+);
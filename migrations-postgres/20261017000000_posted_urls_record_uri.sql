@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS posted_urls_record_uri ON posted_urls (record_uri);
@@ -0,0 +1,2 @@
+ALTER TABLE posted_urls ADD COLUMN deleted_at DATETIME;
+CREATE TABLE IF NOT EXISTS state (key TEXT PRIMARY KEY, value TEXT NOT NULL);
@@ -0,0 +1,2 @@
+ALTER TABLE posted_urls ADD COLUMN fingerprint TEXT;
+CREATE INDEX IF NOT EXISTS posted_urls_fingerprint ON posted_urls (fingerprint);
@@ -0,0 +1 @@
+ALTER TABLE posted_urls ADD COLUMN status TEXT NOT NULL DEFAULT 'posted';
@@ -0,0 +1,11 @@
+CREATE TABLE fetch_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    feed TEXT NOT NULL,
+    fetched_at TEXT NOT NULL,
+    error_class TEXT,
+    error TEXT,
+    item_count INTEGER NOT NULL DEFAULT 0,
+    duration_ms INTEGER NOT NULL
+);
+
+CREATE INDEX fetch_history_feed ON fetch_history (feed, id);
@@ -0,0 +1,4 @@
+CREATE TABLE article_post_counts (
+    article TEXT PRIMARY KEY NOT NULL,
+    count INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,4 @@
+CREATE TABLE daily_post_counts (
+    day TEXT PRIMARY KEY NOT NULL,
+    count INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,8 @@
+CREATE TABLE failed_posts (
+    url TEXT PRIMARY KEY NOT NULL,
+    fingerprint TEXT,
+    payload TEXT NOT NULL,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    last_attempt_at TEXT NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE posted_urls ADD COLUMN content_hash TEXT;
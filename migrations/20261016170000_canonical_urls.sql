@@ -0,0 +1,5 @@
+CREATE TABLE canonical_urls (
+    url TEXT PRIMARY KEY NOT NULL,
+    canonical_url TEXT NOT NULL,
+    resolved_at TEXT NOT NULL
+);
@@ -0,0 +1,3 @@
+ALTER TABLE posted_urls ADD COLUMN source_id TEXT;
+CREATE INDEX IF NOT EXISTS posted_urls_source_id ON posted_urls (source_id);
+ALTER TABLE failed_posts ADD COLUMN source_id TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE posted_urls ADD COLUMN record_uri TEXT;
+ALTER TABLE posted_urls ADD COLUMN record_cid TEXT;
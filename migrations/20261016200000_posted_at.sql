@@ -0,0 +1,2 @@
+ALTER TABLE posted_urls ADD COLUMN posted_at TEXT;
+CREATE INDEX IF NOT EXISTS posted_urls_posted_at ON posted_urls (posted_at);
@@ -0,0 +1,2 @@
+-- URLs stored before posted_at existed are treated as stored now, so that they age out like any other.
+UPDATE posted_urls SET posted_at = strftime('%Y-%m-%dT%H:%M:%S+00:00', 'now') WHERE posted_at IS NULL;
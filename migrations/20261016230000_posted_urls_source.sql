@@ -0,0 +1,3 @@
+ALTER TABLE posted_urls ADD COLUMN source TEXT NOT NULL DEFAULT 'unknown';
+CREATE INDEX IF NOT EXISTS posted_urls_source ON posted_urls (source);
+ALTER TABLE failed_posts ADD COLUMN source TEXT NOT NULL DEFAULT 'unknown';
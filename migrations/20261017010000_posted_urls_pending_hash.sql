@@ -0,0 +1 @@
+ALTER TABLE posted_urls ADD COLUMN pending_hash TEXT;
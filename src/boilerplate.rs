@@ -0,0 +1,147 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use reqwest::Url;
+use std::{fs, path::Path};
+
+/// Phrases that are known to be used as news abstracts without describing the article.
+const DEFAULT_PHRASES: &[&str] = &[
+    "click here for details",
+    "click here for more details",
+    "click here for more information",
+    "see details",
+    "see more",
+    "read more",
+    "more details",
+    "詳細はこちら",
+    "詳しくはこちら",
+    "詳細は公式サイトをご確認ください",
+    "자세한 내용은 여기를 클릭하세요",
+    "자세히 보기",
+];
+
+/// Detects text that is only boilerplate and adds nothing when used as a description.
+#[derive(Debug)]
+pub struct BoilerplateFilter {
+    phrases: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl Default for BoilerplateFilter {
+    fn default() -> Self {
+        Self {
+            phrases: DEFAULT_PHRASES.iter().map(|p| normalise(p)).collect(),
+            patterns: vec![],
+        }
+    }
+}
+
+impl BoilerplateFilter {
+    /// Load a filter from a file containing one phrase per line, replacing the default phrases.
+    ///
+    /// Lines wrapped in slashes such as `/^see .* for details$/` are treated as case-insensitive regular
+    /// expressions, and empty lines or lines starting with `#` are ignored.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read boilerplate phrases from {path:?}"))?;
+        let mut filter = Self {
+            phrases: vec![],
+            patterns: vec![],
+        };
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line
+                .strip_prefix('/')
+                .and_then(|line| line.strip_suffix('/'))
+            {
+                Some(pattern) => filter.patterns.push(
+                    Regex::new(&format!("(?i){pattern}"))
+                        .with_context(|| format!("invalid boilerplate pattern '{line}'"))?,
+                ),
+                None => filter.phrases.push(normalise(line)),
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Whether the given text is empty, only a URL, or matches a boilerplate phrase.
+    pub fn is_boilerplate(&self, text: &str) -> bool {
+        let text = text.trim();
+        if text.is_empty() || Url::parse(text).is_ok_and(|url| url.scheme().starts_with("http")) {
+            return true;
+        }
+        let normalised = normalise(text);
+        self.phrases.contains(&normalised) || self.patterns.iter().any(|p| p.is_match(text))
+    }
+}
+
+/// Lowercase text and strip surrounding punctuation so that phrases match regardless of formatting.
+fn normalise(text: &str) -> String {
+    text.trim()
+        .trim_matches(|c: char| {
+            c.is_ascii_punctuation() || c.is_whitespace() || "。！…→".contains(c)
+        })
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_phrases_match_regardless_of_formatting() {
+        let filter = BoilerplateFilter::default();
+        for text in [
+            "",
+            "  ",
+            "Read more...",
+            "  Click here for details!",
+            "→ See more",
+            "詳細はこちら。",
+            "https://example.com/news/1",
+        ] {
+            assert!(filter.is_boilerplate(text), "{text:?}");
+        }
+        for text in [
+            "Read more about the new event",
+            "The event starts on Friday.",
+            "mailto:support@example.com",
+        ] {
+            assert!(!filter.is_boilerplate(text), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn phrase_files_replace_the_default_phrases() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("boilerplate.txt");
+        fs::write(
+            &path,
+            "# Phrases used by the feed\n\nCheck the website\n/^see .* for details$/\n",
+        )
+        .unwrap();
+        let filter = BoilerplateFilter::from_file(&path).unwrap();
+
+        assert!(filter.is_boilerplate("check the website."));
+        assert!(filter.is_boilerplate("See the notice for details"));
+        assert!(!filter.is_boilerplate("Read more"));
+        assert!(!filter.is_boilerplate("# Phrases used by the feed"));
+        // URLs are never worth using as a description, whatever the phrases are.
+        assert!(filter.is_boilerplate("https://example.com/news/1"));
+    }
+
+    #[test]
+    fn invalid_phrase_files_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(BoilerplateFilter::from_file(&dir.path().join("missing.txt")).is_err());
+
+        let path = dir.path().join("boilerplate.txt");
+        fs::write(&path, "/see (details/\n").unwrap();
+        let err = BoilerplateFilter::from_file(&path).unwrap_err();
+        assert!(
+            err.to_string().contains("invalid boilerplate pattern"),
+            "{err:#}"
+        );
+    }
+}
@@ -1,45 +1,550 @@
-use anyhow::{Context, Result};
+use crate::ratelimit::RateLimitedClient;
+use crate::secret::Secret;
+use crate::template::{Placeholder, PostTemplate, TemplateValues, truncate_graphemes};
+use crate::thumbnail::{
+    ImageOptions, ThumbnailCache, ThumbnailFilter, ThumbnailFormat, process_thumbnail,
+};
+use anyhow::{Context, Result, anyhow, bail};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
 use bsky_sdk::{
     BskyAgent,
     agent::config::{Config, FileStore},
     api::{
         app::bsky::{
-            embed::external::{ExternalData, MainData},
-            feed::post::{self, RecordEmbedRefs},
+            actor::{Profile, profile},
+            embed::{
+                defs::AspectRatioData,
+                external::{ExternalData, MainData},
+                images,
+            },
+            feed::{
+                Post, Postgate, Threadgate,
+                defs::PostViewEmbedRefs,
+                get_author_feed,
+                post::{self, RecordEmbedRefs},
+                postgate, threadgate,
+            },
+            richtext::facet,
+        },
+        com::atproto::{
+            label::defs::{SelfLabelData, SelfLabelsData},
+            repo::{create_record, put_record, strong_ref::MainData as StrongRefData},
+            server::create_session,
         },
         types::{
-            Collection, TryIntoUnknown, Union,
-            string::{Datetime, Language, RecordKey},
+            BlobRef, Collection, LimitedNonZeroU8, TryFromUnknown, TryIntoUnknown, Union, Unknown,
+            string::{Cid, Datetime, Language, Nsid, RecordKey},
+        },
+        xrpc::{
+            self,
+            error::{XrpcError, XrpcErrorKind},
+            http::StatusCode,
         },
     },
+    error::GenericXrpcError,
+    record::Record,
     rich_text::RichText,
 };
 use chrono::{DateTime, Utc};
-use image::{ImageFormat, ImageReader, imageops::FilterType};
+use clap::ValueEnum;
 use reqwest::Url;
-use std::{io::Cursor, path::PathBuf, str::FromStr};
-use tracing::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::IsTerminal,
+    num::NonZeroU64,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tokio::time::sleep;
+use tracing::{debug, info, warn};
+use unicode_segmentation::UnicodeSegmentation;
 
 pub struct BlueskyHandler {
-    pub agent: BskyAgent,
+    pub agent: BskyAgent<RateLimitedClient>,
+    pub client: RateLimitedClient,
+    /// The HTTP client used for requests outside of the Bluesky API, such as fetching images.
+    pub http: reqwest::Client,
     pub data_path: PathBuf,
-    pub disable_comments: bool,
+    pub reply_control: Vec<ReplyControl>,
+    pub disable_quote_posts: bool,
+    /// How link card thumbnails are processed before being uploaded.
+    pub image_options: ImageOptions,
+    /// Where processed images are cached, if they should be.
+    pub thumbnail_cache: Option<ThumbnailCache>,
+    /// The total size of the blobs uploaded by this handler.
+    uploaded_blob_bytes: AtomicU64,
 }
 
-#[derive(Debug)]
+/// Who is allowed to reply to posts made by the bot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReplyControl {
+    /// Nobody can reply.
+    Nobody,
+    /// Anybody can reply.
+    Everyone,
+    /// Users mentioned in the post can reply.
+    Mentioned,
+    /// Users followed by the bot can reply.
+    Following,
+    /// Users following the bot can reply.
+    Followers,
+}
+
+/// Build the allow rules of the threadgate for the given reply controls, or `None` when replies
+/// shouldn't be restricted and no threadgate is needed.
+pub fn threadgate_allow_rules(
+    reply_control: &[ReplyControl],
+) -> Option<Vec<Union<threadgate::RecordAllowItem>>> {
+    if reply_control.is_empty() || reply_control.contains(&ReplyControl::Everyone) {
+        return None;
+    }
+    Some(
+        reply_control
+            .iter()
+            .filter_map(|control| match control {
+                ReplyControl::Nobody | ReplyControl::Everyone => None,
+                ReplyControl::Mentioned => Some(threadgate::RecordAllowItem::MentionRule(
+                    Box::new(threadgate::MentionRuleData {}.into()),
+                )),
+                ReplyControl::Following => Some(threadgate::RecordAllowItem::FollowingRule(
+                    Box::new(threadgate::FollowingRuleData {}.into()),
+                )),
+                ReplyControl::Followers => Some(threadgate::RecordAllowItem::FollowerRule(
+                    Box::new(threadgate::FollowerRuleData {}.into()),
+                )),
+            })
+            .map(Union::Refs)
+            .collect(),
+    )
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PostData {
     pub text: String,
     pub languages: Vec<String>,
     pub created_at: DateTime<Utc>,
+    /// Self-label values to apply to the post, such as `spoiler`.
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Tags to set on the post record, without a leading `#`.
+    #[serde(default)]
+    pub tags: Vec<String>,
     pub embed: Option<PostEmbed>,
+    /// Handles mentioned in the text of the post, without a leading `@`, which should link to their accounts.
+    #[serde(default)]
+    pub mentions: Vec<String>,
+    /// The text of replies continuing the post as a thread, in the order they should be posted.
+    #[serde(default)]
+    pub replies: Vec<String>,
 }
 
-#[derive(Debug)]
+/// The maximum length of a post's text in graphemes, as enforced by Bluesky.
+pub const MAX_POST_GRAPHEMES: usize = 300;
+
+impl PostData {
+    /// Build the text of a post from a template, optionally followed by mentions and hashtags.
+    ///
+    /// The title and abstract are truncated by graphemes when the post would be too long, so that the URL,
+    /// mentions and hashtags are always kept whole and multi-codepoint characters such as emoji are never split. Posts without
+    /// a link card always have the URL added if the template doesn't include it, so that they still link to the news.
+    pub fn build_text(
+        template: &PostTemplate,
+        values: &TemplateValues,
+        prefix: &str,
+        mentions: &[String],
+        hashtags: &[String],
+        has_link_card: bool,
+    ) -> String {
+        let mut suffix = String::new();
+        if !has_link_card && !template.contains(Placeholder::Url) {
+            suffix.push_str(&format!("\n{}", values.url));
+        }
+        for mention in mentions {
+            suffix.push_str(&format!(" @{mention}"));
+        }
+        for hashtag in hashtags {
+            suffix.push_str(&format!(" #{hashtag}"));
+        }
+        let text = template.render(
+            values,
+            MAX_POST_GRAPHEMES
+                .saturating_sub(prefix.graphemes(true).count() + suffix.graphemes(true).count()),
+        );
+        format!("{prefix}{text}{suffix}")
+    }
+
+    /// Build the text of a post like [`PostData::build_text`], moving the abstract into replies when the whole
+    /// post wouldn't fit within the limit instead of truncating it.
+    ///
+    /// Returns the text of the first post alongside the text of every reply continuing it.
+    pub fn build_thread(
+        template: &PostTemplate,
+        values: &TemplateValues,
+        prefix: &str,
+        mentions: &[String],
+        hashtags: &[String],
+        has_link_card: bool,
+    ) -> (String, Vec<String>) {
+        let text = Self::build_text(template, values, prefix, mentions, hashtags, has_link_card);
+        // The text only contains the fully rendered template when nothing had to be truncated to fit.
+        let untruncated = template.render(values, usize::MAX);
+        if !template.contains(Placeholder::Abstract)
+            || values.r#abstract.is_empty()
+            || text.contains(&untruncated)
+        {
+            return (text, vec![]);
+        }
+        (
+            Self::build_text(
+                &template.without(Placeholder::Abstract),
+                values,
+                prefix,
+                mentions,
+                hashtags,
+                has_link_card,
+            ),
+            split_thread_text(values.r#abstract, MAX_POST_GRAPHEMES),
+        )
+    }
+}
+
+/// Get the handles expected to be mentioned in a post which have no mention facet covering them, such as
+/// handles that couldn't be resolved to an account when facets were detected.
+pub fn unresolved_mentions<'a>(
+    text: &str,
+    facets: Option<&[facet::Main]>,
+    handles: &'a [String],
+) -> Vec<&'a str> {
+    let mentioned: Vec<&str> = facets
+        .unwrap_or_default()
+        .iter()
+        .filter(|facet| {
+            facet
+                .features
+                .iter()
+                .any(|feature| matches!(feature, Union::Refs(facet::MainFeaturesItem::Mention(_))))
+        })
+        .filter_map(|facet| text.get(facet.index.byte_start..facet.index.byte_end))
+        .map(|mention| mention.trim_start_matches('@'))
+        .collect();
+    handles
+        .iter()
+        .map(String::as_str)
+        .filter(|handle| {
+            !mentioned
+                .iter()
+                .any(|mention| mention.eq_ignore_ascii_case(handle))
+        })
+        .collect()
+}
+
+/// Split text into parts of at most the given number of graphemes, breaking between words where possible.
+pub fn split_thread_text(text: &str, max_graphemes: usize) -> Vec<String> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut current_len = 0;
+    for word in text.split_word_bounds() {
+        let word_len = word.graphemes(true).count();
+        if current_len + word_len > max_graphemes && !current.trim().is_empty() {
+            parts.push(current.trim().to_string());
+            current.clear();
+            current_len = 0;
+        }
+        if current_len == 0 && word.trim().is_empty() {
+            continue;
+        }
+        // Words that can't fit in a part of their own are split wherever the limit falls.
+        for grapheme in word.graphemes(true) {
+            if current_len == max_graphemes {
+                parts.push(current.trim().to_string());
+                current.clear();
+                current_len = 0;
+            }
+            current.push_str(grapheme);
+            current_len += 1;
+        }
+    }
+    if !current.trim().is_empty() {
+        parts.push(current.trim().to_string());
+    }
+    parts
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PostEmbed {
     pub title: String,
     pub description: String,
     pub uri: Url,
     pub thumbnail_url: Option<Url>,
+    /// Accessibility text describing the embed, used where the embed type supports it.
+    #[serde(default)]
+    pub alt_text: String,
+    #[serde(default)]
+    pub mode: EmbedMode,
+}
+
+/// How news is embedded into posts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbedMode {
+    /// A link card with the title, description and thumbnail of the news.
+    #[default]
+    External,
+    /// The cover image of the news, with its title and description as alt text.
+    ///
+    /// Falls back to a link card for news without a cover image.
+    Image,
+}
+
+/// The record key of an account's profile record, of which there is only ever one.
+const PROFILE_RECORD_KEY: &str = "self";
+
+/// How avatars are processed before being uploaded, as profiles only accept PNG or JPEG images of up to 1MB.
+const AVATAR_IMAGE_OPTIONS: ImageOptions = ImageOptions {
+    max_width: 1000,
+    max_height: 1000,
+    filter: ThumbnailFilter::Lanczos3,
+    format: ThumbnailFormat::Jpeg,
+    max_bytes: 1_000_000,
+};
+
+/// Changes to make to the profile of the logged in account, leaving anything that is `None` as it is.
+#[derive(Debug, Default)]
+pub struct ProfileUpdate {
+    pub display_name: Option<String>,
+    pub description: Option<String>,
+    pub avatar_path: Option<PathBuf>,
+}
+
+/// Build the profile record of an account that doesn't have one yet.
+fn new_profile() -> profile::RecordData {
+    profile::RecordData {
+        avatar: None,
+        banner: None,
+        created_at: Some(Datetime::now()),
+        description: None,
+        display_name: None,
+        joined_via_starter_pack: None,
+        labels: None,
+        pinned_post: None,
+    }
+}
+
+/// The maximum length of image alt text in graphemes.
+const MAX_ALT_TEXT_GRAPHEMES: usize = 2000;
+
+/// A record created by the logged in account.
+#[derive(Debug, Clone)]
+pub struct CreatedRecord {
+    /// The AT URI of the record.
+    pub uri: String,
+    /// The CID of the version of the record that was created.
+    pub cid: String,
+}
+
+/// A post authored by the logged in account.
+#[derive(Debug)]
+pub struct AuthoredPost {
+    pub uri: String,
+    pub created_at: DateTime<Utc>,
+    pub embed_uri: Option<String>,
+    /// The URI of the post at the top of the thread, when the post is a reply.
+    pub reply_root: Option<String>,
+}
+
+/// Error names returned by the service when a session's tokens are no longer valid.
+const AUTH_ERROR_NAMES: [&str; 4] = [
+    "ExpiredToken",
+    "InvalidToken",
+    "AuthenticationRequired",
+    "AuthMissing",
+];
+
+/// The file inside of the data path that the session is cached in between runs.
+pub const SESSION_FILE_NAME: &str = "agentconfig.json";
+
+/// How long before its access token expires a session is refreshed by [`BlueskyHandler::ensure_session_fresh`].
+const SESSION_REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// The account and expiry of a session, for showing to the user.
+#[derive(Debug)]
+pub struct SessionInfo {
+    pub handle: String,
+    pub did: String,
+    pub endpoint: String,
+    pub access_token_expiry: Option<DateTime<Utc>>,
+    pub refresh_token_expiry: Option<DateTime<Utc>>,
+}
+
+/// Whether a failed Bluesky request was rejected because the session's access token has expired, which can be
+/// recovered from by refreshing the session.
+pub fn is_expired_token_error(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<bsky_sdk::Error>() {
+        Some(bsky_sdk::Error::Xrpc(err)) => match err.as_ref() {
+            GenericXrpcError::Response { error, .. } => error
+                .as_deref()
+                .is_some_and(|error| error.starts_with("ExpiredToken")),
+            GenericXrpcError::Other(_) => false,
+        },
+        _ => false,
+    }
+}
+
+/// Read when a session token expires from the `exp` claim of its JWT payload, without verifying it.
+fn token_expiry(jwt: &str) -> Option<DateTime<Utc>> {
+    let payload = URL_SAFE_NO_PAD.decode(jwt.split('.').nth(1)?).ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&payload).ok()?;
+    DateTime::from_timestamp(claims.get("exp")?.as_i64()?, 0)
+}
+
+/// Get the HTTP status code of a failed Bluesky request, if the failure came from an XRPC response.
+pub fn xrpc_status(err: &anyhow::Error) -> Option<StatusCode> {
+    match err.downcast_ref::<bsky_sdk::Error>()? {
+        bsky_sdk::Error::Xrpc(err) => match err.as_ref() {
+            GenericXrpcError::Response { status, .. } => Some(*status),
+            GenericXrpcError::Other(_) => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether a failed Bluesky request was rejected because the session or credentials are no longer valid.
+pub fn is_auth_error(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<bsky_sdk::Error>() {
+        Some(bsky_sdk::Error::NotLoggedIn) => true,
+        Some(bsky_sdk::Error::Xrpc(err)) => match err.as_ref() {
+            GenericXrpcError::Response { status, error } => {
+                *status == StatusCode::UNAUTHORIZED
+                    || error.as_deref().is_some_and(|error| {
+                        AUTH_ERROR_NAMES.iter().any(|name| error.starts_with(name))
+                    })
+            }
+            GenericXrpcError::Other(error) => error.starts_with("Authentication"),
+        },
+        _ => false,
+    }
+}
+
+/// Whether logging in was rejected because the account has email 2FA enabled and a sign in code has to be given.
+pub fn is_auth_factor_required_error(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<bsky_sdk::Error>() {
+        Some(bsky_sdk::Error::Xrpc(err)) => match err.as_ref() {
+            GenericXrpcError::Response { error, .. } => error
+                .as_deref()
+                .is_some_and(|error| error.starts_with("AuthFactorTokenRequired")),
+            GenericXrpcError::Other(_) => false,
+        },
+        _ => false,
+    }
+}
+
+/// Convert an error from creating a session, keeping the name of the errors the lexicon defines for it as `bsky_sdk`
+/// reports them all as a "custom error".
+fn create_session_error(err: xrpc::Error<create_session::Error>) -> bsky_sdk::Error {
+    match err {
+        xrpc::Error::XrpcResponse(XrpcError {
+            status,
+            error: Some(XrpcErrorKind::Custom(error)),
+        }) => bsky_sdk::Error::Xrpc(Box::new(GenericXrpcError::Response {
+            status,
+            error: Some(error.to_string()),
+        })),
+        err => err.into(),
+    }
+}
+
+/// Ask for the sign in code that was emailed for an account with email 2FA, if there's a terminal to ask in.
+fn prompt_auth_factor_token() -> Result<Secret<String>> {
+    if !std::io::stdin().is_terminal() {
+        bail!(
+            "the bluesky account has email 2FA enabled and requires the sign in code that was emailed to it: pass it with --app-auth-factor-token or WHIMSKY_APP_AUTH_FACTOR_TOKEN, or run in a terminal to be prompted for it"
+        );
+    }
+    eprint!("Sign in code sent to your email: ");
+    let mut token = String::new();
+    std::io::stdin()
+        .read_line(&mut token)
+        .context("failed to read the sign in code from stdin")?;
+    let token = token.trim();
+    if token.is_empty() {
+        bail!("no sign in code was given");
+    }
+    Ok(Secret::new(token.to_string()))
+}
+
+/// Whether a failed Bluesky request was rejected because the record it asked for doesn't exist.
+pub fn is_record_not_found(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<bsky_sdk::Error>() {
+        Some(bsky_sdk::Error::Xrpc(err)) => match err.as_ref() {
+            GenericXrpcError::Response { error, .. } => error
+                .as_deref()
+                .is_some_and(|error| error.starts_with("RecordNotFound")),
+            GenericXrpcError::Other(_) => false,
+        },
+        _ => false,
+    }
+}
+
+/// Get the record key at the end of a record URI such as `at://did:plc:abc/app.bsky.feed.post/3k2a`.
+fn record_key(uri: &str) -> Result<RecordKey> {
+    let (_, rkey) = uri
+        .rsplit_once('/')
+        .with_context(|| format!("'{uri}' is not a record URI"))?;
+    RecordKey::new(rkey.to_string())
+        .map_err(|err| anyhow!("'{uri}' doesn't end in a valid record key: {err}"))
+}
+
+/// The number of times a request that failed with a transient error is attempted before giving up.
+const REQUEST_ATTEMPTS: u32 = 3;
+
+/// The longest time to wait for a rate limit to reset before giving up on a request.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(5 * 60);
+
+/// A request was rate limited and the limit won't reset soon enough to wait for it.
+///
+/// Whatever was being attempted should be deferred until after the limit has reset.
+#[derive(Debug)]
+pub struct RateLimited {
+    pub reset: DateTime<Utc>,
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rate limited by bluesky until {}", self.reset)
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Whether a failed request was rejected by the service's rate limit.
+fn is_rate_limit_error(err: &anyhow::Error) -> bool {
+    xrpc_status(err) == Some(StatusCode::TOO_MANY_REQUESTS)
+}
+
+/// Whether a failed request is worth trying again, such as after a timeout, a rate limit or a server error.
+///
+/// Requests the service rejected as invalid fail the same way every time and are never retried.
+fn is_transient_error(err: &anyhow::Error) -> bool {
+    if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+        return err.is_timeout()
+            || err.is_connect()
+            || err.is_body()
+            || err.status().is_some_and(|status| {
+                status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            });
+    }
+    match err.downcast_ref::<bsky_sdk::Error>() {
+        Some(bsky_sdk::Error::Xrpc(err)) => match err.as_ref() {
+            GenericXrpcError::Response { status, .. } => {
+                *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+            // Failures to send the request at all, such as timeouts and dropped connections.
+            GenericXrpcError::Other(error) => error.starts_with("HttpClient"),
+        },
+        _ => false,
+    }
 }
 
 impl BlueskyHandler {
@@ -53,58 +558,386 @@ impl BlueskyHandler {
         }
     }
 
+    async fn build_agent(
+        config: Config,
+        http: &reqwest::Client,
+    ) -> Result<(BskyAgent<RateLimitedClient>, RateLimitedClient)> {
+        let client = RateLimitedClient::new(&config.endpoint, http.clone());
+        let agent = BskyAgent::builder()
+            .client(client.clone())
+            .config(config)
+            .build()
+            .await?;
+        Ok((agent, client))
+    }
+
+    /// Explain why a cached session can't be resumed to act as an account on a service, if it can't.
+    ///
+    /// The identifier is matched against the DID, handle and email of the session's account, with a session for any
+    /// account being accepted when there isn't one.
+    fn session_mismatch(
+        config: &Config,
+        service: &str,
+        identifier: Option<&str>,
+    ) -> Option<String> {
+        let endpoint = Self::make_default_config(service).endpoint;
+        if config.endpoint != endpoint {
+            return Some(format!(
+                "it is for {} rather than {endpoint}",
+                config.endpoint
+            ));
+        }
+        let session = config.session.as_ref()?;
+        let identifier = identifier?.trim_start_matches('@');
+        let is_account = session.did.as_str() == identifier
+            || session.handle.as_str().eq_ignore_ascii_case(identifier)
+            || session
+                .email
+                .as_deref()
+                .is_some_and(|email| email.eq_ignore_ascii_case(identifier));
+        (!is_account).then(|| {
+            format!(
+                "it belongs to {} rather than {identifier}",
+                session.handle.as_str()
+            )
+        })
+    }
+
+    /// Create a handler acting as an account on a service, resuming the session cached in the data path when it is
+    /// for the same account.
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
+        http: reqwest::Client,
         service: Url,
+        identifier: Option<&str>,
         data_path_base: PathBuf,
-        disable_comments: bool,
+        reply_control: Vec<ReplyControl>,
+        disable_quote_posts: bool,
+        image_options: ImageOptions,
+        thumbnail_cache: Option<ThumbnailCache>,
     ) -> Result<Self> {
-        let data_path = data_path_base.join("agentconfig.json");
+        let data_path = data_path_base.join(SESSION_FILE_NAME);
 
-        // Try login with cached token.
-        match Config::load(&FileStore::new(&data_path)).await {
-            Ok(config) => {
+        // Try login with cached token, as long as it is for the same account.
+        let cached_config = Config::load(&FileStore::new(&data_path))
+            .await
+            .ok()
+            .filter(
+                |config| match Self::session_mismatch(config, service.as_str(), identifier) {
+                    Some(reason) => {
+                        info!("Discarding the cached bsky session as {reason}");
+                        false
+                    }
+                    None => true,
+                },
+            );
+        let (agent, client) = match cached_config {
+            Some(config) => {
                 // We have a cached token, attempt to use it.
-                match BskyAgent::builder().config(config).build().await {
-                    Ok(agent) => {
+                match Self::build_agent(config, &http).await {
+                    Ok((agent, client)) => {
                         let handler = Self {
                             agent,
+                            client,
+                            http,
                             data_path,
-                            disable_comments,
+                            reply_control,
+                            disable_quote_posts,
+                            image_options,
+                            thumbnail_cache,
+                            uploaded_blob_bytes: AtomicU64::new(0),
                         };
                         handler.sync_session().await?;
-                        Ok(handler)
+                        return Ok(handler);
                     }
-                    Err(_) => Ok(Self {
-                        // Using that session failed, make a new one.
-                        agent: BskyAgent::builder()
-                            .config(Self::make_default_config(service.as_str()))
-                            .build()
-                            .await?,
-                        data_path,
-                        disable_comments,
-                    }),
-                }
-            }
-            Err(_) => Ok(Self {
-                // We don't cache a cached token, make a new session.
-                agent: BskyAgent::builder()
-                    .config(Self::make_default_config(service.as_str()))
-                    .build()
-                    .await?,
-                data_path,
-                disable_comments,
-            }),
+                    // Using that session failed, make a new one.
+                    Err(_) => {
+                        Self::build_agent(Self::make_default_config(service.as_str()), &http)
+                            .await?
+                    }
+                }
+            }
+            // We don't have a usable cached token, make a new session.
+            None => Self::build_agent(Self::make_default_config(service.as_str()), &http).await?,
+        };
+        Ok(Self {
+            agent,
+            client,
+            http,
+            data_path,
+            reply_control,
+            disable_quote_posts,
+            image_options,
+            thumbnail_cache,
+            uploaded_blob_bytes: AtomicU64::new(0),
+        })
+    }
+
+    /// Create a handler from the session cached in the data path, whichever service and account it is for.
+    ///
+    /// Fails when no session is cached or the service no longer accepts it.
+    pub async fn resume(http: reqwest::Client, data_path_base: PathBuf) -> Result<Self> {
+        let data_path = data_path_base.join(SESSION_FILE_NAME);
+        let config = Config::load(&FileStore::new(&data_path))
+            .await
+            .ok()
+            .filter(|config| config.session.is_some())
+            .with_context(|| format!("no bsky session is cached in {}", data_path.display()))?;
+        let (agent, client) = Self::build_agent(config, &http)
+            .await
+            .context("the cached bsky session is no longer valid")?;
+        let handler = Self {
+            agent,
+            client,
+            http,
+            data_path,
+            reply_control: vec![ReplyControl::Everyone],
+            disable_quote_posts: false,
+            image_options: ImageOptions::default(),
+            thumbnail_cache: None,
+            uploaded_blob_bytes: AtomicU64::new(0),
+        };
+        handler.sync_session().await?;
+        Ok(handler)
+    }
+
+    /// Whether the handler has a session, either resumed from the data path or from logging in.
+    pub async fn has_session(&self) -> bool {
+        self.agent.get_session().await.is_some()
+    }
+
+    /// Describe the current session.
+    pub async fn session_info(&self) -> Option<SessionInfo> {
+        let session = self.agent.get_session().await?;
+        Some(SessionInfo {
+            handle: session.handle.to_string(),
+            did: session.did.to_string(),
+            endpoint: self.agent.get_endpoint().await,
+            access_token_expiry: token_expiry(&session.access_jwt),
+            refresh_token_expiry: token_expiry(&session.refresh_jwt),
+        })
+    }
+
+    /// End the current session on the service and delete the cached session file.
+    ///
+    /// Failing to end the session on the service is only logged, as the tokens stop working when they expire anyway.
+    pub async fn logout(&self) -> Result<()> {
+        if let Some(session) = self.agent.get_session().await {
+            // The service expects the refresh token rather than the access token the agent would send.
+            let result = self
+                .http
+                .post(format!(
+                    "{}/xrpc/com.atproto.server.deleteSession",
+                    self.agent.get_endpoint().await
+                ))
+                .bearer_auth(&session.refresh_jwt)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+            if let Err(err) = result {
+                warn!("Failed to end the bsky session on the service: {err}");
+            }
+        }
+        std::fs::remove_file(&self.data_path).with_context(|| {
+            format!(
+                "failed to delete bsky session file {}",
+                self.data_path.display()
+            )
+        })
+    }
+
+    /// Run a request, trying it again with an exponential backoff of 1s, 4s, 16s... when it fails with a transient error.
+    ///
+    /// Rate limited requests are tried once more after the limit resets, or fail with [`RateLimited`] when it
+    /// won't reset within [`MAX_RATE_LIMIT_WAIT`].
+    async fn with_retries<T, F, Fut>(&self, action: &str, mut request: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        let mut waited_for_rate_limit = false;
+        let mut refreshed_session = false;
+        loop {
+            match request().await {
+                Err(err) if is_rate_limit_error(&err) && !waited_for_rate_limit => {
+                    let now = Utc::now();
+                    let Some(reset) = self
+                        .client
+                        .rate_limit()
+                        .map(|rate_limit| rate_limit.reset)
+                        .filter(|reset| *reset > now)
+                    else {
+                        // Without knowing when the limit resets, fall back to backing off like any other failure.
+                        if attempt >= REQUEST_ATTEMPTS {
+                            return Err(err);
+                        }
+                        let delay = Duration::from_secs(4u64.pow(attempt - 1));
+                        warn!(
+                            "Rate limited while trying to {action} on attempt {attempt}/{REQUEST_ATTEMPTS} - retrying in {} seconds",
+                            delay.as_secs()
+                        );
+                        sleep(delay).await;
+                        attempt += 1;
+                        continue;
+                    };
+                    let wait = (reset - now).to_std().unwrap_or_default();
+                    if wait > MAX_RATE_LIMIT_WAIT {
+                        return Err(err.context(RateLimited { reset }));
+                    }
+                    warn!(
+                        "Rate limited while trying to {action}: waiting for {} seconds until {reset} to resume",
+                        wait.as_secs()
+                    );
+                    sleep(wait).await;
+                    waited_for_rate_limit = true;
+                }
+                Err(err) if is_expired_token_error(&err) && !refreshed_session => {
+                    warn!("Session expired while trying to {action}: refreshing it and retrying");
+                    self.refresh_session().await?;
+                    refreshed_session = true;
+                }
+                Err(err) if attempt < REQUEST_ATTEMPTS && is_transient_error(&err) => {
+                    let delay = Duration::from_secs(4u64.pow(attempt - 1));
+                    warn!(
+                        "Failed to {action} on attempt {attempt}/{REQUEST_ATTEMPTS}: {err:#} - retrying in {} seconds",
+                        delay.as_secs()
+                    );
+                    sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Wait before making a request if the service has reported that its rate limit is close to being hit.
+    async fn pace(&self) {
+        let Some(rate_limit) = self.client.rate_limit() else {
+            return;
+        };
+        if let Some(delay) = rate_limit.pacing_delay(Utc::now()) {
+            warn!(
+                "Only {} requests remain until the rate limit resets at {}: waiting for {} seconds",
+                rate_limit.remaining,
+                rate_limit.reset,
+                delay.as_secs()
+            );
+            sleep(delay).await;
         }
     }
 
-    pub async fn login(&self, identifier: &str, password: &str) -> Result<()> {
-        self.agent.login(identifier, password).await?;
+    /// Log in to Bluesky with an app password and save the session.
+    ///
+    /// Accounts with email 2FA are logged in again with `auth_factor_token`, or a code asked for on the terminal when
+    /// it isn't given.
+    pub async fn login(
+        &self,
+        identifier: &str,
+        password: &Secret<String>,
+        auth_factor_token: Option<&Secret<String>>,
+    ) -> Result<()> {
+        match self
+            .agent
+            .login(identifier, password.expose())
+            .await
+            .map_err(create_session_error)
+        {
+            Ok(_) => {}
+            Err(err) => {
+                let err = anyhow::Error::from(err);
+                if !is_auth_factor_required_error(&err) {
+                    return Err(err);
+                }
+                let token = match auth_factor_token {
+                    Some(token) => token.clone(),
+                    None => prompt_auth_factor_token()?,
+                };
+                info!("Logging in with the email sign in code");
+                self.login_with_auth_factor_token(identifier, password, &token)
+                    .await
+                    .context("failed to log in with the email sign in code")?;
+            }
+        }
         self.sync_session().await?;
         Ok(())
     }
 
+    /// Create a session for an account with email 2FA, as the agent can only log in without a sign in code.
+    async fn login_with_auth_factor_token(
+        &self,
+        identifier: &str,
+        password: &Secret<String>,
+        auth_factor_token: &Secret<String>,
+    ) -> Result<()> {
+        let session = self
+            .agent
+            .api
+            .com
+            .atproto
+            .server
+            .create_session(
+                create_session::InputData {
+                    allow_takendown: None,
+                    auth_factor_token: Some(auth_factor_token.expose().clone()),
+                    identifier: identifier.into(),
+                    password: password.expose().clone(),
+                }
+                .into(),
+            )
+            .await
+            .map_err(create_session_error)?;
+        self.agent
+            .resume_session(session)
+            .await
+            .map_err(bsky_sdk::Error::from)?;
+        Ok(())
+    }
+
+    /// Refresh the session when its access token expires within the next few minutes, so that it can't expire part
+    /// way through a run.
+    pub async fn ensure_session_fresh(&self) -> Result<()> {
+        let Some(session) = self.agent.get_session().await else {
+            return Ok(());
+        };
+        if let Some(expiry) = token_expiry(&session.access_jwt)
+            && expiry - Utc::now() > chrono::Duration::from_std(SESSION_REFRESH_MARGIN)?
+        {
+            return Ok(());
+        }
+        info!("Refreshing the bsky session as its access token is about to expire");
+        self.refresh_session().await
+    }
+
+    /// Exchange the session's refresh token for new tokens and save them.
+    async fn refresh_session(&self) -> Result<()> {
+        let Some(mut session) = self.agent.get_session().await else {
+            return Err(bsky_sdk::Error::NotLoggedIn.into());
+        };
+        let refreshed = self
+            .agent
+            .api
+            .com
+            .atproto
+            .server
+            .refresh_session()
+            .await
+            .map_err(bsky_sdk::Error::from)?;
+        session.access_jwt = refreshed.data.access_jwt;
+        session.refresh_jwt = refreshed.data.refresh_jwt;
+        session.handle = refreshed.data.handle;
+        session.did_doc = refreshed.data.did_doc;
+        self.agent
+            .resume_session(session)
+            .await
+            .map_err(bsky_sdk::Error::from)?;
+        self.sync_session().await
+    }
+
+    /// Save the agent's session to the data path so that it can be resumed by the next run.
     pub async fn sync_session(&self) -> Result<()> {
         debug!("syncing agent session data");
+        restrict_session_file(&self.data_path).context("unable to secure bsky session file")?;
         self.agent
             .to_config()
             .await
@@ -114,10 +947,128 @@ impl BlueskyHandler {
         Ok(())
     }
 
-    pub async fn post(&self, post: PostData) -> Result<()> {
+    /// Fetch a page of posts authored by the logged in account including replies, newest first.
+    ///
+    /// Returns the posts alongside the cursor for the next page, if there is one.
+    pub async fn get_authored_posts(
+        &self,
+        cursor: Option<String>,
+    ) -> Result<(Vec<AuthoredPost>, Option<String>)> {
+        let did = self
+            .agent
+            .did()
+            .await
+            .context("not authenticated with bluesky")?;
+        let output = self
+            .with_retries("fetch authored posts", || async {
+                self.pace().await;
+                Ok(self
+                    .agent
+                    .api
+                    .app
+                    .bsky
+                    .feed
+                    .get_author_feed(
+                        get_author_feed::ParametersData {
+                            actor: did.clone().into(),
+                            cursor: cursor.clone(),
+                            filter: Some("posts_with_replies".into()),
+                            include_pins: None,
+                            limit: Some(LimitedNonZeroU8::MAX),
+                        }
+                        .into(),
+                    )
+                    .await
+                    .map_err(bsky_sdk::Error::from)?)
+            })
+            .await?;
+
+        let mut posts = vec![];
+        for item in output.data.feed {
+            // Skip reposts and anything else that wasn't authored by us.
+            if item.reason.is_some() || item.post.author.did != did {
+                continue;
+            }
+            let record = post::RecordData::try_from_unknown(item.post.record.clone())?;
+            posts.push(AuthoredPost {
+                uri: item.post.uri.clone(),
+                created_at: record.created_at.as_ref().with_timezone(&Utc),
+                embed_uri: match &item.post.embed {
+                    Some(Union::Refs(PostViewEmbedRefs::AppBskyEmbedExternalView(view))) => {
+                        Some(view.external.uri.clone())
+                    }
+                    _ => None,
+                },
+                reply_root: record.reply.map(|reply| reply.root.uri.clone()),
+            });
+        }
+        Ok((posts, output.data.cursor))
+    }
+
+    /// Delete a post alongside any threadgate or postgate records attached to it.
+    pub async fn delete_post(&self, uri: &str) -> Result<()> {
+        info!("Deleting post '{uri}'");
+        self.with_retries("delete post record", || async {
+            self.pace().await;
+            Ok(self.agent.delete_record(uri).await?)
+        })
+        .await?;
+        for collection in [Threadgate::NSID, Postgate::NSID] {
+            let gate_uri = uri.replacen(Post::NSID, collection, 1);
+            if let Err(err) = self.agent.delete_record(&gate_uri).await {
+                warn!("Failed to delete '{gate_uri}': {err}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a post, followed by replies to it for each of [`PostData::replies`].
+    ///
+    /// Returns every record that was created, starting with the first post. Failing to post a reply stops the
+    /// thread without failing the post, as the first post has already been made by then.
+    pub async fn post(&self, post: PostData) -> Result<Vec<CreatedRecord>> {
         info!("Constructing post data for: '{}'", &post.text);
         let rt = RichText::new_with_detect_facets(&post.text).await?;
+        // Handles that fail to resolve are left as plain text rather than failing the post.
+        for handle in unresolved_mentions(&post.text, rt.facets.as_deref(), &post.mentions) {
+            warn!(
+                "The mention of '@{handle}' won't link to an account as the handle couldn't be resolved"
+            );
+        }
+        // Checked up front so that an over-long post fails before anything is uploaded.
+        if rt.grapheme_len() > MAX_POST_GRAPHEMES {
+            bail!(
+                "post text is {} graphemes long which is over the limit of {MAX_POST_GRAPHEMES}",
+                rt.grapheme_len()
+            );
+        }
         let embed = match post.embed {
+            Some(PostEmbed {
+                mode: EmbedMode::Image,
+                thumbnail_url: Some(image_url),
+                title,
+                description,
+                alt_text,
+                uri,
+            }) => {
+                let alt = match alt_text.is_empty() {
+                    true => &title,
+                    false => &alt_text,
+                };
+                match self.embed_image(alt, uri.as_ref(), image_url).await {
+                    Ok(embed) => Some(embed),
+                    Err(err) if err.is::<RateLimited>() || is_auth_error(&err) => return Err(err),
+                    Err(err) => {
+                        warn!(
+                            "Failed to embed the cover image for '{uri}': {err:#} - falling back to a link card"
+                        );
+                        Some(
+                            self.embed_external(&title, &description, uri.as_ref(), None)
+                                .await?,
+                        )
+                    }
+                }
+            }
             Some(data) => Some(
                 self.embed_external(
                     &data.title,
@@ -131,67 +1082,361 @@ impl BlueskyHandler {
         };
 
         info!("Creating post record for: '{}'", &post.text);
+        let record_data = post::RecordData {
+            created_at: Datetime::from_str(&post.created_at.fixed_offset().to_rfc3339())?,
+            embed,
+            entities: None,
+            facets: rt.facets,
+            labels: match post.labels.is_empty() {
+                true => None,
+                false => Some(Union::Refs(
+                    post::RecordLabelsRefs::ComAtprotoLabelDefsSelfLabels(Box::new(
+                        SelfLabelsData {
+                            values: post
+                                .labels
+                                .into_iter()
+                                .map(|val| SelfLabelData { val }.into())
+                                .collect(),
+                        }
+                        .into(),
+                    )),
+                )),
+            },
+            langs: Some(
+                post.languages
+                    .iter()
+                    .map(|language| {
+                        Language::from_str(language)
+                            .map_err(|_| anyhow!("'{language}' is not a valid post language"))
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            reply: None,
+            tags: match post.tags.is_empty() {
+                true => None,
+                false => Some(post.tags),
+            },
+            text: post.text,
+        };
+        let replies = post
+            .replies
+            .iter()
+            .map(|text| {
+                let len = text.graphemes(true).count();
+                if len > MAX_POST_GRAPHEMES {
+                    bail!(
+                        "reply text is {len} graphemes long which is over the limit of {MAX_POST_GRAPHEMES}"
+                    );
+                }
+                Ok(text)
+            })
+            .collect::<Result<Vec<_>>>()?;
         let record = self
-            .agent
-            .create_record(post::RecordData {
-                created_at: Datetime::from_str(&post.created_at.fixed_offset().to_rfc3339())?,
-                embed,
-                entities: None,
-                facets: rt.facets,
-                labels: None,
-                langs: Some(
-                    post.languages
-                        .iter()
-                        .map(|f| Language::from_str(f).unwrap())
-                        .collect(),
-                ),
-                reply: None,
-                tags: None,
-                text: post.text,
+            .with_retries("create post record", || async {
+                self.pace().await;
+                Ok(self.agent.create_record(record_data.clone()).await?)
             })
             .await?;
 
-        if self.disable_comments {
+        if let Some(allow) = threadgate_allow_rules(&self.reply_control) {
             info!(
-                "Disabling post comments via threadgate for '{}'",
-                record.uri
+                "Restricting post replies to {:?} via threadgate for '{}'",
+                self.reply_control, record.uri
             );
-            let rkey = record.uri.rsplit_once('/').map(|(_, rkey)| {
-                RecordKey::from_str(rkey).expect("record key from post should always be vlaid")
-            });
+            let gate = threadgate::RecordData {
+                allow: Some(allow),
+                created_at: Datetime::now(),
+                hidden_replies: None,
+                post: record.uri.clone(),
+            };
+            if let Err(err) = self
+                .create_gate_record(&record.uri, Threadgate::nsid(), gate.try_into_unknown()?)
+                .await
+            {
+                warn!("Failed to create threadgate for '{}': {err}", record.uri);
+            }
+        }
 
-            self.agent
-                .api
-                .com
-                .atproto
-                .repo
-                .create_record(
-                    bsky_sdk::api::com::atproto::repo::create_record::InputData {
-                        collection: bsky_sdk::api::app::bsky::feed::Threadgate::nsid(),
-                        record: bsky_sdk::api::app::bsky::feed::threadgate::RecordData {
-                            allow: Some(vec![]),
-                            created_at: Datetime::now(),
-                            hidden_replies: None,
-                            post: record.uri.clone(),
-                        }
-                        .try_into_unknown()?,
-                        repo: self
-                            .agent
-                            .get_session()
-                            .await
-                            .expect("not unauthenticated")
-                            .data
-                            .did
-                            .into(),
-                        rkey,
-                        swap_commit: None,
-                        validate: None,
+        if self.disable_quote_posts {
+            info!("Disabling quote posts via postgate for '{}'", record.uri);
+            let gate = postgate::RecordData {
+                created_at: Datetime::now(),
+                detached_embedding_uris: None,
+                embedding_rules: Some(vec![Union::Refs(
+                    postgate::RecordEmbeddingRulesItem::DisableRule(Box::new(
+                        postgate::DisableRuleData {}.into(),
+                    )),
+                )]),
+                post: record.uri.clone(),
+            };
+            if let Err(err) = self
+                .create_gate_record(&record.uri, Postgate::nsid(), gate.try_into_unknown()?)
+                .await
+            {
+                warn!("Failed to create postgate for '{}': {err}", record.uri);
+            }
+        }
+
+        let root = StrongRefData {
+            cid: record.cid.clone(),
+            uri: record.uri.clone(),
+        };
+        let mut records = vec![root.clone()];
+        let mut parent = root.clone();
+        for (index, text) in replies.into_iter().enumerate() {
+            let reply_data = post::RecordData {
+                created_at: Datetime::now(),
+                embed: None,
+                facets: RichText::new_with_detect_facets(text).await?.facets,
+                reply: Some(
+                    post::ReplyRefData {
+                        parent: parent.clone().into(),
+                        root: root.clone().into(),
                     }
                     .into(),
-                )
-                .await?;
+                ),
+                text: text.clone(),
+                ..record_data.clone()
+            };
+            info!("Creating reply {} to '{}'", index + 1, root.uri);
+            match self
+                .with_retries("create reply record", || async {
+                    self.pace().await;
+                    Ok(self.agent.create_record(reply_data.clone()).await?)
+                })
+                .await
+            {
+                Ok(reply) => {
+                    parent = StrongRefData {
+                        cid: reply.data.cid,
+                        uri: reply.data.uri,
+                    };
+                    records.push(parent.clone());
+                }
+                Err(err) => {
+                    warn!(
+                        "Failed to create reply {} to '{}': {err:#} - leaving the thread incomplete",
+                        index + 1,
+                        root.uri
+                    );
+                    break;
+                }
+            }
+        }
+
+        Ok(records
+            .into_iter()
+            .map(|record| CreatedRecord {
+                uri: record.uri,
+                cid: record.cid.as_ref().to_string(),
+            })
+            .collect())
+    }
+
+    /// Get the profile record of the logged in account alongside its CID, or `None` if it doesn't have one yet.
+    async fn get_profile(&self) -> Result<Option<(profile::RecordData, Cid)>> {
+        self.pace().await;
+        let output = match profile::RecordData::get(
+            &self.agent,
+            RecordKey::new(PROFILE_RECORD_KEY.to_string()).map_err(|err| anyhow!(err))?,
+        )
+        .await
+        .map_err(anyhow::Error::from)
+        {
+            Ok(output) => output,
+            Err(err) if is_record_not_found(&err) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let cid = output
+            .data
+            .cid
+            .context("the profile record was returned without a CID")?;
+        Ok(Some((
+            profile::RecordData::try_from_unknown(output.data.value)?,
+            cid,
+        )))
+    }
+
+    /// Replace the profile record of the logged in account, failing if it was changed since `previous` was read.
+    async fn put_profile(&self, record: profile::RecordData, previous: Option<Cid>) -> Result<()> {
+        self.pace().await;
+        self.agent
+            .api
+            .com
+            .atproto
+            .repo
+            .put_record(
+                put_record::InputData {
+                    collection: Profile::nsid(),
+                    record: record.try_into_unknown()?,
+                    repo: self
+                        .agent
+                        .get_session()
+                        .await
+                        .ok_or(bsky_sdk::Error::NotLoggedIn)?
+                        .data
+                        .did
+                        .into(),
+                    rkey: RecordKey::new(PROFILE_RECORD_KEY.to_string())
+                        .map_err(|err| anyhow!(err))?,
+                    swap_commit: None,
+                    swap_record: previous,
+                    validate: None,
+                }
+                .into(),
+            )
+            .await
+            .map_err(bsky_sdk::Error::from)?;
+        Ok(())
+    }
+
+    /// Update the display name, description and avatar of the logged in account's profile.
+    ///
+    /// Returns whether anything was changed, so that running the same update twice only writes the profile once.
+    pub async fn update_profile(&self, update: ProfileUpdate) -> Result<bool> {
+        let (current, cid) = match self.get_profile().await? {
+            Some((record, cid)) => (Some(record), Some(cid)),
+            None => (None, None),
+        };
+        let mut record = current.clone().unwrap_or_else(new_profile);
+        if let Some(display_name) = update.display_name {
+            record.display_name = Some(display_name);
+        }
+        if let Some(description) = update.description {
+            record.description = Some(description);
+        }
+        if let Some(avatar_path) = update.avatar_path {
+            let bytes = tokio::fs::read(&avatar_path)
+                .await
+                .with_context(|| format!("failed to read avatar at {}", avatar_path.display()))?;
+            let (buf, _) = process_thumbnail(&bytes, &AVATAR_IMAGE_OPTIONS).with_context(|| {
+                format!("failed to process avatar at {}", avatar_path.display())
+            })?;
+            // Identical images are uploaded as identical blobs, so an unchanged avatar leaves the record unchanged.
+            record.avatar = Some(self.upload_blob(buf).await?);
+        }
+        if current.as_ref() == Some(&record) {
+            info!("Profile is already up to date");
+            return Ok(false);
+        }
+        info!("Updating profile");
+        self.put_profile(record, cid).await?;
+        Ok(true)
+    }
+
+    /// Make sure the logged in account has a pinned post, creating and pinning one with the given text if it
+    /// doesn't. A post that is already pinned is left as it is, even if its text is different.
+    ///
+    /// Returns the URI of the pinned post.
+    pub async fn ensure_pinned_post(&self, text: &str) -> Result<String> {
+        let current = self.get_profile().await?;
+        if let Some((
+            profile::RecordData {
+                pinned_post: Some(pinned),
+                ..
+            },
+            _,
+        )) = &current
+        {
+            let rkey = pinned
+                .uri
+                .rsplit_once('/')
+                .map(|(_, rkey)| rkey.to_string());
+            self.pace().await;
+            match post::RecordData::get(
+                &self.agent,
+                RecordKey::new(rkey.unwrap_or_default()).map_err(|err| anyhow!(err))?,
+            )
+            .await
+            .map_err(anyhow::Error::from)
+            {
+                Ok(_) => {
+                    info!("'{}' is already pinned", pinned.uri);
+                    return Ok(pinned.uri.clone());
+                }
+                Err(err) if is_record_not_found(&err) => {
+                    info!("The pinned post '{}' no longer exists", pinned.uri);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let rt = RichText::new_with_detect_facets(text).await?;
+        if rt.grapheme_len() > MAX_POST_GRAPHEMES {
+            bail!(
+                "pinned post text is {} graphemes long which is over the limit of {MAX_POST_GRAPHEMES}",
+                rt.grapheme_len()
+            );
+        }
+        info!("Creating pinned post for: '{text}'");
+        let record_data = post::RecordData {
+            created_at: Datetime::now(),
+            embed: None,
+            entities: None,
+            facets: rt.facets,
+            labels: None,
+            langs: None,
+            reply: None,
+            tags: None,
+            text: text.to_string(),
         };
+        let record = self
+            .with_retries("create post record", || async {
+                self.pace().await;
+                Ok(self.agent.create_record(record_data.clone()).await?)
+            })
+            .await?;
+
+        let (mut profile, cid) = match current {
+            Some((record, cid)) => (record, Some(cid)),
+            None => (new_profile(), None),
+        };
+        profile.pinned_post = Some(
+            StrongRefData {
+                cid: record.cid.clone(),
+                uri: record.uri.clone(),
+            }
+            .into(),
+        );
+        info!("Pinning '{}'", record.uri);
+        self.put_profile(profile, cid).await?;
+        Ok(record.data.uri)
+    }
 
+    /// Create a gate record such as a threadgate or postgate for a post, which must share the post's record key.
+    async fn create_gate_record(
+        &self,
+        post_uri: &str,
+        collection: Nsid,
+        record: Unknown,
+    ) -> Result<()> {
+        let rkey = record_key(post_uri)?;
+        self.pace().await;
+        self.agent
+            .api
+            .com
+            .atproto
+            .repo
+            .create_record(
+                create_record::InputData {
+                    collection,
+                    record,
+                    repo: self
+                        .agent
+                        .get_session()
+                        .await
+                        .ok_or(bsky_sdk::Error::NotLoggedIn)?
+                        .data
+                        .did
+                        .into(),
+                    rkey: Some(rkey),
+                    swap_commit: None,
+                    validate: None,
+                }
+                .into(),
+            )
+            .await
+            .map_err(bsky_sdk::Error::from)?;
         Ok(())
     }
 
@@ -204,26 +1449,22 @@ impl BlueskyHandler {
     ) -> Result<Union<RecordEmbedRefs>> {
         info!("Constructing external embed data for: '{uri}'");
 
-        let thumb = if let Some(data) = thumbnail_url {
-            debug!("Fetching and uploading image blob data for '{uri}'");
-            let image_bytes = reqwest::get(data).await?.bytes().await?;
-            let buf = (|| -> Result<Vec<u8>> {
-                let mut buf: Vec<u8> = vec![];
-                ImageReader::new(Cursor::new(&image_bytes))
-                    .with_guessed_format()?
-                    .decode()?
-                    .resize(960, 540, FilterType::Nearest)
-                    .write_to(&mut Cursor::new(&mut buf), ImageFormat::Jpeg)?;
-                Ok(buf)
-            })()
-            .unwrap_or_else(|err| {
-                debug!("Failed to convert image data: {err} - using original bytes");
-                image_bytes.to_vec()
-            });
-            let output = self.agent.api.com.atproto.repo.upload_blob(buf).await?;
-            Some(output.data.blob)
-        } else {
-            None
+        // The thumbnail is best-effort, as a link card without one is better than no post at all.
+        let thumb = match thumbnail_url {
+            Some(thumbnail_url) => match self
+                .upload_image(thumbnail_url.clone(), &self.image_options, uri)
+                .await
+            {
+                Ok((blob, _)) => Some(blob),
+                Err(err) if err.is::<RateLimited>() || is_auth_error(&err) => return Err(err),
+                Err(err) => {
+                    warn!(
+                        "Failed to upload thumbnail '{thumbnail_url}' for '{uri}': {err:#} - posting without a thumbnail"
+                    );
+                    None
+                }
+            },
+            None => None,
         };
 
         Ok(Union::Refs(RecordEmbedRefs::AppBskyEmbedExternalMain(
@@ -241,4 +1482,1076 @@ impl BlueskyHandler {
             ),
         )))
     }
+
+    async fn embed_image(
+        &self,
+        alt: &str,
+        uri: &str,
+        image_url: Url,
+    ) -> Result<Union<RecordEmbedRefs>> {
+        info!("Constructing image embed data for: '{uri}'");
+        let (image, (width, height)) = self
+            .upload_image(
+                image_url,
+                &ImageOptions {
+                    max_width: 2000,
+                    max_height: 2000,
+                    ..self.image_options.clone()
+                },
+                uri,
+            )
+            .await?;
+        Ok(Union::Refs(RecordEmbedRefs::AppBskyEmbedImagesMain(
+            Box::new(
+                images::MainData {
+                    images: vec![
+                        images::ImageData {
+                            alt: truncate_graphemes(alt, MAX_ALT_TEXT_GRAPHEMES),
+                            aspect_ratio: NonZeroU64::new(width.into())
+                                .zip(NonZeroU64::new(height.into()))
+                                .map(|(width, height)| AspectRatioData { width, height }.into()),
+                            image,
+                        }
+                        .into(),
+                    ],
+                }
+                .into(),
+            ),
+        )))
+    }
+
+    /// Download an image and process it with the given options, storing the result in the thumbnail cache.
+    async fn fetch_image(
+        &self,
+        image_url: &Url,
+        options: &ImageOptions,
+        uri: &str,
+    ) -> Result<(Vec<u8>, (u32, u32))> {
+        let image_bytes = self
+            .with_retries("download image", || async {
+                Ok(self
+                    .http
+                    .get(image_url.clone())
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await?)
+            })
+            .await?;
+        let (buf, dimensions) = process_thumbnail(&image_bytes, options)
+            .with_context(|| format!("failed to process image for '{uri}'"))?;
+        if let Some(cache) = &self.thumbnail_cache {
+            cache.put(image_url.as_str(), options, &buf);
+        }
+        Ok((buf, dimensions))
+    }
+
+    /// Fetch an image, process it with the given options and upload it as a blob.
+    ///
+    /// The dimensions of the uploaded image are returned alongside it.
+    async fn upload_image(
+        &self,
+        image_url: Url,
+        options: &ImageOptions,
+        uri: &str,
+    ) -> Result<(BlobRef, (u32, u32))> {
+        debug!("Fetching and uploading image blob data for '{uri}'");
+        let cached = self
+            .thumbnail_cache
+            .as_ref()
+            .and_then(|cache| cache.get(image_url.as_str(), options));
+        let (buf, dimensions) = match cached {
+            Some(cached) => cached,
+            None => self.fetch_image(&image_url, options, uri).await?,
+        };
+        Ok((self.upload_blob(buf).await?, dimensions))
+    }
+
+    /// Upload processed image data as a blob.
+    async fn upload_blob(&self, buf: Vec<u8>) -> Result<BlobRef> {
+        let size = buf.len() as u64;
+        let output = self
+            .with_retries("upload image blob", || async {
+                self.pace().await;
+                Ok(self
+                    .agent
+                    .api
+                    .com
+                    .atproto
+                    .repo
+                    .upload_blob(buf.clone())
+                    .await
+                    .map_err(bsky_sdk::Error::from)?)
+            })
+            .await?;
+        self.uploaded_blob_bytes.fetch_add(size, Ordering::Relaxed);
+        Ok(output.data.blob)
+    }
+
+    /// The total size in bytes of the blobs that have been uploaded, such as images.
+    pub fn uploaded_blob_bytes(&self) -> u64 {
+        self.uploaded_blob_bytes.load(Ordering::Relaxed)
+    }
+}
+
+/// Make the session file readable only by its owner before it is written, as its tokens can act as the account.
+///
+/// The file is created with those permissions when it doesn't exist yet, and files written by older versions have
+/// their permissions tightened.
+#[cfg(unix)]
+fn restrict_session_file(path: &Path) -> std::io::Result<()> {
+    use std::fs::{OpenOptions, Permissions, set_permissions};
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(path)?;
+    set_permissions(path, Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+fn restrict_session_file(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use axum::{
+        Json, Router,
+        body::Bytes,
+        http::{HeaderMap, header},
+        response::IntoResponse,
+        routing::{get, post},
+    };
+    use image::{DynamicImage, ImageFormat, RgbImage};
+    use serde_json::{Value, json};
+    use std::{
+        io::Cursor,
+        sync::{Arc, Mutex},
+    };
+    use tokio::net::TcpListener;
+
+    pub(crate) const DID: &str = "did:plc:whimsky";
+    const CID: &str = "bafyreie5737gdxlw5i64vzichcalba3z2v5n6icifvx5xytvske7mr3hpm";
+
+    /// The records created and the number of blobs uploaded to a mock PDS.
+    #[derive(Default)]
+    pub(crate) struct Pds {
+        pub(crate) records: Mutex<Vec<Value>>,
+        pub(crate) uploads: Mutex<usize>,
+        pub(crate) uploaded_bytes: Mutex<usize>,
+        /// The number of times the session was refreshed.
+        pub(crate) refreshes: Mutex<usize>,
+        /// The number of requests to create records to reject as if the access token had expired.
+        pub(crate) expired_records: Mutex<usize>,
+        /// The inputs of every request to create a session.
+        pub(crate) logins: Mutex<Vec<Value>>,
+        /// The sign in code that creating a session needs, as if the account had email 2FA.
+        pub(crate) auth_factor_token: Mutex<Option<String>>,
+        /// When the rate limit that rejects requests to create records resets, if they are being rate limited.
+        pub(crate) records_rate_limited_until: Mutex<Option<DateTime<Utc>>>,
+        /// The URI of every record that was deleted.
+        pub(crate) deleted: Mutex<Vec<String>>,
+        /// The token given to every request to end a session.
+        pub(crate) ended_sessions: Mutex<Vec<String>>,
+    }
+
+    /// A JWT that expires at the given time, which is all that is read from the tokens of a session.
+    fn jwt(expiry: DateTime<Utc>) -> String {
+        let claims = URL_SAFE_NO_PAD.encode(json!({ "exp": expiry.timestamp() }).to_string());
+        format!("header.{claims}.signature")
+    }
+
+    /// Serve a mock PDS on a random local port, along with a valid cover image at `/cover.png` and data
+    /// that isn't an image at `/broken.png`.
+    pub(crate) async fn spawn_pds() -> (Url, Arc<Pds>) {
+        let pds = Arc::new(Pds::default());
+        let records = pds.clone();
+        let uploads = pds.clone();
+        let refreshes = pds.clone();
+        let logins = pds.clone();
+        let deleted = pds.clone();
+        let ended_sessions = pds.clone();
+        let mut cover = vec![];
+        DynamicImage::ImageRgb8(RgbImage::new(16, 9))
+            .write_to(&mut Cursor::new(&mut cover), ImageFormat::Png)
+            .unwrap();
+        let router = Router::new()
+            .route(
+                "/xrpc/com.atproto.server.createSession",
+                post(move |Json(input): Json<Value>| async move {
+                    let required = logins.auth_factor_token.lock().unwrap().clone();
+                    let given = input["authFactorToken"].as_str().map(str::to_string);
+                    logins.logins.lock().unwrap().push(input);
+                    if required.is_some() && given != required {
+                        return (
+                            StatusCode::UNAUTHORIZED,
+                            Json(json!({
+                                "error": "AuthFactorTokenRequired",
+                                "message": "A sign in code has been sent to your email address",
+                            })),
+                        );
+                    }
+                    (
+                        StatusCode::OK,
+                        Json(json!({
+                            "accessJwt": "access",
+                            "refreshJwt": "refresh",
+                            "handle": "whimsky.test",
+                            "did": DID,
+                        })),
+                    )
+                }),
+            )
+            .route(
+                "/xrpc/com.atproto.server.refreshSession",
+                post(move || async move {
+                    *refreshes.refreshes.lock().unwrap() += 1;
+                    Json(json!({
+                        "accessJwt": jwt(Utc::now() + chrono::Duration::hours(2)),
+                        "refreshJwt": "refresh",
+                        "handle": "whimsky.test",
+                        "did": DID,
+                    }))
+                }),
+            )
+            .route(
+                "/xrpc/com.atproto.server.deleteSession",
+                post(move |headers: HeaderMap| async move {
+                    let token = headers[header::AUTHORIZATION].to_str().unwrap().to_string();
+                    ended_sessions.ended_sessions.lock().unwrap().push(token);
+                }),
+            )
+            .route(
+                "/xrpc/com.atproto.server.getSession",
+                get(|| async { Json(json!({ "handle": "whimsky.test", "did": DID })) }),
+            )
+            .route(
+                "/xrpc/com.atproto.repo.uploadBlob",
+                post(move |body: Bytes| async move {
+                    *uploads.uploads.lock().unwrap() += 1;
+                    *uploads.uploaded_bytes.lock().unwrap() += body.len();
+                    Json(json!({
+                        "blob": {
+                            "$type": "blob",
+                            "ref": { "$link": CID },
+                            "mimeType": "image/webp",
+                            "size": body.len(),
+                        }
+                    }))
+                }),
+            )
+            .route(
+                "/xrpc/com.atproto.repo.createRecord",
+                post(move |Json(input): Json<Value>| async move {
+                    {
+                        let mut expired = records.expired_records.lock().unwrap();
+                        if *expired > 0 {
+                            *expired -= 1;
+                            return (
+                                StatusCode::BAD_REQUEST,
+                                Json(json!({ "error": "ExpiredToken", "message": "Token has expired" })),
+                            )
+                                .into_response();
+                        }
+                    }
+                    if let Some(reset) = *records.records_rate_limited_until.lock().unwrap() {
+                        return (
+                            StatusCode::TOO_MANY_REQUESTS,
+                            [
+                                ("ratelimit-remaining", "0".to_string()),
+                                ("ratelimit-reset", reset.timestamp().to_string()),
+                            ],
+                            Json(json!({ "error": "RateLimitExceeded", "message": "Rate Limit Exceeded" })),
+                        )
+                            .into_response();
+                    }
+                    let mut records = records.records.lock().unwrap();
+                    records.push(input["record"].clone());
+                    // Each record gets its own key so that replies can be told apart.
+                    (
+                        StatusCode::OK,
+                        Json(json!({
+                            "uri": format!("at://{DID}/app.bsky.feed.post/3k2akzqtyl2{}", records.len()),
+                            "cid": CID,
+                        })),
+                    )
+                        .into_response()
+                }),
+            )
+            .route(
+                "/xrpc/com.atproto.repo.deleteRecord",
+                post(move |Json(input): Json<Value>| async move {
+                    // Records with these keys act as if they were already deleted, or can't be deleted.
+                    let error = match input["rkey"].as_str() {
+                        Some("missing") => "RecordNotFound",
+                        Some("undeletable") => "InvalidRequest",
+                        _ => {
+                            deleted.deleted.lock().unwrap().push(format!(
+                                "at://{}/{}/{}",
+                                input["repo"].as_str().unwrap(),
+                                input["collection"].as_str().unwrap(),
+                                input["rkey"].as_str().unwrap()
+                            ));
+                            return (StatusCode::OK, Json(json!({})));
+                        }
+                    };
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({ "error": error, "message": "Could not delete the record" })),
+                    )
+                }),
+            )
+            .route("/cover.png", get(move || async move { cover }))
+            .route("/broken.png", get(|| async { "not an image" }));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        (url, pds)
+    }
+
+    pub(crate) async fn logged_out_handler(service: Url, data_path: &Path) -> BlueskyHandler {
+        BlueskyHandler::new(
+            reqwest::Client::new(),
+            service,
+            Some("whimsky.test"),
+            data_path.to_path_buf(),
+            vec![ReplyControl::Everyone],
+            false,
+            ImageOptions::default(),
+            None,
+        )
+        .await
+        .unwrap()
+    }
+
+    pub(crate) async fn handler(service: Url, data_path: &Path) -> BlueskyHandler {
+        let handler = logged_out_handler(service, data_path).await;
+        handler
+            .login("whimsky.test", &Secret::new("password".into()), None)
+            .await
+            .unwrap();
+        handler
+    }
+
+    fn post_with_embed(mode: EmbedMode, thumbnail_url: Url) -> PostData {
+        PostData {
+            text: format!("News - {URL}"),
+            languages: vec!["en".to_string()],
+            created_at: Utc::now(),
+            labels: vec![],
+            tags: vec![],
+            embed: Some(PostEmbed {
+                title: "News".to_string(),
+                description: "About the news".to_string(),
+                uri: Url::parse(URL).unwrap(),
+                thumbnail_url: Some(thumbnail_url),
+                alt_text: String::new(),
+                mode,
+            }),
+            mentions: vec![],
+            replies: vec![],
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn session_files_are_only_readable_by_their_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (service, _) = spawn_pds().await;
+        let handler = handler(service, dir.path()).await;
+        let path = dir.path().join(SESSION_FILE_NAME);
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), 0o600);
+
+        // Files left readable by older versions are locked down the next time the session is synced.
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        handler.sync_session().await.unwrap();
+        assert_eq!(mode(&path), 0o600);
+        assert!(std::fs::read_to_string(&path).unwrap().contains("access"));
+    }
+
+    /// A cached session for `whimsky.test` on a service, as saved by [`BlueskyHandler::sync_session`].
+    fn cached_config(endpoint: &str) -> Config {
+        serde_json::from_value(json!({
+            "endpoint": endpoint,
+            "session": {
+                "accessJwt": "access",
+                "refreshJwt": "refresh",
+                "handle": "whimsky.test",
+                "did": DID,
+                "email": "bot@example.com",
+            },
+            "labelers_header": null,
+            "proxy_header": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn cached_sessions_must_match_the_service_and_account() {
+        let config = cached_config("https://bsky.social");
+        for identifier in [
+            None,
+            Some("whimsky.test"),
+            Some("@Whimsky.Test"),
+            Some(DID),
+            Some("BOT@example.com"),
+        ] {
+            assert_eq!(
+                BlueskyHandler::session_mismatch(&config, "https://bsky.social/", identifier),
+                None,
+                "{identifier:?}"
+            );
+        }
+        assert_eq!(
+            BlueskyHandler::session_mismatch(&config, "https://bsky.social", Some("other.test"))
+                .as_deref(),
+            Some("it belongs to whimsky.test rather than other.test")
+        );
+        assert_eq!(
+            BlueskyHandler::session_mismatch(&config, "https://example.com", Some("whimsky.test"))
+                .as_deref(),
+            Some("it is for https://bsky.social rather than https://example.com")
+        );
+        // A config without a session can be used for any account.
+        let config = Config::default();
+        assert_eq!(
+            BlueskyHandler::session_mismatch(&config, "https://bsky.social", Some("other.test")),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn cached_sessions_are_only_resumed_for_the_same_account() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, _) = spawn_pds().await;
+        let path = dir.path().join(SESSION_FILE_NAME);
+        let new_handler = |identifier| {
+            BlueskyHandler::new(
+                reqwest::Client::new(),
+                service.clone(),
+                Some(identifier),
+                dir.path().to_path_buf(),
+                vec![ReplyControl::Everyone],
+                false,
+                ImageOptions::default(),
+                None,
+            )
+        };
+        let write_cached_config = || {
+            std::fs::write(
+                &path,
+                serde_json::to_string(&cached_config(service.as_str().trim_end_matches('/')))
+                    .unwrap(),
+            )
+            .unwrap();
+        };
+
+        write_cached_config();
+        assert!(
+            new_handler("whimsky.test")
+                .await
+                .unwrap()
+                .has_session()
+                .await
+        );
+        write_cached_config();
+        assert!(!new_handler("other.test").await.unwrap().has_session().await);
+    }
+
+    #[tokio::test]
+    async fn cached_sessions_are_resumed_for_any_account_until_logged_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, pds) = spawn_pds().await;
+        let err = BlueskyHandler::resume(reqwest::Client::new(), dir.path().to_path_buf())
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().starts_with("no bsky session is cached"));
+
+        handler(service.clone(), dir.path()).await;
+        let resumed = BlueskyHandler::resume(reqwest::Client::new(), dir.path().to_path_buf())
+            .await
+            .unwrap();
+        let session = resumed.session_info().await.unwrap();
+        assert_eq!(
+            (session.handle.as_str(), session.did.as_str()),
+            ("whimsky.test", DID)
+        );
+        assert_eq!(session.endpoint, service.as_str().trim_end_matches('/'));
+
+        // Sessions are ended with the refresh token rather than the access token.
+        resumed.logout().await.unwrap();
+        assert_eq!(*pds.ended_sessions.lock().unwrap(), ["Bearer refresh"]);
+        assert!(!dir.path().join(SESSION_FILE_NAME).exists());
+        assert!(
+            BlueskyHandler::resume(reqwest::Client::new(), dir.path().to_path_buf())
+                .await
+                .is_err()
+        );
+    }
+
+    fn xrpc_error(status: StatusCode, error: Option<&str>) -> anyhow::Error {
+        bsky_sdk::Error::Xrpc(Box::new(GenericXrpcError::Response {
+            status,
+            error: error.map(str::to_string),
+        }))
+        .into()
+    }
+
+    #[test]
+    fn expired_tokens_are_told_apart_from_other_errors() {
+        let expired = xrpc_error(
+            StatusCode::BAD_REQUEST,
+            Some("ExpiredToken: Token has expired"),
+        );
+        assert!(is_expired_token_error(&expired));
+        // Expired tokens are still an authentication failure once refreshing doesn't help.
+        assert!(is_auth_error(&expired));
+        assert!(is_expired_token_error(&expired.context("failed to post")));
+        for err in [
+            xrpc_error(StatusCode::BAD_REQUEST, Some("InvalidToken")),
+            xrpc_error(StatusCode::BAD_REQUEST, None),
+            bsky_sdk::Error::Xrpc(Box::new(GenericXrpcError::Other(
+                "ExpiredToken".to_string(),
+            )))
+            .into(),
+            bsky_sdk::Error::NotLoggedIn.into(),
+            anyhow::anyhow!("ExpiredToken"),
+        ] {
+            assert!(!is_expired_token_error(&err), "{err}");
+        }
+
+        assert!(is_auth_error(&xrpc_error(StatusCode::UNAUTHORIZED, None)));
+        assert!(is_auth_error(&bsky_sdk::Error::NotLoggedIn.into()));
+        assert!(!is_auth_error(&xrpc_error(
+            StatusCode::BAD_REQUEST,
+            Some("InvalidRequest")
+        )));
+    }
+
+    #[test]
+    fn auth_factor_required_errors_are_told_apart_from_other_errors() {
+        let required = xrpc_error(
+            StatusCode::UNAUTHORIZED,
+            Some("AuthFactorTokenRequired: A sign in code has been sent to your email address"),
+        );
+        assert!(is_auth_factor_required_error(&required));
+        assert!(is_auth_factor_required_error(
+            &required.context("failed to log in")
+        ));
+        for err in [
+            xrpc_error(StatusCode::UNAUTHORIZED, Some("AuthenticationRequired")),
+            xrpc_error(StatusCode::UNAUTHORIZED, None),
+            bsky_sdk::Error::Xrpc(Box::new(GenericXrpcError::Other(
+                "AuthFactorTokenRequired".to_string(),
+            )))
+            .into(),
+            anyhow::anyhow!("AuthFactorTokenRequired"),
+        ] {
+            assert!(!is_auth_factor_required_error(&err), "{err}");
+        }
+    }
+
+    #[tokio::test]
+    async fn accounts_with_email_2fa_log_in_again_with_the_sign_in_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, pds) = spawn_pds().await;
+        *pds.auth_factor_token.lock().unwrap() = Some("ABCDE-12345".to_string());
+        let handler = logged_out_handler(service, dir.path()).await;
+
+        handler
+            .login(
+                "whimsky.test",
+                &Secret::new("password".to_string()),
+                Some(&Secret::new("ABCDE-12345".to_string())),
+            )
+            .await
+            .unwrap();
+        let logins = pds.logins.lock().unwrap().clone();
+        assert_eq!(logins.len(), 2);
+        assert!(logins[0]["authFactorToken"].is_null());
+        assert_eq!(logins[1]["identifier"], "whimsky.test");
+        assert_eq!(logins[1]["password"], "password");
+        assert_eq!(logins[1]["authFactorToken"], "ABCDE-12345");
+        assert_eq!(handler.session_info().await.unwrap().did, DID);
+    }
+
+    #[tokio::test]
+    async fn wrong_sign_in_codes_fail_to_log_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, pds) = spawn_pds().await;
+        *pds.auth_factor_token.lock().unwrap() = Some("ABCDE-12345".to_string());
+        let handler = logged_out_handler(service, dir.path()).await;
+
+        let err = handler
+            .login(
+                "whimsky.test",
+                &Secret::new("password".to_string()),
+                Some(&Secret::new("54321-EDCBA".to_string())),
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("failed to log in with the email sign in code"),
+            "{err:#}"
+        );
+        assert_eq!(pds.logins.lock().unwrap().len(), 2);
+
+        // Without a code or a terminal to ask for one in, the error says how to give it.
+        if !std::io::stdin().is_terminal() {
+            let err = handler
+                .login("whimsky.test", &Secret::new("password".to_string()), None)
+                .await
+                .unwrap_err();
+            assert!(
+                err.to_string().contains("--app-auth-factor-token"),
+                "{err:#}"
+            );
+            assert_eq!(pds.logins.lock().unwrap().len(), 3);
+        }
+    }
+
+    #[test]
+    fn token_expiry_is_read_from_jwts() {
+        let expiry = DateTime::from_timestamp(1_772_366_400, 0).unwrap();
+        assert_eq!(token_expiry(&jwt(expiry)), Some(expiry));
+        assert_eq!(token_expiry("access"), None);
+        assert_eq!(token_expiry("header.not base64.signature"), None);
+        let claims = URL_SAFE_NO_PAD.encode(r#"{"sub":"did:plc:whimsky"}"#);
+        assert_eq!(token_expiry(&format!("header.{claims}.signature")), None);
+    }
+
+    #[tokio::test]
+    async fn sessions_are_only_refreshed_when_about_to_expire() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, pds) = spawn_pds().await;
+        let handler = handler(service, dir.path()).await;
+
+        // Tokens without a readable expiry are refreshed to be safe, and the new one lasts for a couple of hours.
+        handler.ensure_session_fresh().await.unwrap();
+        assert_eq!(*pds.refreshes.lock().unwrap(), 1);
+        handler.ensure_session_fresh().await.unwrap();
+        assert_eq!(*pds.refreshes.lock().unwrap(), 1);
+        assert!(
+            handler
+                .session_info()
+                .await
+                .unwrap()
+                .access_token_expiry
+                .is_some_and(|expiry| expiry > Utc::now() + chrono::Duration::hours(1))
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_are_retried_once_after_refreshing_an_expired_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, pds) = spawn_pds().await;
+        let handler = handler(service.clone(), dir.path()).await;
+        let mut post = post_with_embed(EmbedMode::External, service.join("cover.png").unwrap());
+        post.embed = None;
+
+        // The agent refreshes and retries once by itself, so rejecting its retry as well needs refreshing again here.
+        *pds.expired_records.lock().unwrap() = 3;
+        handler.post(post.clone()).await.unwrap();
+        assert_eq!(*pds.refreshes.lock().unwrap(), 3);
+        assert_eq!(pds.records.lock().unwrap().len(), 1);
+
+        // A session that is still rejected after refreshing fails the request rather than refreshing forever.
+        *pds.expired_records.lock().unwrap() = 100;
+        let err = handler.post(post).await.unwrap_err();
+        assert!(is_expired_token_error(&err), "{err:#}");
+        assert_eq!(*pds.expired_records.lock().unwrap(), 96);
+        assert_eq!(pds.records.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn posts_rate_limited_past_the_longest_wait_fail_with_the_reset_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, pds) = spawn_pds().await;
+        let handler = handler(service.clone(), dir.path()).await;
+        let mut post = post_with_embed(EmbedMode::External, service.join("cover.png").unwrap());
+        post.embed = None;
+
+        let reset = Utc::now() + chrono::Duration::hours(1);
+        *pds.records_rate_limited_until.lock().unwrap() = Some(reset);
+        let err = handler.post(post).await.unwrap_err();
+        // The start loop defers posts by looking for this, rather than queueing them as failed.
+        let rate_limited = err
+            .downcast_ref::<RateLimited>()
+            .expect("a RateLimited error");
+        assert_eq!(rate_limited.reset.timestamp(), reset.timestamp());
+        assert!(is_rate_limit_error(&err));
+        assert!(!is_auth_error(&err));
+        assert!(pds.records.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn unusable_cover_images_are_left_out_of_posts() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, pds) = spawn_pds().await;
+        let handler = handler(service.clone(), dir.path()).await;
+
+        for mode in [EmbedMode::External, EmbedMode::Image] {
+            handler
+                .post(post_with_embed(mode, service.join("broken.png").unwrap()))
+                .await
+                .unwrap();
+        }
+        handler
+            .post(post_with_embed(
+                EmbedMode::External,
+                service.join("cover.png").unwrap(),
+            ))
+            .await
+            .unwrap();
+
+        let records = pds.records.lock().unwrap();
+        let embeds: Vec<&Value> = records.iter().map(|record| &record["embed"]).collect();
+        // Both modes fall back to a link card without a thumbnail rather than failing the post.
+        for embed in &embeds[..2] {
+            assert_eq!(embed["$type"], "app.bsky.embed.external");
+            assert_eq!(embed["external"]["uri"], URL);
+            assert!(embed["external"].get("thumb").is_none(), "{embed}");
+        }
+        assert_eq!(embeds[2]["external"]["thumb"]["ref"]["$link"], CID);
+        assert_eq!(*pds.uploads.lock().unwrap(), 1);
+        assert!(handler.uploaded_blob_bytes() > 0);
+        assert_eq!(
+            handler.uploaded_blob_bytes(),
+            *pds.uploaded_bytes.lock().unwrap() as u64
+        );
+    }
+
+    const URL: &str = "https://example.com/news/1";
+
+    fn values<'a>(title: &'a str, r#abstract: &'a str) -> TemplateValues<'a> {
+        TemplateValues {
+            title,
+            url: URL,
+            r#abstract,
+            publish_time: DateTime::from_timestamp(1_772_366_400, 0).unwrap(),
+        }
+    }
+
+    fn template(source: &str) -> PostTemplate {
+        source.parse().unwrap()
+    }
+
+    #[test]
+    fn long_titles_are_truncated_to_keep_hashtags_whole() {
+        let title = "a".repeat(400);
+        let hashtags = ["InfinityNikki".to_string(), "Whimsky".to_string()];
+        let text = PostData::build_text(
+            &template("{title} - {url}"),
+            &values(&title, ""),
+            "",
+            &[],
+            &hashtags,
+            true,
+        );
+        assert_eq!(text.graphemes(true).count(), MAX_POST_GRAPHEMES);
+        assert!(
+            text.ends_with(&format!("… - {URL} #InfinityNikki #Whimsky")),
+            "{text}"
+        );
+
+        let text = PostData::build_text(
+            &template("{title} - {url}"),
+            &values("Short title", ""),
+            "",
+            &[],
+            &hashtags,
+            true,
+        );
+        assert_eq!(text, format!("Short title - {URL} #InfinityNikki #Whimsky"));
+    }
+
+    #[test]
+    fn posts_are_limited_by_graphemes_rather_than_bytes_or_chars() {
+        // Each flower is a single grapheme but several bytes, so only the grapheme count matters.
+        let available = MAX_POST_GRAPHEMES - format!(" - {URL}").len();
+        let title = "🌸".repeat(available);
+        let text = PostData::build_text(
+            &template("{title} - {url}"),
+            &values(&title, ""),
+            "",
+            &[],
+            &[],
+            true,
+        );
+        assert_eq!(text, format!("{title} - {URL}"));
+
+        let title = "👩\u{200d}🎨".repeat(available + 1);
+        let text = PostData::build_text(
+            &template("{title} - {url}"),
+            &values(&title, ""),
+            "",
+            &[],
+            &[],
+            true,
+        );
+        assert_eq!(text.graphemes(true).count(), MAX_POST_GRAPHEMES);
+        assert_eq!(
+            text,
+            format!("{}… - {URL}", "👩\u{200d}🎨".repeat(available - 1))
+        );
+    }
+
+    #[test]
+    fn prefixes_count_towards_the_length_of_posts() {
+        let title = "a".repeat(400);
+        let text = PostData::build_text(
+            &template("{title} - {url}"),
+            &values(&title, ""),
+            "🔧 ",
+            &[],
+            &[],
+            true,
+        );
+        assert_eq!(text.graphemes(true).count(), MAX_POST_GRAPHEMES);
+        assert!(text.starts_with("🔧 aaa"), "{text}");
+        assert!(text.ends_with(&format!("… - {URL}")), "{text}");
+    }
+
+    #[test]
+    fn posts_without_a_link_card_always_include_the_url() {
+        let without_url = template("{title} - {url}").without(Placeholder::Url);
+        assert_eq!(
+            PostData::build_text(&without_url, &values("Title", ""), "", &[], &[], true),
+            "Title"
+        );
+        assert_eq!(
+            PostData::build_text(&without_url, &values("Title", ""), "", &[], &[], false),
+            format!("Title\n{URL}")
+        );
+        // The URL isn't repeated when the template already includes it.
+        assert_eq!(
+            PostData::build_text(
+                &template("{title} - {url}"),
+                &values("Title", ""),
+                "",
+                &[],
+                &[],
+                false
+            ),
+            format!("Title - {URL}")
+        );
+    }
+
+    #[test]
+    fn long_abstracts_are_moved_into_replies() {
+        let r#abstract = "Lorem ipsum dolor sit amet. ".repeat(20);
+        let (text, replies) = PostData::build_thread(
+            &template("{title}\n\n{abstract}\n\n{url}"),
+            &values("Title", r#abstract.trim()),
+            "",
+            &[],
+            &[],
+            true,
+        );
+        assert_eq!(text, format!("Title\n\n{URL}"));
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies.join(" "), r#abstract.trim());
+        for reply in &replies {
+            assert!(reply.graphemes(true).count() <= MAX_POST_GRAPHEMES);
+            // Only whole words are carried over into replies.
+            assert!(
+                reply
+                    .split(' ')
+                    .all(|word| ["Lorem", "ipsum", "dolor", "sit", "amet."].contains(&word)),
+                "{reply}"
+            );
+        }
+
+        // Posts that already fit aren't threaded.
+        let (text, replies) = PostData::build_thread(
+            &template("{title}\n\n{abstract}\n\n{url}"),
+            &values("Title", "A short abstract."),
+            "",
+            &[],
+            &[],
+            true,
+        );
+        assert_eq!(text, format!("Title\n\nA short abstract.\n\n{URL}"));
+        assert!(replies.is_empty());
+
+        // Without the abstract in the template, long titles are still just truncated.
+        let title = "a".repeat(400);
+        let (text, replies) = PostData::build_thread(
+            &template("{title} - {url}"),
+            &values(&title, r#abstract.trim()),
+            "",
+            &[],
+            &[],
+            true,
+        );
+        assert_eq!(text.graphemes(true).count(), MAX_POST_GRAPHEMES);
+        assert!(replies.is_empty());
+    }
+
+    #[test]
+    fn thread_text_is_split_between_words() {
+        assert_eq!(
+            split_thread_text("one two three four", 9),
+            ["one two", "three", "four"]
+        );
+        assert_eq!(split_thread_text("  spaced   out  ", 6), ["spaced", "out"]);
+        // Words longer than a whole part are split wherever the limit falls.
+        assert_eq!(
+            split_thread_text("abcdefgh ij", 3),
+            ["abc", "def", "gh", "ij"]
+        );
+        assert_eq!(split_thread_text("🌸🌸🌸🌸", 2), ["🌸🌸", "🌸🌸"]);
+        assert!(split_thread_text("   ", 10).is_empty());
+    }
+
+    #[tokio::test]
+    async fn replies_are_threaded_under_the_first_post() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, pds) = spawn_pds().await;
+        let handler = handler(service, dir.path()).await;
+
+        let mut post = post_with_embed(EmbedMode::External, Url::parse(URL).unwrap());
+        post.embed = None;
+        post.replies = vec!["First reply".to_string(), "Second reply".to_string()];
+        let created = handler.post(post).await.unwrap();
+
+        let uris: Vec<&str> = created.iter().map(|record| record.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            (1..=3)
+                .map(|n| format!("at://{DID}/app.bsky.feed.post/3k2akzqtyl2{n}"))
+                .collect::<Vec<_>>()
+        );
+        let posts = pds.records.lock().unwrap();
+        assert_eq!(posts.len(), 3);
+        assert!(posts[0].get("reply").is_none());
+        assert_eq!(posts[1]["text"], "First reply");
+        assert_eq!(posts[1]["reply"]["root"]["uri"], uris[0]);
+        assert_eq!(posts[1]["reply"]["parent"]["uri"], uris[0]);
+        assert_eq!(posts[2]["text"], "Second reply");
+        assert_eq!(posts[2]["reply"]["root"]["uri"], uris[0]);
+        assert_eq!(posts[2]["reply"]["parent"]["uri"], uris[1]);
+    }
+
+    #[test]
+    fn mentions_are_added_before_hashtags() {
+        let title = "a".repeat(400);
+        let mentions = ["whimsky.test".to_string()];
+        let hashtags = ["InfinityNikki".to_string()];
+        let text = PostData::build_text(
+            &template("{title} - {url}"),
+            &values(&title, ""),
+            "",
+            &mentions,
+            &hashtags,
+            true,
+        );
+        assert_eq!(text.graphemes(true).count(), MAX_POST_GRAPHEMES);
+        assert!(
+            text.ends_with(&format!("… - {URL} @whimsky.test #InfinityNikki")),
+            "{text}"
+        );
+    }
+
+    /// A mention facet covering the given bytes of a post's text.
+    fn mention_facet(byte_start: usize, byte_end: usize) -> facet::Main {
+        facet::MainData {
+            features: vec![Union::Refs(facet::MainFeaturesItem::Mention(Box::new(
+                facet::MentionData {
+                    did: DID.parse().unwrap(),
+                }
+                .into(),
+            )))],
+            index: facet::ByteSliceData {
+                byte_start,
+                byte_end,
+            }
+            .into(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn mentions_without_a_facet_are_unresolved() {
+        let text = "News @whimsky.test @Missing.test";
+        let handles = ["whimsky.test".to_string(), "missing.test".to_string()];
+        assert_eq!(
+            unresolved_mentions(text, None, &handles),
+            ["whimsky.test", "missing.test"]
+        );
+        assert_eq!(
+            unresolved_mentions(text, Some(&[mention_facet(5, 18)]), &handles),
+            ["missing.test"]
+        );
+        // Handles are matched without regard to case.
+        assert!(
+            unresolved_mentions(
+                text,
+                Some(&[mention_facet(5, 18), mention_facet(19, 32)]),
+                &handles
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn record_keys_come_from_the_end_of_record_uris() {
+        let rkey = record_key("at://did:plc:abc/app.bsky.feed.post/3k2akzqtyl22a").unwrap();
+        assert_eq!(rkey.as_str(), "3k2akzqtyl22a");
+        assert!(record_key("not a uri").is_err());
+        assert!(record_key("at://did:plc:abc/app.bsky.feed.post/").is_err());
+        assert!(record_key("at://did:plc:abc/app.bsky.feed.post/bad key").is_err());
+    }
+
+    /// The names of the allow rules of the threadgate for the given reply controls.
+    fn allow_rules(reply_control: &[ReplyControl]) -> Option<Vec<&'static str>> {
+        threadgate_allow_rules(reply_control).map(|rules| {
+            rules
+                .iter()
+                .map(|rule| match rule {
+                    Union::Refs(threadgate::RecordAllowItem::MentionRule(_)) => "mention",
+                    Union::Refs(threadgate::RecordAllowItem::FollowingRule(_)) => "following",
+                    Union::Refs(threadgate::RecordAllowItem::FollowerRule(_)) => "follower",
+                    _ => "other",
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn threadgates_allow_the_chosen_repliers() {
+        assert_eq!(allow_rules(&[ReplyControl::Everyone]), None);
+        assert_eq!(allow_rules(&[]), None);
+        // An empty list of allow rules stops everyone from replying.
+        assert_eq!(allow_rules(&[ReplyControl::Nobody]), Some(vec![]));
+        assert_eq!(
+            allow_rules(&[ReplyControl::Mentioned]),
+            Some(vec!["mention"])
+        );
+        assert_eq!(
+            allow_rules(&[ReplyControl::Following]),
+            Some(vec!["following"])
+        );
+        assert_eq!(
+            allow_rules(&[ReplyControl::Followers]),
+            Some(vec!["follower"])
+        );
+        assert_eq!(
+            allow_rules(&[
+                ReplyControl::Mentioned,
+                ReplyControl::Following,
+                ReplyControl::Followers
+            ]),
+            Some(vec!["mention", "following", "follower"])
+        );
+    }
 }
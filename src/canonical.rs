@@ -0,0 +1,152 @@
+use crate::database::Database;
+use crate::html;
+use crate::http;
+use anyhow::Result;
+use reqwest::Url;
+use tracing::{debug, warn};
+
+/// Resolves the canonical URL of articles so that the same article linked from different sources under different
+/// URLs is only posted once.
+#[derive(Clone)]
+pub struct CanonicalResolver {
+    client: reqwest::Client,
+}
+
+impl CanonicalResolver {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    /// Get the canonical URL of an article, falling back to the URL itself when it can't be resolved.
+    ///
+    /// Resolved URLs are cached in the database so that articles are only requested once.
+    pub async fn resolve(&self, database: &Database, url: &Url) -> Result<Url> {
+        if let Some(canonical_url) = database.get_canonical_url(url.as_str()).await? {
+            return Ok(Url::parse(&canonical_url).unwrap_or_else(|_| url.clone()));
+        }
+        match self.fetch_canonical(url).await {
+            Ok(canonical_url) => {
+                debug!("Resolved canonical URL of '{url}' to '{canonical_url}'");
+                database
+                    .set_canonical_url(url.as_str(), canonical_url.as_str())
+                    .await?;
+                Ok(canonical_url)
+            }
+            Err(err) => {
+                // Not cached so that it is resolved again on the next fetch.
+                warn!("Failed to resolve canonical URL of '{url}': {err:#}");
+                Ok(url.clone())
+            }
+        }
+    }
+
+    /// Request an article, following any redirects, and read the `<link rel="canonical">` of the page it ends up on.
+    async fn fetch_canonical(&self, url: &Url) -> Result<Url> {
+        let response = self
+            .client
+            .get(url.clone())
+            .timeout(http::PAGE_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?;
+        let final_url = response.url().clone();
+        let body = http::read_limited(response, http::MAX_PAGE_BYTES).await?;
+        let document = String::from_utf8_lossy(&body);
+        Ok(html::link_href(&document, "canonical")
+            .and_then(|href| final_url.join(&href).ok())
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .unwrap_or(final_url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Router,
+        http::{StatusCode, Uri},
+        response::{IntoResponse, Redirect},
+    };
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    /// Serve article pages on a random local port, recording the path of every request.
+    ///
+    /// `/en/news/1` redirects twice before reaching a page with a canonical link, `/en/news/2` redirects to a page
+    /// without one and `/en/news/3` always fails.
+    async fn spawn_site() -> (Url, Arc<Mutex<Vec<String>>>) {
+        let requested = Arc::new(Mutex::new(vec![]));
+        let recorder = requested.clone();
+        let router = Router::new().fallback(move |uri: Uri| async move {
+            recorder.lock().unwrap().push(uri.path().to_string());
+            match uri.path() {
+                "/en/news/1" => Redirect::temporary("/news/1").into_response(),
+                "/news/1" => Redirect::permanent("/news/starlit-dream?ref=1").into_response(),
+                "/news/starlit-dream" => {
+                    r#"<head><link rel="canonical" href="/news/starlit-dream"></head>"#
+                        .into_response()
+                }
+                "/en/news/2" => Redirect::temporary("/news/maintenance").into_response(),
+                "/news/maintenance" => "<head></head>".into_response(),
+                _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        (url, requested)
+    }
+
+    #[tokio::test]
+    async fn resolves_redirects_and_canonical_links() {
+        let (site, _) = spawn_site().await;
+        let database = Database::new_in_memory().await.unwrap();
+        let resolver = CanonicalResolver::new(reqwest::Client::new());
+        let resolve = async |path: &str| {
+            resolver
+                .resolve(&database, &site.join(path).unwrap())
+                .await
+                .unwrap()
+        };
+
+        assert_eq!(
+            resolve("/en/news/1").await,
+            site.join("/news/starlit-dream").unwrap()
+        );
+        // Without a canonical link the page that was redirected to is used.
+        assert_eq!(
+            resolve("/en/news/2").await,
+            site.join("/news/maintenance").unwrap()
+        );
+        // Articles that can't be resolved keep their own URL.
+        assert_eq!(
+            resolve("/en/news/3").await,
+            site.join("/en/news/3").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn only_resolved_urls_are_cached() {
+        let (site, requested) = spawn_site().await;
+        let database = Database::new_in_memory().await.unwrap();
+        let resolver = CanonicalResolver::new(reqwest::Client::new());
+        for _ in 0..2 {
+            for path in ["/en/news/1", "/en/news/3"] {
+                resolver
+                    .resolve(&database, &site.join(path).unwrap())
+                    .await
+                    .unwrap();
+            }
+        }
+        assert_eq!(
+            *requested.lock().unwrap(),
+            [
+                "/en/news/1",
+                "/news/1",
+                "/news/starlit-dream",
+                "/en/news/3",
+                "/en/news/3"
+            ]
+        );
+    }
+}
@@ -0,0 +1,690 @@
+use crate::rss::{FeedFormat, RssFeed};
+use crate::secret::Secret;
+use anyhow::{Context, Result, bail};
+use bsky_sdk::api::types::string::{Handle, Language};
+use chrono::{DateTime, Duration, Utc};
+use clap::{ArgMatches, Args, Command, ValueEnum, error::ErrorKind};
+use regex::{Regex, RegexBuilder};
+use reqwest::Url;
+use std::{collections::HashSet, io::IsTerminal, str::FromStr, sync::OnceLock};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Read a secret from the file at a path, ignoring surrounding whitespace.
+pub fn secret_file_value(value: &str) -> Result<Secret<String>, String> {
+    let secret =
+        std::fs::read_to_string(value).map_err(|err| format!("failed to read the file: {err}"))?;
+    let secret = secret.trim();
+    if secret.is_empty() {
+        return Err("the file is empty".to_string());
+    }
+    Ok(Secret::new(secret.to_string()))
+}
+
+/// Parse a single segment of a comma-separated or repeated list argument.
+///
+/// Surrounding whitespace and quotes are trimmed so that values such as `"en, ja"` coming from
+/// env files behave the same as `--flag en --flag ja` on the command line.
+pub fn list_value(value: &str) -> Result<String, String> {
+    Ok(value
+        .trim_matches(|c: char| c.is_whitespace() || c == '"' || c == '\'')
+        .to_string())
+}
+
+/// Drop the empty segments left in a parsed list argument by stray commas, such as in `en,ja,`.
+///
+/// Duplicate values are rejected while parsing by [`reject_duplicate_values`].
+pub fn normalise_list(values: Vec<String>) -> Vec<String> {
+    values.into_iter().filter(|v| !v.is_empty()).collect()
+}
+
+/// Parse a single segment of a list argument taking the values of an enum, such as `nobody` or `following`.
+///
+/// Segments are kept as strings so that stray commas can be dropped by [`normalise_list`] before they are turned
+/// into the enum with [`enum_list`].
+pub fn enum_list_value<T: ValueEnum>(value: &str) -> Result<String, String> {
+    let value = list_value(value)?;
+    if !value.is_empty() && T::from_str(&value, false).is_err() {
+        let possible_values: Vec<_> = T::value_variants()
+            .iter()
+            .filter_map(ValueEnum::to_possible_value)
+            .map(|value| value.get_name().to_string())
+            .collect();
+        return Err(format!(
+            "'{value}' isn't one of {}",
+            possible_values.join(", ")
+        ));
+    }
+    Ok(value)
+}
+
+/// Turn the segments of a list argument parsed by [`enum_list_value`] into the enum, dropping empty ones.
+pub fn enum_list<T: ValueEnum>(values: Vec<String>) -> Vec<T> {
+    normalise_list(values)
+        .iter()
+        .map(|value| T::from_str(value, false).expect("values are checked while parsing"))
+        .collect()
+}
+
+/// Parse a single URL of a list argument, such as one of `https://a/feed.xml,https://b/feed.xml`.
+///
+/// Segments are kept as strings so that stray commas can be dropped by [`normalise_list`] before they are turned
+/// into URLs with [`url_list`].
+pub fn url_list_value(value: &str) -> Result<String, String> {
+    let value = list_value(value)?;
+    if !value.is_empty() {
+        Url::parse(&value).map_err(|err| format!("'{value}' is not a valid URL: {err}"))?;
+    }
+    Ok(value)
+}
+
+/// Turn the segments of a list argument parsed by [`url_list_value`] into URLs, dropping empty ones.
+pub fn url_list(values: Vec<String>) -> Vec<Url> {
+    normalise_list(values)
+        .iter()
+        .map(|value| Url::parse(value).expect("values are checked while parsing"))
+        .collect()
+}
+
+/// Reject any list argument of a command that was given the same value more than once, whether in a
+/// comma-separated list or by repeating the flag.
+///
+/// This runs on the parsed arguments, as a value parser only ever sees one value at a time. Values are compared
+/// as parsed when they are strings, such as tags without their leading `#`, and otherwise as they were written.
+pub fn reject_duplicate_values(command: &Command, matches: &ArgMatches) -> Result<(), clap::Error> {
+    for arg in command
+        .get_arguments()
+        .filter(|arg| arg.get_value_delimiter().is_some())
+    {
+        let id = arg.get_id().as_str();
+        let values: Vec<String> = match matches.try_get_many::<String>(id) {
+            Ok(values) => values.into_iter().flatten().cloned().collect(),
+            Err(_) => matches
+                .get_raw(id)
+                .into_iter()
+                .flatten()
+                .map(|value| list_value(&value.to_string_lossy()).unwrap_or_default())
+                .collect(),
+        };
+        let mut seen = HashSet::new();
+        if let Some(value) = values
+            .iter()
+            .filter(|value| !value.is_empty())
+            .find(|value| !seen.insert(*value))
+        {
+            return Err(clap::Error::raw(
+                ErrorKind::ValueValidation,
+                format!(
+                    "the value '{value}' was provided more than once to --{}",
+                    arg.get_long().unwrap_or(id)
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The maximum length in bytes of a self-label value.
+pub const MAX_LABEL_LENGTH: usize = 128;
+
+/// Parse a single self-label value of a list argument, rejecting values that Bluesky wouldn't accept.
+///
+/// Empty segments are passed through to be dropped by [`normalise_list`].
+pub fn label_value(value: &str) -> Result<String, String> {
+    let value = list_value(value)?;
+    if value.len() > MAX_LABEL_LENGTH {
+        return Err(format!(
+            "'{value}' is longer than the maximum label length of {MAX_LABEL_LENGTH} bytes"
+        ));
+    }
+    Ok(value)
+}
+
+/// The maximum length in graphemes of a post tag.
+pub const MAX_TAG_GRAPHEMES: usize = 64;
+
+/// Parse a single post tag of a list argument, with or without a leading `#`.
+///
+/// Empty segments are passed through to be dropped by [`normalise_list`], but a lone `#` is rejected.
+pub fn tag_value(value: &str) -> Result<String, String> {
+    let value = list_value(value)?;
+    if value.is_empty() {
+        return Ok(value);
+    }
+    let value = value.strip_prefix('#').unwrap_or(&value);
+    if value.is_empty() {
+        return Err("tags can't be empty".to_string());
+    }
+    if value.chars().any(char::is_whitespace) {
+        return Err(format!("'{value}' can't contain whitespace"));
+    }
+    if value.graphemes(true).count() > MAX_TAG_GRAPHEMES {
+        return Err(format!(
+            "'{value}' is longer than the maximum tag length of {MAX_TAG_GRAPHEMES} characters"
+        ));
+    }
+    Ok(value.to_string())
+}
+
+/// Parse a single handle to mention of a list argument, with or without a leading `@`.
+pub fn mention_value(value: &str) -> Result<String, String> {
+    let value = list_value(value)?;
+    if value.is_empty() {
+        return Ok(value);
+    }
+    let value = value.strip_prefix('@').unwrap_or(&value);
+    Handle::new(value.to_lowercase())
+        .map(|handle| handle.to_string())
+        .map_err(|err| format!("'{value}' is not a valid handle: {err}"))
+}
+
+/// Parse a single `key=value` segment of a mapping list argument such as `en=en,ja=ja,kr=ko`.
+pub fn mapping_value(value: &str) -> Result<(String, String), String> {
+    let value = list_value(value)?;
+    match value.split_once('=') {
+        Some((key, mapped)) if !key.trim().is_empty() && !mapped.trim().is_empty() => {
+            Ok((key.trim().to_string(), mapped.trim().to_string()))
+        }
+        _ => Err(format!("'{value}' is not in the format 'key=value'")),
+    }
+}
+
+/// Parse a single numeric id of a list argument, such as a news section.
+pub fn id_value(value: &str) -> Result<usize, String> {
+    let value = list_value(value)?;
+    value
+        .parse()
+        .map_err(|_| format!("'{value}' is not a valid numeric id"))
+}
+
+/// Parse a regular expression, which matches case-insensitively unless it opts out with `(?-i)`.
+pub fn regex_value(value: &str) -> Result<Regex, String> {
+    RegexBuilder::new(value)
+        .case_insensitive(true)
+        .build()
+        .map_err(|err| format!("'{value}' is not a valid regular expression: {err}"))
+}
+
+/// Parse a single language of a list argument, which must be an ISO-639-1 code such as `en` optionally followed
+/// by a region as in `en-US` or `pt-BR`.
+pub fn language_value(value: &str) -> Result<String, String> {
+    let value = list_value(value)?;
+    if value.is_empty() {
+        return Ok(value);
+    }
+    // Bluesky accepts any BCP-47 tag, but the primary language is also checked so that names such as `english` are
+    // caught rather than silently accepted.
+    let primary = value.split('-').next().unwrap_or_default();
+    if !(2..=3).contains(&primary.len())
+        || !primary.chars().all(|c| c.is_ascii_alphabetic())
+        || Language::from_str(&value).is_err()
+    {
+        return Err(format!(
+            "'{value}' is not a valid language (expected a code such as 'en', 'ja' or 'pt-BR')"
+        ));
+    }
+    Ok(value)
+}
+
+/// Parse a single `locale=language` segment of a mapping list argument, validating the language.
+pub fn locale_language_value(value: &str) -> Result<(String, String), String> {
+    let (locale, language) = mapping_value(value)?;
+    Ok((locale, language_value(&language)?))
+}
+
+/// Parse a base URL that paths are added to, which can't have a query string or fragment.
+pub fn base_url_value(value: &str) -> Result<Url, String> {
+    let url =
+        Url::parse(value.trim()).map_err(|err| format!("'{value}' is not a valid URL: {err}"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("'{value}' must be an http or https URL"));
+    }
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err(format!(
+            "'{value}' can't have a query string or fragment as it is used as a base URL"
+        ));
+    }
+    Ok(url)
+}
+
+/// Parse a feed given as comma-separated `key=value` pairs, such as `url=https://a/feed.xml,langs=en,backdate-hours=6`.
+///
+/// `url` is required, while `langs` can be given more than once to post entries under multiple languages and
+/// `format` is one of `auto`, `rss` or `json`. As options are separated by commas, a URL containing one has to
+/// percent-encode it as `%2C`.
+pub fn rss_feed_value(value: &str) -> Result<RssFeed, String> {
+    let mut url = None;
+    let mut languages = Vec::new();
+    let mut backdate = None;
+    let mut format = FeedFormat::default();
+    for segment in value
+        .split(',')
+        .filter(|segment| !segment.trim().is_empty())
+    {
+        let (key, value) = mapping_value(segment)?;
+        match key.as_str() {
+            "url" => {
+                url = Some(
+                    Url::parse(&value)
+                        .map_err(|err| format!("'{value}' is not a valid URL: {err}"))?,
+                )
+            }
+            "langs" => {
+                let language = language_value(&value)?;
+                if languages.contains(&language) {
+                    return Err(format!(
+                        "the language '{language}' was given more than once"
+                    ));
+                }
+                languages.push(language);
+            }
+            "backdate-hours" => {
+                let hours: u16 = value
+                    .parse()
+                    .map_err(|_| format!("'{value}' is not a valid number of hours"))?;
+                backdate = Some(Duration::hours(hours as i64));
+            }
+            "format" => {
+                format = FeedFormat::from_str(&value, true).map_err(|_| {
+                    format!(
+                        "'{value}' is not a valid feed format (expected one of auto, rss, json)"
+                    )
+                })?
+            }
+            _ => {
+                return Err(format!(
+                    "'{key}' is not a valid feed option (expected one of url, langs, backdate-hours, format)"
+                ));
+            }
+        }
+    }
+    Ok(RssFeed {
+        url: url.ok_or_else(|| format!("'{value}' is missing a url"))?,
+        format,
+        languages,
+        backdate,
+    })
+}
+
+/// Parse a human-friendly duration such as `90d`, `12h`, `30m`, `45s` or `2w`.
+///
+/// Durations too long to be subtracted from the current time are rejected rather than overflowing.
+pub fn duration_value(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("'{value}' is missing a unit (expected one of s, m, h, d, w)"))?;
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .map_err(|_| format!("'{value}' does not start with a whole number"))?;
+    let duration = match unit {
+        "s" => Duration::try_seconds(amount),
+        "m" => Duration::try_minutes(amount),
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        _ => {
+            return Err(format!(
+                "'{unit}' is not a valid duration unit (expected one of s, m, h, d, w)"
+            ));
+        }
+    };
+    duration
+        .filter(|duration| Utc::now().checked_sub_signed(*duration).is_some())
+        .ok_or_else(|| format!("'{value}' is too long"))
+}
+
+/// Parse a point in time given either as a duration before now such as "24h" or as an RFC 3339 timestamp.
+pub fn since_value(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value.trim()) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    duration_value(value)
+        .ok()
+        .and_then(|duration| Utc::now().checked_sub_signed(duration))
+        .ok_or_else(|| {
+            format!(
+                "'{}' is not a duration such as \"24h\" or an RFC 3339 timestamp",
+                value.trim()
+            )
+        })
+}
+
+/// Arguments required to authenticate with a Bluesky service.
+///
+/// None of them are required by the parser, as commands that can resume a cached session don't need them. Commands
+/// that always log in check for them through [`BlueskyArgs::credentials`] instead.
+#[derive(Debug, Args)]
+pub struct BlueskyArgs {
+    /// The base URL of the service to communicate with.
+    #[clap(
+        default_value = "https://bsky.social",
+        long = "app-service",
+        env = "WHIMSKY_APP_SERVICE"
+    )]
+    pub service: Url,
+
+    /// The username or email of the application's account.
+    #[clap(long = "app-identifier", env = "WHIMSKY_APP_IDENTIFIER")]
+    pub identifier: Option<String>,
+
+    /// The app password to use for authentication.
+    ///
+    /// Prefer --app-password-file or --app-password-stdin, as the value of this is visible to other processes.
+    #[clap(
+        conflicts_with_all = ["password_file", "password_stdin"],
+        long = "app-password",
+        env = "WHIMSKY_APP_PASSWORD",
+        hide_env_values = true
+    )]
+    pub password: Option<Secret<String>>,
+
+    /// A file to read the app password from, such as a mounted Docker or Kubernetes secret.
+    ///
+    /// Whitespace surrounding the password is ignored.
+    #[clap(
+        long = "app-password-file",
+        env = "WHIMSKY_APP_PASSWORD_FILE",
+        conflicts_with = "password_stdin",
+        value_parser = secret_file_value
+    )]
+    pub password_file: Option<Secret<String>>,
+
+    /// Read the app password from the first line of stdin.
+    #[clap(long = "app-password-stdin")]
+    pub password_stdin: bool,
+
+    /// The sign in code emailed to an account with email 2FA, needed to log in without a terminal to enter it in.
+    #[clap(
+        long = "app-auth-factor-token",
+        env = "WHIMSKY_APP_AUTH_FACTOR_TOKEN",
+        hide_env_values = true
+    )]
+    pub auth_factor_token: Option<Secret<String>>,
+
+    #[clap(skip)]
+    stdin_password: OnceLock<Secret<String>>,
+}
+
+impl BlueskyArgs {
+    /// Whether an app password was given in any way, rather than relying on a session cached by `auth login`.
+    pub fn has_password(&self) -> bool {
+        self.password.is_some() || self.password_file.is_some() || self.password_stdin
+    }
+
+    /// Get the identifier and password to authenticate with.
+    ///
+    /// With --app-password-stdin the password is read from stdin the first time this is called.
+    pub fn credentials(&self) -> Result<(&str, &Secret<String>)> {
+        let password = match (&self.password, &self.password_file) {
+            (Some(password), _) | (_, Some(password)) => Some(password),
+            _ if self.password_stdin => Some(self.stdin_password()?),
+            _ => None,
+        };
+        match (&self.identifier, password) {
+            (Some(identifier), Some(password)) => Ok((identifier, password)),
+            _ => bail!(
+                "--app-identifier and --app-password are required to authenticate with bluesky, unless a session was cached by `auth login`"
+            ),
+        }
+    }
+
+    fn stdin_password(&self) -> Result<&Secret<String>> {
+        if let Some(password) = self.stdin_password.get() {
+            return Ok(password);
+        }
+        if std::io::stdin().is_terminal() {
+            eprint!("App password: ");
+        }
+        let mut password = String::new();
+        std::io::stdin()
+            .read_line(&mut password)
+            .context("failed to read the app password from stdin")?;
+        let password = password.trim();
+        if password.is_empty() {
+            bail!("no app password was given on stdin");
+        }
+        Ok(self
+            .stdin_password
+            .get_or_init(|| Secret::new(password.to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bsky::ReplyControl;
+    use clap::{CommandFactory, FromArgMatches, Parser};
+
+    #[derive(Debug, Parser)]
+    struct ListArgs {
+        #[clap(long, value_delimiter = ',', value_parser = language_value)]
+        languages: Vec<String>,
+        #[clap(long, value_delimiter = ',', value_parser = tag_value)]
+        tags: Vec<String>,
+    }
+
+    #[derive(Debug, Parser)]
+    struct ReplyArgs {
+        #[clap(long, value_delimiter = ',', value_parser = enum_list_value::<ReplyControl>)]
+        reply_control: Vec<String>,
+    }
+
+    fn parse_reply_control(args: &[&str]) -> Result<Vec<ReplyControl>, clap::Error> {
+        let args = ReplyArgs::try_parse_from(["test"].iter().chain(args))?;
+        Ok(enum_list(args.reply_control))
+    }
+
+    #[derive(Debug, Parser)]
+    struct FeedArgs {
+        #[clap(long, value_delimiter = ',', value_parser = url_list_value)]
+        feed_url: Vec<String>,
+    }
+
+    fn parse_feed_urls(args: &[&str]) -> Result<Vec<String>, clap::Error> {
+        let args = FeedArgs::try_parse_from(["test"].iter().chain(args))?;
+        Ok(url_list(args.feed_url)
+            .into_iter()
+            .map(String::from)
+            .collect())
+    }
+
+    #[derive(Debug, Parser)]
+    struct AuthArgs {
+        #[command(flatten)]
+        bluesky: BlueskyArgs,
+    }
+
+    fn parse(args: &[&str]) -> Result<Vec<String>, clap::Error> {
+        let command = ListArgs::command();
+        let matches = command
+            .clone()
+            .try_get_matches_from(["test"].iter().chain(args))?;
+        reject_duplicate_values(&command, &matches)?;
+        Ok(normalise_list(
+            ListArgs::from_arg_matches(&matches)?.languages,
+        ))
+    }
+
+    #[test]
+    fn credentials_are_only_required_by_commands_that_ask_for_them() {
+        let args = AuthArgs::try_parse_from(["test"]).unwrap();
+        assert!(!args.bluesky.has_password());
+        assert!(args.bluesky.credentials().is_err());
+
+        let args = AuthArgs::try_parse_from([
+            "test",
+            "--app-identifier",
+            "whimsky.test",
+            "--app-password",
+            "password",
+        ])
+        .unwrap();
+        let (identifier, password) = args.bluesky.credentials().unwrap();
+        assert_eq!(identifier, "whimsky.test");
+        assert_eq!(password.expose(), "password");
+    }
+
+    #[test]
+    fn enum_list_values_drop_trailing_commas() {
+        assert_eq!(
+            parse_reply_control(&["--reply-control", "nobody,"]).unwrap(),
+            [ReplyControl::Nobody]
+        );
+        assert_eq!(
+            parse_reply_control(&["--reply-control", " mentioned, followers"]).unwrap(),
+            [ReplyControl::Mentioned, ReplyControl::Followers]
+        );
+        assert!(parse_reply_control(&["--reply-control", "friends"]).is_err());
+    }
+
+    #[test]
+    fn url_list_values_drop_trailing_commas() {
+        assert_eq!(
+            parse_feed_urls(&["--feed-url", "https://a.com/feed,"]).unwrap(),
+            ["https://a.com/feed"]
+        );
+        assert_eq!(
+            parse_feed_urls(&["--feed-url", "https://a.com/feed, 'https://b.com/feed'"]).unwrap(),
+            ["https://a.com/feed", "https://b.com/feed"]
+        );
+        assert!(parse_feed_urls(&["--feed-url", "https://a.com/feed,not a url"]).is_err());
+    }
+
+    #[test]
+    fn tags_are_validated() {
+        assert_eq!(tag_value("#InfinityNikki").unwrap(), "InfinityNikki");
+        assert_eq!(tag_value(" Whimsky").unwrap(), "Whimsky");
+        assert!(tag_value("#").is_err());
+        assert!(tag_value("Infinity Nikki").is_err());
+        assert!(tag_value(&"🌸".repeat(MAX_TAG_GRAPHEMES)).is_ok());
+        assert!(tag_value(&"🌸".repeat(MAX_TAG_GRAPHEMES + 1)).is_err());
+    }
+
+    #[test]
+    fn languages_are_validated() {
+        for language in ["en", "ja", "en-US", "pt-BR", " zh-Hant"] {
+            assert_eq!(language_value(language).unwrap(), language.trim());
+        }
+        for language in ["english", "en_US", "e", "12", "--"] {
+            let err = language_value(language).unwrap_err();
+            assert!(err.contains("is not a valid language"), "{err}");
+        }
+        assert_eq!(
+            locale_language_value("kr=ko").unwrap(),
+            ("kr".to_string(), "ko".to_string())
+        );
+        assert!(locale_language_value("kr=korean").is_err());
+    }
+
+    #[test]
+    fn mentions_are_validated() {
+        assert_eq!(
+            mention_value("@Example.bsky.social").unwrap(),
+            "example.bsky.social"
+        );
+        assert_eq!(mention_value(" whimsky.test").unwrap(), "whimsky.test");
+        for mention in ["@", "example", "not a handle.com", "@.bsky.social"] {
+            let err = mention_value(mention).unwrap_err();
+            assert!(err.contains("is not a valid handle"), "{err}");
+        }
+    }
+
+    #[test]
+    fn list_values_drop_trailing_commas() {
+        assert_eq!(parse(&["--languages", "en,ja,"]).unwrap(), ["en", "ja"]);
+        assert_eq!(parse(&["--languages", ",en,,ja"]).unwrap(), ["en", "ja"]);
+    }
+
+    #[test]
+    fn list_values_trim_spaces_after_commas() {
+        assert_eq!(
+            parse(&["--languages", "en, ja,  pt-BR"]).unwrap(),
+            ["en", "ja", "pt-BR"]
+        );
+    }
+
+    #[test]
+    fn list_values_trim_quotes_from_env_files() {
+        // Env files often keep the quotes around a value, which clap then sees as part of it.
+        assert_eq!(parse(&["--languages", "\"en, ja\""]).unwrap(), ["en", "ja"]);
+        assert_eq!(parse(&["--languages", "'en','ja'"]).unwrap(), ["en", "ja"]);
+    }
+
+    #[test]
+    fn list_values_combine_delimiters_and_repeated_flags() {
+        assert_eq!(
+            parse(&["--languages", "en,ja", "--languages", "ko"]).unwrap(),
+            ["en", "ja", "ko"]
+        );
+    }
+
+    #[test]
+    fn duplicate_list_values_are_rejected_while_parsing() {
+        let err = parse(&["--languages", "en,ja", "--languages", " en"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ValueValidation);
+        assert!(
+            err.to_string()
+                .contains("'en' was provided more than once to --languages")
+        );
+
+        // Tags are compared as parsed, so a leading `#` doesn't make them different.
+        let err = parse(&["--tags", "#news,news"]).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("'news' was provided more than once to --tags")
+        );
+    }
+
+    #[test]
+    fn empty_list_values_are_not_duplicates() {
+        assert_eq!(parse(&["--languages", "en,,ja,"]).unwrap(), ["en", "ja"]);
+    }
+
+    #[test]
+    fn invalid_list_values_are_rejected() {
+        assert!(parse(&["--languages", "en,english"]).is_err());
+        assert!(parse(&["--tags", "#"]).is_err());
+        assert!(parse(&["--tags", "two words"]).is_err());
+    }
+
+    #[test]
+    fn durations_are_parsed_with_units() {
+        assert_eq!(duration_value("45s"), Ok(Duration::seconds(45)));
+        assert_eq!(duration_value(" 12h "), Ok(Duration::hours(12)));
+        assert_eq!(duration_value("2w"), Ok(Duration::weeks(2)));
+        assert!(duration_value("90").is_err());
+        assert!(duration_value("90y").is_err());
+        assert!(duration_value("-5d").is_err());
+    }
+
+    #[test]
+    fn durations_too_long_to_subtract_from_now_are_rejected() {
+        assert!(duration_value(&format!("{}w", i64::MAX)).is_err());
+        assert!(duration_value("9999999999d").is_err());
+        assert!(since_value("9999999999d").is_err());
+        assert!(since_value("24h").is_ok());
+        assert!(since_value("2026-01-01T00:00:00Z").is_ok());
+    }
+
+    #[test]
+    fn feeds_are_parsed_from_options() {
+        let feed = rss_feed_value(
+            "url=https://a/feed.xml, langs=en,langs=ja,backdate-hours=6,format=json",
+        )
+        .unwrap();
+        assert_eq!(feed.url.as_str(), "https://a/feed.xml");
+        assert_eq!(feed.languages, ["en", "ja"]);
+        assert_eq!(feed.backdate, Some(Duration::hours(6)));
+        assert_eq!(feed.format, FeedFormat::Json);
+        assert!(rss_feed_value("langs=en").is_err());
+        assert!(rss_feed_value("url=https://a/feed.xml,colour=red").is_err());
+    }
+
+    #[test]
+    fn feed_urls_need_commas_percent_encoded() {
+        let feed = rss_feed_value("url=https://a/feed.xml?tags=a%2Cb,langs=en").unwrap();
+        assert_eq!(feed.url.as_str(), "https://a/feed.xml?tags=a%2Cb");
+        assert!(rss_feed_value("url=https://a/feed.xml?tags=a,b,langs=en").is_err());
+    }
+}
@@ -0,0 +1,46 @@
+use crate::bsky::{BlueskyHandler, ReplyControl};
+use crate::commands::args::BlueskyArgs;
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::http::{self, DEFAULT_HTTP_TIMEOUT_SECONDS};
+use crate::thumbnail::ImageOptions;
+use anyhow::{Context, Result};
+use clap::Parser;
+use tracing::info;
+
+/// Log in to Bluesky and cache the session in the data path, so that `start` can run without being given the app
+/// password.
+#[derive(Debug, Parser)]
+pub struct LoginCommand {
+    #[command(flatten)]
+    bluesky: BlueskyArgs,
+}
+
+impl ExecutableCommand for LoginCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let (identifier, password) = self.bluesky.credentials()?;
+        let bsky_handler = BlueskyHandler::new(
+            http::build_client(std::time::Duration::from_secs(DEFAULT_HTTP_TIMEOUT_SECONDS))?,
+            self.bluesky.service.clone(),
+            Some(identifier),
+            global_args.data_path,
+            vec![ReplyControl::Everyone],
+            false,
+            ImageOptions::default(),
+            None,
+        )
+        .await?;
+        bsky_handler
+            .login(
+                identifier,
+                password,
+                self.bluesky.auth_factor_token.as_ref(),
+            )
+            .await?;
+        let session = bsky_handler
+            .session_info()
+            .await
+            .context("no session was created by logging in")?;
+        info!("Logged in as {} ({})", session.handle, session.did);
+        Ok(())
+    }
+}
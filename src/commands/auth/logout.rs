@@ -0,0 +1,39 @@
+use crate::bsky::{BlueskyHandler, SESSION_FILE_NAME};
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::http::{self, DEFAULT_HTTP_TIMEOUT_SECONDS};
+use anyhow::{Context, Result};
+use clap::Parser;
+use tracing::{info, warn};
+
+/// End the cached session on the service and delete it from the data path.
+#[derive(Debug, Parser)]
+pub struct LogoutCommand;
+
+impl ExecutableCommand for LogoutCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let session_path = global_args.data_path.join(SESSION_FILE_NAME);
+        if !session_path.exists() {
+            info!("Not logged in: no bsky session is cached");
+            return Ok(());
+        }
+        match BlueskyHandler::resume(
+            http::build_client(std::time::Duration::from_secs(DEFAULT_HTTP_TIMEOUT_SECONDS))?,
+            global_args.data_path,
+        )
+        .await
+        {
+            Ok(bsky_handler) => bsky_handler.logout().await?,
+            Err(err) => {
+                warn!("{err}: deleting it without ending it on the service");
+                std::fs::remove_file(&session_path).with_context(|| {
+                    format!(
+                        "failed to delete bsky session file {}",
+                        session_path.display()
+                    )
+                })?;
+            }
+        }
+        info!("Logged out");
+        Ok(())
+    }
+}
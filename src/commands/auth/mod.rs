@@ -0,0 +1,34 @@
+mod login;
+mod logout;
+mod status;
+
+use super::{ExecutableCommand, GlobalArguments};
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use login::LoginCommand;
+use logout::LogoutCommand;
+use status::StatusCommand;
+
+/// Manage the Bluesky session cached in the data path.
+#[derive(Debug, Parser)]
+pub struct AuthCommandBase {
+    #[clap(subcommand)]
+    command: AuthCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum AuthCommands {
+    Login(Box<LoginCommand>),
+    Status(StatusCommand),
+    Logout(LogoutCommand),
+}
+
+impl ExecutableCommand for AuthCommandBase {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        match self.command {
+            AuthCommands::Login(cmd) => cmd.run(global_args).await,
+            AuthCommands::Status(cmd) => cmd.run(global_args).await,
+            AuthCommands::Logout(cmd) => cmd.run(global_args).await,
+        }
+    }
+}
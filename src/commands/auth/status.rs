@@ -0,0 +1,44 @@
+use crate::bsky::BlueskyHandler;
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::http::{self, DEFAULT_HTTP_TIMEOUT_SECONDS};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+
+/// Show the account and expiry of the cached session, failing when there isn't a valid one.
+#[derive(Debug, Parser)]
+pub struct StatusCommand;
+
+impl ExecutableCommand for StatusCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let bsky_handler = BlueskyHandler::resume(
+            http::build_client(std::time::Duration::from_secs(DEFAULT_HTTP_TIMEOUT_SECONDS))?,
+            global_args.data_path,
+        )
+        .await
+        .context("not logged in: log in with `auth login`")?;
+        let session = bsky_handler
+            .session_info()
+            .await
+            .context("not logged in: log in with `auth login`")?;
+        let format_time = |time: Option<DateTime<Utc>>| {
+            time.map_or("unknown".to_string(), |time| {
+                time.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+            })
+        };
+        println!("{:<16}  {}", "HANDLE", session.handle);
+        println!("{:<16}  {}", "DID", session.did);
+        println!("{:<16}  {}", "SERVICE", session.endpoint);
+        println!(
+            "{:<16}  {}",
+            "ACCESS EXPIRES",
+            format_time(session.access_token_expiry)
+        );
+        println!(
+            "{:<16}  {}",
+            "REFRESH EXPIRES",
+            format_time(session.refresh_token_expiry)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,503 @@
+use super::args::{BlueskyArgs, duration_value};
+use super::{ExecutableCommand, GlobalArguments};
+use crate::bsky::{BlueskyHandler, RateLimited, ReplyControl};
+use crate::database::Database;
+use crate::http::{self, DEFAULT_HTTP_TIMEOUT_SECONDS};
+use crate::thumbnail::ImageOptions;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use clap::Parser;
+use std::collections::{HashMap, HashSet};
+use tokio::time::sleep;
+use tracing::{error, info};
+
+const CURSOR_STATE_KEY: &str = "cleanup_bsky_cursor";
+
+/// Delete posts older than a retention period from Bluesky.
+///
+/// Only posts matching a URL stored in the database and the thread replies under them are deleted, leaving posts
+/// made by hand on the same account alone. Deleted posts are kept in the database so they will never be posted again.
+#[derive(Debug, Parser)]
+pub struct CleanupBskyCommand {
+    #[command(flatten)]
+    bluesky: BlueskyArgs,
+
+    /// How old a post must be before it is deleted, e.g. "90d" or "12w".
+    #[clap(long = "older-than", value_parser = duration_value)]
+    older_than: Duration,
+
+    /// Only report the posts that would be deleted without deleting anything.
+    #[clap(long = "dry-run")]
+    dry_run: bool,
+
+    /// The interval of time in milliseconds to wait between deleting posts.
+    ///
+    /// This keeps the command well under the service's rate limits when deleting large amounts of posts.
+    #[clap(default_value_t = 500, long = "delete-interval-ms")]
+    delete_interval_ms: u64,
+}
+
+impl ExecutableCommand for CleanupBskyCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let (identifier, password) = self.bluesky.credentials()?;
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        let bsky_handler = BlueskyHandler::new(
+            http::build_client(std::time::Duration::from_secs(DEFAULT_HTTP_TIMEOUT_SECONDS))?,
+            self.bluesky.service.clone(),
+            Some(identifier),
+            global_args.data_path,
+            vec![ReplyControl::Everyone],
+            false,
+            ImageOptions::default(),
+            None,
+        )
+        .await?;
+        bsky_handler
+            .login(
+                identifier,
+                password,
+                self.bluesky.auth_factor_token.as_ref(),
+            )
+            .await?;
+
+        let cutoff = Utc::now()
+            .checked_sub_signed(self.older_than)
+            .context("--older-than is too long")?;
+        info!("Deleting posts created before {cutoff}");
+        let counts = self.cleanup(&bsky_handler, &database, cutoff).await?;
+
+        let Counts {
+            deleted,
+            failed,
+            skipped,
+        } = counts;
+        match self.dry_run {
+            true => {
+                info!("Cleanup dry run finished: {deleted} would be deleted, {skipped} skipped")
+            }
+            false => {
+                info!("Cleanup finished: {deleted} deleted, {failed} failed, {skipped} skipped")
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The number of posts handled so far by a cleanup.
+#[derive(Debug, Default)]
+struct Counts {
+    deleted: u64,
+    failed: u64,
+    skipped: u64,
+}
+
+impl CleanupBskyCommand {
+    /// Delete the posts made by whimsky before the cutoff, resuming from the cursor stored by a previous run.
+    ///
+    /// Only posts matching a URL stored in the database and the replies threaded under them are deleted, so that
+    /// posts made by hand on the same account are left alone.
+    async fn cleanup(
+        &self,
+        bsky_handler: &BlueskyHandler,
+        database: &Database,
+        cutoff: DateTime<Utc>,
+    ) -> Result<Counts> {
+        let mut cursor = match self.dry_run {
+            true => None,
+            false => database.get_state(CURSOR_STATE_KEY).await?,
+        };
+        if cursor.is_some() {
+            info!("Resuming cleanup from the cursor stored by a previous run");
+        }
+
+        let mut counts = Counts::default();
+        // Every post known to have been made by whimsky, which the replies in its threads are matched against.
+        let mut bot_posts = HashSet::new();
+        // Posts stored with the record they were published as are deleted first, which finds them without walking
+        // the whole author feed and keeps the URL they were posted for exact.
+        for (url, uri) in database.get_published_records_before(cutoff).await? {
+            self.delete(bsky_handler, database, &uri, Some(&url), &mut counts)
+                .await?;
+            bot_posts.insert(uri);
+        }
+        // Anything left, such as thread replies and posts made before records were stored, is found through the
+        // author feed instead. The feed is newest first so replies are seen before the post they thread under, and
+        // are held onto until it turns up.
+        let mut waiting_replies: HashMap<String, Vec<String>> = HashMap::new();
+        loop {
+            let (posts, next_cursor) =
+                wait_for_rate_limit(|| bsky_handler.get_authored_posts(cursor.clone())).await?;
+            for post in posts {
+                if bot_posts.contains(&post.uri) {
+                    continue;
+                }
+                if post.created_at > cutoff {
+                    counts.skipped += 1;
+                    continue;
+                }
+                if let Some(root) = post.reply_root {
+                    match bot_posts.contains(&root) {
+                        true => {
+                            self.delete(bsky_handler, database, &post.uri, None, &mut counts)
+                                .await?
+                        }
+                        false => waiting_replies.entry(root).or_default().push(post.uri),
+                    }
+                    continue;
+                }
+                // Posts with an image rather than a link card are only matched through their stored record.
+                let url = match database.get_url_by_record_uri(&post.uri).await? {
+                    Some(url) => Some(url),
+                    None => match post.embed_uri {
+                        Some(embed_uri) if database.has_posted_url(&embed_uri).await? => {
+                            Some(embed_uri)
+                        }
+                        _ => None,
+                    },
+                };
+                let Some(url) = url else {
+                    counts.skipped += 1;
+                    continue;
+                };
+                self.delete(bsky_handler, database, &post.uri, Some(&url), &mut counts)
+                    .await?;
+                for reply in waiting_replies.remove(&post.uri).unwrap_or_default() {
+                    self.delete(bsky_handler, database, &reply, None, &mut counts)
+                        .await?;
+                }
+                bot_posts.insert(post.uri);
+            }
+
+            match next_cursor {
+                Some(next_cursor) => {
+                    // Resuming past replies that are still waiting for their thread would leave them behind.
+                    if !self.dry_run && waiting_replies.is_empty() {
+                        database.set_state(CURSOR_STATE_KEY, &next_cursor).await?;
+                    }
+                    cursor = Some(next_cursor);
+                }
+                None => break,
+            }
+        }
+        // Replies in threads that weren't started by whimsky.
+        counts.skipped += waiting_replies.values().map(Vec::len).sum::<usize>() as u64;
+        if !self.dry_run {
+            database.remove_state(CURSOR_STATE_KEY).await?;
+        }
+        Ok(counts)
+    }
+
+    /// Delete a post, marking the URL it was posted for as deleted when it is known.
+    ///
+    /// Failing to delete a post is counted rather than returned.
+    async fn delete(
+        &self,
+        bsky_handler: &BlueskyHandler,
+        database: &Database,
+        uri: &str,
+        url: Option<&str>,
+        counts: &mut Counts,
+    ) -> Result<()> {
+        if self.dry_run {
+            match url {
+                Some(url) => info!("Would delete '{uri}' posted for '{url}'"),
+                None => info!("Would delete '{uri}'"),
+            }
+            counts.deleted += 1;
+            return Ok(());
+        }
+        match wait_for_rate_limit(|| bsky_handler.delete_post(uri)).await {
+            Ok(()) => {
+                counts.deleted += 1;
+                if let Some(url) = url {
+                    database.mark_posted_url_deleted(url).await?;
+                }
+            }
+            Err(err) => {
+                error!("Failed to delete '{uri}': {err:?}");
+                counts.failed += 1;
+            }
+        }
+        sleep(std::time::Duration::from_millis(self.delete_interval_ms)).await;
+        Ok(())
+    }
+}
+
+/// Run a request, waiting for the service's rate limit to reset however long that takes.
+///
+/// Cleaning up has nothing else to do in the meantime, unlike posting which defers its work until the next run.
+async fn wait_for_rate_limit<T, F, Fut>(mut request: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    loop {
+        match request().await {
+            Err(err) => {
+                let Some(&RateLimited { reset }) = err.downcast_ref::<RateLimited>() else {
+                    return Err(err);
+                };
+                let wait = (reset - Utc::now()).to_std().unwrap_or_default();
+                info!(
+                    "Rate limited by the bluesky service: waiting for {} seconds until {reset} to continue",
+                    wait.as_secs()
+                );
+                sleep(wait).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::PostStatus;
+    use crate::secret::Secret;
+    use axum::{
+        Json, Router,
+        extract::RawQuery,
+        routing::{get, post},
+    };
+    use reqwest::Url;
+    use serde_json::{Value, json};
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    const DID: &str = "did:plc:whimsky";
+    const CID: &str = "bafyreie5737gdxlw5i64vzichcalba3z2v5n6icifvx5xytvske7mr3hpm";
+
+    fn post_uri(rkey: &str) -> String {
+        format!("at://{DID}/app.bsky.feed.post/{rkey}")
+    }
+
+    /// A post in the author feed, optionally replying in the thread started by `reply_root` and with a link card.
+    fn feed_item(
+        rkey: &str,
+        created_at: DateTime<Utc>,
+        reply_root: Option<&str>,
+        link: Option<&str>,
+    ) -> Value {
+        let mut record = json!({
+            "$type": "app.bsky.feed.post",
+            "text": "",
+            "createdAt": created_at.to_rfc3339(),
+        });
+        if let Some(root) = reply_root {
+            let root = json!({ "uri": root, "cid": CID });
+            record["reply"] = json!({ "root": root, "parent": root });
+        }
+        let mut post = json!({
+            "uri": post_uri(rkey),
+            "cid": CID,
+            "author": { "did": DID, "handle": "whimsky.test" },
+            "record": record,
+            "indexedAt": created_at.to_rfc3339(),
+        });
+        if let Some(link) = link {
+            post["embed"] = json!({
+                "$type": "app.bsky.embed.external#view",
+                "external": { "uri": link, "title": "", "description": "" },
+            });
+        }
+        json!({ "post": post })
+    }
+
+    /// Serve an author feed split into pages on a random local port, recording the keys of the posts deleted.
+    async fn spawn_pds(pages: Vec<Vec<Value>>) -> (Url, Arc<Mutex<Vec<String>>>) {
+        let deleted = Arc::new(Mutex::new(vec![]));
+        let recorder = deleted.clone();
+        let router = Router::new()
+            .route(
+                "/xrpc/com.atproto.server.createSession",
+                post(|| async {
+                    Json(json!({
+                        "accessJwt": "access",
+                        "refreshJwt": "refresh",
+                        "handle": "whimsky.test",
+                        "did": DID,
+                    }))
+                }),
+            )
+            .route(
+                "/xrpc/app.bsky.feed.getAuthorFeed",
+                get(move |RawQuery(query): RawQuery| async move {
+                    let page = query
+                        .unwrap_or_default()
+                        .split('&')
+                        .find_map(|param| param.strip_prefix("cursor="))
+                        .map_or(0, |cursor| cursor.parse().unwrap());
+                    let cursor = (page + 1 < pages.len()).then(|| (page + 1).to_string());
+                    Json(json!({ "feed": pages[page], "cursor": cursor }))
+                }),
+            )
+            .route(
+                "/xrpc/com.atproto.repo.deleteRecord",
+                post(move |Json(input): Json<Value>| async move {
+                    if input["collection"] == "app.bsky.feed.post" {
+                        let rkey = input["rkey"].as_str().unwrap().to_string();
+                        recorder.lock().unwrap().push(rkey);
+                    }
+                    Json(json!({}))
+                }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        (url, deleted)
+    }
+
+    async fn handler(service: Url, data_path: &std::path::Path) -> BlueskyHandler {
+        let handler = BlueskyHandler::new(
+            reqwest::Client::new(),
+            service,
+            Some("whimsky.test"),
+            data_path.to_path_buf(),
+            vec![ReplyControl::Everyone],
+            false,
+            ImageOptions::default(),
+            None,
+        )
+        .await
+        .unwrap();
+        handler
+            .login("whimsky.test", &Secret::new("password".into()), None)
+            .await
+            .unwrap();
+        handler
+    }
+
+    fn command(dry_run: bool) -> CleanupBskyCommand {
+        let mut args = vec![
+            "cleanup-bsky",
+            "--older-than",
+            "30d",
+            "--delete-interval-ms",
+            "0",
+            "--app-identifier",
+            "whimsky.test",
+            "--app-password",
+            "password",
+        ];
+        if dry_run {
+            args.push("--dry-run");
+        }
+        CleanupBskyCommand::try_parse_from(args).unwrap()
+    }
+
+    /// A database holding a post published with its record and a post stored from before records were, alongside
+    /// an author feed mixing them with replies, recent posts and posts made by hand.
+    async fn mixed_feed() -> (Database, Vec<Vec<Value>>) {
+        let database = Database::new_in_memory().await.unwrap();
+        for url in ["https://example.com/stored", "https://example.com/linked"] {
+            database
+                .record_posted(url, "test", None, None, PostStatus::Posted)
+                .await
+                .unwrap();
+        }
+        database
+            .set_posted_record("https://example.com/stored", &post_uri("stored"), CID)
+            .await
+            .unwrap();
+
+        let old = Utc::now() - Duration::days(60);
+        let someone_else = "at://did:plc:someone/app.bsky.feed.post/1";
+        let pages = vec![
+            vec![
+                feed_item("recent", Utc::now(), None, Some("https://example.com/new")),
+                feed_item("thread", old, Some(&post_uri("linked")), None),
+                feed_item("other-reply", old, Some(someone_else), None),
+                feed_item("manual", old, None, Some("https://example.com/unknown")),
+            ],
+            vec![
+                feed_item("linked", old, None, Some("https://example.com/linked")),
+                feed_item("stored", old, None, None),
+                feed_item("manual-old", old, None, None),
+            ],
+        ];
+        (database, pages)
+    }
+
+    #[tokio::test]
+    async fn dry_run_counts_posts_without_deleting_them() {
+        let (database, pages) = mixed_feed().await;
+        let (service, deleted) = spawn_pds(pages).await;
+        let dir = tempfile::tempdir().unwrap();
+        let bsky_handler = handler(service, dir.path()).await;
+
+        let cutoff = Utc::now() - Duration::days(30);
+        let counts = command(true)
+            .cleanup(&bsky_handler, &database, cutoff)
+            .await
+            .unwrap();
+        assert_eq!((counts.deleted, counts.failed, counts.skipped), (3, 0, 4));
+        assert!(deleted.lock().unwrap().is_empty());
+        assert_eq!(database.get_state(CURSOR_STATE_KEY).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn only_posts_made_by_whimsky_are_deleted() {
+        let (database, pages) = mixed_feed().await;
+        let (service, deleted) = spawn_pds(pages).await;
+        let dir = tempfile::tempdir().unwrap();
+        let bsky_handler = handler(service, dir.path()).await;
+
+        let cutoff = Utc::now() - Duration::days(30);
+        let counts = command(false)
+            .cleanup(&bsky_handler, &database, cutoff)
+            .await
+            .unwrap();
+        assert_eq!((counts.deleted, counts.failed, counts.skipped), (3, 0, 4));
+        let mut deleted = deleted.lock().unwrap().clone();
+        deleted.sort();
+        assert_eq!(deleted, ["linked", "stored", "thread"]);
+        // The post is no longer found as one still to be deleted.
+        assert!(
+            database
+                .get_published_records_before(Utc::now() + Duration::days(1))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn cleanup_resumes_from_the_stored_cursor() {
+        let database = Database::new_in_memory().await.unwrap();
+        for url in ["https://example.com/first", "https://example.com/second"] {
+            database
+                .record_posted(url, "test", None, None, PostStatus::Posted)
+                .await
+                .unwrap();
+        }
+        let old = Utc::now() - Duration::days(60);
+        let (service, deleted) = spawn_pds(vec![
+            vec![feed_item(
+                "first",
+                old,
+                None,
+                Some("https://example.com/first"),
+            )],
+            vec![feed_item(
+                "second",
+                old,
+                None,
+                Some("https://example.com/second"),
+            )],
+        ])
+        .await;
+        let dir = tempfile::tempdir().unwrap();
+        let bsky_handler = handler(service, dir.path()).await;
+        database.set_state(CURSOR_STATE_KEY, "1").await.unwrap();
+
+        let cutoff = Utc::now() - Duration::days(30);
+        let counts = command(false)
+            .cleanup(&bsky_handler, &database, cutoff)
+            .await
+            .unwrap();
+        assert_eq!(counts.deleted, 1);
+        assert_eq!(*deleted.lock().unwrap(), ["second"]);
+        assert_eq!(database.get_state(CURSOR_STATE_KEY).await.unwrap(), None);
+    }
+}
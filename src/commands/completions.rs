@@ -0,0 +1,96 @@
+use super::CommandRoot;
+use anyhow::{Context, Result};
+use clap::{Command, CommandFactory, Parser};
+use clap_complete::Shell;
+use std::{fs::create_dir_all, io::Write, path::PathBuf};
+
+/// Print a shell completion script, or write man pages, for every command.
+///
+/// Completion scripts are printed to stdout, e.g. `whimsky completions bash > /etc/bash_completion.d/whimsky`.
+#[derive(Debug, Parser)]
+pub struct CompletionsCommand {
+    /// The shell to print a completion script for.
+    #[clap(required_unless_present = "man", conflicts_with = "man", value_enum)]
+    shell: Option<Shell>,
+
+    /// Write a man page for each command to this directory instead of printing a completion script.
+    #[clap(long = "man")]
+    man: Option<PathBuf>,
+}
+
+impl CompletionsCommand {
+    /// Unlike other commands this doesn't use the data path or database, so it is run before they are set up.
+    pub fn run(self) -> Result<()> {
+        let mut command = CommandRoot::command();
+        if let Some(dir) = self.man {
+            create_dir_all(&dir)
+                .with_context(|| format!("failed to create directory {}", dir.display()))?;
+            clap_mangen::generate_to(command, &dir)
+                .with_context(|| format!("failed to write man pages to {}", dir.display()))?;
+            return Ok(());
+        }
+        if let Some(shell) = self.shell {
+            std::io::stdout()
+                .write_all(&completion_script(shell, &mut command))
+                .context("failed to write the completion script")?;
+        }
+        Ok(())
+    }
+}
+
+/// Generate the completion script for a shell into a buffer, so that a closed stdout is reported as an error instead
+/// of a panic.
+fn completion_script(shell: Shell, command: &mut Command) -> Vec<u8> {
+    let mut script = vec![];
+    clap_complete::generate(shell, command, env!("CARGO_PKG_NAME"), &mut script);
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completion_scripts_cover_every_command() {
+        let script =
+            String::from_utf8(completion_script(Shell::Bash, &mut CommandRoot::command())).unwrap();
+        assert!(script.contains("--rerun-interval-seconds"));
+        assert!(script.contains("whimsky__subcmd__database,list-posts)"));
+        assert!(script.contains("whimsky__subcmd__database,import-posts)"));
+        for shell in [Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+            let script =
+                String::from_utf8(completion_script(shell, &mut CommandRoot::command())).unwrap();
+            assert!(script.contains("rerun-interval-seconds"), "{shell}");
+        }
+    }
+
+    #[test]
+    fn man_pages_are_written_for_every_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let man = dir.path().join("man");
+        CompletionsCommand::try_parse_from(["completions", "--man", man.to_str().unwrap()])
+            .unwrap()
+            .run()
+            .unwrap();
+        for page in [
+            "whimsky.1",
+            "whimsky-start.1",
+            "whimsky-database.1",
+            "whimsky-database-list-posts.1",
+        ] {
+            let page = std::fs::read_to_string(man.join(page)).unwrap();
+            assert!(page.contains(".TH "), "{page}");
+        }
+        let start = std::fs::read_to_string(man.join("whimsky-start.1")).unwrap();
+        assert!(start.contains("rerun\\-interval\\-seconds"));
+    }
+
+    #[test]
+    fn a_shell_or_man_directory_is_required() {
+        assert!(CompletionsCommand::try_parse_from(["completions"]).is_err());
+        assert!(
+            CompletionsCommand::try_parse_from(["completions", "bash", "--man", "man"]).is_err()
+        );
+        assert!(CompletionsCommand::try_parse_from(["completions", "tcsh"]).is_err());
+    }
+}
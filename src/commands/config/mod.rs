@@ -0,0 +1,26 @@
+mod validate;
+
+use super::{ExecutableCommand, GlobalArguments};
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use validate::ValidateCommand;
+
+/// Inspect the options read from the config file.
+#[derive(Debug, Parser)]
+pub struct ConfigCommandBase {
+    #[clap(subcommand)]
+    command: ConfigCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommands {
+    Validate(ValidateCommand),
+}
+
+impl ExecutableCommand for ConfigCommandBase {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        match self.command {
+            ConfigCommands::Validate(cmd) => cmd.run(global_args).await,
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use crate::commands::{CommandRoot, ExecutableCommand, GlobalArguments};
+use crate::config::{self, config_option, leaf_command};
+use anyhow::{Result, anyhow};
+use clap::{ArgAction, CommandFactory, Parser, parser::ValueSource};
+use std::ffi::OsString;
+use toml::Value;
+
+/// Check the config file and print the options that `start` would run with, combined from environment variables,
+/// the config file and defaults.
+///
+/// Each option is printed as it would be written in the config file, with the app password redacted.
+#[derive(Debug, Parser)]
+pub struct ValidateCommand;
+
+impl ExecutableCommand for ValidateCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let mut args: Vec<OsString> = vec![
+            env!("CARGO_PKG_NAME").into(),
+            "--data-path".into(),
+            global_args.data_path.into(),
+        ];
+        if let Some(config_path) = global_args.config_path {
+            args.extend(["--config".into(), config_path.into()]);
+        }
+        args.push("start".into());
+        let merged = config::merge_config_file(args)?;
+        let command = CommandRoot::command();
+        let matches = config::get_matches(&command, &merged.args).map_err(|err| {
+            // Only the first paragraph describes the problem, with the rest being usage.
+            let message = err.to_string();
+            let message = message.split("\n\n").next().unwrap_or_default();
+            anyhow!(
+                "invalid options for start: {}",
+                message.trim_start_matches("error: ")
+            )
+        })?;
+
+        let (start, matches) = leaf_command(&command, &matches);
+        match &merged.path {
+            Some(path) => println!("# Options read from {}", path.display()),
+            None => println!("# No config file was found"),
+        }
+        for arg in start.get_arguments() {
+            let id = arg.get_id().as_str();
+            if config_option(start, id).is_none() {
+                continue;
+            }
+            let source = match matches.value_source(id) {
+                Some(ValueSource::CommandLine) => "config file",
+                Some(ValueSource::EnvVariable) => "environment",
+                Some(ValueSource::DefaultValue) => "default",
+                _ => continue,
+            };
+            let values = matches
+                .get_raw(id)
+                .into_iter()
+                .flatten()
+                .map(|value| value.to_string_lossy().into_owned())
+                .collect::<Vec<_>>();
+            let value = match (arg.get_action(), arg.get_value_delimiter()) {
+                (ArgAction::SetTrue, _) => Value::Boolean(matches.get_flag(id)),
+                _ if arg.is_hide_env_values_set() => Value::String("[REDACTED]".to_string()),
+                (ArgAction::Append, _) => {
+                    Value::Array(values.into_iter().map(toml_value).collect())
+                }
+                (_, Some(delimiter)) => Value::String(values.join(&delimiter.to_string())),
+                _ => toml_value(values.concat()),
+            };
+            println!("{id} = {value}  # {source}");
+        }
+        Ok(())
+    }
+}
+
+/// Write a value as a number or boolean when it reads back the same, and otherwise as a string.
+fn toml_value(value: String) -> Value {
+    if let Ok(number) = value.parse::<i64>()
+        && number.to_string() == value
+    {
+        return Value::Integer(number);
+    }
+    match value.as_str() {
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        _ => Value::String(value),
+    }
+}
@@ -0,0 +1,27 @@
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::database::Database;
+use anyhow::{Result, bail};
+use clap::Parser;
+use std::path::PathBuf;
+use tracing::info;
+
+/// Write a consistent snapshot of the sqlite database to a file, which is safe to do while the bot is running.
+#[derive(Debug, Parser)]
+pub struct BackupCommand {
+    /// The file to write the backup to, which must not already exist.
+    #[clap(long = "output")]
+    output: PathBuf,
+}
+
+impl ExecutableCommand for BackupCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        if self.output.exists() {
+            bail!("{} already exists", self.output.display());
+        }
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        database.backup(&self.output).await?;
+        info!("Backed up the database to {}", self.output.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+use crate::bsky::{BlueskyHandler, ReplyControl, is_record_not_found};
+use crate::commands::args::BlueskyArgs;
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::database::Database;
+use crate::http::{self, DEFAULT_HTTP_TIMEOUT_SECONDS};
+use crate::thumbnail::ImageOptions;
+use anyhow::{Result, bail};
+use clap::Parser;
+use tracing::{error, info, warn};
+
+/// Delete the Bluesky posts made for URLs and remove the URLs from the database.
+///
+/// Only URLs posted since the record of each post started being stored can be deleted.
+#[derive(Debug, Parser)]
+pub struct DeletePostCommand {
+    #[command(flatten)]
+    bluesky: BlueskyArgs,
+
+    /// The URLs of the posts to delete.
+    #[clap(required = true)]
+    urls: Vec<String>,
+
+    /// Keep the URLs in the database after deleting their posts so they will never be posted again.
+    #[clap(long = "keep-db-entry")]
+    keep_db_entry: bool,
+}
+
+impl ExecutableCommand for DeletePostCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let (identifier, password) = self.bluesky.credentials()?;
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        let bsky_handler = BlueskyHandler::new(
+            http::build_client(std::time::Duration::from_secs(DEFAULT_HTTP_TIMEOUT_SECONDS))?,
+            self.bluesky.service.clone(),
+            Some(identifier),
+            global_args.data_path,
+            vec![ReplyControl::Everyone],
+            false,
+            ImageOptions::default(),
+            None,
+        )
+        .await?;
+        bsky_handler
+            .login(
+                identifier,
+                password,
+                self.bluesky.auth_factor_token.as_ref(),
+            )
+            .await?;
+
+        let (mut deleted, mut not_found, mut failed) = (0, 0, 0);
+        for url in &self.urls {
+            if !database.has_posted_url(url).await? {
+                warn!("'{url}' was not found in the database");
+                not_found += 1;
+                continue;
+            }
+            let Some(uri) = database.get_posted_record_uri(url).await? else {
+                warn!(
+                    "'{url}' has no stored Bluesky record as it was posted before records were stored: delete it manually"
+                );
+                not_found += 1;
+                continue;
+            };
+            match bsky_handler.delete_post(&uri).await {
+                Ok(()) => info!("Deleted '{uri}' posted for '{url}'"),
+                Err(err) if is_record_not_found(&err) => {
+                    info!("'{uri}' posted for '{url}' was already deleted from Bluesky")
+                }
+                Err(err) => {
+                    error!("Failed to delete '{uri}' posted for '{url}': {err:#}");
+                    failed += 1;
+                    continue;
+                }
+            }
+            match self.keep_db_entry {
+                true => {
+                    database.mark_posted_url_deleted(url).await?;
+                }
+                false => {
+                    database.remove_posted_url(url).await?;
+                    info!("Removed '{url}' from the database");
+                }
+            }
+            deleted += 1;
+        }
+
+        info!("Finished deleting posts: {deleted} deleted, {not_found} not found, {failed} failed");
+        if failed > 0 {
+            bail!("failed to delete {failed} posts from bluesky");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bsky::tests::{DID, spawn_pds};
+    use crate::database::PostStatus;
+    use chrono::Utc;
+
+    /// Record a URL as posted, along with the key of the post record made for it if there is one.
+    async fn posted(database: &Database, url: &str, rkey: Option<&str>) {
+        database
+            .record_posted(url, "test", None, None, PostStatus::Pending)
+            .await
+            .unwrap();
+        let uri = rkey.map(|rkey| format!("at://{DID}/app.bsky.feed.post/{rkey}"));
+        database
+            .mark_published(
+                url,
+                uri.as_deref().map(|uri| (uri, "cid")),
+                Utc::now().date_naive(),
+            )
+            .await
+            .unwrap();
+    }
+
+    fn command(service: &reqwest::Url, args: &[&str]) -> DeletePostCommand {
+        DeletePostCommand::try_parse_from(
+            [
+                "delete-post",
+                "--app-service",
+                service.as_str(),
+                "--app-identifier",
+                "whimsky.test",
+                "--app-password",
+                "password",
+            ]
+            .iter()
+            .chain(args),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn posts_are_deleted_along_with_their_urls() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, pds) = spawn_pds().await;
+        let database = Database::new(&GlobalArguments::in_dir(dir.path()).database_url, &[])
+            .await
+            .unwrap();
+        posted(&database, "https://example.com/deleted", Some("3k2a")).await;
+        posted(&database, "https://example.com/gone", Some("missing")).await;
+        posted(&database, "https://example.com/kept", Some("3k2b")).await;
+
+        command(
+            &service,
+            &["https://example.com/deleted", "https://example.com/gone"],
+        )
+        .run(GlobalArguments::in_dir(dir.path()))
+        .await
+        .unwrap();
+        // Posts already deleted from Bluesky are still removed from the database.
+        for url in ["https://example.com/deleted", "https://example.com/gone"] {
+            assert!(!database.has_posted_url(url).await.unwrap(), "{url}");
+        }
+        assert_eq!(
+            pds.deleted.lock().unwrap()[0],
+            format!("at://{DID}/app.bsky.feed.post/3k2a")
+        );
+
+        command(&service, &["https://example.com/kept", "--keep-db-entry"])
+            .run(GlobalArguments::in_dir(dir.path()))
+            .await
+            .unwrap();
+        assert!(
+            database
+                .has_posted_url("https://example.com/kept")
+                .await
+                .unwrap()
+        );
+        assert!(
+            pds.deleted
+                .lock()
+                .unwrap()
+                .contains(&format!("at://{DID}/app.bsky.feed.post/3k2b"))
+        );
+    }
+
+    #[tokio::test]
+    async fn posts_that_cant_be_deleted_fail_the_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, pds) = spawn_pds().await;
+        let database = Database::new(&GlobalArguments::in_dir(dir.path()).database_url, &[])
+            .await
+            .unwrap();
+        posted(
+            &database,
+            "https://example.com/undeletable",
+            Some("undeletable"),
+        )
+        .await;
+        posted(&database, "https://example.com/unrecorded", None).await;
+
+        let err = command(
+            &service,
+            &[
+                "https://example.com/undeletable",
+                "https://example.com/unrecorded",
+                "https://example.com/unknown",
+            ],
+        )
+        .run(GlobalArguments::in_dir(dir.path()))
+        .await
+        .unwrap_err();
+        assert_eq!(err.to_string(), "failed to delete 1 posts from bluesky");
+        // Only posts that were deleted are removed, so nothing is forgotten while its post is still up.
+        for url in [
+            "https://example.com/undeletable",
+            "https://example.com/unrecorded",
+        ] {
+            assert!(database.has_posted_url(url).await.unwrap(), "{url}");
+        }
+        assert!(pds.deleted.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::database::{Database, MANUAL_SOURCE, PostStatus, PostedEntry};
+use anyhow::{Context, Result};
+use clap::Parser;
+use reqwest::Url;
+use std::path::PathBuf;
+use tracing::info;
+
+/// Record a list of URLs as posted, such as when moving the bot to a new machine.
+///
+/// URLs can be separated by commas or newlines, and are all recorded together so an import that fails part way
+/// through records nothing.
+#[derive(Debug, Parser)]
+pub struct ImportPostsCommand {
+    /// The file to read URLs from, or `-` to read them from stdin.
+    #[clap(long = "input")]
+    input: PathBuf,
+
+    /// Only print the URLs that would be imported without recording them.
+    #[clap(long = "dry-run")]
+    dry_run: bool,
+}
+
+impl ExecutableCommand for ImportPostsCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let contents = match self.input.to_str() {
+            Some("-") => std::io::read_to_string(std::io::stdin())
+                .context("failed to read URLs from stdin")?,
+            _ => std::fs::read_to_string(&self.input)
+                .with_context(|| format!("failed to read URLs from {}", self.input.display()))?,
+        };
+        let urls = parse_urls(&contents)?;
+
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        if self.dry_run {
+            let mut new = 0;
+            for url in &urls {
+                if !database.has_posted_url(url).await? {
+                    info!("Would import '{url}'");
+                    new += 1;
+                }
+            }
+            info!(
+                "Would import {new} URLs, {} of {} are already recorded",
+                urls.len() - new,
+                urls.len()
+            );
+            return Ok(());
+        }
+
+        let entries = urls
+            .iter()
+            .map(|url| PostedEntry {
+                url,
+                source: MANUAL_SOURCE,
+                source_id: None,
+                fingerprint: None,
+            })
+            .collect::<Vec<_>>();
+        let stored = database.record_batch(&entries, PostStatus::Posted).await?;
+        info!(
+            "Imported {stored} URLs, {} of {} were already recorded",
+            urls.len() as u64 - stored,
+            urls.len()
+        );
+        Ok(())
+    }
+}
+
+/// Split a list of URLs separated by commas or newlines, making sure each is a valid URL.
+///
+/// A comma only separates URLs when what follows it is a URL too, so that URLs containing commas are kept whole.
+fn parse_urls(contents: &str) -> Result<Vec<String>> {
+    let mut urls: Vec<String> = vec![];
+    for line in contents.lines() {
+        let mut line_urls: Vec<String> = vec![];
+        for segment in line.split(',') {
+            match line_urls.last_mut() {
+                Some(url) if !segment.trim().is_empty() && Url::parse(segment.trim()).is_err() => {
+                    url.push(',');
+                    url.push_str(segment);
+                }
+                _ => line_urls.push(segment.to_string()),
+            }
+        }
+        urls.extend(line_urls);
+    }
+    urls.iter()
+        .map(|url| url.trim())
+        .filter(|url| !url.is_empty())
+        .map(|url| {
+            Url::parse(url).with_context(|| format!("'{url}' is not a valid URL"))?;
+            Ok(url.to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{list_posts::ListPostsCommand, remove_posts::RemovePostsCommand};
+    use super::*;
+
+    const URLS: [&str; 3] = [
+        "https://example.com/news/1",
+        "https://example.com/news/2?page=1,2",
+        "https://example.org/feed/item",
+    ];
+
+    #[test]
+    fn urls_are_split_by_commas_and_newlines() {
+        assert_eq!(
+            parse_urls("https://example.com/a, https://example.com/b\n\nhttps://example.com/c\n")
+                .unwrap(),
+            [
+                "https://example.com/a",
+                "https://example.com/b",
+                "https://example.com/c"
+            ]
+        );
+        assert_eq!(
+            parse_urls("https://example.com/?page=1,2,https://example.com/b").unwrap(),
+            ["https://example.com/?page=1,2", "https://example.com/b"]
+        );
+        assert_eq!(
+            parse_urls("https://example.com/a,\n").unwrap(),
+            ["https://example.com/a"]
+        );
+        let err = parse_urls("https://example.com/a\nnot a url").unwrap_err();
+        assert_eq!(err.to_string(), "'not a url' is not a valid URL");
+    }
+
+    #[tokio::test]
+    async fn exported_posts_can_be_imported_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::new(&GlobalArguments::in_dir(dir.path()).database_url, &[])
+            .await
+            .unwrap();
+        for url in URLS {
+            database
+                .record_posted(url, "test", None, None, PostStatus::Posted)
+                .await
+                .unwrap();
+        }
+
+        let export = dir.path().join("posts.txt");
+        ListPostsCommand::try_parse_from([
+            "list-posts",
+            "--format",
+            "lines",
+            "--output",
+            export.to_str().unwrap(),
+        ])
+        .unwrap()
+        .run(GlobalArguments::in_dir(dir.path()))
+        .await
+        .unwrap();
+        RemovePostsCommand::try_parse_from(["remove-posts", "--match-prefix", "https://"])
+            .unwrap()
+            .run(GlobalArguments::in_dir(dir.path()))
+            .await
+            .unwrap();
+        assert_eq!(database.stats().await.unwrap().posted_urls, 0);
+
+        // A dry run only reports what would be imported.
+        let import = |dry_run| ImportPostsCommand {
+            input: export.clone(),
+            dry_run,
+        };
+        import(true)
+            .run(GlobalArguments::in_dir(dir.path()))
+            .await
+            .unwrap();
+        assert_eq!(database.stats().await.unwrap().posted_urls, 0);
+
+        import(false)
+            .run(GlobalArguments::in_dir(dir.path()))
+            .await
+            .unwrap();
+        for url in URLS {
+            assert!(database.has_posted_url(url).await.unwrap(), "{url}");
+        }
+        // Importing the same URLs again leaves them as they are.
+        import(false)
+            .run(GlobalArguments::in_dir(dir.path()))
+            .await
+            .unwrap();
+        assert_eq!(database.stats().await.unwrap().posted_urls, 3);
+    }
+}
@@ -0,0 +1,35 @@
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::database::Database;
+use anyhow::Result;
+use clap::Parser;
+
+/// List the posts that failed to be published and are queued to be retried or have been given up on.
+#[derive(Debug, Parser)]
+pub struct ListFailedCommand;
+
+impl ExecutableCommand for ListFailedCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        let failed_posts = database.get_failed_posts(None).await?;
+        if failed_posts.is_empty() {
+            println!("No posts have failed to be published");
+            return Ok(());
+        }
+
+        println!(
+            "{:<25}  {:>8}  {:<60}  ERROR",
+            "LAST ATTEMPT", "ATTEMPTS", "URL"
+        );
+        for failed_post in failed_posts {
+            println!(
+                "{:<25}  {:>8}  {:<60}  {}",
+                failed_post.last_attempt_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                failed_post.attempts,
+                failed_post.url,
+                failed_post.error
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::database::Database;
+use anyhow::Result;
+use clap::Parser;
+
+/// List the posts that were being published when the bot stopped and may or may not have been posted.
+#[derive(Debug, Parser)]
+pub struct ListPendingCommand;
+
+impl ExecutableCommand for ListPendingCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        let urls = database.get_pending_urls().await?;
+        if urls.is_empty() {
+            println!("No posts are pending");
+            return Ok(());
+        }
+        for url in urls {
+            println!("{url}");
+        }
+        Ok(())
+    }
+}
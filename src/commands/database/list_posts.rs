@@ -0,0 +1,244 @@
+use crate::commands::args::since_value;
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::database::{Database, PostFilter, PostedUrl};
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::{Parser, ValueEnum};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// Aligned columns for reading in a terminal.
+    Table,
+    /// A JSON array of objects.
+    Json,
+    /// Comma-separated values with a header row.
+    Csv,
+    /// One URL per line, which can be read back with `database import-posts`.
+    Lines,
+}
+
+/// List the URLs that have been posted, most recent first.
+#[derive(Debug, Parser)]
+pub struct ListPostsCommand {
+    /// Only list URLs stored since a point in time, given as a duration such as "24h" or an RFC 3339 timestamp.
+    ///
+    /// URLs stored before their time was recorded count as stored when whimsky was upgraded to record it.
+    #[clap(long = "since", value_parser = since_value)]
+    since: Option<DateTime<Utc>>,
+
+    /// The maximum number of URLs to list.
+    #[clap(long = "limit")]
+    limit: Option<u32>,
+
+    /// Only list URLs containing this text, ignoring case.
+    #[clap(long = "contains")]
+    contains: Option<String>,
+
+    /// Only list URLs from this source, such as "nikki:en", "rss:https://example.com/feed.xml", "manual", or
+    /// "unknown" for URLs stored before sources were tracked.
+    #[clap(long = "source")]
+    source: Option<String>,
+
+    /// Only list the URL of the article with this id in its source, such as "nikki:123".
+    #[clap(long = "source-id")]
+    source_id: Option<String>,
+
+    /// How to print the URLs.
+    #[clap(default_value = "table", long = "format", value_enum)]
+    format: OutputFormat,
+
+    /// Write the URLs to a file instead of stdout, replacing the file if it exists.
+    #[clap(long = "output")]
+    output: Option<PathBuf>,
+}
+
+impl ExecutableCommand for ListPostsCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        let posts = database
+            .query_posts(&PostFilter {
+                since: self.since,
+                contains: self.contains,
+                source: self.source,
+                source_id: self.source_id,
+                limit: self.limit,
+                ..Default::default()
+            })
+            .await?;
+
+        let mut out: Box<dyn Write> =
+            match &self.output {
+                Some(path) => Box::new(BufWriter::new(File::create(path).with_context(|| {
+                    format!("failed to create output file {}", path.display())
+                })?)),
+                None => Box::new(std::io::stdout().lock()),
+            };
+        write_posts(&mut out, self.format, &posts)?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Write posts to an output in the given format.
+fn write_posts(out: &mut dyn Write, format: OutputFormat, posts: &[PostedUrl]) -> Result<()> {
+    match format {
+        OutputFormat::Table => print_table(out, posts)?,
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&posts)?)?,
+        OutputFormat::Csv => {
+            writeln!(out, "url,status,source,source_id,posted_at,record_uri")?;
+            for post in posts {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{}",
+                    csv_field(&post.url),
+                    csv_field(&post.status),
+                    csv_field(&post.source),
+                    csv_field(post.source_id.as_deref().unwrap_or_default()),
+                    post.posted_at
+                        .map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true))
+                        .unwrap_or_default(),
+                    csv_field(post.record_uri.as_deref().unwrap_or_default()),
+                )?;
+            }
+        }
+        OutputFormat::Lines => {
+            for post in posts {
+                writeln!(out, "{}", post.url)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_table(out: &mut dyn Write, posts: &[PostedUrl]) -> Result<()> {
+    if posts.is_empty() {
+        writeln!(out, "No posts match")?;
+        return Ok(());
+    }
+    writeln!(out, "{:<25}  {:<9}  URL", "POSTED AT", "STATUS")?;
+    for post in posts {
+        writeln!(
+            out,
+            "{:<25}  {:<9}  {}",
+            post.posted_at.map_or("unknown".to_string(), |time| time
+                .format("%Y-%m-%d %H:%M:%S UTC")
+                .to_string()),
+            post.status,
+            post.url
+        )?;
+    }
+    Ok(())
+}
+
+/// Quote a CSV field when it contains characters that would otherwise break the row apart.
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_fields_are_only_quoted_when_needed() {
+        assert_eq!(csv_field("https://example.com/a"), "https://example.com/a");
+        assert_eq!(
+            csv_field("https://example.com/?a=1,2"),
+            "\"https://example.com/?a=1,2\""
+        );
+        assert_eq!(csv_field("say \"hi\"\n"), "\"say \"\"hi\"\"\n\"");
+    }
+
+    fn posts() -> Vec<PostedUrl> {
+        vec![
+            PostedUrl {
+                url: "https://example.com/?page=1,2".to_string(),
+                status: "posted".to_string(),
+                source: "nikki:en".to_string(),
+                source_id: Some("42".to_string()),
+                posted_at: DateTime::from_timestamp(1_772_366_400, 0),
+                record_uri: Some("at://did:plc:example/app.bsky.feed.post/a".to_string()),
+            },
+            PostedUrl {
+                url: "https://example.com/b".to_string(),
+                status: "skipped".to_string(),
+                source: "unknown".to_string(),
+                source_id: None,
+                posted_at: None,
+                record_uri: None,
+            },
+        ]
+    }
+
+    fn write(format: OutputFormat, posts: &[PostedUrl]) -> String {
+        let mut out = vec![];
+        write_posts(&mut out, format, posts).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn posts_are_written_as_tables() {
+        assert_eq!(
+            write(OutputFormat::Table, &posts()),
+            format!(
+                "{:<25}  {:<9}  URL\n{:<25}  {:<9}  https://example.com/?page=1,2\n{:<25}  {:<9}  https://example.com/b\n",
+                "POSTED AT", "STATUS", "2026-03-01 12:00:00 UTC", "posted", "unknown", "skipped"
+            )
+        );
+        assert_eq!(write(OutputFormat::Table, &[]), "No posts match\n");
+    }
+
+    #[test]
+    fn posts_are_written_as_json() {
+        let json: serde_json::Value =
+            serde_json::from_str(&write(OutputFormat::Json, &posts())).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {
+                    "url": "https://example.com/?page=1,2",
+                    "status": "posted",
+                    "source": "nikki:en",
+                    "source_id": "42",
+                    "posted_at": "2026-03-01T12:00:00Z",
+                    "record_uri": "at://did:plc:example/app.bsky.feed.post/a",
+                },
+                {
+                    "url": "https://example.com/b",
+                    "status": "skipped",
+                    "source": "unknown",
+                    "source_id": null,
+                    "posted_at": null,
+                    "record_uri": null,
+                },
+            ])
+        );
+        assert_eq!(write(OutputFormat::Json, &[]), "[]\n");
+    }
+
+    #[test]
+    fn posts_are_written_as_csv() {
+        assert_eq!(
+            write(OutputFormat::Csv, &posts()),
+            "url,status,source,source_id,posted_at,record_uri\n\
+            \"https://example.com/?page=1,2\",posted,nikki:en,42,2026-03-01T12:00:00Z,at://did:plc:example/app.bsky.feed.post/a\n\
+            https://example.com/b,skipped,unknown,,,\n"
+        );
+    }
+
+    #[test]
+    fn posts_are_written_as_lines() {
+        assert_eq!(
+            write(OutputFormat::Lines, &posts()),
+            "https://example.com/?page=1,2\nhttps://example.com/b\n"
+        );
+        assert_eq!(write(OutputFormat::Lines, &[]), "");
+    }
+}
@@ -0,0 +1,46 @@
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::database::Database;
+use anyhow::{Result, bail};
+use clap::Parser;
+use tracing::info;
+
+/// Check the database for corruption and reclaim the space left behind by removed posts.
+///
+/// The database isn't vacuumed if it fails the integrity check, and the command exits with an error instead.
+#[derive(Debug, Parser)]
+pub struct MaintenanceCommand {
+    /// Only run if it has been at least a week since maintenance was last run, such as when run from a
+    /// scheduled job.
+    #[clap(long = "auto")]
+    auto: bool,
+}
+
+impl ExecutableCommand for MaintenanceCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        if self.auto && !database.is_maintenance_due().await? {
+            info!("Maintenance was run within the last week: skipping");
+            return Ok(());
+        }
+
+        let report = database.run_maintenance().await?;
+        match &report.integrity_problems {
+            Some(problems) if !problems.is_empty() => {
+                bail!(
+                    "the database failed its integrity check: {}",
+                    problems.join("; ")
+                );
+            }
+            Some(_) => info!("Integrity check passed"),
+            None => info!("Skipped the integrity check as the database doesn't support one"),
+        }
+        if let Some(size_after) = report.size_after {
+            info!(
+                "Vacuumed the database from {} bytes to {size_after} bytes",
+                report.size_before
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+mod backup;
+mod delete_post;
+mod import_posts;
+mod list_failed;
+mod list_pending;
+mod list_posts;
+mod maintenance;
+mod normalise_urls;
+mod promote_collected;
+mod remove_posts;
+mod resolve_pending;
+mod restore;
+mod stats;
+
+use super::{ExecutableCommand, GlobalArguments};
+use anyhow::Result;
+use backup::BackupCommand;
+use clap::{Parser, Subcommand};
+use delete_post::DeletePostCommand;
+use import_posts::ImportPostsCommand;
+use list_failed::ListFailedCommand;
+use list_pending::ListPendingCommand;
+use list_posts::ListPostsCommand;
+use maintenance::MaintenanceCommand;
+use normalise_urls::NormaliseUrlsCommand;
+use promote_collected::PromoteCollectedCommand;
+use remove_posts::RemovePostsCommand;
+use resolve_pending::ResolvePendingCommand;
+use restore::RestoreCommand;
+use stats::StatsCommand;
+
+/// Inspect and manage the data stored in the database.
+#[derive(Debug, Parser)]
+pub struct DatabaseCommandBase {
+    #[clap(subcommand)]
+    command: DatabaseCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum DatabaseCommands {
+    PromoteCollected(PromoteCollectedCommand),
+    ListFailed(ListFailedCommand),
+    ListPending(ListPendingCommand),
+    ListPosts(ListPostsCommand),
+    ResolvePending(ResolvePendingCommand),
+    #[clap(visible_alias = "normalize-urls")]
+    NormaliseUrls(NormaliseUrlsCommand),
+    DeletePost(Box<DeletePostCommand>),
+    ImportPosts(ImportPostsCommand),
+    RemovePosts(RemovePostsCommand),
+    Stats(StatsCommand),
+    Backup(BackupCommand),
+    Restore(RestoreCommand),
+    Maintenance(MaintenanceCommand),
+}
+
+impl ExecutableCommand for DatabaseCommandBase {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        match self.command {
+            DatabaseCommands::PromoteCollected(cmd) => cmd.run(global_args).await,
+            DatabaseCommands::ListFailed(cmd) => cmd.run(global_args).await,
+            DatabaseCommands::ListPending(cmd) => cmd.run(global_args).await,
+            DatabaseCommands::ListPosts(cmd) => cmd.run(global_args).await,
+            DatabaseCommands::ResolvePending(cmd) => cmd.run(global_args).await,
+            DatabaseCommands::NormaliseUrls(cmd) => cmd.run(global_args).await,
+            DatabaseCommands::DeletePost(cmd) => cmd.run(global_args).await,
+            DatabaseCommands::ImportPosts(cmd) => cmd.run(global_args).await,
+            DatabaseCommands::RemovePosts(cmd) => cmd.run(global_args).await,
+            DatabaseCommands::Stats(cmd) => cmd.run(global_args).await,
+            DatabaseCommands::Backup(cmd) => cmd.run(global_args).await,
+            DatabaseCommands::Restore(cmd) => cmd.run(global_args).await,
+            DatabaseCommands::Maintenance(cmd) => cmd.run(global_args).await,
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::database::Database;
+use anyhow::Result;
+use clap::Parser;
+use tracing::info;
+
+/// Rewrite the URLs of posts stored before URLs were normalised so that they match the URLs stored now.
+///
+/// URLs that only differed by something removed when normalising are collapsed into a single entry.
+#[derive(Debug, Parser)]
+pub struct NormaliseUrlsCommand;
+
+impl ExecutableCommand for NormaliseUrlsCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        let (rewritten, collapsed) = database.normalise_stored_urls().await?;
+        info!(
+            "Normalised {rewritten} stored URLs, {collapsed} of which were duplicates and removed"
+        );
+        Ok(())
+    }
+}
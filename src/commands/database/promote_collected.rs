@@ -0,0 +1,21 @@
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::database::Database;
+use anyhow::Result;
+use clap::Parser;
+use tracing::info;
+
+/// Forget every news post recorded without being posted so that it can be posted.
+///
+/// Only posts that are still within the news backdate window will be posted afterwards.
+#[derive(Debug, Parser)]
+pub struct PromoteCollectedCommand;
+
+impl ExecutableCommand for PromoteCollectedCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        let promoted = database.promote_collected().await?;
+        info!("Promoted {promoted} collected posts");
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+use crate::commands::args::duration_value;
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::database::{Database, PostFilter};
+use anyhow::{Context, Result, bail};
+use chrono::{Duration, Utc};
+use clap::Parser;
+use std::io::{IsTerminal, Write};
+use tracing::info;
+
+/// The number of URLs that can be removed by a pattern or age without confirming it.
+const CONFIRMATION_THRESHOLD: usize = 50;
+
+/// Forget posted URLs so that they can be posted again, either by their exact URL or every URL matching a pattern,
+/// age, source or source id.
+#[derive(Debug, Parser)]
+pub struct RemovePostsCommand {
+    /// The URLs to remove.
+    #[clap(
+        required_unless_present_any = ["match_prefix", "match_contains", "older_than", "source", "source_id"],
+        conflicts_with_all = ["match_prefix", "match_contains", "older_than", "source", "source_id"]
+    )]
+    urls: Vec<String>,
+
+    /// Remove every URL starting with this text.
+    #[clap(long = "match-prefix")]
+    match_prefix: Option<String>,
+
+    /// Remove every URL containing this text, ignoring case.
+    #[clap(long = "match-contains")]
+    match_contains: Option<String>,
+
+    /// Remove every URL stored longer ago than this, e.g. "90d" or "12w".
+    #[clap(long = "older-than", value_parser = duration_value)]
+    older_than: Option<Duration>,
+
+    /// Remove every URL from this source, such as "nikki:en" or "rss:https://example.com/feed.xml".
+    #[clap(long = "source")]
+    source: Option<String>,
+
+    /// Remove the URL of the article with this id in its source, such as "nikki:123", which still matches after
+    /// the article's link has changed.
+    #[clap(long = "source-id")]
+    source_id: Option<String>,
+
+    /// Only print the URLs that would be removed without removing anything.
+    #[clap(long = "dry-run")]
+    dry_run: bool,
+
+    /// Remove the URLs without asking for confirmation when more than 50 match.
+    #[clap(long = "yes")]
+    yes: bool,
+}
+
+impl ExecutableCommand for RemovePostsCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+
+        if !self.urls.is_empty() {
+            if self.dry_run {
+                let mut removed = 0;
+                for url in &self.urls {
+                    if database.has_posted_url(url).await? {
+                        info!("Would remove '{url}'");
+                        removed += 1;
+                    }
+                }
+                info!("Would remove {removed} posted URLs");
+                return Ok(());
+            }
+            // Removed together so that stopping part way through doesn't leave only some of them removed.
+            let removed = database.remove_posted_urls(&self.urls).await?;
+            info!(
+                "Removed {removed} posted URLs, {} of {} weren't recorded",
+                self.urls.len() as u64 - removed,
+                self.urls.len()
+            );
+            return Ok(());
+        }
+
+        let before = self
+            .older_than
+            .map(|older_than| Utc::now().checked_sub_signed(older_than))
+            .map(|before| before.context("--older-than is too long"))
+            .transpose()?;
+        let filter = PostFilter {
+            before,
+            contains: self.match_contains,
+            prefix: self.match_prefix,
+            source: self.source,
+            source_id: self.source_id,
+            ..Default::default()
+        };
+        let matching = database.query_posts(&filter).await?;
+        if self.dry_run {
+            for post in &matching {
+                info!("Would remove '{}'", post.url);
+            }
+            info!("Would remove {} posted URLs", matching.len());
+            return Ok(());
+        }
+        if matching.is_empty() {
+            info!("No posted URLs match");
+            return Ok(());
+        }
+        if matching.len() > CONFIRMATION_THRESHOLD && !self.yes && !confirm(matching.len())? {
+            bail!(
+                "{} posted URLs match: pass --yes to remove them, or --dry-run to list them",
+                matching.len()
+            );
+        }
+        let removed = database.remove_posts(&filter).await?;
+        info!("Removed {removed} posted URLs");
+        Ok(())
+    }
+}
+
+/// Ask whether to remove the matching URLs, treating a non-interactive stdin as a refusal.
+fn confirm(count: usize) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    print!("Remove {count} posted URLs? [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::PostStatus;
+
+    /// A database file in a new directory holding the given number of URLs.
+    async fn database_with_urls(count: usize) -> (tempfile::TempDir, Database) {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::new(&GlobalArguments::in_dir(dir.path()).database_url, &[])
+            .await
+            .unwrap();
+        for n in 0..count {
+            database
+                .record_posted(
+                    &format!("https://example.com/news/{n}"),
+                    "test",
+                    None,
+                    None,
+                    PostStatus::Posted,
+                )
+                .await
+                .unwrap();
+        }
+        (dir, database)
+    }
+
+    fn command(args: &[&str]) -> RemovePostsCommand {
+        RemovePostsCommand::try_parse_from(["remove-posts"].iter().chain(args)).unwrap()
+    }
+
+    #[test]
+    fn urls_cant_be_combined_with_matchers() {
+        for args in [
+            &["https://example.com/a", "--match-prefix", "https://"][..],
+            &["https://example.com/a", "--older-than", "90d"],
+            &["https://example.com/a", "--source-id", "nikki:1"],
+            &[],
+        ] {
+            assert!(
+                RemovePostsCommand::try_parse_from(["remove-posts"].iter().chain(args)).is_err(),
+                "{args:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn exact_urls_are_removed() {
+        let (dir, database) = database_with_urls(2).await;
+        command(&["https://example.com/news/0", "https://example.com/missing"])
+            .run(GlobalArguments::in_dir(dir.path()))
+            .await
+            .unwrap();
+        assert!(
+            !database
+                .has_posted_url("https://example.com/news/0")
+                .await
+                .unwrap()
+        );
+        assert_eq!(database.stats().await.unwrap().posted_urls, 1);
+    }
+
+    #[tokio::test]
+    async fn articles_are_removed_by_their_source_id() {
+        let (dir, database) = database_with_urls(1).await;
+        // The article has moved since it was posted, so its current URL wouldn't match.
+        database
+            .record_posted(
+                "https://example.com/old-locale/news/123",
+                "nikki:en",
+                Some("nikki:123"),
+                None,
+                PostStatus::Posted,
+            )
+            .await
+            .unwrap();
+        command(&["--source-id", "nikki:123"])
+            .run(GlobalArguments::in_dir(dir.path()))
+            .await
+            .unwrap();
+        assert!(!database.has_posted_source_id("nikki:123").await.unwrap());
+        assert_eq!(database.stats().await.unwrap().posted_urls, 1);
+    }
+
+    #[tokio::test]
+    async fn dry_runs_remove_nothing() {
+        let (dir, database) = database_with_urls(2).await;
+        command(&["--match-contains", "news", "--dry-run"])
+            .run(GlobalArguments::in_dir(dir.path()))
+            .await
+            .unwrap();
+        assert_eq!(database.stats().await.unwrap().posted_urls, 2);
+    }
+
+    #[tokio::test]
+    async fn removing_many_urls_needs_confirming() {
+        let (dir, database) = database_with_urls(CONFIRMATION_THRESHOLD + 1).await;
+        // Tests don't run with an interactive stdin, which is treated as refusing.
+        let err = command(&["--match-prefix", "https://example.com/"])
+            .run(GlobalArguments::in_dir(dir.path()))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("pass --yes"), "{err}");
+        assert_eq!(
+            database.stats().await.unwrap().posted_urls,
+            CONFIRMATION_THRESHOLD as i64 + 1
+        );
+
+        command(&["--match-prefix", "https://example.com/", "--yes"])
+            .run(GlobalArguments::in_dir(dir.path()))
+            .await
+            .unwrap();
+        assert_eq!(database.stats().await.unwrap().posted_urls, 0);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::database::{Database, PostStatus};
+use anyhow::{Result, bail};
+use clap::{Parser, ValueEnum};
+use tracing::info;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Resolution {
+    /// The post was published, so it should never be posted again.
+    Posted,
+    /// The post wasn't published, so it should be posted by the next run if it is still recent enough.
+    Unposted,
+}
+
+/// Resolve a pending post after checking manually whether it was published.
+#[derive(Debug, Parser)]
+pub struct ResolvePendingCommand {
+    /// The URL of the pending post as shown by `database list-pending`.
+    url: String,
+
+    /// Whether the post was actually published.
+    #[clap(value_enum)]
+    resolution: Resolution,
+}
+
+impl ExecutableCommand for ResolvePendingCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        if !database.get_pending_urls().await?.contains(&self.url) {
+            bail!("'{}' is not pending", self.url);
+        }
+        match self.resolution {
+            Resolution::Posted => {
+                database
+                    .set_posted_url_status(&self.url, PostStatus::Posted)
+                    .await?;
+                info!("Marked '{}' as posted", self.url);
+            }
+            Resolution::Unposted => {
+                database.remove_posted_url(&self.url).await?;
+                info!("Removed '{}' so that it can be posted again", self.url);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://example.com/news/1";
+
+    /// A database file holding a single pending post, alongside the arguments to run commands against it.
+    async fn pending_database() -> (tempfile::TempDir, GlobalArguments) {
+        let dir = tempfile::tempdir().unwrap();
+        let global_args = GlobalArguments::in_dir(dir.path());
+        Database::new(&global_args.database_url, &[])
+            .await
+            .unwrap()
+            .record_posted(URL, "test", None, None, PostStatus::Pending)
+            .await
+            .unwrap();
+        (dir, global_args)
+    }
+
+    fn command(resolution: Resolution) -> ResolvePendingCommand {
+        ResolvePendingCommand {
+            url: URL.to_string(),
+            resolution,
+        }
+    }
+
+    #[tokio::test]
+    async fn pending_posts_resolved_as_posted_are_kept() {
+        let (_dir, global_args) = pending_database().await;
+        let database_url = global_args.database_url.clone();
+        command(Resolution::Posted).run(global_args).await.unwrap();
+
+        let database = Database::new(&database_url, &[]).await.unwrap();
+        assert!(database.get_pending_urls().await.unwrap().is_empty());
+        assert!(database.has_posted_url(URL).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn pending_posts_resolved_as_unposted_are_forgotten() {
+        let (_dir, global_args) = pending_database().await;
+        let database_url = global_args.database_url.clone();
+        command(Resolution::Unposted)
+            .run(global_args)
+            .await
+            .unwrap();
+
+        let database = Database::new(&database_url, &[]).await.unwrap();
+        assert!(!database.has_posted_url(URL).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn only_pending_posts_can_be_resolved() {
+        let (_dir, global_args) = pending_database().await;
+        let mut command = command(Resolution::Posted);
+        command.url = "https://example.com/news/2".to_string();
+        assert!(command.run(global_args).await.is_err());
+    }
+}
@@ -0,0 +1,27 @@
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::database::Database;
+use anyhow::Result;
+use clap::Parser;
+use std::path::PathBuf;
+use tracing::info;
+
+/// Replace everything stored in the sqlite database with a backup made by `database backup`.
+///
+/// The backup is checked for corruption before anything is replaced, and nothing is replaced while another
+/// process such as the bot is writing to the database.
+#[derive(Debug, Parser)]
+pub struct RestoreCommand {
+    /// The backup file to restore.
+    #[clap(long = "input")]
+    input: PathBuf,
+}
+
+impl ExecutableCommand for RestoreCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        database.restore(&self.input).await?;
+        info!("Restored the database from {}", self.input.display());
+        Ok(())
+    }
+}
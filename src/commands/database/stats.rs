@@ -0,0 +1,48 @@
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::database::Database;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use clap::{Parser, ValueEnum};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// Aligned rows for reading in a terminal.
+    Table,
+    /// A JSON object, such as for scraping into monitoring.
+    Json,
+}
+
+/// Show an overview of what is stored in the database.
+#[derive(Debug, Parser)]
+pub struct StatsCommand {
+    /// How to print the stats.
+    #[clap(default_value = "table", long = "format", value_enum)]
+    format: OutputFormat,
+}
+
+impl ExecutableCommand for StatsCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        let stats = database.stats().await?;
+        match self.format {
+            OutputFormat::Table => {
+                let format_time = |time: Option<DateTime<Utc>>| {
+                    time.map_or("never".to_string(), |time| {
+                        time.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+                    })
+                };
+                println!("{:<18}  {}", "POSTED URLS", stats.posted_urls);
+                println!("{:<18}  {}", "OLDEST", format_time(stats.oldest_posted_at));
+                println!("{:<18}  {}", "NEWEST", format_time(stats.newest_posted_at));
+                println!("{:<18}  {}", "ADDED LAST 24H", stats.added_last_24h);
+                println!("{:<18}  {}", "ADDED LAST 7D", stats.added_last_7d);
+                println!("{:<18}  {}", "PENDING", stats.pending);
+                println!("{:<18}  {}", "FAILED", stats.failed);
+                println!("{:<18}  {} bytes", "SIZE", stats.size_bytes);
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+        }
+        Ok(())
+    }
+}
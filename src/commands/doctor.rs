@@ -0,0 +1,394 @@
+use super::args::{
+    BlueskyArgs, base_url_value, list_value, normalise_list, rss_feed_value, url_list,
+    url_list_value,
+};
+use super::{ExecutableCommand, GlobalArguments};
+use crate::bsky::{BlueskyHandler, ReplyControl};
+use crate::database::Database;
+use crate::fetcher::{DEFAULT_NEWS_BASE_URL, NewsApi};
+use crate::http::{self, DEFAULT_HTTP_TIMEOUT_SECONDS};
+use crate::rss::{self, FeedFormat, RssFeed};
+use crate::thumbnail::{ImageOptions, process_thumbnail};
+use anyhow::{Context, Result, bail};
+use clap::{Parser, ValueEnum};
+use reqwest::Url;
+use serde::Serialize;
+use std::{num::NonZeroUsize, path::Path};
+
+/// The file written to the data path to check that it is writable.
+const WRITE_CHECK_FILE_NAME: &str = ".doctor";
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// A line for each check for reading in a terminal.
+    Table,
+    /// A JSON object, such as for checking a deployment from a script.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CheckStatus {
+    Pass,
+    Fail,
+    Skip,
+}
+
+#[derive(Debug, Serialize)]
+struct Check {
+    name: String,
+    status: CheckStatus,
+    /// Whether the bot can't run when this check fails.
+    critical: bool,
+    message: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    ok: bool,
+    checks: Vec<Check>,
+}
+
+/// Check that the bot can run with the given options, printing whether each check passed.
+///
+/// The data path, database, Bluesky account, news API and feeds are checked, along with processing a cover image
+/// from the news. Exits with an error when any check other than the image check fails.
+#[derive(Debug, Parser)]
+pub struct DoctorCommand {
+    #[command(flatten)]
+    bluesky: BlueskyArgs,
+
+    /// How to print the results.
+    #[clap(default_value = "table", long = "format", value_enum)]
+    format: OutputFormat,
+
+    /// Skip checking the Bluesky account, as when running with `start --collect-only`.
+    #[clap(long = "collect-only", env = "WHIMSKY_COLLECT_ONLY")]
+    collect_only: bool,
+
+    /// The time in seconds to wait for a connection or a response before an HTTP request fails.
+    #[clap(
+        default_value_t = DEFAULT_HTTP_TIMEOUT_SECONDS,
+        long = "http-timeout-seconds",
+        env = "WHIMSKY_HTTP_TIMEOUT_SECONDS"
+    )]
+    http_timeout_seconds: u64,
+
+    /// The base URL of the news API, such as a caching proxy in front of it.
+    #[clap(
+        default_value = DEFAULT_NEWS_BASE_URL,
+        long = "news-api-base-url",
+        env = "WHIMSKY_NEWS_API_BASE_URL",
+        value_parser = base_url_value
+    )]
+    news_api_base_url: Url,
+
+    /// A comma-separated list of locales to check the news of.
+    #[clap(
+        default_value = "en",
+        long = "news-locale",
+        env = "WHIMSKY_NEWS_LOCALE",
+        value_delimiter = ',',
+        value_parser = list_value
+    )]
+    news_locale: Vec<String>,
+
+    /// A comma-separated list of URLs of RSS, Atom or JSON feeds to check.
+    ///
+    /// Commas in a feed URL have to be written as "%2C" as they separate the URLs.
+    #[clap(
+        long = "rss-feed-url",
+        env = "WHIMSKY_RSS_FEED_URL",
+        value_delimiter = ',',
+        value_parser = url_list_value
+    )]
+    rss_feed_url: Vec<String>,
+
+    /// An RSS, Atom or JSON feed to check, in the same format as `start --rss-feed`.
+    #[clap(
+        long = "rss-feed",
+        env = "WHIMSKY_RSS_FEEDS",
+        value_delimiter = ';',
+        value_parser = rss_feed_value
+    )]
+    rss_feeds: Vec<RssFeed>,
+}
+
+impl ExecutableCommand for DoctorCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let report = self.report(&global_args).await?;
+        match self.format {
+            OutputFormat::Table => {
+                let name_width = report
+                    .checks
+                    .iter()
+                    .map(|check| check.name.len())
+                    .max()
+                    .unwrap_or_default();
+                for check in &report.checks {
+                    let status = match check.status {
+                        CheckStatus::Pass => "PASS",
+                        CheckStatus::Fail if check.critical => "FAIL",
+                        CheckStatus::Fail => "WARN",
+                        CheckStatus::Skip => "SKIP",
+                    };
+                    println!("{status}  {:<name_width$}  {}", check.name, check.message);
+                }
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        }
+        if !report.ok {
+            bail!("checks that the bot needs to run failed");
+        }
+        Ok(())
+    }
+}
+
+impl DoctorCommand {
+    /// Run every check.
+    async fn report(&self, global_args: &GlobalArguments) -> Result<Report> {
+        let http = http::build_client(std::time::Duration::from_secs(self.http_timeout_seconds))?;
+        let mut checks = vec![];
+        let mut record = |name: String, critical: bool, (status, message)| {
+            checks.push(Check {
+                name,
+                status,
+                critical,
+                message,
+            });
+        };
+
+        record(
+            "data path".to_string(),
+            true,
+            outcome(check_data_path(&global_args.data_path)),
+        );
+        record(
+            "database".to_string(),
+            true,
+            outcome(
+                Database::new(&global_args.database_url, &global_args.stripped_url_params)
+                    .await
+                    .map(|_| "connected and migrations are up to date".to_string()),
+            ),
+        );
+        let result = match self.collect_only {
+            true => (CheckStatus::Skip, "--collect-only is set".to_string()),
+            false => outcome(self.check_bluesky(http.clone(), global_args).await),
+        };
+        record("bluesky".to_string(), true, result);
+
+        let api = NewsApi {
+            base_url: self.news_api_base_url.clone(),
+            article_base_url: self.news_api_base_url.clone(),
+            page_size: NonZeroUsize::new(20).unwrap(),
+        };
+        let mut cover = None;
+        for locale in normalise_list(self.news_locale.clone()) {
+            let result = api.fetch_latest(&http, &locale).await.map(|items| {
+                cover = cover
+                    .take()
+                    .or_else(|| items.iter().find_map(|item| item.cover.clone()));
+                format!("parsed {} news items", items.len())
+            });
+            record(format!("news ({locale})"), true, outcome(result));
+        }
+
+        let feed_urls = url_list(self.rss_feed_url.clone());
+        let feeds = feed_urls
+            .iter()
+            .map(|url| (url, FeedFormat::Auto))
+            .chain(self.rss_feeds.iter().map(|feed| (&feed.url, feed.format)));
+        for (url, format) in feeds {
+            let result = rss::fetch_entries(&http, url, format)
+                .await
+                .map(|entries| format!("parsed {} entries", entries.len()));
+            record(format!("feed ({url})"), true, outcome(result));
+        }
+
+        let result = match cover {
+            Some(cover) => outcome(check_image(&http, &cover).await),
+            None => (
+                CheckStatus::Skip,
+                "no news with a cover image was fetched".to_string(),
+            ),
+        };
+        record("image processing".to_string(), false, result);
+
+        Ok(Report {
+            ok: !checks
+                .iter()
+                .any(|check| check.critical && check.status == CheckStatus::Fail),
+            checks,
+        })
+    }
+
+    /// Log in to Bluesky the same way `start` would, either with the app password or a cached session.
+    async fn check_bluesky(
+        &self,
+        http: reqwest::Client,
+        global_args: &GlobalArguments,
+    ) -> Result<String> {
+        let bsky_handler = BlueskyHandler::new(
+            http,
+            self.bluesky.service.clone(),
+            self.bluesky.identifier.as_deref(),
+            global_args.data_path.clone(),
+            vec![ReplyControl::Everyone],
+            false,
+            ImageOptions::default(),
+            None,
+        )
+        .await?;
+        if self.bluesky.has_password() || !bsky_handler.has_session().await {
+            let (identifier, password) = self.bluesky.credentials()?;
+            bsky_handler
+                .login(
+                    identifier,
+                    password,
+                    self.bluesky.auth_factor_token.as_ref(),
+                )
+                .await?;
+        }
+        let session = bsky_handler
+            .session_info()
+            .await
+            .context("no session was created by logging in")?;
+        Ok(format!(
+            "logged in to {} as {} ({})",
+            session.endpoint, session.handle, session.did
+        ))
+    }
+}
+
+/// Turn the result of a check into its status and the message to print alongside it.
+fn outcome(result: Result<String>) -> (CheckStatus, String) {
+    match result {
+        Ok(message) => (CheckStatus::Pass, message),
+        Err(err) => (CheckStatus::Fail, format!("{err:#}")),
+    }
+}
+
+/// Write and remove a file in the data path.
+fn check_data_path(data_path: &Path) -> Result<String> {
+    let path = data_path.join(WRITE_CHECK_FILE_NAME);
+    std::fs::write(&path, b"")
+        .and_then(|_| std::fs::remove_file(&path))
+        .with_context(|| format!("{} is not writable", data_path.display()))?;
+    Ok(format!("{} is writable", data_path.display()))
+}
+
+/// Download an image and process it the same way as a post's thumbnail.
+async fn check_image(http: &reqwest::Client, url: &Url) -> Result<String> {
+    let bytes = http
+        .get(url.clone())
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let (_, (width, height)) = process_thumbnail(&bytes, &ImageOptions::default())
+        .with_context(|| format!("failed to process {url}"))?;
+    Ok(format!("processed {url} into a {width}x{height} thumbnail"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bsky::tests::spawn_pds;
+    use crate::fetcher::tests::{news_item, spawn_news};
+    use crate::rss::tests::spawn_feed;
+    use chrono::Utc;
+    use std::sync::{Arc, Mutex};
+
+    /// Serve a single news item with the given cover image.
+    async fn news_with_cover(cover: Url) -> Url {
+        let mut item = news_item(1, Utc::now());
+        item["cover"] = serde_json::json!(cover);
+        spawn_news(Arc::new(Mutex::new(vec![item]))).await
+    }
+
+    fn statuses(report: &Report) -> Vec<(&str, CheckStatus)> {
+        report
+            .checks
+            .iter()
+            .map(|check| (check.name.as_str(), check.status))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn deployments_that_can_run_pass_every_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, _) = spawn_pds().await;
+        let (feed, _) = spawn_feed().await;
+        let news = news_with_cover(service.join("cover.png").unwrap()).await;
+        let command = DoctorCommand::try_parse_from([
+            "doctor",
+            "--app-service",
+            service.as_str(),
+            "--app-identifier",
+            "whimsky.test",
+            "--app-password",
+            "password",
+            "--news-api-base-url",
+            news.as_str(),
+            "--rss-feed-url",
+            feed.as_str(),
+        ])
+        .unwrap();
+        let report = command
+            .report(&GlobalArguments::in_dir(dir.path()))
+            .await
+            .unwrap();
+        assert!(report.ok);
+        assert_eq!(
+            statuses(&report),
+            [
+                ("data path", CheckStatus::Pass),
+                ("database", CheckStatus::Pass),
+                ("bluesky", CheckStatus::Pass),
+                ("news (en)", CheckStatus::Pass),
+                (format!("feed ({feed})").as_str(), CheckStatus::Pass),
+                ("image processing", CheckStatus::Pass),
+            ]
+        );
+        assert!(!dir.path().join(WRITE_CHECK_FILE_NAME).exists());
+    }
+
+    #[tokio::test]
+    async fn only_failed_checks_that_the_bot_needs_fail_the_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, _) = spawn_pds().await;
+        let (feed, _) = spawn_feed().await;
+        let news = news_with_cover(service.join("broken.png").unwrap()).await;
+        let command = |feed: &Url| {
+            DoctorCommand::try_parse_from([
+                "doctor",
+                "--collect-only",
+                "--news-api-base-url",
+                news.as_str(),
+                "--rss-feed-url",
+                feed.as_str(),
+            ])
+            .unwrap()
+        };
+
+        let report = command(&feed)
+            .report(&GlobalArguments::in_dir(dir.path()))
+            .await
+            .unwrap();
+        assert!(report.ok);
+        // Posts without a thumbnail are better than no posts, so a broken image only warns.
+        let checks = statuses(&report);
+        assert_eq!(checks[2], ("bluesky", CheckStatus::Skip));
+        assert_eq!(checks[5], ("image processing", CheckStatus::Fail));
+
+        let missing = feed.join("missing.xml").unwrap();
+        let report = command(&missing)
+            .report(&GlobalArguments::in_dir(dir.path()))
+            .await
+            .unwrap();
+        assert!(!report.ok);
+        assert_eq!(statuses(&report)[4].1, CheckStatus::Fail);
+    }
+}
@@ -0,0 +1,45 @@
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::database::Database;
+use anyhow::Result;
+use clap::Parser;
+
+/// Print the most recent attempts at fetching a feed, newest first.
+#[derive(Debug, Parser)]
+pub struct HistoryCommand {
+    /// The URL of the feed as shown by `feeds list`.
+    feed: String,
+
+    /// The maximum number of attempts to print.
+    #[clap(default_value_t = 20, long = "limit")]
+    limit: u32,
+}
+
+impl ExecutableCommand for HistoryCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        let attempts = database
+            .get_fetch_history(&self.feed, self.limit as i64)
+            .await?;
+        if attempts.is_empty() {
+            println!("No fetch attempts have been recorded for {}", self.feed);
+            return Ok(());
+        }
+
+        println!(
+            "{:<25}  {:<8}  {:>5}  {:>8}  ERROR",
+            "FETCHED AT", "RESULT", "ITEMS", "DURATION"
+        );
+        for attempt in attempts {
+            println!(
+                "{:<25}  {:<8}  {:>5}  {:>6}ms  {}",
+                attempt.fetched_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                attempt.error_class.as_deref().unwrap_or("ok"),
+                attempt.item_count,
+                attempt.duration_ms,
+                attempt.error.as_deref().unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use crate::commands::args::duration_value;
+use crate::commands::{ExecutableCommand, GlobalArguments};
+use crate::database::{Database, FeedSummary};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use clap::Parser;
+
+/// List every feed that has been fetched along with the outcome of its most recent attempt.
+#[derive(Debug, Parser)]
+pub struct ListCommand {
+    /// Only list feeds that are failing.
+    #[clap(long = "failing-only")]
+    failing_only: bool,
+
+    /// How long ago the last successful fetch of a feed must be for it to be considered failing, e.g. "6h".
+    #[clap(default_value = "1h", long = "failing-after", value_parser = duration_value)]
+    failing_after: Duration,
+}
+
+impl ExecutableCommand for ListCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        let failing_since = Utc::now()
+            .checked_sub_signed(self.failing_after)
+            .context("--failing-after is too long")?;
+        let feeds = feed_statuses(
+            database.get_feed_summaries().await?,
+            failing_since,
+            self.failing_only,
+        );
+        if feeds.is_empty() {
+            println!("No feeds to list");
+            return Ok(());
+        }
+
+        println!(
+            "{:<7}  {:<25}  {:<25}  {:<8}  FEED",
+            "STATUS", "LAST ATTEMPT", "LAST SUCCESS", "RESULT"
+        );
+        for (feed, failing) in feeds {
+            println!(
+                "{:<7}  {:<25}  {:<25}  {:<8}  {}",
+                match failing {
+                    true => "FAILING",
+                    false => "ok",
+                },
+                feed.last_attempt.format("%Y-%m-%d %H:%M:%S UTC"),
+                feed.last_success.map_or("never".to_string(), |t| t
+                    .format("%Y-%m-%d %H:%M:%S UTC")
+                    .to_string()),
+                feed.last_error_class.as_deref().unwrap_or("ok"),
+                feed.feed
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Pair each feed with whether it is failing, which is when it hasn't been fetched successfully since the given
+/// time, optionally leaving out those that aren't.
+fn feed_statuses(
+    feeds: Vec<FeedSummary>,
+    failing_since: DateTime<Utc>,
+    failing_only: bool,
+) -> Vec<(FeedSummary, bool)> {
+    feeds
+        .into_iter()
+        .map(|feed| {
+            let failing = feed
+                .last_success
+                .is_none_or(|last_success| last_success < failing_since);
+            (feed, failing)
+        })
+        .filter(|(_, failing)| !failing_only || *failing)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::FetchAttempt;
+
+    fn attempt(fetched_at: DateTime<Utc>, error_class: Option<&str>) -> FetchAttempt {
+        FetchAttempt {
+            fetched_at,
+            error_class: error_class.map(str::to_string),
+            error: error_class.map(|class| format!("{class} error")),
+            item_count: 0,
+            duration_ms: 10,
+        }
+    }
+
+    #[tokio::test]
+    async fn only_failing_feeds_are_listed_with_failing_only() {
+        let database = Database::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        for (feed, attempts) in [
+            ("rss:ok", vec![attempt(now, None)]),
+            // Failing since it last succeeded two hours ago.
+            (
+                "rss:failing",
+                vec![
+                    attempt(now - Duration::hours(2), None),
+                    attempt(now, Some("http")),
+                ],
+            ),
+            ("rss:never", vec![attempt(now, Some("timeout"))]),
+            // A single failure right after a success isn't failing yet.
+            (
+                "rss:flaky",
+                vec![
+                    attempt(now - Duration::minutes(10), None),
+                    attempt(now, Some("http")),
+                ],
+            ),
+        ] {
+            for attempt in attempts {
+                database.add_fetch_attempt(feed, &attempt).await.unwrap();
+            }
+        }
+        let database = &database;
+        let statuses = |failing_only| async move {
+            feed_statuses(
+                database.get_feed_summaries().await.unwrap(),
+                now - Duration::hours(1),
+                failing_only,
+            )
+            .into_iter()
+            .map(|(feed, failing)| (feed.feed, failing))
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            statuses(false).await,
+            [
+                ("rss:failing".to_string(), true),
+                ("rss:flaky".to_string(), false),
+                ("rss:never".to_string(), true),
+                ("rss:ok".to_string(), false),
+            ]
+        );
+        assert_eq!(
+            statuses(true).await,
+            [
+                ("rss:failing".to_string(), true),
+                ("rss:never".to_string(), true),
+            ]
+        );
+    }
+}
@@ -0,0 +1,30 @@
+mod history;
+mod list;
+
+use super::{ExecutableCommand, GlobalArguments};
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use history::HistoryCommand;
+use list::ListCommand;
+
+/// Review the outcome of recent attempts at fetching news feeds.
+#[derive(Debug, Parser)]
+pub struct FeedsCommandBase {
+    #[clap(subcommand)]
+    command: FeedsCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum FeedsCommands {
+    List(ListCommand),
+    History(HistoryCommand),
+}
+
+impl ExecutableCommand for FeedsCommandBase {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        match self.command {
+            FeedsCommands::List(cmd) => cmd.run(global_args).await,
+            FeedsCommands::History(cmd) => cmd.run(global_args).await,
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use super::{ExecutableCommand, GlobalArguments};
+use crate::http;
+use anyhow::{Context, Result, bail};
+use clap::Parser;
+use reqwest::Url;
+use std::{path::PathBuf, time::Duration};
+use tracing::info;
+
+/// Check that a running `start` process is healthy, exiting with an error when it isn't.
+///
+/// Checks the healthcheck file written by `start --healthcheck-file` is recent enough, the `/healthz` endpoint
+/// served by `start --listen`, or both, such as for a container healthcheck.
+#[derive(Debug, Parser)]
+pub struct HealthcheckCommand {
+    /// The healthcheck file written by `start`.
+    #[clap(
+        long = "healthcheck-file",
+        env = "WHIMSKY_HEALTHCHECK_FILE",
+        required_unless_present = "url"
+    )]
+    healthcheck_file: Option<PathBuf>,
+
+    /// The number of seconds since the healthcheck file was last written after which the bot is unhealthy.
+    ///
+    /// Should be longer than the time between checks for news.
+    #[clap(
+        default_value_t = 900,
+        long = "max-age-seconds",
+        env = "WHIMSKY_HEALTHCHECK_MAX_AGE_SECONDS"
+    )]
+    max_age_seconds: u64,
+
+    /// The URL of the `/healthz` endpoint, e.g. "http://127.0.0.1:8080/healthz".
+    #[clap(long = "url", env = "WHIMSKY_HEALTHCHECK_URL")]
+    url: Option<Url>,
+
+    /// The time in seconds to wait for the endpoint to respond.
+    #[clap(default_value_t = 5, long = "timeout-seconds")]
+    timeout_seconds: u64,
+}
+
+impl ExecutableCommand for HealthcheckCommand {
+    async fn run(self, _global_args: GlobalArguments) -> Result<()> {
+        if let Some(path) = &self.healthcheck_file {
+            let modified = std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .with_context(|| format!("failed to read healthcheck file {}", path.display()))?;
+            let age = modified.elapsed().unwrap_or_default();
+            if age > Duration::from_secs(self.max_age_seconds) {
+                bail!(
+                    "healthcheck file {} was last written {} seconds ago, more than {} seconds",
+                    path.display(),
+                    age.as_secs(),
+                    self.max_age_seconds
+                );
+            }
+            info!(
+                "Healthcheck file {} was written {} seconds ago",
+                path.display(),
+                age.as_secs()
+            );
+        }
+        if let Some(url) = &self.url {
+            let response = http::build_client(Duration::from_secs(self.timeout_seconds))?
+                .get(url.clone())
+                .send()
+                .await
+                .with_context(|| format!("failed to reach {url}"))?;
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            if !status.is_success() {
+                bail!("{url} responded with {status}: {body}");
+            }
+            info!("{url} responded with {status}: {body}");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{DEFAULT_UNHEALTHY_AFTER_FAILED_CYCLES, SharedRunStatus, tests::spawn};
+    use std::{fs::File, time::SystemTime};
+
+    async fn healthcheck(args: &[&str]) -> Result<()> {
+        HealthcheckCommand::try_parse_from(["healthcheck"].iter().chain(args))
+            .unwrap()
+            .run(GlobalArguments::in_dir(&std::env::temp_dir()))
+            .await
+    }
+
+    #[tokio::test]
+    async fn healthcheck_files_must_have_been_written_recently() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("healthcheck");
+        let args = [
+            "--healthcheck-file",
+            path.to_str().unwrap(),
+            "--max-age-seconds",
+            "60",
+        ];
+        assert!(healthcheck(&args).await.is_err());
+
+        File::create(&path).unwrap();
+        healthcheck(&args).await.unwrap();
+
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(120))
+            .unwrap();
+        let err = healthcheck(&args).await.unwrap_err();
+        assert!(err.to_string().contains("more than 60 seconds"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn healthz_must_respond_successfully() {
+        let status = SharedRunStatus::default();
+        let (url, shutdown) = spawn(status.clone()).await;
+        let url = format!("{url}/healthz");
+        healthcheck(&["--url", &url]).await.unwrap();
+
+        status.write().unwrap().consecutive_failed_cycles = DEFAULT_UNHEALTHY_AFTER_FAILED_CYCLES;
+        let err = healthcheck(&["--url", &url]).await.unwrap_err();
+        assert!(err.to_string().contains("503"), "{err}");
+        shutdown.cancel();
+    }
+}
@@ -1,17 +1,66 @@
+mod args;
+mod auth;
+mod cleanup_bsky;
+mod completions;
+mod config;
+mod database;
+mod doctor;
+mod feeds;
+mod healthcheck;
+mod post;
+mod profile;
 mod start;
 
-use anyhow::{Context, Result};
+use crate::database::Database;
+use crate::dedup::DEFAULT_STRIPPED_URL_PARAMS;
+use crate::logging::{LogFormat, LogLevel};
+use anyhow::{Context, Result, bail};
+pub use args::reject_duplicate_values;
+use args::{list_value, normalise_list};
+use auth::AuthCommandBase;
 use clap::Parser;
+use cleanup_bsky::CleanupBskyCommand;
+use completions::CompletionsCommand;
+use config::ConfigCommandBase;
+use database::DatabaseCommandBase;
+use doctor::DoctorCommand;
+use feeds::FeedsCommandBase;
+use healthcheck::HealthcheckCommand;
+use post::PostCommand;
+use profile::ProfileCommand;
 use start::StartCommand;
 use std::{
-    fs::{create_dir_all, exists},
-    path::PathBuf,
+    fs::{create_dir_all, exists, remove_file},
+    path::{Path, PathBuf},
 };
+use tracing::info;
+
+const DATABASE_FILE_NAME: &str = "db.sqlite3";
+/// Directories inside of the local config directory that older versions stored their database in.
+const LEGACY_DATA_DIRS: [&str; 2] = ["whimsky", "skywrite"];
 
 #[derive(Debug)]
 pub struct GlobalArguments {
     data_path: PathBuf,
+    config_path: Option<PathBuf>,
     database_url: String,
+    stripped_url_params: Vec<String>,
+}
+
+#[cfg(test)]
+impl GlobalArguments {
+    /// The arguments to run a command with against a database file inside of a directory.
+    pub fn in_dir(dir: &Path) -> Self {
+        Self {
+            data_path: dir.to_path_buf(),
+            config_path: None,
+            database_url: format!(
+                "sqlite://{}?mode=rwc",
+                dir.join(DATABASE_FILE_NAME).display()
+            ),
+            stripped_url_params: vec![],
+        }
+    }
 }
 
 pub trait ExecutableCommand {
@@ -34,34 +83,163 @@ pub struct CommandRoot {
     )]
     data_path: PathBuf,
 
+    /// A TOML file to read options from, with keys named after the options of commands such as
+    /// `run_interval_seconds` or `post_languages`, which apply to whichever command takes them.
+    ///
+    /// Options given on the command line or through environment variables take priority over the file.
+    /// Defaults to `config.toml` inside of --data-path when it exists.
+    #[arg(long = "config", env = "WHIMSKY_CONFIG", global = true)]
+    config: Option<PathBuf>,
+
     /// The connection string to use when connecting to the sqlite database.
-    /// Supports some connection parameters.
+    /// Supports some connection parameters, including `journal_mode`, `synchronous`, `foreign_keys` and
+    /// `busy_timeout` (in milliseconds) which default to WAL mode with a 10 second busy timeout.
+    ///
+    /// `sqlite::memory:` uses an empty database that is thrown away on exit, such as for trying out options.
+    ///
+    /// A PostgreSQL database is used instead when given a `postgres://` or `postgresql://` connection string.
+    ///
+    /// Defaults to a database named `db.sqlite3` inside of --data-path.
+    #[arg(long = "database-url", env = "DATABASE_URL", global = true)]
+    database_url: Option<String>,
+
+    /// A comma-separated list of query parameters removed from URLs before they are stored or compared, such as
+    /// tracking parameters. A parameter ending in `*` matches any parameter starting with the rest of it.
     #[arg(
-        long = "database-url",
-        env = "DATABASE_URL",
-        default_value = format!("sqlite://{}?mode=rwc", dirs::config_local_dir().unwrap().join("whimsky").join("db.sqlite3").to_str().unwrap()),
+        default_value = DEFAULT_STRIPPED_URL_PARAMS,
+        long = "url-strip-params",
+        env = "WHIMSKY_URL_STRIP_PARAMS",
+        value_delimiter = ',',
+        value_parser = list_value,
         global = true
     )]
-    database_url: String,
+    stripped_url_params: Vec<String>,
+
+    /// How log lines are written: "text" for reading in a terminal, or "json" for a log aggregator.
+    #[arg(
+        default_value = "text",
+        long = "log-format",
+        env = "WHIMSKY_LOG_FORMAT",
+        value_enum,
+        global = true
+    )]
+    log_format: LogFormat,
+
+    /// The most verbose level of log lines to write. `RUST_LOG` takes priority when it is set.
+    #[arg(
+        default_value = "info",
+        long = "log-level",
+        env = "WHIMSKY_LOG_LEVEL",
+        value_enum,
+        global = true
+    )]
+    log_level: LogLevel,
 }
 
 #[derive(Debug, Parser)]
 enum Commands {
     Start(Box<StartCommand>),
+    Auth(Box<AuthCommandBase>),
+    CleanupBsky(Box<CleanupBskyCommand>),
+    Completions(Box<CompletionsCommand>),
+    Config(Box<ConfigCommandBase>),
+    Database(Box<DatabaseCommandBase>),
+    Doctor(Box<DoctorCommand>),
+    Feeds(Box<FeedsCommandBase>),
+    Healthcheck(Box<HealthcheckCommand>),
+    Post(Box<PostCommand>),
+    Profile(Box<ProfileCommand>),
 }
 
 impl CommandRoot {
+    pub fn log_format(&self) -> LogFormat {
+        self.log_format
+    }
+
+    pub fn log_level(&self) -> LogLevel {
+        self.log_level
+    }
+
     pub async fn run(self) -> Result<()> {
+        if let Commands::Completions(cmd) = self.command {
+            return cmd.run();
+        }
         if !exists(&self.data_path)? {
             create_dir_all(&self.data_path)
                 .context("failed to create directory at provided --data-path")?;
         }
+        let database_url = match self.database_url {
+            Some(database_url) => database_url,
+            None => default_database_url(&self.data_path).await?,
+        };
         let global_args = GlobalArguments {
             data_path: self.data_path,
-            database_url: self.database_url,
+            config_path: self.config,
+            database_url,
+            stripped_url_params: normalise_list(self.stripped_url_params),
         };
         match self.command {
             Commands::Start(cmd) => cmd.run(global_args).await,
+            Commands::Auth(cmd) => cmd.run(global_args).await,
+            Commands::CleanupBsky(cmd) => cmd.run(global_args).await,
+            Commands::Completions(_) => {
+                unreachable!("completions are generated before the data path is set up")
+            }
+            Commands::Config(cmd) => cmd.run(global_args).await,
+            Commands::Database(cmd) => cmd.run(global_args).await,
+            Commands::Doctor(cmd) => cmd.run(global_args).await,
+            Commands::Feeds(cmd) => cmd.run(global_args).await,
+            Commands::Healthcheck(cmd) => cmd.run(global_args).await,
+            Commands::Post(cmd) => cmd.run(global_args).await,
+            Commands::Profile(cmd) => cmd.run(global_args).await,
+        }
+    }
+}
+
+/// Build the URL of the database stored inside of the data path, copying over a database from a location used
+/// by older versions if one exists and the data path doesn't have one yet.
+async fn default_database_url(data_path: &Path) -> Result<String> {
+    let database_path = data_path.join(DATABASE_FILE_NAME);
+    if !exists(&database_path)? {
+        let legacy_path = dirs::config_local_dir()
+            .into_iter()
+            .flat_map(|dir| LEGACY_DATA_DIRS.map(|name| dir.join(name).join(DATABASE_FILE_NAME)))
+            .find(|path| path != &database_path && path.is_file());
+        if let Some(legacy_path) = legacy_path {
+            info!(
+                "Migrating the database used by an older version from {} to {}",
+                legacy_path.display(),
+                database_path.display()
+            );
+            Database::copy_sqlite_file(&legacy_path, &database_path)
+                .await
+                .inspect_err(|_| {
+                    // A partial copy would otherwise be used as the database from then on.
+                    let _ = remove_file(&database_path);
+                })
+                .with_context(|| {
+                    format!(
+                        "failed to copy existing database from {}",
+                        legacy_path.display()
+                    )
+                })?;
+            info!(
+                "Migrated the database: {} is no longer used and can be removed once the bot works",
+                legacy_path.display()
+            );
         }
     }
+
+    let Some(database_path) = database_path.to_str() else {
+        bail!(
+            "--data-path {} is not valid UTF-8 so a database URL can't be derived from it: provide --database-url instead",
+            data_path.display()
+        );
+    };
+    // Characters that have a special meaning in the URL have to be percent-encoded.
+    let database_path = database_path
+        .replace('%', "%25")
+        .replace('?', "%3F")
+        .replace('#', "%23");
+    Ok(format!("sqlite://{database_path}?mode=rwc"))
 }
@@ -0,0 +1,156 @@
+use super::args::{BlueskyArgs, language_value, normalise_list};
+use super::{ExecutableCommand, GlobalArguments};
+use crate::bsky::{BlueskyHandler, EmbedMode, PostData, PostEmbed, ReplyControl};
+use crate::database::{Database, MANUAL_SOURCE, PostStatus};
+use crate::http::{self, DEFAULT_HTTP_TIMEOUT_SECONDS};
+use crate::thumbnail::ImageOptions;
+use anyhow::{Result, bail};
+use chrono::Utc;
+use clap::Parser;
+use reqwest::Url;
+use std::primitive;
+use tracing::info;
+
+/// Publish a one-off post that isn't from any news feed, such as an announcement.
+///
+/// The URI of the created post is printed once it has been published.
+#[derive(Debug, Parser)]
+pub struct PostCommand {
+    #[command(flatten)]
+    bluesky: BlueskyArgs,
+
+    /// The text of the post.
+    #[clap(long = "text")]
+    text: String,
+
+    /// A URL to embed into the post as a link card.
+    #[clap(long = "embed-url")]
+    embed_url: Option<Url>,
+
+    /// The title of the link card. Defaults to the embed URL.
+    #[clap(long = "embed-title", requires = "embed_url")]
+    embed_title: Option<String>,
+
+    /// The description of the link card.
+    #[clap(long = "embed-description", requires = "embed_url")]
+    embed_description: Option<String>,
+
+    /// The URL of an image to use as the link card's thumbnail.
+    #[clap(long = "embed-thumbnail-url", requires = "embed_url")]
+    embed_thumbnail_url: Option<Url>,
+
+    /// A comma-separated list of languages to classify the post under.
+    #[clap(
+        default_value = "en",
+        long = "languages",
+        value_delimiter = ',',
+        value_parser = language_value
+    )]
+    languages: Vec<String>,
+
+    /// Whether the post should have comments disabled.
+    #[clap(
+        default_value_t = true,
+        long = "disable-post-comments",
+        env = "WHIMSKY_DISABLE_POST_COMMENTS"
+    )]
+    disable_post_comments: primitive::bool,
+
+    /// Only print the post that would be made without publishing it.
+    #[clap(long = "dry-run")]
+    dry_run: bool,
+
+    /// Record --embed-url as posted so that news with the same URL is never posted from a feed.
+    #[clap(long = "mark-posted", requires = "embed_url")]
+    mark_posted: bool,
+}
+
+impl ExecutableCommand for PostCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        if self.text.trim().is_empty() {
+            bail!("--text can't be empty");
+        }
+        let post_data = PostData {
+            text: self.text,
+            languages: normalise_list(self.languages),
+            created_at: Utc::now(),
+            labels: vec![],
+            tags: vec![],
+            embed: self.embed_url.as_ref().map(|url| PostEmbed {
+                title: self.embed_title.unwrap_or_else(|| url.to_string()),
+                description: self.embed_description.unwrap_or_default(),
+                uri: url.clone(),
+                thumbnail_url: self.embed_thumbnail_url,
+                alt_text: String::new(),
+                mode: EmbedMode::External,
+            }),
+            mentions: vec![],
+            replies: vec![],
+        };
+        if self.dry_run {
+            info!(
+                "Would post '{}' in {:?}",
+                post_data.text, post_data.languages
+            );
+            if let Some(embed) = &post_data.embed {
+                info!(
+                    "Would embed a link card titled '{}' for '{}'",
+                    embed.title, embed.uri
+                );
+            }
+            return Ok(());
+        }
+
+        // Opened before posting so that a database that can't be opened doesn't leave the post unrecorded.
+        let database = match self.mark_posted {
+            true => Some(
+                Database::new(&global_args.database_url, &global_args.stripped_url_params).await?,
+            ),
+            false => None,
+        };
+        let (identifier, password) = self.bluesky.credentials()?;
+        let bsky_handler = BlueskyHandler::new(
+            http::build_client(std::time::Duration::from_secs(DEFAULT_HTTP_TIMEOUT_SECONDS))?,
+            self.bluesky.service.clone(),
+            Some(identifier),
+            global_args.data_path,
+            match self.disable_post_comments {
+                true => vec![ReplyControl::Nobody],
+                false => vec![ReplyControl::Everyone],
+            },
+            false,
+            ImageOptions::default(),
+            None,
+        )
+        .await?;
+        bsky_handler
+            .login(
+                identifier,
+                password,
+                self.bluesky.auth_factor_token.as_ref(),
+            )
+            .await?;
+        let records = bsky_handler.post(post_data).await?;
+        if let Some(record) = records.first() {
+            println!("{}", record.uri);
+        }
+
+        if let Some(database) = database
+            && let Some(url) = self.embed_url
+        {
+            match database
+                .record_posted(url.as_str(), MANUAL_SOURCE, None, None, PostStatus::Posted)
+                .await?
+            {
+                true => info!("Recorded '{url}' as posted"),
+                false => info!("'{url}' was already recorded"),
+            }
+            if let Some(record) = records.first() {
+                database
+                    .set_posted_record(url.as_str(), &record.uri, &record.cid)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use super::args::BlueskyArgs;
+use super::{ExecutableCommand, GlobalArguments};
+use crate::bsky::{BlueskyHandler, ProfileUpdate, ReplyControl};
+use crate::http::{self, DEFAULT_HTTP_TIMEOUT_SECONDS};
+use crate::thumbnail::ImageOptions;
+use anyhow::{Result, bail};
+use clap::Parser;
+use std::path::PathBuf;
+use tracing::info;
+
+/// Update the bot account's profile and make sure it has a pinned post explaining what it is.
+///
+/// Running this again with the same options changes nothing, and a pinned post is only created when the
+/// account doesn't already have one.
+#[derive(Debug, Parser)]
+pub struct ProfileCommand {
+    #[command(flatten)]
+    bluesky: BlueskyArgs,
+
+    /// The display name to set on the profile.
+    #[clap(long = "profile-display-name", env = "WHIMSKY_PROFILE_DISPLAY_NAME")]
+    display_name: Option<String>,
+
+    /// The description to set on the profile.
+    #[clap(long = "profile-description", env = "WHIMSKY_PROFILE_DESCRIPTION")]
+    description: Option<String>,
+
+    /// The path to an image to set as the profile's avatar.
+    ///
+    /// The image is resized to fit within 1000x1000 and converted to a JPEG before being uploaded.
+    #[clap(long = "profile-avatar-path", env = "WHIMSKY_PROFILE_AVATAR_PATH")]
+    avatar_path: Option<PathBuf>,
+
+    /// The text of a post to create and pin to the profile if it doesn't already have a pinned post.
+    #[clap(long = "pinned-post-text", env = "WHIMSKY_PINNED_POST_TEXT")]
+    pinned_post_text: Option<String>,
+}
+
+impl ExecutableCommand for ProfileCommand {
+    async fn run(self, global_args: GlobalArguments) -> Result<()> {
+        if self.display_name.is_none()
+            && self.description.is_none()
+            && self.avatar_path.is_none()
+            && self.pinned_post_text.is_none()
+        {
+            bail!(
+                "nothing to update: provide at least one of --profile-display-name, --profile-description, --profile-avatar-path or --pinned-post-text"
+            );
+        }
+        let (identifier, password) = self.bluesky.credentials()?;
+        let bsky_handler = BlueskyHandler::new(
+            http::build_client(std::time::Duration::from_secs(DEFAULT_HTTP_TIMEOUT_SECONDS))?,
+            self.bluesky.service.clone(),
+            Some(identifier),
+            global_args.data_path,
+            vec![ReplyControl::Everyone],
+            false,
+            ImageOptions::default(),
+            None,
+        )
+        .await?;
+        bsky_handler
+            .login(
+                identifier,
+                password,
+                self.bluesky.auth_factor_token.as_ref(),
+            )
+            .await?;
+
+        if self.display_name.is_some() || self.description.is_some() || self.avatar_path.is_some() {
+            bsky_handler
+                .update_profile(ProfileUpdate {
+                    display_name: self.display_name,
+                    description: self.description,
+                    avatar_path: self.avatar_path,
+                })
+                .await?;
+        }
+        if let Some(text) = self.pinned_post_text {
+            let uri = bsky_handler.ensure_pinned_post(&text).await?;
+            info!("Pinned post is '{uri}'");
+        }
+        Ok(())
+    }
+}
@@ -1,47 +1,98 @@
+use super::args::{
+    BlueskyArgs, base_url_value, enum_list, enum_list_value, id_value, label_value, language_value,
+    list_value, locale_language_value, mapping_value, mention_value, normalise_list, regex_value,
+    rss_feed_value, tag_value, url_list, url_list_value,
+};
 use super::{ExecutableCommand, GlobalArguments};
-use crate::bsky::{BlueskyHandler, PostData, PostEmbed};
-use crate::database::Database;
-use crate::fetcher::NikkiNewsFetcher;
-use anyhow::Result;
-use chrono::Duration;
+use crate::boilerplate::BoilerplateFilter;
+use crate::bsky::{
+    BlueskyHandler, CreatedRecord, EmbedMode, MAX_POST_GRAPHEMES, PostData, PostEmbed, RateLimited,
+    ReplyControl, is_auth_error,
+};
+use crate::database::{Database, FailedPost, FetchAttempt, PostStatus};
+use crate::fetcher::{self, DEFAULT_NEWS_BASE_URL};
+use crate::http::{self, DEFAULT_HTTP_TIMEOUT_SECONDS};
+use crate::language::detect_language;
+use crate::quiet_hours::{QuietHours, is_within_quiet_hours};
+use crate::refresh::refresh_notify;
+use crate::rss::{FeedFormat, RssFeed};
+use crate::secret::Secret;
+use crate::server::{self, DEFAULT_UNHEALTHY_AFTER_FAILED_CYCLES, RunStatus, SharedRunStatus};
+use crate::shutdown::shutdown_token;
+use crate::source::{self, PostSource, SourceConfig, SourcePost};
+use crate::template::{
+    NoticeTemplate, Placeholder, PostTemplate, PrefixRule, TemplateValues, truncate_graphemes,
+};
+use crate::thumbnail::{
+    DEFAULT_MAX_THUMBNAIL_BYTES, ImageOptions, THUMBNAIL_CACHE_DIR, THUMBNAIL_CACHE_MAX_AGE,
+    ThumbnailCache, ThumbnailFilter, ThumbnailFormat,
+};
+use crate::webhook::{ErrorClass, ErrorNotifier, WebhookFormat};
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
 use clap::Parser;
+use rand::Rng;
+use regex::Regex;
 use reqwest::Url;
-use std::primitive;
+use std::{
+    collections::HashMap,
+    mem,
+    net::SocketAddr,
+    num::{NonZeroU16, NonZeroU32, NonZeroUsize},
+    ops::ControlFlow,
+    path::PathBuf,
+    primitive,
+    sync::RwLock,
+    time::Instant,
+};
+use tokio::net::TcpListener;
 use tokio::time::sleep;
-use tracing::{error, info, warn};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, Span, debug, error, field, info, info_span, warn};
 
 /// Start the bot and begin checking for news posts on an interval.
+///
+/// Credentials aren't needed when a session was cached by `auth login`.
 #[derive(Debug, Parser)]
 pub struct StartCommand {
-    /// The base URL of the service to communicate with.
-    ///
-    /// Note that that you must delete the file at `{data-path}/agentconfig.json` to change this after it has been initially set.
+    #[command(flatten)]
+    bluesky: BlueskyArgs,
+
+    /// The interval of time in seconds between checking for news.
     #[clap(
-        default_value = "https://bsky.social",
-        long = "app-service",
-        env = "WHIMSKY_APP_SERVICE"
+        default_value_t = 300,
+        long = "rerun-interval-seconds",
+        env = "WHIMSKY_RERUN_INTERVAL_SECONDS"
     )]
-    service: Url,
+    run_interval_seconds: u64,
 
-    /// The username or email of the application's account.
+    /// The time in seconds to wait for a connection or a response before an HTTP request fails.
     #[clap(
-        required = true,
-        long = "app-identifier",
-        env = "WHIMSKY_APP_IDENTIFIER"
+        default_value_t = DEFAULT_HTTP_TIMEOUT_SECONDS,
+        long = "http-timeout-seconds",
+        env = "WHIMSKY_HTTP_TIMEOUT_SECONDS"
     )]
-    identifier: String,
+    http_timeout_seconds: u64,
 
-    /// The app password to use for authentication.
-    #[clap(required = true, long = "app-password", env = "WHIMSKY_APP_PASSWORD")]
-    password: String,
+    /// The maximum amount of seconds to randomly add to each interval between checking for news.
+    ///
+    /// Useful for de-synchronizing multiple instances that share the same service.
+    #[clap(
+        default_value_t = 0,
+        long = "rerun-interval-jitter-seconds",
+        env = "WHIMSKY_RERUN_INTERVAL_JITTER_SECONDS"
+    )]
+    run_interval_jitter_seconds: u64,
 
-    /// The interval of time in seconds between checking for news.
+    /// The interval of time in seconds to wait between each post when multiple are made at once, including retries
+    /// of failed posts.
     #[clap(
-        default_value_t = 300,
-        long = "rerun-interval-seconds",
-        env = "WHIMSKY_RERUN_INTERVAL_SECONDS"
+        default_value_t = 0,
+        long = "post-delay-seconds",
+        env = "WHIMSKY_POST_DELAY_SECONDS"
     )]
-    run_interval_seconds: u64,
+    post_delay_seconds: u64,
 
     /// The number of hours in the past the bot should check for news that hasn't been posted.
     ///
@@ -53,7 +104,98 @@ pub struct StartCommand {
     )]
     news_backdate_hours: u16,
 
+    /// The number of days to remember posted URLs for before forgetting them.
+    ///
+    /// URLs are always kept for at least as long as the longest backdate or catch-up window, so that
+    /// forgotten news can't be found and posted again.
+    #[clap(
+        default_value = "180",
+        long = "posted-url-retention-days",
+        env = "WHIMSKY_POSTED_URL_RETENTION_DAYS"
+    )]
+    posted_url_retention_days: NonZeroU16,
+
+    /// The number of news posts to request from the news API at a time.
+    ///
+    /// Further pages are requested until one reaches past the backdate window, up to a limit of 10 pages.
+    #[clap(
+        default_value = "20",
+        long = "news-fetch-limit",
+        env = "WHIMSKY_NEWS_FETCH_LIMIT"
+    )]
+    news_fetch_limit: NonZeroUsize,
+
+    /// The base URL of the news API, such as a caching proxy in front of it.
+    #[clap(
+        default_value = DEFAULT_NEWS_BASE_URL,
+        long = "news-api-base-url",
+        env = "WHIMSKY_NEWS_API_BASE_URL",
+        value_parser = base_url_value
+    )]
+    news_api_base_url: Url,
+
+    /// The base URL that links to news articles are built from, when it differs from --news-api-base-url.
+    #[clap(
+        long = "news-article-base-url",
+        env = "WHIMSKY_NEWS_ARTICLE_BASE_URL",
+        value_parser = base_url_value
+    )]
+    news_article_base_url: Option<Url>,
+
+    /// A comma-separated list of URLs of RSS, Atom or JSON feeds to post entries from alongside news.
+    ///
+    /// Entries are checked within the same backdate window as news. Commas in a feed URL have to be written as
+    /// "%2C" as they separate the URLs.
+    #[clap(
+        long = "rss-feed-url",
+        env = "WHIMSKY_RSS_FEED_URL",
+        value_delimiter = ',',
+        value_parser = url_list_value
+    )]
+    rss_feed_url: Vec<String>,
+
+    /// An RSS, Atom or JSON feed to post entries from with its own options, such as
+    /// "url=https://a/feed.xml,langs=en,backdate-hours=6".
+    ///
+    /// Can be repeated for each feed, or separated with ";" when set through the environment. "langs" can be
+    /// given more than once and overrides --post-languages, while "backdate-hours" overrides --news-backdate-hours.
+    /// "format" is one of "auto", "rss" or "json", where "auto" reads JSON Feeds served as `application/feed+json`.
+    /// Commas and semicolons in a feed URL have to be written as "%2C" and "%3B" as they separate the options
+    /// and feeds.
+    #[clap(
+        long = "rss-feed",
+        env = "WHIMSKY_RSS_FEEDS",
+        value_delimiter = ';',
+        value_parser = rss_feed_value
+    )]
+    rss_feeds: Vec<RssFeed>,
+
+    /// Read the thumbnail and description of feed entries from the Open Graph metadata of the article they
+    /// link to when the entry has no media of its own.
+    #[clap(long = "fetch-og-image", env = "WHIMSKY_FETCH_OG_IMAGE")]
+    fetch_og_image: bool,
+
+    /// Store posts under the canonical URL of the article they link to, so that the same article linked from
+    /// news and feeds under different URLs is only posted once.
+    ///
+    /// Articles are requested once with redirects followed, using the URL they end up on or their
+    /// `<link rel="canonical">`. Resolved URLs are cached in the database for 30 days.
+    #[clap(long = "resolve-canonical", env = "WHIMSKY_RESOLVE_CANONICAL")]
+    resolve_canonical: bool,
+
+    /// The maximum number of hours in the past the bot should catch up on news that was posted while it wasn't running.
+    ///
+    /// This prevents a bot that has been offline for a long time from posting a large amount of outdated news.
+    #[clap(
+        default_value_t = 24,
+        long = "max-catchup-hours",
+        env = "WHIMSKY_MAX_CATCHUP_HOURS"
+    )]
+    max_catchup_hours: u16,
+
     /// Whether Bluesky posts should have comments disabled.
+    ///
+    /// Deprecated: use --reply-control instead, which takes priority when set.
     #[clap(
         default_value_t = true,
         long = "disable-post-comments",
@@ -61,88 +203,2126 @@ pub struct StartCommand {
     )]
     disable_post_comments: primitive::bool,
 
-    /// The locale to use when fetching news posts.
+    /// A comma-separated list of who is allowed to reply to posts.
     ///
-    /// Existing options so far appear to be "en", "kr" and "ja".
+    /// "mentioned", "following" and "followers" can be combined, while "nobody" and "everyone" must be
+    /// used alone. Defaults to "nobody" unless --disable-post-comments is false.
+    #[clap(
+        long = "reply-control",
+        env = "WHIMSKY_REPLY_CONTROL",
+        value_delimiter = ',',
+        value_parser = enum_list_value::<ReplyControl>
+    )]
+    reply_control: Option<Vec<String>>,
+
+    /// Whether Bluesky posts should have quote posts disabled.
+    #[clap(long = "disable-quote-posts", env = "WHIMSKY_DISABLE_QUOTE_POSTS")]
+    disable_quote_posts: bool,
+
+    /// A comma-separated list of locales to fetch news posts for.
+    ///
+    /// Existing options so far appear to be "en", "kr" and "ja". Each locale is checked in turn and posts
+    /// its own news, so the same article in two locales will be posted twice.
     #[clap(
         default_value = "en",
         long = "news-locale",
         env = "WHIMSKY_NEWS_LOCALE",
-        value_delimiter = ','
+        value_delimiter = ',',
+        value_parser = list_value
     )]
-    news_locale: String,
+    news_locale: Vec<String>,
 
-    /// A comma-seperated list of languages in ISO-639-1 format to classify posts under.
+    /// A comma-separated list of languages in ISO-639-1 format to classify posts under.
     /// This should corrolate to the language of the posts the feed is linking to.
+    ///
+    /// Can also be provided by repeating the flag. When not set, news is classified under the language of its
+    /// locale ("en", "ja" or "ko" for "kr") and anything else under "en".
     #[clap(
-        default_value = "en",
         long = "post-languages",
         env = "WHIMSKY_POST_LANGUAGES",
-        value_delimiter = ','
+        value_delimiter = ',',
+        value_parser = language_value
     )]
     post_languages: Vec<String>,
+
+    /// A comma-separated list of `locale=language` pairs overriding --post-languages for news from a locale,
+    /// e.g. "en=en,ja=ja,kr=ko".
+    ///
+    /// A locale can be given more than once to classify its posts under multiple languages.
+    #[clap(
+        long = "locale-languages",
+        visible_alias = "locale-language-map",
+        env = "WHIMSKY_LOCALE_LANGUAGES",
+        value_delimiter = ',',
+        value_parser = locale_language_value
+    )]
+    locale_languages: Vec<(String, String)>,
+
+    /// Detect the language of each post from its title and abstract, falling back to --post-languages and
+    /// --locale-languages when it can't be detected reliably.
+    ///
+    /// Useful for feeds that mix posts in different languages.
+    #[clap(long = "detect-post-language", env = "WHIMSKY_DETECT_POST_LANGUAGE")]
+    detect_post_language: bool,
+
+    /// A comma-separated list of self-labels to apply to every post, such as "spoiler".
+    #[clap(
+        long = "post-self-labels",
+        env = "WHIMSKY_POST_SELF_LABELS",
+        value_delimiter = ',',
+        value_parser = label_value
+    )]
+    post_self_labels: Vec<String>,
+
+    /// A comma-separated list of `label=keyword` pairs applying a self-label only to posts with a title
+    /// containing the keyword, ignoring case, e.g. "spoiler=Chapter".
+    #[clap(
+        long = "label-if-title-matches",
+        env = "WHIMSKY_LABEL_IF_TITLE_MATCHES",
+        value_delimiter = ',',
+        value_parser = mapping_value
+    )]
+    label_if_title_matches: Vec<(String, String)>,
+
+    /// A comma-separated list of rules adding a prefix to the text of matching posts, such as
+    /// "section:3=🎀 ,title~Maintenance=🔧 ".
+    ///
+    /// "section:<id>" matches news from a section and "title~<pattern>" matches titles against a regular
+    /// expression, ignoring case. The prefix is everything after the "=", including any trailing space, and only
+    /// the first matching rule applies.
+    #[clap(
+        long = "post-prefix-rules",
+        env = "WHIMSKY_POST_PREFIX_RULES",
+        value_delimiter = ','
+    )]
+    post_prefix_rules: Vec<PrefixRule>,
+
+    /// The template to build the text of posts from.
+    ///
+    /// Supports the "{title}", "{url}", "{abstract}" and "{publish_date}" placeholders and "\n" for a new line.
+    /// The title and abstract are shortened when a post would be too long.
+    #[clap(
+        default_value = "{title} - {url}",
+        long = "post-text-template",
+        env = "WHIMSKY_POST_TEXT_TEMPLATE"
+    )]
+    post_text_template: PostTemplate,
+
+    /// How news is embedded into posts.
+    #[clap(
+        default_value = "external",
+        long = "embed-mode",
+        env = "WHIMSKY_EMBED_MODE",
+        value_enum
+    )]
+    embed_mode: EmbedMode,
+
+    /// The maximum number of characters of a news abstract to show in the description of a link card.
+    ///
+    /// Longer abstracts are shortened with an ellipsis.
+    #[clap(
+        default_value_t = 300,
+        long = "embed-description-max-chars",
+        env = "WHIMSKY_EMBED_DESCRIPTION_MAX_CHARS"
+    )]
+    embed_description_max_chars: usize,
+
+    /// The maximum width of link card thumbnails. Images are resized to fit while keeping their aspect ratio.
+    #[clap(
+        default_value_t = 960,
+        long = "thumbnail-max-width",
+        env = "WHIMSKY_THUMBNAIL_MAX_WIDTH"
+    )]
+    thumbnail_max_width: u32,
+
+    /// The maximum height of link card thumbnails. Images are resized to fit while keeping their aspect ratio.
+    #[clap(
+        default_value_t = 540,
+        long = "thumbnail-max-height",
+        env = "WHIMSKY_THUMBNAIL_MAX_HEIGHT"
+    )]
+    thumbnail_max_height: u32,
+
+    /// The filter used to resize images before they are uploaded.
+    #[clap(
+        value_enum,
+        default_value_t = ThumbnailFilter::Lanczos3,
+        long = "thumbnail-filter",
+        env = "WHIMSKY_THUMBNAIL_FILTER"
+    )]
+    thumbnail_filter: ThumbnailFilter,
+
+    /// The format images are encoded in before they are uploaded.
+    #[clap(
+        value_enum,
+        default_value_t = ThumbnailFormat::Webp,
+        long = "thumbnail-format",
+        env = "WHIMSKY_THUMBNAIL_FORMAT"
+    )]
+    thumbnail_format: ThumbnailFormat,
+
+    /// The maximum size in bytes of images uploaded to Bluesky.
+    ///
+    /// Images that are larger are re-encoded at a lower quality or smaller size until they fit, or left out of the post if they can't be.
+    #[clap(
+        default_value_t = DEFAULT_MAX_THUMBNAIL_BYTES,
+        long = "max-thumbnail-bytes",
+        env = "WHIMSKY_MAX_THUMBNAIL_BYTES"
+    )]
+    max_thumbnail_bytes: usize,
+
+    /// Don't cache processed images under `{data-path}/thumb-cache`.
+    ///
+    /// Cached images are otherwise reused when a post is retried or an image is shared between locales, and are removed after 7 days.
+    #[clap(long = "no-thumbnail-cache", env = "WHIMSKY_NO_THUMBNAIL_CACHE")]
+    no_thumbnail_cache: bool,
+
+    /// Leave the URL out of the text of posts and rely on the link card to link to the news instead.
+    ///
+    /// Removes "{url}" and any separator before it from --post-text-template. Posts without a link card
+    /// still include the URL.
+    #[clap(long = "omit-url-from-text", env = "WHIMSKY_OMIT_URL_FROM_TEXT")]
+    omit_url_from_text: bool,
+
+    /// Continue the abstract in replies to the post instead of truncating it when the post would be too long.
+    ///
+    /// Only has an effect when --post-text-template includes "{abstract}". The first post keeps the rest of the
+    /// template and the link card, and reply controls and quote post settings only apply to it.
+    #[clap(long = "thread-long-posts", env = "WHIMSKY_THREAD_LONG_POSTS")]
+    thread_long_posts: bool,
+
+    /// A comma-separated list of tags to set on every post, such as "InfinityNikki".
+    #[clap(
+        long = "post-tags",
+        env = "WHIMSKY_POST_TAGS",
+        value_delimiter = ',',
+        value_parser = tag_value
+    )]
+    post_tags: Vec<String>,
+
+    /// Whether --post-tags should also be appended to the text of posts as hashtags.
+    #[clap(long = "append-hashtags", env = "WHIMSKY_APPEND_HASHTAGS")]
+    append_hashtags: bool,
+
+    /// A comma-separated list of handles to mention at the end of every post, such as "example.bsky.social".
+    ///
+    /// A warning is logged for any handle that can't be resolved to an account, which is posted as plain text.
+    #[clap(
+        long = "post-mentions",
+        env = "WHIMSKY_POST_MENTIONS",
+        value_delimiter = ',',
+        value_parser = mention_value
+    )]
+    post_mentions: Vec<String>,
+
+    /// The maximum number of posts to make each time news is checked.
+    ///
+    /// Any remaining posts will be made on following runs instead. Unlimited by default.
+    #[clap(long = "max-posts-per-run", env = "WHIMSKY_MAX_POSTS_PER_RUN")]
+    max_posts_per_run: Option<NonZeroUsize>,
+
+    /// The maximum number of posts to make each UTC day.
+    ///
+    /// Once reached, any remaining posts are deferred until the next day. Unlimited by default.
+    #[clap(long = "max-posts-per-day", env = "WHIMSKY_MAX_POSTS_PER_DAY")]
+    max_posts_per_day: Option<NonZeroUsize>,
+
+    /// The maximum number of attempts to make at publishing a post that keeps failing before giving up on it.
+    ///
+    /// Failed posts are retried at the start of each check for news, and can be listed with
+    /// `database list-failed`.
+    #[clap(
+        default_value = "5",
+        long = "max-post-attempts",
+        env = "WHIMSKY_MAX_POST_ATTEMPTS"
+    )]
+    max_post_attempts: NonZeroU32,
+
+    /// The maximum number of times the same article may ever be posted.
+    ///
+    /// This is a safety net against bugs that could cause an article to be posted repeatedly.
+    #[clap(
+        default_value = "3",
+        long = "max-posts-per-article",
+        env = "WHIMSKY_MAX_POSTS_PER_ARTICLE"
+    )]
+    max_posts_per_article: NonZeroU32,
+
+    /// Whether to skip news posts that share a normalised title and publish date with an already posted article.
+    ///
+    /// This catches articles that have been republished under a new id.
+    #[clap(long = "fingerprint-dedup", env = "WHIMSKY_FINGERPRINT_DEDUP")]
+    fingerprint_dedup: bool,
+
+    /// Whether to post articles again when they are edited after being posted, such as when their title is corrected.
+    ///
+    /// Edited articles are skipped otherwise. Updates count towards --max-posts-per-article.
+    #[clap(long = "post-updates", env = "WHIMSKY_POST_UPDATES")]
+    post_updates: bool,
+
+    /// The prefix added to the title of edited articles when they are posted again.
+    #[clap(
+        default_value = "[Updated] ",
+        long = "update-title-prefix",
+        env = "WHIMSKY_UPDATE_TITLE_PREFIX"
+    )]
+    update_title_prefix: String,
+
+    /// A comma-separated list of numeric news section ids to post news from, skipping every other section.
+    ///
+    /// The section of skipped news is logged at the debug level to help discover which ids are which.
+    #[clap(
+        long = "news-include-sections",
+        env = "WHIMSKY_NEWS_INCLUDE_SECTIONS",
+        value_delimiter = ',',
+        value_parser = id_value,
+        conflicts_with = "news_exclude_sections"
+    )]
+    news_include_sections: Option<Vec<usize>>,
+
+    /// A comma-separated list of numeric news section ids to skip news from.
+    #[clap(
+        long = "news-exclude-sections",
+        env = "WHIMSKY_NEWS_EXCLUDE_SECTIONS",
+        value_delimiter = ',',
+        value_parser = id_value
+    )]
+    news_exclude_sections: Option<Vec<usize>>,
+
+    /// A regular expression that the title or abstract of news must match to be posted.
+    ///
+    /// Also applies to the title or summary of feed entries. Matches case-insensitively unless the expression starts with "(?-i)". Can be repeated, in which case news must match at least one.
+    #[clap(
+        long = "filter-include-regex",
+        env = "WHIMSKY_FILTER_INCLUDE_REGEX",
+        value_parser = regex_value
+    )]
+    filter_include_regex: Vec<Regex>,
+
+    /// A regular expression that skips news when its title or abstract matches, such as "maintenance notice".
+    ///
+    /// Also applies to the title or summary of feed entries. Matches case-insensitively unless the expression starts with "(?-i)". Can be repeated to skip news matching any of them.
+    #[clap(
+        long = "filter-exclude-regex",
+        env = "WHIMSKY_FILTER_EXCLUDE_REGEX",
+        value_parser = regex_value
+    )]
+    filter_exclude_regex: Vec<Regex>,
+
+    /// Record news posts as collected instead of posting them, without ever connecting to Bluesky.
+    ///
+    /// Useful for building up history before the bot's account exists. Collected posts will not be posted
+    /// when the bot is later run normally unless `database promote-collected` is used first.
+    #[clap(long = "collect-only", env = "WHIMSKY_COLLECT_ONLY")]
+    collect_only: bool,
+
+    /// Record the news that is available at startup as skipped without posting it.
+    ///
+    /// Useful when first deploying the bot to an account that already has history, so that only news
+    /// published from now on is posted.
+    #[clap(long = "startup-skip-backfill", env = "WHIMSKY_STARTUP_SKIP_BACKFILL")]
+    startup_skip_backfill: bool,
+
+    /// The number of consecutive times removing old stored posts from the database can fail before it is
+    /// logged as an error instead of a warning.
+    #[clap(
+        default_value = "5",
+        long = "db-cleanup-failure-threshold",
+        env = "WHIMSKY_DB_CLEANUP_FAILURE_THRESHOLD"
+    )]
+    db_cleanup_failure_threshold: NonZeroU32,
+
+    /// Exit with an error once --db-cleanup-failure-threshold is reached instead of continuing.
+    ///
+    /// Useful for letting an orchestrator restart the bot when its database has become unwritable.
+    #[clap(
+        long = "exit-on-persistent-db-errors",
+        env = "WHIMSKY_EXIT_ON_PERSISTENT_DB_ERRORS"
+    )]
+    exit_on_persistent_db_errors: bool,
+
+    /// Check the database for corruption and vacuum it once a week, the same as `database maintenance`.
+    #[clap(long = "weekly-db-maintenance", env = "WHIMSKY_WEEKLY_DB_MAINTENANCE")]
+    weekly_db_maintenance: bool,
+
+    /// The address to serve health, readiness, status and metrics endpoints on, e.g. "0.0.0.0:8080".
+    ///
+    /// Endpoints are not served unless this is set.
+    #[clap(long = "listen", env = "WHIMSKY_LISTEN")]
+    listen: Option<SocketAddr>,
+
+    /// The number of checks for news in a row that can fail to fetch from every source before `/healthz`
+    /// reports the bot as unhealthy.
+    #[clap(
+        default_value_t = DEFAULT_UNHEALTHY_AFTER_FAILED_CYCLES,
+        long = "unhealthy-after-failed-cycles",
+        env = "WHIMSKY_UNHEALTHY_AFTER_FAILED_CYCLES",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    unhealthy_after_failed_cycles: u64,
+
+    /// A file to write the current time to after each check for news that fetched from at least one source or was
+    /// held by --quiet-hours.
+    ///
+    /// Its modification time can be checked by `healthcheck --healthcheck-file` or `find -mmin`, such as for a
+    /// container healthcheck.
+    #[clap(long = "healthcheck-file", env = "WHIMSKY_HEALTHCHECK_FILE")]
+    healthcheck_file: Option<PathBuf>,
+
+    /// A webhook URL to post a message to when the bot is persistently failing.
+    ///
+    /// Messages are sent once --unhealthy-after-failed-cycles checks in a row fail to fetch from every source,
+    /// once --error-webhook-post-failures posts in a row fail to be published, when logging in fails, and when
+    /// --max-posts-per-article refuses a post.
+    #[clap(
+        long = "error-webhook-url",
+        env = "WHIMSKY_ERROR_WEBHOOK_URL",
+        hide_env_values = true
+    )]
+    error_webhook_url: Option<Secret<String>>,
+
+    /// The format of the messages posted to --error-webhook-url.
+    #[clap(
+        default_value = "generic-json",
+        long = "error-webhook-format",
+        env = "WHIMSKY_ERROR_WEBHOOK_FORMAT",
+        value_enum
+    )]
+    error_webhook_format: WebhookFormat,
+
+    /// The number of posts in a row that can fail to be published before a message is posted to
+    /// --error-webhook-url.
+    #[clap(
+        default_value = "3",
+        long = "error-webhook-post-failures",
+        env = "WHIMSKY_ERROR_WEBHOOK_POST_FAILURES"
+    )]
+    error_webhook_post_failures: NonZeroU32,
+
+    /// The minimum number of minutes between messages about the same kind of failure.
+    #[clap(
+        default_value_t = 30,
+        long = "error-webhook-cooldown-minutes",
+        env = "WHIMSKY_ERROR_WEBHOOK_COOLDOWN_MINUTES"
+    )]
+    error_webhook_cooldown_minutes: u64,
+
+    /// Post a notice before catching up on news when the bot starts after not having fetched news for at least
+    /// this many hours.
+    ///
+    /// Nothing is posted on a fresh database, or without this set.
+    #[clap(
+        long = "downtime-notice-threshold-hours",
+        env = "WHIMSKY_DOWNTIME_NOTICE_THRESHOLD_HOURS"
+    )]
+    downtime_notice_threshold_hours: Option<NonZeroU32>,
+
+    /// The template to build the text of the downtime notice from.
+    ///
+    /// Supports the "{hours}" placeholder for how long the bot was offline, "{since}" for when it last fetched
+    /// news and "\n" for a new line.
+    #[clap(
+        default_value = "whimsky was offline for {hours} hours; catching up on missed news now",
+        long = "downtime-notice-template",
+        env = "WHIMSKY_DOWNTIME_NOTICE_TEMPLATE"
+    )]
+    downtime_notice_template: NoticeTemplate,
+
+    /// A daily window of time in the format "HH:MM-HH:MM" during which news will be held instead of posted.
+    ///
+    /// Held news is posted once the window ends. Windows may cross midnight, e.g. "23:00-07:00".
+    #[clap(long = "quiet-hours", env = "WHIMSKY_QUIET_HOURS")]
+    quiet_hours: Option<QuietHours>,
+
+    /// The IANA timezone that --quiet-hours is specified in, e.g. "Europe/London".
+    #[clap(
+        default_value = "UTC",
+        long = "quiet-hours-timezone",
+        env = "WHIMSKY_QUIET_HOURS_TIMEZONE"
+    )]
+    quiet_hours_timezone: Tz,
+
+    /// A file containing phrases that should be treated as an empty news abstract, one per line.
+    ///
+    /// Lines wrapped in slashes are treated as case-insensitive regular expressions. Replaces the built-in list
+    /// of known English, Japanese and Korean boilerplate phrases.
+    #[clap(
+        long = "boilerplate-phrases-file",
+        env = "WHIMSKY_BOILERPLATE_PHRASES_FILE"
+    )]
+    boilerplate_phrases_file: Option<PathBuf>,
 }
 
 impl ExecutableCommand for StartCommand {
-    async fn run(self, global_args: GlobalArguments) -> Result<()> {
-        let database = Database::new(&global_args.database_url).await?;
+    async fn run(mut self, global_args: GlobalArguments) -> Result<()> {
+        let shutdown = shutdown_token();
+        let refresh = refresh_notify();
+        let reply_control = self.reply_control()?;
+        let post_options = self.post_options()?;
+        let database =
+            Database::new(&global_args.database_url, &global_args.stripped_url_params).await?;
+        let http = http::build_client(std::time::Duration::from_secs(self.http_timeout_seconds))?;
+        let error_notifier = self.error_notifier(&http)?;
+        let bsky_handler = match self.collect_only {
+            true => {
+                info!("Running in collect-only mode: news posts will be recorded but not posted");
+                None
+            }
+            false => Some(
+                self.log_in(
+                    http.clone(),
+                    global_args.data_path,
+                    reply_control,
+                    error_notifier.as_ref(),
+                )
+                .await?,
+            ),
+        };
+
+        for url in database.get_pending_urls().await? {
+            warn!(
+                "'{url}' was being posted when the bot last stopped and may or may not have been published: check manually and resolve it with `database resolve-pending`"
+            );
+        }
+        let last_successful_fetch = database.get_last_successful_fetch().await?;
+        if let Some(bsky_handler) = &bsky_handler
+            && let Some(last_successful_fetch) = last_successful_fetch
+        {
+            self.post_downtime_notice(bsky_handler, last_successful_fetch, &post_options.languages)
+                .await;
+        }
+        let rss_feeds = self.rss_feeds();
+        let posted_url_retention = self.posted_url_retention(&rss_feeds);
+        let mut sources = source::build_sources(
+            http.clone(),
+            &database,
+            self.source_config(rss_feeds, last_successful_fetch),
+        )
+        .await?;
+
+        let run_status = SharedRunStatus::new(RwLock::new(RunStatus {
+            unhealthy_after_failed_cycles: self.unhealthy_after_failed_cycles,
+            ..Default::default()
+        }));
+        let server = match self.listen {
+            Some(listen) => {
+                let listener = TcpListener::bind(listen)
+                    .await
+                    .with_context(|| format!("failed to listen on {listen}"))?;
+                Some(tokio::spawn(server::serve(
+                    listener,
+                    run_status.clone(),
+                    shutdown.clone(),
+                )))
+            }
+            None => None,
+        };
+        run_status.write().unwrap().ready = true;
+
+        let mut cleanup_failures = CleanupFailures::new(
+            self.db_cleanup_failure_threshold,
+            self.exit_on_persistent_db_errors,
+        );
+        let runner = Runner {
+            command: &self,
+            database: &database,
+            bsky_handler: bsky_handler.as_ref(),
+            error_notifier: error_notifier.as_ref(),
+            run_status: &run_status,
+            shutdown: &shutdown,
+            post_options,
+            posted_url_retention,
+        };
+        let mut cycle = 0;
+        while !shutdown.is_cancelled() {
+            cycle += 1;
+            let cycle_span = info_span!("cycle", cycle, error = field::Empty);
+            let run_cycle = async {
+                runner
+                    .run_cycle(&mut sources, &mut cleanup_failures)
+                    .await?;
+                let interval_seconds = self.run_interval_seconds
+                    + rand::thread_rng().gen_range(0..=self.run_interval_jitter_seconds);
+                info!("Now waiting for {interval_seconds} seconds before re-running");
+                tokio::select! {
+                    _ = sleep(std::time::Duration::from_secs(interval_seconds)) => {},
+                    _ = refresh.notified() => info!("Manual refresh requested via signal"),
+                    _ = shutdown.cancelled() => {},
+                }
+                Ok::<_, anyhow::Error>(())
+            };
+            if let Err(err) = run_cycle.instrument(cycle_span.clone()).await {
+                cycle_span.record("error", format!("{err:#}"));
+                if let Some(error_notifier) = &error_notifier
+                    && is_auth_error(&err)
+                {
+                    error_notifier
+                        .notify(ErrorClass::Authentication, &format!("{err:#}"))
+                        .await;
+                }
+                return Err(err);
+            }
+        }
+
+        if let Some(bsky_handler) = &bsky_handler {
+            bsky_handler.sync_session().await?;
+        }
+        if let Some(server) = server {
+            server.await??;
+        }
+        info!("Shut down gracefully");
+        Ok(())
+    }
+}
+
+impl StartCommand {
+    /// Choose who can reply to posts from --reply-control, or --disable-post-comments when it isn't given.
+    fn reply_control(&mut self) -> Result<Vec<ReplyControl>> {
+        match self
+            .reply_control
+            .take()
+            .map(enum_list::<ReplyControl>)
+            .filter(|reply_control| !reply_control.is_empty())
+        {
+            Some(reply_control) => {
+                if reply_control.len() > 1
+                    && reply_control
+                        .iter()
+                        .any(|c| matches!(c, ReplyControl::Nobody | ReplyControl::Everyone))
+                {
+                    bail!(
+                        "--reply-control values 'nobody' and 'everyone' can't be combined with others"
+                    );
+                }
+                Ok(reply_control)
+            }
+            None => Ok(match self.disable_post_comments {
+                true => vec![ReplyControl::Nobody],
+                false => vec![ReplyControl::Everyone],
+            }),
+        }
+    }
+
+    /// Validate the options that posts are built from, preparing them once for every post.
+    fn post_options(&mut self) -> Result<PostOptions> {
+        for (label, _) in &self.label_if_title_matches {
+            label_value(label).map_err(|err| anyhow!("invalid --label-if-title-matches: {err}"))?;
+        }
+        self.post_self_labels = normalise_list(mem::take(&mut self.post_self_labels));
+        let mut possible_labels = self.post_self_labels.clone();
+        possible_labels.extend(
+            self.label_if_title_matches
+                .iter()
+                .map(|(label, _)| label.clone()),
+        );
+        possible_labels.sort();
+        possible_labels.dedup();
+        if possible_labels.len() > MAX_SELF_LABELS {
+            bail!("posts can't have more than {MAX_SELF_LABELS} self-labels");
+        }
+        let text_template = match self.omit_url_from_text {
+            true => self.post_text_template.without(Placeholder::Url),
+            false => self.post_text_template.clone(),
+        };
+        let tags = normalise_list(mem::take(&mut self.post_tags));
+        if tags.len() > MAX_POST_TAGS {
+            bail!("posts can't have more than {MAX_POST_TAGS} tags");
+        }
+        let hashtags = match self.append_hashtags {
+            true => tags.clone(),
+            false => Vec::new(),
+        };
+        let mentions = normalise_list(mem::take(&mut self.post_mentions));
+        self.news_locale = normalise_list(mem::take(&mut self.news_locale));
+        let (languages, locale_languages) = locale_post_languages(
+            &self.news_locale,
+            normalise_list(mem::take(&mut self.post_languages)),
+            mem::take(&mut self.locale_languages),
+        )?;
+        for locale in &self.news_locale {
+            debug!(
+                "Classifying news from the '{locale}' locale under {:?}",
+                locale_languages.get(locale).unwrap_or(&languages)
+            );
+        }
+        let boilerplate_filter = match &self.boilerplate_phrases_file {
+            Some(path) => BoilerplateFilter::from_file(path)?,
+            None => BoilerplateFilter::default(),
+        };
+        Ok(PostOptions {
+            text_template,
+            tags,
+            hashtags,
+            mentions,
+            languages,
+            locale_languages,
+            boilerplate_filter,
+        })
+    }
+
+    fn error_notifier(&self, http: &reqwest::Client) -> Result<Option<ErrorNotifier>> {
+        let Some(url) = &self.error_webhook_url else {
+            return Ok(None);
+        };
+        let url = Url::parse(url.expose()).context("invalid --error-webhook-url")?;
+        let bot = self
+            .bluesky
+            .identifier
+            .clone()
+            .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string());
+        Ok(Some(ErrorNotifier::new(
+            http.clone(),
+            url,
+            self.error_webhook_format,
+            bot,
+            Duration::minutes(self.error_webhook_cooldown_minutes as i64),
+        )))
+    }
+
+    /// Log in to Bluesky, or resume the session cached by `auth login` when no password is given.
+    async fn log_in(
+        &self,
+        http: reqwest::Client,
+        data_path: PathBuf,
+        reply_control: Vec<ReplyControl>,
+        error_notifier: Option<&ErrorNotifier>,
+    ) -> Result<BlueskyHandler> {
+        let thumbnail_cache = match self.no_thumbnail_cache {
+            true => None,
+            false => {
+                let cache = ThumbnailCache::new(data_path.join(THUMBNAIL_CACHE_DIR))?;
+                if let Err(err) = cache.remove_older_than(THUMBNAIL_CACHE_MAX_AGE) {
+                    warn!("Failed to remove expired images from the thumbnail cache: {err}");
+                }
+                Some(cache)
+            }
+        };
         let bsky_handler = BlueskyHandler::new(
-            self.service,
-            global_args.data_path,
-            self.disable_post_comments,
+            http,
+            self.bluesky.service.clone(),
+            self.bluesky.identifier.as_deref(),
+            data_path,
+            reply_control,
+            self.disable_quote_posts,
+            ImageOptions {
+                max_width: self.thumbnail_max_width,
+                max_height: self.thumbnail_max_height,
+                filter: self.thumbnail_filter,
+                format: self.thumbnail_format,
+                max_bytes: self.max_thumbnail_bytes,
+            },
+            thumbnail_cache,
         )
         .await?;
-        bsky_handler.login(&self.identifier, &self.password).await?;
+        match self.bluesky.has_password() || !bsky_handler.has_session().await {
+            true => {
+                let (identifier, password) = self.bluesky.credentials()?;
+                if let Err(err) = bsky_handler
+                    .login(
+                        identifier,
+                        password,
+                        self.bluesky.auth_factor_token.as_ref(),
+                    )
+                    .await
+                {
+                    if let Some(error_notifier) = error_notifier {
+                        error_notifier
+                            .notify(ErrorClass::Authentication, &format!("{err:#}"))
+                            .await;
+                    }
+                    return Err(err);
+                }
+            }
+            false => info!("Using the bsky session cached by `auth login`"),
+        }
+        Ok(bsky_handler)
+    }
 
-        let mut news_fetcher = NikkiNewsFetcher::new(
-            self.news_locale,
-            &database,
-            Duration::hours(self.news_backdate_hours as i64),
+    /// Post --downtime-notice-template when news hasn't been fetched for --downtime-notice-threshold-hours.
+    async fn post_downtime_notice(
+        &self,
+        bsky_handler: &BlueskyHandler,
+        last_successful_fetch: DateTime<Utc>,
+        languages: &[String],
+    ) {
+        let Some(threshold_hours) = self.downtime_notice_threshold_hours else {
+            return;
+        };
+        let now = Utc::now();
+        if now - last_successful_fetch < Duration::hours(threshold_hours.get() as i64) {
+            return;
+        }
+        let text = truncate_graphemes(
+            &self
+                .downtime_notice_template
+                .render(last_successful_fetch, now),
+            MAX_POST_GRAPHEMES,
         );
-        loop {
+        info!("Posting a downtime notice: {text}");
+        let post_data = PostData {
+            text,
+            languages: languages.to_vec(),
+            created_at: now,
+            labels: vec![],
+            tags: vec![],
+            embed: None,
+            mentions: vec![],
+            replies: vec![],
+        };
+        // The notice has no URL so it isn't recorded, and news is still worth posting without it.
+        if let Err(err) = post_with_relogin(bsky_handler, &self.bluesky, post_data).await {
+            warn!("Failed to post the downtime notice: {err:#}");
+        }
+    }
+
+    /// Combine the feeds given by --rss-feed-url with the ones given by --rss-feed.
+    fn rss_feeds(&mut self) -> Vec<RssFeed> {
+        url_list(mem::take(&mut self.rss_feed_url))
+            .into_iter()
+            .map(|url| RssFeed {
+                url,
+                format: FeedFormat::Auto,
+                languages: Vec::new(),
+                backdate: None,
+            })
+            .chain(mem::take(&mut self.rss_feeds))
+            .collect()
+    }
+
+    /// How long posted URLs are kept for, which is never shorter than the longest window news is looked for in.
+    fn posted_url_retention(&self, rss_feeds: &[RssFeed]) -> Duration {
+        // Anything newer than the longest window news is looked for in has to be remembered to not be posted again.
+        let lookback = rss_feeds
+            .iter()
+            .filter_map(|feed| feed.backdate)
+            .chain([
+                Duration::hours(self.news_backdate_hours as i64),
+                Duration::hours(self.max_catchup_hours as i64),
+            ])
+            .max()
+            .unwrap_or_default();
+        let retention = Duration::days(self.posted_url_retention_days.get() as i64);
+        if retention < lookback {
+            warn!(
+                "--posted-url-retention-days is shorter than the longest backdate or catch-up window: keeping posted URLs for {} hours instead",
+                lookback.num_hours()
+            );
+            return lookback;
+        }
+        retention
+    }
+
+    fn source_config(
+        &mut self,
+        rss_feeds: Vec<RssFeed>,
+        last_successful_fetch: Option<DateTime<Utc>>,
+    ) -> SourceConfig {
+        SourceConfig {
+            news_locales: mem::take(&mut self.news_locale),
+            news_api_base_url: self.news_api_base_url.clone(),
+            news_article_base_url: self.news_article_base_url.clone(),
+            news_fetch_limit: self.news_fetch_limit,
+            news_include_sections: self.news_include_sections.take(),
+            news_exclude_sections: self.news_exclude_sections.take(),
+            include_regex: mem::take(&mut self.filter_include_regex),
+            exclude_regex: mem::take(&mut self.filter_exclude_regex),
+            rss_feeds,
+            backdate: Duration::hours(self.news_backdate_hours as i64),
+            catch_up: last_successful_fetch.map(|last_successful_fetch| {
+                (
+                    last_successful_fetch,
+                    Duration::hours(self.max_catchup_hours as i64),
+                )
+            }),
+            fingerprint_dedup: self.fingerprint_dedup,
+            resolve_canonical: self.resolve_canonical,
+            update_title_prefix: self.post_updates.then(|| self.update_title_prefix.clone()),
+            fetch_og_image: self.fetch_og_image,
+            skip_backfill: self.startup_skip_backfill,
+        }
+    }
+}
+
+/// The options that posts are built from, prepared once at startup.
+struct PostOptions {
+    text_template: PostTemplate,
+    tags: Vec<String>,
+    hashtags: Vec<String>,
+    mentions: Vec<String>,
+    /// The languages that posts are classified under when their source and locale don't have any.
+    languages: Vec<String>,
+    locale_languages: LocaleLanguages,
+    boilerplate_filter: BoilerplateFilter,
+}
+
+/// Everything the start loop runs with once the bot has been set up.
+struct Runner<'a> {
+    command: &'a StartCommand,
+    database: &'a Database,
+    /// Not set in collect-only mode, where posts are recorded instead of published.
+    bsky_handler: Option<&'a BlueskyHandler>,
+    error_notifier: Option<&'a ErrorNotifier>,
+    run_status: &'a SharedRunStatus,
+    shutdown: &'a CancellationToken,
+    post_options: PostOptions,
+    posted_url_retention: Duration,
+}
+
+/// The posts that can still be made in a cycle.
+struct CyclePosts {
+    /// The number of posts left under --max-posts-per-run and --max-posts-per-day, if either is set.
+    remaining: Option<usize>,
+    /// Set once Bluesky rate limits a post, so that the rest are deferred without hitting the limit again.
+    rate_limited: Option<DateTime<Utc>>,
+    /// Retries and new posts are spaced out by --post-delay-seconds alike.
+    pacer: PostPacer,
+}
+
+impl CyclePosts {
+    fn count_post(&mut self) {
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_sub(1);
+        }
+    }
+}
+
+/// A post on its way to Bluesky, with what is recorded about it alongside its URL.
+struct QueuedPost<'a> {
+    /// The URL that the post is stored under.
+    url: &'a str,
+    source: &'a str,
+    source_id: Option<&'a str>,
+    fingerprint: Option<&'a str>,
+    /// Whether the post is an update to an article that was already posted, so is already recorded.
+    is_update: bool,
+    data: PostData,
+}
+
+/// What happened to a post given to [`Runner::publish_post`].
+enum PublishOutcome {
+    Published,
+    /// Something else recorded the post while it was being prepared, so it was skipped.
+    AlreadyRecorded,
+    /// Bluesky is rate limiting posts, so the post was deferred to the next run without counting as an attempt.
+    Deferred(anyhow::Error),
+    /// Publishing failed and the post was queued to be retried, after this many attempts in total.
+    Failed {
+        attempts: i64,
+        error: anyhow::Error,
+    },
+}
+
+impl Runner<'_> {
+    /// Check for news and post it, then clean up the database and report on how the cycle went.
+    async fn run_cycle(
+        &self,
+        sources: &mut [Box<dyn PostSource + '_>],
+        cleanup_failures: &mut CleanupFailures,
+    ) -> Result<()> {
+        let cycle_started = Utc::now();
+        if let Some(bsky_handler) = self.bsky_handler {
+            // A session that can't be refreshed is logged in again by the first post that it fails.
+            if let Err(err) = bsky_handler.ensure_session_fresh().await {
+                warn!("Failed to refresh the bsky session: {err:?}");
+            }
             bsky_handler.sync_session().await?;
+        }
+        // Skipping the fetch entirely keeps the filter date where it is, so held news is picked up afterwards.
+        // The rest of the cycle still runs so that cleanup, metrics and the healthcheck file stay current.
+        let quiet = match &self.command.quiet_hours {
+            Some(quiet_hours)
+                if is_within_quiet_hours(
+                    Utc::now(),
+                    quiet_hours,
+                    self.command.quiet_hours_timezone,
+                ) =>
+            {
+                info!(
+                    "Within quiet hours of {quiet_hours} ({}): holding news until they end",
+                    self.command.quiet_hours_timezone
+                );
+                true
+            }
+            _ => false,
+        };
+        if !quiet {
+            self.post_news(sources).await?;
+        }
+        cleanup_failures.record(
+            self.database
+                .remove_old_stored_posts(self.posted_url_retention)
+                .await,
+        )?;
+        if self.command.weekly_db_maintenance {
+            run_weekly_maintenance(self.database).await;
+        }
+        let fetched = self.update_run_status(cycle_started, quiet).await;
+        self.notify_failures().await;
+        if fetched
+            && let Some(healthcheck_file) = &self.command.healthcheck_file
+            && let Err(err) = std::fs::write(healthcheck_file, Utc::now().to_rfc3339())
+        {
+            warn!(
+                "Failed to write healthcheck file {}: {err}",
+                healthcheck_file.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Retry posts that failed to be published, then post anything new from each source.
+    async fn post_news(&self, sources: &mut [Box<dyn PostSource + '_>]) -> Result<()> {
+        let mut posts = CyclePosts {
+            remaining: self.remaining_posts().await?,
+            rate_limited: None,
+            pacer: PostPacer::new(std::time::Duration::from_secs(
+                self.command.post_delay_seconds,
+            )),
+        };
+        if let Some(bsky_handler) = self.bsky_handler {
+            self.retry_failed_posts(bsky_handler, &mut posts).await?;
+        }
+        let mut fetch_summary = Vec::with_capacity(sources.len());
+        for source in sources.iter_mut() {
+            if self.shutdown.is_cancelled() {
+                break;
+            }
+            let Some(new_posts) = self
+                .fetch(source.as_mut(), posts.remaining, &mut fetch_summary)
+                .await
+            else {
+                continue;
+            };
+            let source_name = source.name();
+            for post in new_posts {
+                posts.pacer.wait(self.shutdown).await;
+                if self.shutdown.is_cancelled() {
+                    info!("Skipping remaining posts as the bot is shutting down");
+                    break;
+                }
+                let post_span = info_span!(
+                    "post",
+                    source = %source_name,
+                    url = %post.url,
+                    title = %post.title,
+                    error = field::Empty
+                );
+                self.post_from_source(source.as_ref(), post, &mut posts)
+                    .instrument(post_span.clone())
+                    .await
+                    .inspect_err(|err| {
+                        post_span.record("error", format!("{err:#}"));
+                    })?;
+            }
+            let mut run_status = self.run_status.write().unwrap();
+            run_status.last_success = Some(Utc::now());
+            run_status.consecutive_failures = 0;
+        }
+        if !fetch_summary.is_empty() {
+            info!("Finished checking for news: {}", fetch_summary.join(", "));
+        }
+        // Posts skipped by a shutdown haven't been processed, so don't record them as such.
+        // Fetchers that failed keep their previous time, so the earliest one is safe to catch up from.
+        if !self.shutdown.is_cancelled()
+            && let Some(processed_until) =
+                sources.iter().map(|source| source.processed_until()).min()
+        {
+            self.database
+                .set_last_successful_fetch(processed_until)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// The number of posts that can be made this cycle under --max-posts-per-run and --max-posts-per-day.
+    async fn remaining_posts(&self) -> Result<Option<usize>> {
+        let remaining_posts = self.command.max_posts_per_run.map(NonZeroUsize::get);
+        let Some(max_posts_per_day) = self.command.max_posts_per_day else {
+            return Ok(remaining_posts);
+        };
+        let posted_today = self
+            .database
+            .get_daily_post_count(Utc::now().date_naive())
+            .await?;
+        let remaining_today = max_posts_per_day
+            .get()
+            .saturating_sub(posted_today as usize);
+        if remaining_today == 0 {
             info!(
-                "Checking for unposted entries for news url {}",
-                news_fetcher.get_news_url()
+                "Daily post cap of {max_posts_per_day} has been reached: deferring news until tomorrow (UTC)"
             );
+        }
+        Ok(Some(
+            remaining_posts.map_or(remaining_today, |remaining_posts| {
+                remaining_posts.min(remaining_today)
+            }),
+        ))
+    }
 
-            match news_fetcher.fetch_unposted().await {
-                Ok(posts) => {
-                    for post in posts {
-                        info!("Running for post '{}'", post.url);
-
-                        let post_data = {
-                            PostData {
-                                created_at: post.publish_time,
-                                text: format!("{} - {}", post.title, post.url),
-                                languages: self.post_languages.clone(),
-                                embed: Some(PostEmbed {
-                                    title: post.title,
-                                    description: post.r#abstract,
-                                    thumbnail_url: Some(post.cover),
-                                    uri: post.url.clone(),
-                                }),
-                            }
-                        };
-                        bsky_handler.post(post_data).await?;
-                        database.add_posted_url(post.url.as_str()).await?;
-                    }
-                    if let Err(err) = database.remove_old_stored_posts().await {
-                        warn!("Failed to run query to remove old stored posts {err}");
-                    }
+    /// Retry the posts that previously failed to be published, oldest first.
+    async fn retry_failed_posts(
+        &self,
+        bsky_handler: &BlueskyHandler,
+        posts: &mut CyclePosts,
+    ) -> Result<()> {
+        for failed_post in self
+            .database
+            .get_failed_posts(Some(self.command.max_post_attempts.get() as i64))
+            .await?
+        {
+            posts.pacer.wait(self.shutdown).await;
+            if self.shutdown.is_cancelled() || posts.remaining == Some(0) {
+                break;
+            }
+            let retry_span = info_span!(
+                "post",
+                source = %failed_post.source,
+                url = %failed_post.url,
+                attempt = failed_post.attempts + 1,
+                error = field::Empty
+            );
+            let flow = self
+                .retry_failed_post(bsky_handler, &failed_post, posts)
+                .instrument(retry_span.clone())
+                .await
+                .inspect_err(|err| {
+                    retry_span.record("error", format!("{err:#}"));
+                })?;
+            if flow.is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Retry a post that failed to be published, breaking once Bluesky starts rate limiting posts.
+    async fn retry_failed_post(
+        &self,
+        bsky_handler: &BlueskyHandler,
+        failed_post: &FailedPost,
+        posts: &mut CyclePosts,
+    ) -> Result<ControlFlow<()>> {
+        info!(
+            "Retrying failed post '{}' (attempt {})",
+            failed_post.url,
+            failed_post.attempts + 1
+        );
+        if self
+            .refuse_capped_post(&failed_post.url, |post_count| {
+                format!(
+                    "Refusing to retry '{}' as it has already been posted {post_count} times: removing it from the retry queue, see --max-posts-per-article",
+                    failed_post.url
+                )
+            })
+            .await?
+        {
+            // Recorded so that it isn't fetched and refused again, unless it already is.
+            self.database
+                .record_posted(
+                    &failed_post.url,
+                    &failed_post.source,
+                    failed_post.source_id.as_deref(),
+                    failed_post.fingerprint.as_deref(),
+                    PostStatus::Skipped,
+                )
+                .await?;
+            self.database.remove_failed_post(&failed_post.url).await?;
+            return Ok(ControlFlow::Continue(()));
+        }
+        let data: PostData = serde_json::from_str(&failed_post.payload)
+            .with_context(|| format!("failed to read queued post for {}", failed_post.url))?;
+        let post = QueuedPost {
+            url: &failed_post.url,
+            source: &failed_post.source,
+            source_id: failed_post.source_id.as_deref(),
+            fingerprint: failed_post.fingerprint.as_deref(),
+            // Updates to edited articles are already recorded from when they were first posted.
+            is_update: self.database.has_posted_url(&failed_post.url).await?,
+            data,
+        };
+        match self.publish_post(bsky_handler, &post, posts).await? {
+            PublishOutcome::Published => {}
+            PublishOutcome::AlreadyRecorded => {
+                self.database.remove_failed_post(&failed_post.url).await?;
+            }
+            PublishOutcome::Deferred(err) => {
+                warn!("Deferring retries of failed posts until the next run: {err:#}");
+                return Ok(ControlFlow::Break(()));
+            }
+            PublishOutcome::Failed { attempts, error } => {
+                match attempts >= self.command.max_post_attempts.get() as i64 {
+                    true => error!(
+                        "Giving up on posting '{}' after {attempts} attempts: {error:#}",
+                        failed_post.url
+                    ),
+                    false => warn!("Failed to post '{}' again: {error:#}", failed_post.url),
                 }
-                _ => {
-                    error!(
-                        "Failed to fetch news from {}: skipping for this iteration",
-                        news_fetcher.get_news_url()
-                    );
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Fetch the unposted entries of a source, recording the attempt in its fetch history and the metrics.
+    async fn fetch(
+        &self,
+        source: &mut dyn PostSource,
+        limit: Option<usize>,
+        fetch_summary: &mut Vec<String>,
+    ) -> Option<Vec<SourcePost>> {
+        info!("Checking for unposted entries from {}", source.describe());
+        let source_name = source.name();
+        let fetched_at = Utc::now();
+        let fetch_started = Instant::now();
+        let result = source
+            .fetch_unposted(limit)
+            .instrument(info_span!("fetch", source = %source_name))
+            .await;
+        let attempt = FetchAttempt {
+            fetched_at,
+            error_class: result
+                .as_ref()
+                .err()
+                .map(|err| source.error_class(err).to_string()),
+            error: result.as_ref().err().map(|err| format!("{err:#}")),
+            item_count: result.as_ref().map_or(0, |posts| posts.len() as i64),
+            duration_ms: fetch_started.elapsed().as_millis() as i64,
+        };
+        if let Err(err) = self
+            .database
+            .add_fetch_attempt(source.describe().as_str(), &attempt)
+            .await
+        {
+            warn!("Failed to record fetch attempt in fetch history: {err}");
+        }
+
+        match result {
+            Ok(posts) => {
+                fetch_summary.push(format!("{} new from {}", posts.len(), source.describe()));
+                Some(posts)
+            }
+            Err(err) => {
+                fetch_summary.push(format!("failed to fetch {}", source.describe()));
+                error!(
+                    "Failed to fetch news from {}: skipping for this iteration",
+                    source.describe()
+                );
+                self.run_status
+                    .write()
+                    .unwrap()
+                    .record_fetch_failure(&source_name, format!("{err:#}"));
+                None
+            }
+        }
+    }
+
+    /// Post news fetched from a source, or record it as collected in collect-only mode.
+    async fn post_from_source(
+        &self,
+        source: &dyn PostSource,
+        post: SourcePost,
+        posts: &mut CyclePosts,
+    ) -> Result<()> {
+        info!("Running for post '{}'", post.url);
+        let source_name = source.name();
+        if self.bsky_handler.is_some()
+            && self
+                .refuse_capped_post(post.key.as_str(), |post_count| {
+                    format!(
+                        "Refusing to post '{}' as it has already been posted {post_count} times: see --max-posts-per-article",
+                        post.url
+                    )
+                })
+                .await?
+        {
+            // Recorded so that it isn't fetched and refused again by every iteration.
+            match post.is_update {
+                true => self.database.apply_pending_hash(post.key.as_str()).await?,
+                false => {
+                    self.database
+                        .record_posted(
+                            post.key.as_str(),
+                            &source_name,
+                            post.source_id.as_deref(),
+                            Some(&post.fingerprint),
+                            PostStatus::Skipped,
+                        )
+                        .await?;
                 }
-            };
+            }
+            return Ok(());
+        }
+
+        let data = self.build_post(source, &post);
+        let Some(bsky_handler) = self.bsky_handler else {
+            info!("Collected post that would have been published: {data:?}");
+            match post.is_update {
+                true => self.database.apply_pending_hash(post.key.as_str()).await?,
+                false => {
+                    self.database
+                        .record_posted(
+                            post.key.as_str(),
+                            &source_name,
+                            post.source_id.as_deref(),
+                            Some(&post.fingerprint),
+                            PostStatus::Collected,
+                        )
+                        .await?;
+                }
+            }
+            self.run_status.write().unwrap().record_post();
+            posts.count_post();
+            return Ok(());
+        };
+        let queued = QueuedPost {
+            url: post.key.as_str(),
+            source: &source_name,
+            source_id: post.source_id.as_deref(),
+            fingerprint: Some(&post.fingerprint),
+            is_update: post.is_update,
+            data,
+        };
+        if let Some(reset) = posts.rate_limited {
             info!(
-                "Now waiting for {} seconds before re-running",
-                self.run_interval_seconds
+                "Deferring '{}' until the next run as bluesky is rate limiting posts until {reset}",
+                post.url
             );
-            sleep(std::time::Duration::from_secs(self.run_interval_seconds)).await;
+            self.database
+                .defer_post(
+                    queued.url,
+                    queued.source,
+                    queued.source_id,
+                    queued.fingerprint,
+                    &serde_json::to_string(&queued.data)?,
+                    &format!("rate limited by bluesky until {reset}"),
+                )
+                .await?;
+            return Ok(());
+        }
+        match self.publish_post(bsky_handler, &queued, posts).await? {
+            PublishOutcome::Published | PublishOutcome::AlreadyRecorded => {}
+            PublishOutcome::Deferred(err) => {
+                warn!("Deferring '{}' until the next run: {err:#}", post.url);
+            }
+            PublishOutcome::Failed { error, .. } => {
+                error!(
+                    "Failed to post '{}': queueing it to be retried: {error:#}",
+                    post.url
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the post for news fetched from a source.
+    fn build_post(&self, source: &dyn PostSource, post: &SourcePost) -> PostData {
+        let options = &self.post_options;
+        let description = match options.boilerplate_filter.is_boilerplate(&post.summary) {
+            true => String::new(),
+            false => post.summary.clone(),
+        };
+        let alt_text = match description.is_empty() {
+            true => post.title.clone(),
+            false => format!("{}\n\n{description}", post.title),
+        };
+        let detected_language = match self.command.detect_post_language {
+            true => detect_language(&format!("{}\n{description}", post.title)),
+            false => None,
+        };
+        let languages = match detected_language {
+            Some(language) => vec![language.to_string()],
+            None => source
+                .languages()
+                .or_else(|| {
+                    options
+                        .locale_languages
+                        .get(source.locale().unwrap_or_default())
+                        .map(Vec::as_slice)
+                })
+                .unwrap_or(&options.languages)
+                .to_vec(),
+        };
+        let values = TemplateValues {
+            title: &post.title,
+            url: post.url.as_str(),
+            r#abstract: &description,
+            publish_time: post.published,
+        };
+        let prefix = post_prefix(&self.command.post_prefix_rules, post.section, &post.title);
+        let has_link_card = self.command.embed_mode == EmbedMode::External;
+        let (text, replies) = match self.command.thread_long_posts {
+            true => PostData::build_thread(
+                &options.text_template,
+                &values,
+                prefix,
+                &options.mentions,
+                &options.hashtags,
+                has_link_card,
+            ),
+            false => (
+                PostData::build_text(
+                    &options.text_template,
+                    &values,
+                    prefix,
+                    &options.mentions,
+                    &options.hashtags,
+                    has_link_card,
+                ),
+                vec![],
+            ),
+        };
+        PostData {
+            created_at: post.published,
+            labels: post_labels(
+                &self.command.post_self_labels,
+                &self.command.label_if_title_matches,
+                &post.title,
+            ),
+            text,
+            mentions: options.mentions.clone(),
+            replies,
+            tags: options.tags.clone(),
+            languages,
+            embed: Some(PostEmbed {
+                title: post.title.clone(),
+                description: truncate_graphemes(
+                    &description,
+                    self.command.embed_description_max_chars,
+                ),
+                alt_text,
+                thumbnail_url: post.thumbnail.clone(),
+                uri: post.url.clone(),
+                mode: self.command.embed_mode,
+            }),
+        }
+    }
+
+    /// Whether an article has already been posted --max-posts-per-article times, reporting the post as refused with
+    /// the message built from its post count if it has.
+    async fn refuse_capped_post(
+        &self,
+        url: &str,
+        message: impl FnOnce(i64) -> String,
+    ) -> Result<bool> {
+        let post_count = self.database.get_article_post_count(url).await?;
+        if post_count < self.command.max_posts_per_article.get() as i64 {
+            return Ok(false);
         }
+        let message = message(post_count);
+        error!("{message}");
+        self.run_status.write().unwrap().record_capped_post();
+        if let Some(error_notifier) = self.error_notifier {
+            error_notifier
+                .notify(ErrorClass::CappedPosts, &message)
+                .await;
+        }
+        Ok(true)
+    }
+
+    /// Publish a post, recording it as pending first so that a crash while posting can't cause it to be posted
+    /// twice.
+    ///
+    /// Posts that fail are queued to be retried, and ones refused by a rate limit are deferred. Authentication
+    /// failures are returned as errors, as nothing else can be posted either.
+    async fn publish_post(
+        &self,
+        bsky_handler: &BlueskyHandler,
+        post: &QueuedPost<'_>,
+        posts: &mut CyclePosts,
+    ) -> Result<PublishOutcome> {
+        match post.is_update {
+            true => {
+                self.database
+                    .set_posted_url_status(post.url, PostStatus::Pending)
+                    .await?;
+            }
+            false => {
+                if !self
+                    .database
+                    .record_posted(
+                        post.url,
+                        post.source,
+                        post.source_id,
+                        post.fingerprint,
+                        PostStatus::Pending,
+                    )
+                    .await?
+                {
+                    warn!(
+                        "Skipping '{}' as it was recorded by something else while it was being prepared",
+                        post.url
+                    );
+                    return Ok(PublishOutcome::AlreadyRecorded);
+                }
+            }
+        }
+        let err =
+            match post_with_relogin(bsky_handler, &self.command.bluesky, post.data.clone()).await {
+                Ok(records) => {
+                    self.database.remove_failed_post(post.url).await?;
+                    record_published(
+                        self.database,
+                        post.url,
+                        records.first(),
+                        self.command.max_posts_per_day,
+                    )
+                    .await?;
+                    self.run_status.write().unwrap().record_post();
+                    posts.count_post();
+                    return Ok(PublishOutcome::Published);
+                }
+                Err(err) => err,
+            };
+        self.database
+            .abandon_pending(post.url, post.is_update)
+            .await?;
+        if is_auth_error(&err) {
+            return Err(err);
+        }
+        Span::current().record("error", format!("{err:#}"));
+        let payload = serde_json::to_string(&post.data)?;
+        if let Some(limit) = err.downcast_ref::<RateLimited>() {
+            posts.rate_limited = Some(limit.reset);
+            self.database
+                .defer_post(
+                    post.url,
+                    post.source,
+                    post.source_id,
+                    post.fingerprint,
+                    &payload,
+                    &format!("{err:#}"),
+                )
+                .await?;
+            return Ok(PublishOutcome::Deferred(err));
+        }
+        self.run_status
+            .write()
+            .unwrap()
+            .record_post_failure(format!("{err:#}"));
+        let attempts = self
+            .database
+            .add_failed_post(
+                post.url,
+                post.source,
+                post.source_id,
+                post.fingerprint,
+                &payload,
+                &format!("{err:#}"),
+            )
+            .await?;
+        Ok(PublishOutcome::Failed {
+            attempts,
+            error: err,
+        })
+    }
+
+    /// Update the metrics at the end of a cycle, returning whether anything was fetched or held by quiet hours.
+    async fn update_run_status(&self, cycle_started: DateTime<Utc>, quiet: bool) -> bool {
+        let posted_urls = match self.database.stats().await {
+            Ok(stats) => Some(stats.posted_urls),
+            Err(err) => {
+                warn!("Failed to count the posted URLs for the metrics: {err:#}");
+                None
+            }
+        };
+        let mut run_status = self.run_status.write().unwrap();
+        run_status.cycles += 1;
+        run_status.uploaded_blob_bytes = self
+            .bsky_handler
+            .map_or(0, |bsky_handler| bsky_handler.uploaded_blob_bytes());
+        run_status.posted_urls = posted_urls.or(run_status.posted_urls);
+        // Holding news during quiet hours is expected, so it doesn't count as a failed cycle.
+        let fetched = quiet
+            || run_status
+                .last_success
+                .is_some_and(|last_success| last_success >= cycle_started);
+        match fetched {
+            true => run_status.consecutive_failed_cycles = 0,
+            false => run_status.consecutive_failed_cycles += 1,
+        }
+        fetched
+    }
+
+    /// Post to --error-webhook-url once fetching or posting has been failing for too long.
+    async fn notify_failures(&self) {
+        let Some(error_notifier) = self.error_notifier else {
+            return;
+        };
+        let (failed_cycles, post_failures, last_error) = {
+            let run_status = self.run_status.read().unwrap();
+            let failed_cycles =
+                (!run_status.is_healthy()).then_some(run_status.consecutive_failed_cycles);
+            let post_failures = (run_status.consecutive_post_failures
+                >= self.command.error_webhook_post_failures.get() as u64)
+                .then_some(run_status.consecutive_post_failures);
+            (failed_cycles, post_failures, run_status.last_error.clone())
+        };
+        let last_error = last_error.unwrap_or_default();
+        if let Some(failed_cycles) = failed_cycles {
+            error_notifier
+                .notify(
+                    ErrorClass::FetchFailures,
+                    &format!(
+                        "Nothing could be fetched for {failed_cycles} checks in a row, last error: {last_error}"
+                    ),
+                )
+                .await;
+        }
+        if let Some(post_failures) = post_failures {
+            error_notifier
+                .notify(
+                    ErrorClass::PostFailures,
+                    &format!(
+                        "{post_failures} posts in a row failed to be published, last error: {last_error}"
+                    ),
+                )
+                .await;
+        }
+    }
+}
+
+/// The maximum number of self-labels that a post can have.
+const MAX_SELF_LABELS: usize = 10;
+/// The maximum number of tags that a post can have.
+const MAX_POST_TAGS: usize = 8;
+/// The language posts are classified under when none is known from --post-languages or their locale.
+const DEFAULT_POST_LANGUAGE: &str = "en";
+
+/// Get the self-labels to apply to a post, including any whose keyword is found in its title.
+fn post_labels(
+    self_labels: &[String],
+    label_if_title_matches: &[(String, String)],
+    title: &str,
+) -> Vec<String> {
+    let title = title.to_lowercase();
+    let mut labels = self_labels.to_vec();
+    for (label, keyword) in label_if_title_matches {
+        if title.contains(&keyword.to_lowercase()) && !labels.contains(label) {
+            labels.push(label.clone());
+        }
+    }
+    labels
+}
+
+/// Get the prefix of the first rule that a post matches, if any.
+fn post_prefix<'a>(rules: &'a [PrefixRule], section: Option<usize>, title: &str) -> &'a str {
+    rules
+        .iter()
+        .find(|rule| rule.matches(section, title))
+        .map_or("", |rule| rule.prefix.as_str())
+}
+
+/// Tracks failures to remove old stored posts, escalating them once --db-cleanup-failure-threshold happen in a row.
+struct CleanupFailures {
+    threshold: NonZeroU32,
+    exit_on_threshold: bool,
+    consecutive: u32,
+}
+
+impl CleanupFailures {
+    fn new(threshold: NonZeroU32, exit_on_threshold: bool) -> Self {
+        Self {
+            threshold,
+            exit_on_threshold,
+            consecutive: 0,
+        }
+    }
+
+    /// Log the result of removing old stored posts, returning an error once the bot should exit because of them.
+    fn record(&mut self, result: Result<()>) -> Result<()> {
+        let Err(err) = result else {
+            self.consecutive = 0;
+            return Ok(());
+        };
+        self.consecutive += 1;
+        if self.consecutive < self.threshold.get() {
+            warn!("Failed to run query to remove old stored posts {err}");
+            return Ok(());
+        }
+        error!(
+            "Failed to run query to remove old stored posts {} times in a row: {err:#}",
+            self.consecutive
+        );
+        match self.exit_on_threshold {
+            true => Err(err.context(
+                "exiting as the database has persistently failed to remove old stored posts",
+            )),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Spaces out the posts made in an iteration by --post-delay-seconds, without waiting before the first of them.
+struct PostPacer {
+    delay: std::time::Duration,
+    posts: u64,
+}
+
+impl PostPacer {
+    fn new(delay: std::time::Duration) -> Self {
+        Self { delay, posts: 0 }
+    }
+
+    /// Wait before making another post unless it is the first, stopping early if the bot is shutting down.
+    async fn wait(&mut self, shutdown: &CancellationToken) {
+        self.posts += 1;
+        if self.posts == 1 || self.delay.is_zero() {
+            return;
+        }
+        debug!(
+            "Waiting for {} seconds before the next post",
+            self.delay.as_secs()
+        );
+        tokio::select! {
+            _ = sleep(self.delay) => {},
+            _ = shutdown.cancelled() => {},
+        }
+    }
+}
+
+/// Post to Bluesky, logging in again once if the current session has been rejected.
+async fn post_with_relogin(
+    bsky_handler: &BlueskyHandler,
+    bluesky: &BlueskyArgs,
+    post_data: PostData,
+) -> Result<Vec<CreatedRecord>> {
+    match bsky_handler.post(post_data.clone()).await {
+        Err(err) if is_auth_error(&err) && !bluesky.has_password() => Err(err.context(
+            "the cached bsky session was rejected: log in again with `auth login`, or provide --app-password",
+        )),
+        Err(err) if is_auth_error(&err) => {
+            warn!("Bluesky rejected the current session ({err}): attempting to log in again");
+            let (identifier, password) = bluesky.credentials()?;
+            bsky_handler
+                .login(identifier, password, bluesky.auth_factor_token.as_ref())
+                .await
+                .context("authentication failed, check WHIMSKY_APP_PASSWORD")?;
+            bsky_handler
+                .post(post_data)
+                .await
+                .map_err(|err| match is_auth_error(&err) {
+                    true => err.context("authentication failed, check WHIMSKY_APP_PASSWORD"),
+                    false => err,
+                })
+        }
+        result => result,
+    }
+}
+
+/// Run database maintenance if it hasn't been run in the last week, logging rather than returning any failure.
+async fn run_weekly_maintenance(database: &Database) {
+    let result = async {
+        if !database.is_maintenance_due().await? {
+            return Ok(None);
+        }
+        database.run_maintenance().await.map(Some)
+    }
+    .await;
+    match result {
+        Ok(None) => {}
+        Ok(Some(report)) => match (report.integrity_problems, report.size_after) {
+            (Some(problems), _) if !problems.is_empty() => error!(
+                "The database failed its integrity check: {}",
+                problems.join("; ")
+            ),
+            (_, Some(size_after)) => info!(
+                "Ran weekly database maintenance, vacuuming it from {} bytes to {size_after} bytes",
+                report.size_before
+            ),
+            (_, None) => {}
+        },
+        Err(err) => warn!("Failed to run weekly database maintenance: {err:#}"),
+    }
+}
+
+/// Mark a pending post as posted alongside the record it was published as, and update the post counters after
+/// it has been published.
+async fn record_published(
+    database: &Database,
+    url: &str,
+    record: Option<&CreatedRecord>,
+    max_posts_per_day: Option<NonZeroUsize>,
+) -> Result<()> {
+    let posted_today = database
+        .mark_published(
+            url,
+            record.map(|record| (record.uri.as_str(), record.cid.as_str())),
+            Utc::now().date_naive(),
+        )
+        .await?;
+    if let Some(max_posts_per_day) = max_posts_per_day
+        && posted_today as usize == max_posts_per_day.get()
+    {
+        warn!(
+            "Reached the daily post cap of {max_posts_per_day}: deferring any further news until tomorrow (UTC)"
+        );
+    }
+    Ok(())
+}
+
+/// The languages that news from each locale is classified under, by locale.
+type LocaleLanguages = HashMap<String, Vec<String>>;
+
+/// Choose the languages that news from each locale is classified under, returning the languages used for any other
+/// posts alongside them.
+///
+/// Without any --post-languages, news is classified under the language of its locale unless --locale-languages
+/// says otherwise.
+fn locale_post_languages(
+    news_locales: &[String],
+    mut post_languages: Vec<String>,
+    overrides: Vec<(String, String)>,
+) -> Result<(Vec<String>, LocaleLanguages)> {
+    let mut locale_languages = LocaleLanguages::new();
+    for (locale, language) in overrides {
+        if !news_locales.contains(&locale) {
+            bail!("--locale-languages was given for '{locale}' which is not in --news-locale");
+        }
+        locale_languages.entry(locale).or_default().push(language);
+    }
+    if post_languages.is_empty() {
+        for locale in news_locales {
+            if let Some(language) = fetcher::locale_language(locale) {
+                locale_languages
+                    .entry(locale.clone())
+                    .or_insert_with(|| vec![language.to_string()]);
+            }
+        }
+        post_languages = vec![DEFAULT_POST_LANGUAGE.to_string()];
+    }
+    Ok((post_languages, locale_languages))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn secrets_are_redacted_from_debug_output() {
+        let command = StartCommand::try_parse_from([
+            "start",
+            "--app-identifier",
+            "whimsky.test",
+            "--app-password",
+            "hunter2",
+            "--error-webhook-url",
+            "https://example.com/webhook/token",
+        ])
+        .unwrap();
+        let debug = format!("{command:?}");
+        assert!(debug.contains("[REDACTED]"), "{debug}");
+        assert!(!debug.contains("hunter2"), "{debug}");
+        assert!(!debug.contains("/webhook/token"), "{debug}");
+    }
+
+    #[tokio::test]
+    async fn rejected_sessions_are_only_replaced_when_given_a_password() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, pds) = crate::bsky::tests::spawn_pds().await;
+        let bsky_handler = crate::bsky::tests::handler(service.clone(), dir.path()).await;
+        let post_data = PostData {
+            text: "News".to_string(),
+            languages: vec!["en".to_string()],
+            created_at: Utc::now(),
+            labels: vec![],
+            tags: vec![],
+            embed: None,
+            mentions: vec![],
+            replies: vec![],
+        };
+        let bluesky = |args: &[&str]| {
+            StartCommand::try_parse_from(
+                ["start", "--app-service", service.as_str()]
+                    .iter()
+                    .chain(args),
+            )
+            .unwrap()
+            .bluesky
+        };
+
+        // Enough rejections that refreshing the session doesn't help.
+        *pds.expired_records.lock().unwrap() = 4;
+        let err = post_with_relogin(&bsky_handler, &bluesky(&[]), post_data.clone())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("auth login"), "{err:#}");
+        assert_eq!(pds.logins.lock().unwrap().len(), 1);
+
+        *pds.expired_records.lock().unwrap() = 4;
+        post_with_relogin(
+            &bsky_handler,
+            &bluesky(&[
+                "--app-identifier",
+                "whimsky.test",
+                "--app-password",
+                "password",
+            ]),
+            post_data,
+        )
+        .await
+        .unwrap();
+        assert_eq!(pds.logins.lock().unwrap().len(), 2);
+        assert_eq!(pds.records.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn downtime_notices_are_only_posted_past_the_threshold() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, pds) = crate::bsky::tests::spawn_pds().await;
+        let bsky_handler = crate::bsky::tests::handler(service, dir.path()).await;
+        let command = |args: &[&str]| {
+            StartCommand::try_parse_from(["start", "--collect-only"].iter().chain(args)).unwrap()
+        };
+        let languages = strings(&["en"]);
+        let offline_for = |hours| Utc::now() - Duration::hours(hours) - Duration::minutes(1);
+
+        command(&[])
+            .post_downtime_notice(&bsky_handler, offline_for(100), &languages)
+            .await;
+        let threshold = command(&["--downtime-notice-threshold-hours", "6"]);
+        threshold
+            .post_downtime_notice(&bsky_handler, offline_for(5), &languages)
+            .await;
+        assert!(pds.records.lock().unwrap().is_empty());
+
+        threshold
+            .post_downtime_notice(&bsky_handler, offline_for(7), &languages)
+            .await;
+        let records = pds.records.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0]["text"],
+            "whimsky was offline for 7 hours; catching up on missed news now"
+        );
+        assert_eq!(records[0]["langs"], serde_json::json!(["en"]));
+    }
+
+    #[test]
+    fn the_first_matching_prefix_rule_wins() {
+        let rules: Vec<PrefixRule> = ["title~maintenance=🔧 ", "section:3=🎀 ", "section:4=📢 "]
+            .iter()
+            .map(|rule| rule.parse().unwrap())
+            .collect();
+        assert_eq!(post_prefix(&rules, Some(3), "Maintenance notice"), "🔧 ");
+        assert_eq!(post_prefix(&rules, Some(3), "New event"), "🎀 ");
+        assert_eq!(post_prefix(&rules, Some(4), "New event"), "📢 ");
+        assert_eq!(post_prefix(&rules, None, "New event"), "");
+        assert_eq!(post_prefix(&[], Some(3), "New event"), "");
+    }
+
+    #[test]
+    fn news_is_classified_under_the_language_of_its_locale() {
+        let (post_languages, locale_languages) =
+            locale_post_languages(&strings(&["en", "ja", "kr", "fr"]), vec![], vec![]).unwrap();
+        assert_eq!(post_languages, ["en"]);
+        assert_eq!(locale_languages["en"], ["en"]);
+        assert_eq!(locale_languages["ja"], ["ja"]);
+        // Korean news is under the "kr" locale but the "ko" language.
+        assert_eq!(locale_languages["kr"], ["ko"]);
+        assert!(!locale_languages.contains_key("fr"));
+    }
+
+    #[test]
+    fn locale_languages_override_the_built_in_mapping() {
+        let (_, locale_languages) = locale_post_languages(
+            &strings(&["kr", "zh"]),
+            vec![],
+            vec![
+                ("zh".to_string(), "zh-Hant".to_string()),
+                ("zh".to_string(), "zh".to_string()),
+            ],
+        )
+        .unwrap();
+        assert_eq!(locale_languages["kr"], ["ko"]);
+        assert_eq!(locale_languages["zh"], ["zh-Hant", "zh"]);
+
+        let err = locale_post_languages(
+            &strings(&["en"]),
+            vec![],
+            vec![("kr".to_string(), "ko".to_string())],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("not in --news-locale"), "{err}");
+    }
+
+    #[test]
+    fn post_languages_replace_the_built_in_mapping() {
+        let (post_languages, locale_languages) =
+            locale_post_languages(&strings(&["ja", "kr"]), strings(&["en"]), vec![]).unwrap();
+        assert_eq!(post_languages, ["en"]);
+        assert!(locale_languages.is_empty());
+    }
+
+    #[test]
+    fn feed_urls_can_end_with_a_comma() {
+        let command =
+            StartCommand::try_parse_from(["start", "--rss-feed-url", "https://a.com/feed,"])
+                .unwrap();
+        assert_eq!(
+            url_list(command.rss_feed_url),
+            [Url::parse("https://a.com/feed").unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn cleanup_failures_are_escalated_once_they_persist() {
+        let database = Database::new_in_memory().await.unwrap();
+        let retention = Duration::days(1);
+        let mut failures = CleanupFailures::new(NonZeroU32::new(3).unwrap(), true);
+        failures
+            .record(database.remove_old_stored_posts(retention).await)
+            .unwrap();
+
+        database.close().await;
+        for _ in 0..2 {
+            failures
+                .record(database.remove_old_stored_posts(retention).await)
+                .unwrap();
+        }
+        assert!(
+            failures
+                .record(database.remove_old_stored_posts(retention).await)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn cleanup_failures_reset_after_a_success() {
+        let mut failures = CleanupFailures::new(NonZeroU32::new(2).unwrap(), true);
+        failures.record(Err(anyhow!("read-only database"))).unwrap();
+        failures.record(Ok(())).unwrap();
+        failures.record(Err(anyhow!("read-only database"))).unwrap();
+        assert!(failures.record(Err(anyhow!("read-only database"))).is_err());
+    }
+
+    #[test]
+    fn cleanup_failures_only_exit_when_asked_to() {
+        let mut failures = CleanupFailures::new(NonZeroU32::new(1).unwrap(), false);
+        for _ in 0..3 {
+            failures.record(Err(anyhow!("read-only database"))).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn posts_are_spaced_out_without_waiting_before_the_first() {
+        let delay = std::time::Duration::from_millis(200);
+        let shutdown = CancellationToken::new();
+        let mut pacer = PostPacer::new(delay);
+
+        let start = Instant::now();
+        pacer.wait(&shutdown).await;
+        assert!(start.elapsed() < delay);
+        for _ in 0..2 {
+            pacer.wait(&shutdown).await;
+        }
+        // Three posts wait twice.
+        assert!(start.elapsed() >= delay * 2);
+        assert!(start.elapsed() < delay * 3);
+    }
+
+    #[tokio::test]
+    async fn waiting_between_posts_stops_on_shutdown() {
+        let shutdown = CancellationToken::new();
+        let mut pacer = PostPacer::new(std::time::Duration::from_secs(60));
+        pacer.wait(&shutdown).await;
+        shutdown.cancel();
+
+        let start = Instant::now();
+        pacer.wait(&shutdown).await;
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn metrics_can_be_scraped_after_a_cycle() {
+        let news = axum::Router::new()
+            .route(
+                "/api/news",
+                axum::routing::get(|| async {
+                    axum::Json(serde_json::json!({ "data": { "total": 1, "data": [{
+                        "id": 1,
+                        "title": "News 1",
+                        "section": 1,
+                        "publish_time": Utc::now().to_rfc3339(),
+                        "abstract": "The abstract of news 1",
+                    }] } }))
+                }),
+            )
+            .route(
+                "/feed.xml",
+                axum::routing::get(|| async { axum::http::StatusCode::NOT_FOUND }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let news_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, news).await });
+        let listen = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let feed_url = format!("{news_url}/feed.xml");
+
+        let dir = tempfile::tempdir().unwrap();
+        let healthcheck_file = dir.path().join("healthcheck");
+        let command = StartCommand::try_parse_from([
+            "start",
+            "--collect-only",
+            "--healthcheck-file",
+            healthcheck_file.to_str().unwrap(),
+            "--listen",
+            &listen,
+            "--news-api-base-url",
+            &news_url,
+            "--rss-feed-url",
+            &feed_url,
+        ])
+        .unwrap();
+        let scrape = async {
+            let mut body = String::new();
+            for _ in 0..100 {
+                if let Ok(response) = reqwest::get(format!("http://{listen}/metrics")).await {
+                    body = response.text().await.unwrap();
+                    if body.lines().any(|line| line == "whimsky_cycles_total 1") {
+                        break;
+                    }
+                }
+                sleep(std::time::Duration::from_millis(100)).await;
+            }
+            body
+        };
+        // The bot only shuts down gracefully on a signal, so it is dropped once the metrics have been scraped.
+        let body = tokio::select! {
+            result = command.run(GlobalArguments::in_dir(dir.path())) => panic!("the bot stopped: {result:?}"),
+            body = scrape => body,
+        };
+
+        let lines: Vec<&str> = body.lines().collect();
+        assert!(lines.contains(&"whimsky_cycles_total 1"), "{body}");
+        assert!(lines.contains(&"whimsky_fetch_failures_total 1"), "{body}");
+        assert!(
+            lines.contains(
+                &format!("whimsky_source_fetch_failures_total{{source=\"rss:{feed_url}\"}} 1")
+                    .as_str()
+            ),
+            "{body}"
+        );
+        assert!(lines.contains(&"whimsky_posted_urls 1"), "{body}");
+        assert!(
+            lines.contains(&"whimsky_uploaded_blob_bytes_total 0"),
+            "{body}"
+        );
+        let seconds_since_last_success = lines
+            .iter()
+            .find_map(|line| line.strip_prefix("whimsky_seconds_since_last_success "))
+            .unwrap()
+            .parse::<f64>()
+            .unwrap();
+        assert!(seconds_since_last_success < 10.0, "{body}");
+        // The cycle fetched the news even though the feed failed.
+        assert!(healthcheck_file.exists());
     }
 }
@@ -0,0 +1,447 @@
+use crate::commands::{CommandRoot, reject_duplicate_values};
+use anyhow::{Context, Result, bail};
+use clap::{
+    Arg, ArgAction, ArgMatches, Command, CommandFactory, FromArgMatches, error::ErrorKind,
+    parser::ValueSource,
+};
+use std::{ffi::OsString, fs, path::PathBuf};
+use toml::{Table, Value};
+
+/// The config file that is read from inside of the data path when --config isn't given.
+const CONFIG_FILE_NAME: &str = "config.toml";
+/// The key of a config file that sets the app password.
+const PASSWORD_KEY: &str = "password";
+/// The key of a config file that reads the app password from a separate file.
+const PASSWORD_FILE_KEY: &str = "password_file";
+
+/// Command line arguments with the options read from a config file added to them.
+///
+/// This isn't `Debug` as the arguments can hold secrets such as the app password, which are only redacted once
+/// they are parsed into a [`CommandRoot`].
+pub struct MergedArgs {
+    pub args: Vec<OsString>,
+    /// The config file that options were read from, if one was found.
+    pub path: Option<PathBuf>,
+    /// Problems with the config file that don't stop it from being used, such as unknown keys.
+    pub warnings: Vec<String>,
+}
+
+/// Parse the command line, reading any options that weren't given on the command line or through the environment
+/// from the config file.
+///
+/// Problems with the config file are returned rather than logged, as logging isn't set up until the command line
+/// has been parsed.
+pub fn parse_args() -> Result<(CommandRoot, Vec<String>)> {
+    let merged = merge_config_file(std::env::args_os().collect())?;
+    let command = CommandRoot::command();
+    let root = get_matches(&command, &merged.args)
+        .and_then(|matches| CommandRoot::from_arg_matches(&matches))
+        .unwrap_or_else(|err| err.exit());
+    Ok((root, merged.warnings))
+}
+
+/// Parse a command line, also rejecting list options that were given the same value more than once.
+pub fn get_matches(command: &Command, args: &[OsString]) -> Result<ArgMatches, clap::Error> {
+    let mut built = command.clone();
+    let matches = built.try_get_matches_from_mut(args)?;
+    // Building fills in the full name of each subcommand for the usage shown with errors.
+    built.build();
+    let (mut command, mut level) = (&built, &matches);
+    loop {
+        reject_duplicate_values(command, level).map_err(|err| err.format(&mut command.clone()))?;
+        let Some((name, subcommand_matches)) = level.subcommand() else {
+            break;
+        };
+        let Some(subcommand) = command.find_subcommand(name) else {
+            break;
+        };
+        command = subcommand;
+        level = subcommand_matches;
+    }
+    Ok(matches)
+}
+
+/// Add the options of the command being run from the config file to a list of command line arguments, unless they
+/// were already given on the command line or through the environment.
+///
+/// Keys are named after options, such as `run_interval_seconds`, and are applied to whichever command takes the
+/// same option. Keys that no command takes are warned about.
+pub fn merge_config_file(args: Vec<OsString>) -> Result<MergedArgs> {
+    let command = CommandRoot::command();
+    let matches = match command.clone().try_get_matches_from(&args) {
+        Ok(matches) => matches,
+        // Required options can be given by the config file, so they can't be missing yet.
+        Err(err) if err.kind() == ErrorKind::MissingRequiredArgument => command
+            .clone()
+            .ignore_errors(true)
+            .try_get_matches_from(&args)
+            .unwrap_or_else(|err| err.exit()),
+        Err(err) => err.exit(),
+    };
+    let path = match matches.get_one::<PathBuf>("config") {
+        Some(path) => Some(path.clone()),
+        None => matches
+            .get_one::<PathBuf>("data_path")
+            .map(|data_path| data_path.join(CONFIG_FILE_NAME))
+            .filter(|path| path.is_file()),
+    };
+    let mut merged = MergedArgs {
+        args,
+        path,
+        warnings: vec![],
+    };
+    let Some(path) = &merged.path else {
+        return Ok(merged);
+    };
+
+    let table = fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file {}", path.display()))?
+        .parse::<Table>()
+        .with_context(|| format!("failed to parse config file {}", path.display()))?;
+    if table.contains_key(PASSWORD_KEY) {
+        merged.warnings.push(format!(
+            "Storing `{PASSWORD_KEY}` in config file {} is discouraged: use `{PASSWORD_FILE_KEY}` to read it from a separate file instead",
+            path.display()
+        ));
+    }
+
+    let root = &command;
+    let (command, matches) = leaf_command(root, &matches);
+    let mut config_args = vec![];
+    for (key, value) in table {
+        // Options of the root command, such as --data-path, are needed to find the config file in the first place.
+        if !root
+            .get_subcommands()
+            .any(|subcommand| is_config_key(subcommand, &key))
+        {
+            merged.warnings.push(format!(
+                "Ignoring unknown key `{key}` in config file {}",
+                path.display()
+            ));
+            continue;
+        }
+        let Some(arg) = config_option(command, &key) else {
+            continue;
+        };
+        if is_given(command, matches, arg) {
+            continue;
+        }
+        config_args.extend(
+            option_args(arg, value)
+                .with_context(|| format!("invalid `{key}` in config file {}", path.display()))?,
+        );
+    }
+    // Anything after `--` is a positional argument, so options have to come before it.
+    let end = merged
+        .args
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(merged.args.len());
+    merged.args.splice(end..end, config_args);
+    Ok(merged)
+}
+
+/// Follow the subcommands that were given down to the command being run.
+pub fn leaf_command<'a>(
+    mut command: &'a Command,
+    mut matches: &'a ArgMatches,
+) -> (&'a Command, &'a ArgMatches) {
+    while let Some((name, subcommand_matches)) = matches.subcommand() {
+        let Some(subcommand) = command.find_subcommand(name) else {
+            break;
+        };
+        command = subcommand;
+        matches = subcommand_matches;
+    }
+    (command, matches)
+}
+
+/// Whether an option, or any option that it conflicts with, was given on the command line or through the environment.
+fn is_given(command: &Command, matches: &ArgMatches, arg: &Arg) -> bool {
+    let is_set = |arg: &Arg| {
+        matches!(
+            matches.value_source(arg.get_id().as_str()),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        )
+    };
+    is_set(arg)
+        || command.get_arg_conflicts_with(arg).into_iter().any(is_set)
+        || command.get_arguments().any(|other| {
+            is_set(other)
+                && command
+                    .get_arg_conflicts_with(other)
+                    .iter()
+                    .any(|conflict| conflict.get_id() == arg.get_id())
+        })
+}
+
+/// Find the option of a command that can be set by a key in a config file.
+pub fn config_option<'a>(command: &'a Command, key: &str) -> Option<&'a Arg> {
+    command.get_arguments().find(|arg| {
+        arg.get_id() == key
+            && arg.get_long().is_some()
+            && !matches!(
+                arg.get_action(),
+                ArgAction::Help | ArgAction::HelpShort | ArgAction::HelpLong | ArgAction::Version
+            )
+    })
+}
+
+/// Whether a command or any of its subcommands takes an option that can be set by a key in a config file.
+fn is_config_key(command: &Command, key: &str) -> bool {
+    config_option(command, key).is_some()
+        || command
+            .get_subcommands()
+            .any(|subcommand| is_config_key(subcommand, key))
+}
+
+/// Turn the value of a key in a config file into the command line arguments that set the option.
+fn option_args(arg: &Arg, value: Value) -> Result<Vec<OsString>> {
+    let long = arg.get_long().expect("config options have a long name");
+    if matches!(arg.get_action(), ArgAction::SetTrue) {
+        return match value {
+            Value::Boolean(true) => Ok(vec![format!("--{long}").into()]),
+            Value::Boolean(false) => Ok(vec![]),
+            _ => bail!("expected true or false"),
+        };
+    }
+    let values = match value {
+        Value::Array(values) => {
+            let values = values
+                .into_iter()
+                .map(scalar_value)
+                .collect::<Result<Vec<_>>>()?;
+            match (arg.get_action(), arg.get_value_delimiter()) {
+                (ArgAction::Append, _) => values,
+                (_, Some(delimiter)) => vec![values.join(&delimiter.to_string())],
+                _ => bail!("expected a single value rather than a list"),
+            }
+        }
+        value => vec![scalar_value(value)?],
+    };
+    Ok(values
+        .into_iter()
+        .map(|value| format!("--{long}={value}").into())
+        .collect())
+}
+
+fn scalar_value(value: Value) -> Result<String> {
+    match value {
+        Value::String(value) => Ok(value),
+        Value::Array(_) | Value::Table(_) => bail!("expected a string, number or boolean"),
+        value => Ok(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Merge a config file into a command line, returning the merged arguments and any warnings.
+    fn merge(config: &str, args: &[&str]) -> Result<(Vec<String>, Vec<String>)> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+        fs::write(&path, config).unwrap();
+        let merged = merge_config_file(
+            ["whimsky", "--config", path.to_str().unwrap()]
+                .iter()
+                .chain(args)
+                .map(OsString::from)
+                .collect(),
+        )?;
+        Ok((
+            merged.args[3..]
+                .iter()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            merged.warnings,
+        ))
+    }
+
+    #[test]
+    fn command_line_options_beat_the_config_file() {
+        let (args, _) = merge(
+            "run_interval_seconds = 60\npost_delay_seconds = 5\n",
+            &["start", "--rerun-interval-seconds", "30"],
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            [
+                "start",
+                "--rerun-interval-seconds",
+                "30",
+                "--post-delay-seconds=5"
+            ]
+        );
+    }
+
+    #[test]
+    fn environment_options_beat_the_config_file() {
+        // Options read their environment variable when they are built.
+        // SAFETY: the variable is only read by this test.
+        unsafe { std::env::set_var("WHIMSKY_CONFIG_TEST_TIMEOUT", "5") };
+        let command = Command::new("test")
+            .arg(
+                Arg::new("timeout")
+                    .long("timeout")
+                    .env("WHIMSKY_CONFIG_TEST_TIMEOUT"),
+            )
+            .arg(Arg::new("delay").long("delay"));
+        let matches = command.clone().try_get_matches_from(["test"]).unwrap();
+        assert!(is_given(
+            &command,
+            &matches,
+            config_option(&command, "timeout").unwrap()
+        ));
+        assert!(!is_given(
+            &command,
+            &matches,
+            config_option(&command, "delay").unwrap()
+        ));
+    }
+
+    #[test]
+    fn config_options_are_skipped_when_a_conflicting_option_is_given() {
+        // --app-password conflicts with --app-password-file.
+        let (args, _) = merge(
+            "password_file = \"/run/secrets/password\"\n",
+            &["start", "--app-password", "secret"],
+        )
+        .unwrap();
+        assert_eq!(args, ["start", "--app-password", "secret"]);
+        // --app-password-stdin is what --app-password-file conflicts with, rather than the other way around.
+        let dir = tempfile::tempdir().unwrap();
+        let password_file = dir.path().join("password");
+        fs::write(&password_file, "secret").unwrap();
+        let password_file = password_file.to_str().unwrap();
+        let (args, _) = merge(
+            "password_stdin = true\n",
+            &["start", "--app-password-file", password_file],
+        )
+        .unwrap();
+        assert_eq!(args, ["start", "--app-password-file", password_file]);
+    }
+
+    #[test]
+    fn lists_are_repeated_or_joined_by_their_delimiter() {
+        let values = || Value::Array(vec![Value::String("a".to_string()), Value::Integer(1)]);
+        let args = |arg: Arg| {
+            option_args(&arg, values()).map(|args| {
+                args.into_iter()
+                    .map(|arg| arg.into_string().unwrap())
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(
+            args(Arg::new("tags").long("tags").action(ArgAction::Append)).unwrap(),
+            ["--tags=a", "--tags=1"]
+        );
+        assert_eq!(
+            args(
+                Arg::new("langs")
+                    .long("langs")
+                    .action(ArgAction::Set)
+                    .value_delimiter(',')
+            )
+            .unwrap(),
+            ["--langs=a,1"]
+        );
+        let err = args(Arg::new("interval").long("interval").action(ArgAction::Set)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected a single value rather than a list"
+        );
+
+        let err = merge("run_interval_seconds = [60, 120]\n", &["start"]).unwrap_err();
+        assert_eq!(
+            format!("{err:#}").split_once(" in config file").unwrap().0,
+            "invalid `run_interval_seconds`"
+        );
+    }
+
+    #[test]
+    fn flags_are_only_given_when_true() {
+        let (args, _) = merge("collect_only = true\n", &["start"]).unwrap();
+        assert_eq!(args, ["start", "--collect-only"]);
+        let (args, _) = merge("collect_only = false\n", &["start"]).unwrap();
+        assert_eq!(args, ["start"]);
+        let err = merge("collect_only = \"yes\"\n", &["start"]).unwrap_err();
+        assert!(
+            format!("{err:#}").ends_with("expected true or false"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn only_keys_that_no_command_takes_are_warned_about() {
+        let config =
+            "not_an_option = 1\nmatch_prefix = \"https://example.com/\"\ndata_path = \"/tmp\"\n";
+        let (args, warnings) = merge(config, &["start"]).unwrap();
+        assert_eq!(args, ["start"]);
+        assert_eq!(warnings.len(), 2, "{warnings:?}");
+        // Keys are ordered by name.
+        assert!(warnings[0].starts_with("Ignoring unknown key `data_path`"));
+        assert!(warnings[1].starts_with("Ignoring unknown key `not_an_option`"));
+
+        let (args, _) = merge(config, &["database", "remove-posts"]).unwrap();
+        assert_eq!(
+            args,
+            [
+                "database",
+                "remove-posts",
+                "--match-prefix=https://example.com/"
+            ]
+        );
+    }
+
+    #[test]
+    fn config_options_are_given_before_positional_arguments() {
+        let (args, _) = merge(
+            "dry_run = true\n",
+            &["database", "remove-posts", "--", "https://example.com/a"],
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            [
+                "database",
+                "remove-posts",
+                "--dry-run",
+                "--",
+                "https://example.com/a"
+            ]
+        );
+    }
+
+    #[test]
+    fn secrets_are_redacted_once_parsed() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join(CONFIG_FILE_NAME);
+        fs::write(
+            &config,
+            "password = \"config-password\"\nerror_webhook_url = \"https://example.com/webhook-token\"\n",
+        )
+        .unwrap();
+        let args = [
+            "whimsky",
+            "--config",
+            config.to_str().unwrap(),
+            "start",
+            "--app-identifier",
+            "whimsky.test",
+            "--app-auth-factor-token",
+            "sign-in-code",
+        ];
+        let merged = merge_config_file(args.into_iter().map(OsString::from).collect()).unwrap();
+        let matches = get_matches(&CommandRoot::command(), &merged.args).unwrap();
+        let root = format!("{:?}", CommandRoot::from_arg_matches(&matches).unwrap());
+
+        for secret in ["config-password", "webhook-token", "sign-in-code"] {
+            assert!(!root.contains(secret), "{secret} was in {root}");
+        }
+        assert!(root.contains("whimsky.test"));
+        assert!(root.contains("password: Some([REDACTED])"));
+        assert!(root.contains("error_webhook_url: Some([REDACTED])"));
+    }
+}
@@ -1,37 +1,3411 @@
-use anyhow::Result;
-use sqlx::{SqlitePool, migrate, query};
+use crate::dedup::normalise_url;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
+use sqlx::{
+    Connection, PgPool, Postgres, Sqlite, SqliteConnection, SqlitePool, Transaction, migrate,
+    query, raw_sql,
+};
+use std::path::Path;
+use std::str::FromStr;
 use tracing::debug;
 
+/// Run a query against whichever database a [`Backend`] holds, using the SQL written for that database.
+///
+/// Both queries take the same arguments, and `body` is evaluated with `query` and `executor` bound for the
+/// database being used so that it can read the rows of either. The queries can't be identical as the
+/// offline query data used to build without a database is keyed by the text of each query.
+macro_rules! query_each {
+    ($backend:expr, sqlite: $sqlite:literal, postgres: $postgres:literal $(, $arg:expr)* => |$query:ident, $executor:ident| $body:expr) => {
+        match $backend {
+            Backend::Sqlite($executor) => {
+                let $query = query!($sqlite $(, $arg)*);
+                $body
+            }
+            Backend::Postgres($executor) => {
+                let $query = query!($postgres $(, $arg)*);
+                $body
+            }
+        }
+    };
+}
+
+/// The source of URLs that were recorded by hand from the command line rather than by fetching a feed.
+pub const MANUAL_SOURCE: &str = "manual";
+const LAST_SUCCESSFUL_FETCH_KEY: &str = "last_successful_fetch";
+const LAST_MAINTENANCE_KEY: &str = "last_maintenance";
+/// How many days [`Database::is_maintenance_due`] waits between each run of maintenance.
+const MAINTENANCE_INTERVAL_DAYS: i64 = 7;
+/// The number of connections kept open to a sqlite database, which only ever allows one writer at a time.
+const SQLITE_MAX_CONNECTIONS: u32 = 4;
+/// Every table holding data that is replaced when restoring a backup.
+const RESTORED_TABLES: [&str; 7] = [
+    "posted_urls",
+    "state",
+    "fetch_history",
+    "article_post_counts",
+    "daily_post_counts",
+    "failed_posts",
+    "canonical_urls",
+];
+/// How long a sqlite connection waits for another writer to finish before failing, unless set by the database URL.
+const SQLITE_BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// The number of fetch attempts to keep in `fetch_history` for each feed.
+const FETCH_HISTORY_PER_FEED: i64 = 200;
+
+/// A value that exists for each supported database.
+enum Backend<S, P> {
+    Sqlite(S),
+    Postgres(P),
+}
+
+type BackendTransaction = Backend<Transaction<'static, Sqlite>, Transaction<'static, Postgres>>;
+
+impl BackendTransaction {
+    async fn commit(self) -> Result<()> {
+        match self {
+            Backend::Sqlite(tx) => tx.commit().await?,
+            Backend::Postgres(tx) => tx.commit().await?,
+        }
+        Ok(())
+    }
+}
+
 pub struct Database {
-    pool: SqlitePool,
+    pool: Backend<SqlitePool, PgPool>,
+    /// The query parameters removed when normalising URLs stored in `posted_urls`.
+    stripped_url_params: Vec<String>,
+}
+
+/// How a URL stored in `posted_urls` was handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostStatus {
+    /// The URL was posted to Bluesky.
+    Posted,
+    /// The URL was recorded without being posted in collect-only mode, to be posted later once promoted.
+    Collected,
+    /// The URL was recorded without being posted when skipping backfill, and is never posted.
+    Skipped,
+    /// The URL was recorded just before being posted and it isn't yet known whether posting succeeded.
+    Pending,
+}
+
+impl PostStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Posted => "posted",
+            Self::Collected => "collected",
+            Self::Skipped => "skipped",
+            Self::Pending => "pending",
+        }
+    }
+}
+
+/// The outcome of a single attempt at fetching a feed.
+#[derive(Debug, Clone)]
+pub struct FetchAttempt {
+    pub fetched_at: DateTime<Utc>,
+    /// A short classification of the error that caused the fetch to fail, or `None` if it succeeded.
+    pub error_class: Option<String>,
+    pub error: Option<String>,
+    pub item_count: i64,
+    pub duration_ms: i64,
+}
+
+/// The most recent fetch attempts of a feed found in `fetch_history`.
+#[derive(Debug, Clone)]
+pub struct FeedSummary {
+    pub feed: String,
+    pub last_attempt: DateTime<Utc>,
+    pub last_error_class: Option<String>,
+    pub last_success: Option<DateTime<Utc>>,
+}
+
+/// A post that failed to be published and is queued to be retried.
+#[derive(Debug, Clone)]
+pub struct FailedPost {
+    pub url: String,
+    /// Where the post came from, such as `nikki:en` or `rss:<feed url>`.
+    pub source: String,
+    pub source_id: Option<String>,
+    pub fingerprint: Option<String>,
+    /// The post as serialized JSON.
+    pub payload: String,
+    pub error: String,
+    pub attempts: i64,
+    pub last_attempt_at: DateTime<Utc>,
+}
+
+/// A URL stored in `posted_urls`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PostedUrl {
+    pub url: String,
+    pub status: String,
+    /// Where the URL came from, such as `nikki:en`, `rss:<feed url>`, `manual`, or `unknown` for URLs stored before
+    /// sources were tracked.
+    pub source: String,
+    pub source_id: Option<String>,
+    /// When the URL was first stored.
+    pub posted_at: Option<DateTime<Utc>>,
+    pub record_uri: Option<String>,
+}
+
+/// An overview of what is stored in the database.
+#[derive(Debug, Clone, Serialize)]
+pub struct DatabaseStats {
+    /// The number of URLs stored in `posted_urls`.
+    pub posted_urls: i64,
+    /// When the oldest URL with a known time was stored.
+    pub oldest_posted_at: Option<DateTime<Utc>>,
+    /// When the newest URL was stored.
+    pub newest_posted_at: Option<DateTime<Utc>>,
+    pub added_last_24h: i64,
+    pub added_last_7d: i64,
+    /// The number of URLs that were being posted when the bot stopped.
+    pub pending: i64,
+    /// The number of posts queued to be retried after failing to publish.
+    pub failed: i64,
+    /// The space taken up by the database in bytes.
+    pub size_bytes: i64,
+}
+
+/// The outcome of [`Database::run_maintenance`].
+#[derive(Debug, Clone)]
+pub struct MaintenanceReport {
+    /// Every problem found by the integrity check, or `None` if the database has no integrity check.
+    pub integrity_problems: Option<Vec<String>>,
+    pub size_before: i64,
+    /// The size after vacuuming, or `None` if the database wasn't vacuumed as it failed its integrity check.
+    pub size_after: Option<i64>,
+}
+
+/// A URL to store in `posted_urls` with [`Database::record_batch`].
+#[derive(Debug, Clone, Copy)]
+pub struct PostedEntry<'a> {
+    pub url: &'a str,
+    pub source: &'a str,
+    pub source_id: Option<&'a str>,
+    pub fingerprint: Option<&'a str>,
+}
+
+/// Which URLs stored in `posted_urls` to return from [`Database::query_posts`].
+#[derive(Debug, Clone, Default)]
+pub struct PostFilter {
+    /// Only URLs stored at or after this time, which excludes those without a known time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only URLs stored before this time, which excludes those without a known time.
+    pub before: Option<DateTime<Utc>>,
+    /// Only URLs containing this text, ignoring case.
+    pub contains: Option<String>,
+    /// Only URLs starting with this text.
+    pub prefix: Option<String>,
+    /// Only URLs from this source.
+    pub source: Option<String>,
+    /// Only the URL of the article with this id in its source, such as "nikki:123".
+    pub source_id: Option<String>,
+    /// The maximum number of URLs to return.
+    pub limit: Option<u32>,
+}
+
+fn parse_timestamp(value: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(value)?.with_timezone(&Utc))
+}
+
+/// Check a sqlite database for corruption, returning every problem that was found.
+async fn integrity_problems(pool: &SqlitePool) -> Result<Vec<String>> {
+    Ok(query!("PRAGMA integrity_check")
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .filter_map(|row| row.integrity_check)
+        .filter(|problem| problem != "ok")
+        .collect())
+}
+
+/// Build the options for connecting to a sqlite database so that other processes, such as a database command run
+/// while the bot is running, wait for each other's writes instead of failing with "database is locked".
+///
+/// The `journal_mode`, `synchronous`, `foreign_keys` and `busy_timeout` (in milliseconds) query parameters of the
+/// database URL override the defaults, with the rest of the URL being handled by sqlx.
+fn sqlite_options(database_url: &str) -> Result<SqliteConnectOptions> {
+    let (base, params) = database_url.split_once('?').unwrap_or((database_url, ""));
+    let (mut journal_mode, mut synchronous, mut foreign_keys, mut busy_timeout) = (
+        SqliteJournalMode::Wal,
+        SqliteSynchronous::Normal,
+        true,
+        SQLITE_BUSY_TIMEOUT,
+    );
+    let mut remaining = vec![];
+    for param in params.split('&').filter(|param| !param.is_empty()) {
+        let (key, value) = param.split_once('=').unwrap_or((param, ""));
+        match key {
+            "journal_mode" => journal_mode = SqliteJournalMode::from_str(value)?,
+            "synchronous" => synchronous = SqliteSynchronous::from_str(value)?,
+            "foreign_keys" => {
+                foreign_keys = match value.to_ascii_lowercase().as_str() {
+                    "true" | "on" | "1" => true,
+                    "false" | "off" | "0" => false,
+                    _ => bail!("unknown value {value:?} for `foreign_keys`"),
+                }
+            }
+            "busy_timeout" => {
+                busy_timeout = std::time::Duration::from_millis(
+                    value
+                        .parse()
+                        .with_context(|| format!("invalid value {value:?} for `busy_timeout`"))?,
+                )
+            }
+            _ => remaining.push(param),
+        }
+    }
+    let database_url = match remaining.is_empty() {
+        true => base.to_string(),
+        false => format!("{base}?{}", remaining.join("&")),
+    };
+    Ok(SqliteConnectOptions::from_str(&database_url)?
+        .journal_mode(journal_mode)
+        .synchronous(synchronous)
+        .foreign_keys(foreign_keys)
+        .busy_timeout(busy_timeout))
+}
+
+/// Whether a sqlite database URL is for an in-memory database, such as `sqlite::memory:`.
+fn is_in_memory(database_url: &str) -> bool {
+    let (base, params) = database_url.split_once('?').unwrap_or((database_url, ""));
+    base.trim_start_matches("sqlite:").trim_start_matches("//") == ":memory:"
+        || params.split('&').any(|param| param == "mode=memory")
 }
 
 impl Database {
-    pub async fn new(database_url: &str) -> Result<Self> {
-        let pool = SqlitePool::connect(database_url).await?;
-        migrate!().run(&pool).await?;
-        Ok(Self { pool })
+    pub async fn new(database_url: &str, stripped_url_params: &[String]) -> Result<Self> {
+        let pool = match database_url.starts_with("postgres://")
+            || database_url.starts_with("postgresql://")
+        {
+            true => {
+                let pool = PgPool::connect(database_url).await?;
+                migrate!("./migrations-postgres").run(&pool).await?;
+                Backend::Postgres(pool)
+            }
+            false => {
+                let options = sqlite_options(database_url)?;
+                let in_memory = is_in_memory(database_url);
+                if !in_memory
+                    && let Some(parent) = options.get_filename().parent()
+                    && !parent.as_os_str().is_empty()
+                    && !parent.is_dir()
+                {
+                    bail!(
+                        "the directory {} holding the sqlite database doesn't exist: only the database file is created, so create the directory first",
+                        parent.display()
+                    );
+                }
+                // An in-memory database is gone once every connection to it closes, so it is kept on a single
+                // connection that stays open for as long as the pool.
+                let pool_options = match in_memory {
+                    true => SqlitePoolOptions::new()
+                        .max_connections(1)
+                        .min_connections(1)
+                        .idle_timeout(None)
+                        .max_lifetime(None),
+                    false => SqlitePoolOptions::new().max_connections(SQLITE_MAX_CONNECTIONS),
+                };
+                let pool = pool_options.connect_with(options).await?;
+                migrate!().run(&pool).await?;
+                Backend::Sqlite(pool)
+            }
+        };
+        Ok(Self {
+            pool,
+            stripped_url_params: stripped_url_params.to_vec(),
+        })
     }
 
-    pub async fn add_posted_url(&self, url: &str) -> Result<()> {
-        debug!("Storing {url} in posted_urls");
-        query!("INSERT INTO posted_urls (url) VALUES (?)", url)
-            .execute(&self.pool)
-            .await?;
+    /// Open an empty in-memory database that is thrown away once it is dropped, such as for tests.
+    #[cfg(test)]
+    pub async fn new_in_memory() -> Result<Self> {
+        Self::new("sqlite::memory:", &[]).await
+    }
+
+    /// Close every connection to the database, making any further query fail, such as to test handling errors.
+    #[cfg(test)]
+    pub async fn close(&self) {
+        match &self.pool {
+            Backend::Sqlite(pool) => pool.close().await,
+            Backend::Postgres(pool) => pool.close().await,
+        }
+    }
+
+    async fn begin(&self) -> Result<BackendTransaction> {
+        Ok(match &self.pool {
+            Backend::Sqlite(pool) => Backend::Sqlite(pool.begin().await?),
+            Backend::Postgres(pool) => Backend::Postgres(pool.begin().await?),
+        })
+    }
+
+    /// Normalise a URL the same way as every URL stored in `posted_urls` and `article_post_counts`.
+    fn normalise_url(&self, url: &str) -> String {
+        normalise_url(url, &self.stripped_url_params)
+    }
+
+    /// Record how a URL was handled and where it came from, alongside the id of the item it was posted for when its
+    /// source has one.
+    ///
+    /// Returns whether the URL was stored, which it isn't when it has already been recorded.
+    pub async fn record_posted(
+        &self,
+        url: &str,
+        source: &str,
+        source_id: Option<&str>,
+        fingerprint: Option<&str>,
+        status: PostStatus,
+    ) -> Result<bool> {
+        let url = self.normalise_url(url);
+        debug!("Storing {url} in posted_urls as {}", status.as_str());
+        let status = status.as_str();
+        let now = Utc::now().to_rfc3339();
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "INSERT INTO posted_urls (url, source, source_id, fingerprint, status, posted_at) VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT(url) DO NOTHING",
+            postgres: "INSERT INTO posted_urls (url, source, source_id, fingerprint, status, posted_at) VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT(url) DO NOTHING",
+            url,
+            source,
+            source_id,
+            fingerprint,
+            status,
+            now
+            => |query, pool| query.execute(pool).await?.rows_affected()
+        ) > 0)
+    }
+
+    /// Record how several URLs were handled in a single transaction, so that either all or none of them are stored.
+    ///
+    /// Returns the number of URLs that were stored, with the rest having already been recorded or appearing
+    /// earlier in the same batch.
+    pub async fn record_batch(
+        &self,
+        entries: &[PostedEntry<'_>],
+        status: PostStatus,
+    ) -> Result<u64> {
+        debug!(
+            "Storing {} entries in posted_urls as {}",
+            entries.len(),
+            status.as_str()
+        );
+        let status = status.as_str();
+        let now = Utc::now().to_rfc3339();
+        let mut tx = self.begin().await?;
+        let mut stored = 0;
+        for entry in entries {
+            let url = self.normalise_url(entry.url);
+            stored += query_each!(
+                &mut tx,
+                sqlite: "INSERT INTO posted_urls (url, source, source_id, fingerprint, status, posted_at) VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT(url) DO NOTHING",
+                postgres: "INSERT INTO posted_urls (url, source, source_id, fingerprint, status, posted_at) VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT(url) DO NOTHING",
+                url,
+                entry.source,
+                entry.source_id,
+                entry.fingerprint,
+                status,
+                now
+                => |query, tx| query.execute(&mut **tx).await?.rows_affected()
+            );
+        }
+        tx.commit().await?;
+        Ok(stored)
+    }
+
+    /// Mark a pending URL as posted alongside the record it was published as and count it towards the post
+    /// counters, all in a single transaction.
+    ///
+    /// The content hash of an edited article stored by [`Database::set_pending_hash`] replaces the posted one, so
+    /// that the edit is only considered handled once its update has been published.
+    ///
+    /// Returns the number of posts made on the given UTC day, including this one.
+    pub async fn mark_published(
+        &self,
+        url: &str,
+        record: Option<(&str, &str)>,
+        day: NaiveDate,
+    ) -> Result<i64> {
+        let url = self.normalise_url(url);
+        let day = day.format("%Y-%m-%d").to_string();
+        debug!("Marking {url} as published in posted_urls");
+        let status = PostStatus::Posted.as_str();
+        let mut tx = self.begin().await?;
+        query_each!(
+            &mut tx,
+            sqlite: "UPDATE posted_urls SET status = ?, content_hash = COALESCE(pending_hash, content_hash), pending_hash = NULL WHERE url = ?",
+            postgres: "UPDATE posted_urls SET status = $1, content_hash = COALESCE(pending_hash, content_hash), pending_hash = NULL WHERE url = $2",
+            status,
+            url
+            => |query, tx| {
+                query.execute(&mut **tx).await?;
+            }
+        );
+        if let Some((uri, cid)) = record {
+            query_each!(
+                &mut tx,
+                sqlite: "UPDATE posted_urls SET record_uri = ?, record_cid = ? WHERE url = ?",
+                postgres: "UPDATE posted_urls SET record_uri = $1, record_cid = $2 WHERE url = $3",
+                uri,
+                cid,
+                url
+                => |query, tx| {
+                    query.execute(&mut **tx).await?;
+                }
+            );
+        }
+        query_each!(
+            &mut tx,
+            sqlite: "INSERT INTO article_post_counts (article, count) VALUES (?, 1) ON CONFLICT(article) DO UPDATE SET count = count + 1",
+            postgres: "INSERT INTO article_post_counts (article, count) VALUES ($1, 1)
+            ON CONFLICT(article) DO UPDATE SET count = article_post_counts.count + 1",
+            url
+            => |query, tx| {
+                query.execute(&mut **tx).await?;
+            }
+        );
+        let posted_today = query_each!(
+            &mut tx,
+            sqlite: "INSERT INTO daily_post_counts (day, count) VALUES (?, 1) ON CONFLICT(day) DO UPDATE SET count = count + 1 RETURNING count",
+            postgres: "INSERT INTO daily_post_counts (day, count) VALUES ($1, 1)
+            ON CONFLICT(day) DO UPDATE SET count = daily_post_counts.count + 1 RETURNING count",
+            day
+            => |query, tx| query.fetch_one(&mut **tx).await?.count
+        );
+        tx.commit().await?;
+        Ok(posted_today)
+    }
+
+    /// Remove URLs that were stored longer ago than the given age, returning how many were removed.
+    pub async fn remove_posts_older_than(&self, age: Duration) -> Result<u64> {
+        let cutoff = (Utc::now() - age).to_rfc3339();
+        debug!("Removing posted_urls entries stored before {cutoff}");
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "DELETE FROM posted_urls WHERE datetime(posted_at) < datetime(?)",
+            postgres: "DELETE FROM posted_urls WHERE posted_at::timestamptz < $1::text::timestamptz",
+            cutoff
+            => |query, pool| query.execute(pool).await?.rows_affected()
+        ))
+    }
+
+    /// Remove URLs stored longer ago than the given retention period along with any other data that is no
+    /// longer needed.
+    pub async fn remove_old_stored_posts(&self, retention: Duration) -> Result<()> {
+        let removed = self.remove_posts_older_than(retention).await?;
+        if removed > 0 {
+            debug!("Removed {removed} old posted_urls entries");
+        }
+        query_each!(
+            &self.pool,
+            sqlite: "DELETE FROM daily_post_counts WHERE day < date('now', '-30 days')",
+            postgres: "DELETE FROM daily_post_counts WHERE day < to_char((now() AT TIME ZONE 'UTC')::date - 30, 'YYYY-MM-DD')"
+            => |query, pool| {
+                query.execute(pool).await?;
+            }
+        );
+        // Articles rarely move, but resolving them again now and then picks up any that have.
+        query_each!(
+            &self.pool,
+            sqlite: "DELETE FROM canonical_urls WHERE resolved_at < datetime('now', '-30 days')",
+            postgres: "DELETE FROM canonical_urls WHERE resolved_at::timestamp < (now() AT TIME ZONE 'UTC') - interval '30 days'"
+            => |query, pool| {
+                query.execute(pool).await?;
+            }
+        );
+        // Post counts are kept for as long as the article is still known to have been posted.
+        query_each!(
+            &self.pool,
+            sqlite: "DELETE FROM article_post_counts WHERE article NOT IN (SELECT url FROM posted_urls)",
+            postgres: "DELETE FROM article_post_counts a WHERE NOT EXISTS (SELECT 1 FROM posted_urls p WHERE p.url = a.article)"
+            => |query, pool| {
+                query.execute(pool).await?;
+            }
+        );
         Ok(())
     }
 
-    pub async fn remove_old_stored_posts(&self) -> Result<()> {
-        debug!("Removing old posted_urls entries");
-        query!("DELETE FROM posted_urls WHERE ROWID IN (SELECT ROWID FROM posted_urls ORDER BY ROWID DESC LIMIT -1 OFFSET 25000)").execute(&self.pool).await?;
+    /// Undo marking a post as pending after it failed to publish, returning an update to an edited article to the
+    /// posted state it was in before and forgetting any other post so that it can be posted again.
+    pub async fn abandon_pending(&self, url: &str, is_update: bool) -> Result<()> {
+        match is_update {
+            true => self.set_posted_url_status(url, PostStatus::Posted).await?,
+            false => self.remove_posted_url(url).await?,
+        };
         Ok(())
     }
 
+    pub async fn set_posted_url_status(&self, url: &str, status: PostStatus) -> Result<bool> {
+        let url = self.normalise_url(url);
+        debug!("Marking {url} as {} in posted_urls", status.as_str());
+        let status = status.as_str();
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "UPDATE posted_urls SET status = ? WHERE url = ?",
+            postgres: "UPDATE posted_urls SET status = $1 WHERE url = $2",
+            status,
+            url
+            => |query, pool| query.execute(pool).await?.rows_affected()
+        ) > 0)
+    }
+
+    /// Store the Bluesky record that a URL was posted as, so that the post can be found again later.
+    pub async fn set_posted_record(&self, url: &str, uri: &str, cid: &str) -> Result<bool> {
+        let url = self.normalise_url(url);
+        debug!("Storing the record of {url} in posted_urls as {uri}");
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "UPDATE posted_urls SET record_uri = ?, record_cid = ? WHERE url = ?",
+            postgres: "UPDATE posted_urls SET record_uri = $1, record_cid = $2 WHERE url = $3",
+            uri,
+            cid,
+            url
+            => |query, pool| query.execute(pool).await?.rows_affected()
+        ) > 0)
+    }
+
+    /// Get the URLs stored in `posted_urls` that match a filter, most recently stored first.
+    pub async fn query_posts(&self, filter: &PostFilter) -> Result<Vec<PostedUrl>> {
+        debug!("Querying posted_urls with {filter:?}");
+        let since = filter.since.map(|since| since.to_rfc3339());
+        let before = filter.before.map(|before| before.to_rfc3339());
+        let contains = filter.contains.as_deref();
+        let prefix = filter.prefix.as_deref();
+        let source = filter.source.as_deref();
+        let source_id = filter.source_id.as_deref();
+        // A negative limit has no upper bound in SQLite and is treated as no limit in PostgreSQL.
+        let limit = filter.limit.map_or(-1, i64::from);
+        query_each!(
+            &self.pool,
+            sqlite: r#"SELECT url AS "url!", status, source, source_id, posted_at, record_uri FROM posted_urls
+            WHERE (?1 IS NULL OR datetime(posted_at) >= datetime(?1))
+            AND (?2 IS NULL OR datetime(posted_at) < datetime(?2))
+            AND (?3 IS NULL OR instr(lower(url), lower(?3)) > 0)
+            AND (?4 IS NULL OR substr(url, 1, length(?4)) = ?4)
+            AND (?5 IS NULL OR source = ?5)
+            AND (?6 IS NULL OR source_id = ?6)
+            ORDER BY posted_at IS NULL, datetime(posted_at) DESC, ROWID DESC
+            LIMIT ?7"#,
+            postgres: r#"SELECT url AS "url!", status, source, source_id, posted_at, record_uri FROM posted_urls
+            WHERE ($1::text IS NULL OR posted_at::timestamptz >= $1::text::timestamptz)
+            AND ($2::text IS NULL OR posted_at::timestamptz < $2::text::timestamptz)
+            AND ($3::text IS NULL OR strpos(lower(url), lower($3::text)) > 0)
+            AND ($4::text IS NULL OR starts_with(url, $4::text))
+            AND ($5::text IS NULL OR source = $5::text)
+            AND ($6::text IS NULL OR source_id = $6::text)
+            ORDER BY posted_at IS NULL, posted_at::timestamptz DESC, id DESC
+            LIMIT NULLIF($7::bigint, -1)"#,
+            since,
+            before,
+            contains,
+            prefix,
+            source,
+            source_id,
+            limit
+            => |query, pool| query
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .map(|row| {
+                    Ok(PostedUrl {
+                        url: row.url,
+                        status: row.status,
+                        source: row.source,
+                        source_id: row.source_id,
+                        posted_at: row.posted_at.as_deref().map(parse_timestamp).transpose()?,
+                        record_uri: row.record_uri,
+                    })
+                })
+                .collect()
+        )
+    }
+
+    /// Remove the URLs stored in `posted_urls` that match a filter, returning how many were removed.
+    ///
+    /// The same URLs are removed as would be returned by [`Database::query_posts`] with the same filter.
+    pub async fn remove_posts(&self, filter: &PostFilter) -> Result<u64> {
+        debug!("Removing posted_urls entries matching {filter:?}");
+        let since = filter.since.map(|since| since.to_rfc3339());
+        let before = filter.before.map(|before| before.to_rfc3339());
+        let contains = filter.contains.as_deref();
+        let prefix = filter.prefix.as_deref();
+        let source = filter.source.as_deref();
+        let source_id = filter.source_id.as_deref();
+        let limit = filter.limit.map_or(-1, i64::from);
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "DELETE FROM posted_urls WHERE url IN (SELECT url FROM posted_urls
+            WHERE (?1 IS NULL OR datetime(posted_at) >= datetime(?1))
+            AND (?2 IS NULL OR datetime(posted_at) < datetime(?2))
+            AND (?3 IS NULL OR instr(lower(url), lower(?3)) > 0)
+            AND (?4 IS NULL OR substr(url, 1, length(?4)) = ?4)
+            AND (?5 IS NULL OR source = ?5)
+            AND (?6 IS NULL OR source_id = ?6)
+            ORDER BY posted_at IS NULL, datetime(posted_at) DESC, ROWID DESC
+            LIMIT ?7)",
+            postgres: "DELETE FROM posted_urls WHERE url IN (SELECT url FROM posted_urls
+            WHERE ($1::text IS NULL OR posted_at::timestamptz >= $1::text::timestamptz)
+            AND ($2::text IS NULL OR posted_at::timestamptz < $2::text::timestamptz)
+            AND ($3::text IS NULL OR strpos(lower(url), lower($3::text)) > 0)
+            AND ($4::text IS NULL OR starts_with(url, $4::text))
+            AND ($5::text IS NULL OR source = $5::text)
+            AND ($6::text IS NULL OR source_id = $6::text)
+            ORDER BY posted_at IS NULL, posted_at::timestamptz DESC, id DESC
+            LIMIT NULLIF($7::bigint, -1))",
+            since,
+            before,
+            contains,
+            prefix,
+            source,
+            source_id,
+            limit
+            => |query, pool| query.execute(pool).await?.rows_affected()
+        ))
+    }
+
+    /// Get an overview of what is stored in the database.
+    pub async fn stats(&self) -> Result<DatabaseStats> {
+        debug!("Reading database stats");
+        let now = Utc::now();
+        let day_ago = (now - Duration::days(1)).to_rfc3339();
+        let week_ago = (now - Duration::days(7)).to_rfc3339();
+        let (posted_urls, oldest, newest, added_last_24h, added_last_7d, pending, failed) = query_each!(
+            &self.pool,
+            sqlite: r#"SELECT
+                COUNT(*) AS "total!: i64",
+                MIN(posted_at) AS "oldest?: String",
+                MAX(posted_at) AS "newest?: String",
+                COUNT(CASE WHEN datetime(posted_at) >= datetime(?1) THEN 1 END) AS "day!: i64",
+                COUNT(CASE WHEN datetime(posted_at) >= datetime(?2) THEN 1 END) AS "week!: i64",
+                COUNT(CASE WHEN status = 'pending' THEN 1 END) AS "pending!: i64",
+                (SELECT COUNT(*) FROM failed_posts) AS "failed!: i64"
+            FROM posted_urls"#,
+            postgres: r#"SELECT
+                COUNT(*) AS "total!: i64",
+                MIN(posted_at) AS "oldest?: String",
+                MAX(posted_at) AS "newest?: String",
+                COUNT(CASE WHEN posted_at::timestamptz >= $1::text::timestamptz THEN 1 END) AS "day!: i64",
+                COUNT(CASE WHEN posted_at::timestamptz >= $2::text::timestamptz THEN 1 END) AS "week!: i64",
+                COUNT(CASE WHEN status = 'pending' THEN 1 END) AS "pending!: i64",
+                (SELECT COUNT(*) FROM failed_posts) AS "failed!: i64"
+            FROM posted_urls"#,
+            day_ago,
+            week_ago
+            => |query, pool| {
+                let row = query.fetch_one(pool).await?;
+                (row.total, row.oldest, row.newest, row.day, row.week, row.pending, row.failed)
+            }
+        );
+        let size_bytes = self.size_bytes().await?;
+        Ok(DatabaseStats {
+            posted_urls,
+            oldest_posted_at: oldest.as_deref().map(parse_timestamp).transpose()?,
+            newest_posted_at: newest.as_deref().map(parse_timestamp).transpose()?,
+            added_last_24h,
+            added_last_7d,
+            pending,
+            failed,
+            size_bytes,
+        })
+    }
+
+    /// Get the space taken up by the database in bytes.
+    pub async fn size_bytes(&self) -> Result<i64> {
+        Ok(query_each!(
+            &self.pool,
+            sqlite: r#"SELECT page_count * page_size AS "size!: i64" FROM pragma_page_count(), pragma_page_size()"#,
+            postgres: r#"SELECT pg_database_size(current_database()) AS "size!: i64""#
+            => |query, pool| query.fetch_one(pool).await?.size
+        ))
+    }
+
+    /// Check the database for corruption, returning every problem that was found.
+    ///
+    /// Returns `None` for PostgreSQL databases, which have no equivalent check.
+    pub async fn check_integrity(&self) -> Result<Option<Vec<String>>> {
+        match &self.pool {
+            Backend::Sqlite(pool) => Ok(Some(integrity_problems(pool).await?)),
+            Backend::Postgres(_) => Ok(None),
+        }
+    }
+
+    /// Reclaim the space left behind by removed data and refresh the statistics used to plan queries.
+    pub async fn vacuum(&self) -> Result<()> {
+        debug!("Vacuuming the database");
+        if let Backend::Sqlite(pool) = &self.pool {
+            raw_sql("PRAGMA optimize").execute(pool).await?;
+        }
+        query_each!(
+            &self.pool,
+            sqlite: "VACUUM",
+            postgres: "VACUUM ANALYZE"
+            => |query, pool| {
+                query.execute(pool).await?;
+            }
+        );
+        self.set_state(LAST_MAINTENANCE_KEY, &Utc::now().to_rfc3339())
+            .await
+    }
+
+    /// Check the database for corruption and, if none is found, vacuum it.
+    pub async fn run_maintenance(&self) -> Result<MaintenanceReport> {
+        let size_before = self.size_bytes().await?;
+        let integrity_problems = self.check_integrity().await?;
+        if integrity_problems
+            .as_ref()
+            .is_some_and(|problems| !problems.is_empty())
+        {
+            return Ok(MaintenanceReport {
+                integrity_problems,
+                size_before,
+                size_after: None,
+            });
+        }
+        self.vacuum().await?;
+        Ok(MaintenanceReport {
+            integrity_problems,
+            size_before,
+            size_after: Some(self.size_bytes().await?),
+        })
+    }
+
+    /// Check whether it has been at least a week since the database was last vacuumed by [`Database::vacuum`].
+    pub async fn is_maintenance_due(&self) -> Result<bool> {
+        let last_maintenance = self
+            .get_state(LAST_MAINTENANCE_KEY)
+            .await?
+            .as_deref()
+            .map(parse_timestamp)
+            .transpose()?;
+        Ok(last_maintenance.is_none_or(|last_maintenance| {
+            Utc::now() - last_maintenance >= Duration::days(MAINTENANCE_INTERVAL_DAYS)
+        }))
+    }
+
+    /// Get the URI of the Bluesky record that a URL was posted as, if one was stored.
+    pub async fn get_posted_record_uri(&self, url: &str) -> Result<Option<String>> {
+        let url = self.normalise_url(url);
+        debug!("Reading the record of {url} from posted_urls");
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "SELECT record_uri FROM posted_urls WHERE url = ?",
+            postgres: "SELECT record_uri FROM posted_urls WHERE url = $1",
+            url
+            => |query, pool| query.fetch_optional(pool).await?.and_then(|row| row.record_uri)
+        ))
+    }
+
+    /// Get the URL that was posted as a Bluesky record, if it is stored.
+    pub async fn get_url_by_record_uri(&self, uri: &str) -> Result<Option<String>> {
+        debug!("Reading the URL posted as {uri} from posted_urls");
+        Ok(query_each!(
+            &self.pool,
+            sqlite: r#"SELECT url AS "url!" FROM posted_urls WHERE record_uri = ?"#,
+            postgres: r#"SELECT url AS "url!" FROM posted_urls WHERE record_uri = $1"#,
+            uri
+            => |query, pool| query.fetch_optional(pool).await?.map(|row| row.url)
+        ))
+    }
+
+    /// Get the URLs stored before a time alongside the Bluesky record they were posted as, oldest first, skipping
+    /// those without a stored record and those whose post has already been deleted.
+    pub async fn get_published_records_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<(String, String)>> {
+        debug!("Reading the records posted before {before} from posted_urls");
+        let before = before.to_rfc3339();
+        Ok(query_each!(
+            &self.pool,
+            sqlite: r#"SELECT url AS "url!", record_uri AS "record_uri!" FROM posted_urls
+            WHERE record_uri IS NOT NULL AND deleted_at IS NULL AND datetime(posted_at) < datetime(?)
+            ORDER BY datetime(posted_at)"#,
+            postgres: r#"SELECT url AS "url!", record_uri AS "record_uri!" FROM posted_urls
+            WHERE record_uri IS NOT NULL AND deleted_at IS NULL AND posted_at::timestamptz < $1::text::timestamptz
+            ORDER BY posted_at::timestamptz"#,
+            before
+            => |query, pool| query
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .map(|row| (row.url, row.record_uri))
+                .collect()
+        ))
+    }
+
+    pub async fn remove_posted_url(&self, url: &str) -> Result<bool> {
+        let url = self.normalise_url(url);
+        debug!("Removing {url} from posted_urls");
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "DELETE FROM posted_urls WHERE url = ?",
+            postgres: "DELETE FROM posted_urls WHERE url = $1",
+            url
+            => |query, pool| query.execute(pool).await?.rows_affected()
+        ) > 0)
+    }
+
+    /// Remove several URLs from `posted_urls` in a single transaction, so that an interruption removes none of them.
+    ///
+    /// Returns the number of URLs that were removed, with the rest not having been recorded.
+    pub async fn remove_posted_urls(&self, urls: &[String]) -> Result<u64> {
+        debug!("Removing {} entries from posted_urls", urls.len());
+        let mut tx = self.begin().await?;
+        let mut removed = 0;
+        for url in urls {
+            let url = self.normalise_url(url);
+            removed += query_each!(
+                &mut tx,
+                sqlite: "DELETE FROM posted_urls WHERE url = ?",
+                postgres: "DELETE FROM posted_urls WHERE url = $1",
+                url
+                => |query, tx| query.execute(&mut **tx).await?.rows_affected()
+            );
+        }
+        tx.commit().await?;
+        Ok(removed)
+    }
+
+    /// Get every URL that was being posted when the bot stopped and may or may not have been published.
+    pub async fn get_pending_urls(&self) -> Result<Vec<String>> {
+        debug!("Reading pending entries from posted_urls");
+        Ok(query_each!(
+            &self.pool,
+            sqlite: r#"SELECT url AS "url!" FROM posted_urls WHERE status = 'pending' ORDER BY ROWID"#,
+            postgres: r#"SELECT url AS "url!" FROM posted_urls WHERE status = 'pending' ORDER BY id"#
+            => |query, pool| query.fetch_all(pool).await?.into_iter().map(|row| row.url).collect()
+        ))
+    }
+
+    /// Check whether a URL has been handled in any way, including if it is pending or was only collected.
     pub async fn has_posted_url(&self, url: &str) -> Result<bool> {
+        let url = self.normalise_url(url);
         debug!("Checking if {url} exists in posted_urls table");
-        Ok(query!("SELECT url FROM posted_urls WHERE url = ?", url)
-            .fetch_optional(&self.pool)
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "SELECT url FROM posted_urls WHERE url = ?",
+            postgres: "SELECT url FROM posted_urls WHERE url = $1",
+            url
+            => |query, pool| query.fetch_optional(pool).await?.is_some()
+        ))
+    }
+
+    /// Check whether an item has been handled in any way by the id given to it by its source, such as `nikki:123`.
+    ///
+    /// Entries stored before ids were recorded can only be found by their URL.
+    pub async fn has_posted_source_id(&self, source_id: &str) -> Result<bool> {
+        debug!("Checking if {source_id} exists in posted_urls table");
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "SELECT url FROM posted_urls WHERE source_id = ?",
+            postgres: "SELECT url FROM posted_urls WHERE source_id = $1",
+            source_id
+            => |query, pool| query.fetch_optional(pool).await?.is_some()
+        ))
+    }
+
+    /// Record the source id of an entry that was stored before ids were recorded.
+    pub async fn set_posted_source_id(&self, url: &str, source_id: &str) -> Result<()> {
+        let url = self.normalise_url(url);
+        debug!("Storing source id of {url} in posted_urls");
+        query_each!(
+            &self.pool,
+            sqlite: "UPDATE posted_urls SET source_id = ? WHERE url = ? AND source_id IS NULL",
+            postgres: "UPDATE posted_urls SET source_id = $1 WHERE url = $2 AND source_id IS NULL",
+            source_id,
+            url
+            => |query, pool| {
+                query.execute(pool).await?;
+            }
+        );
+        Ok(())
+    }
+
+    /// Get the content hash of an article that has been posted, if one has been stored.
+    pub async fn get_posted_hash(&self, url: &str) -> Result<Option<String>> {
+        let url = self.normalise_url(url);
+        debug!("Reading content hash of {url} from posted_urls");
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "SELECT content_hash FROM posted_urls WHERE url = ? AND status = 'posted'",
+            postgres: "SELECT content_hash FROM posted_urls WHERE url = $1 AND status = 'posted'",
+            url
+            => |query, pool| query.fetch_optional(pool).await?.and_then(|row| row.content_hash)
+        ))
+    }
+
+    /// Store the content hash of an article that was posted before its content was tracked, leaving any hash that
+    /// is already stored as it is.
+    pub async fn seed_posted_hash(&self, url: &str, content_hash: &str) -> Result<()> {
+        let url = self.normalise_url(url);
+        debug!("Seeding content hash of {url} in posted_urls");
+        query_each!(
+            &self.pool,
+            sqlite: "UPDATE posted_urls SET content_hash = ? WHERE url = ? AND content_hash IS NULL",
+            postgres: "UPDATE posted_urls SET content_hash = $1 WHERE url = $2 AND content_hash IS NULL",
+            content_hash,
+            url
+            => |query, pool| {
+                query.execute(pool).await?;
+            }
+        );
+        Ok(())
+    }
+
+    /// Store the content hash of an edited article whose update is about to be posted, which replaces the posted hash
+    /// once the update has been published by [`Database::mark_published`] or [`Database::apply_pending_hash`].
+    pub async fn set_pending_hash(&self, url: &str, content_hash: &str) -> Result<()> {
+        let url = self.normalise_url(url);
+        debug!("Storing pending content hash of {url} in posted_urls");
+        query_each!(
+            &self.pool,
+            sqlite: "UPDATE posted_urls SET pending_hash = ? WHERE url = ?",
+            postgres: "UPDATE posted_urls SET pending_hash = $1 WHERE url = $2",
+            content_hash,
+            url
+            => |query, pool| {
+                query.execute(pool).await?;
+            }
+        );
+        Ok(())
+    }
+
+    /// Replace the posted content hash of an article with its pending one without publishing an update, such as
+    /// when the update was collected in collect-only mode.
+    pub async fn apply_pending_hash(&self, url: &str) -> Result<()> {
+        let url = self.normalise_url(url);
+        debug!("Applying pending content hash of {url} in posted_urls");
+        query_each!(
+            &self.pool,
+            sqlite: "UPDATE posted_urls SET content_hash = pending_hash, pending_hash = NULL WHERE url = ? AND pending_hash IS NOT NULL",
+            postgres: "UPDATE posted_urls SET content_hash = pending_hash, pending_hash = NULL WHERE url = $1 AND pending_hash IS NOT NULL",
+            url
+            => |query, pool| {
+                query.execute(pool).await?;
+            }
+        );
+        Ok(())
+    }
+
+    /// Get the canonical URL that a URL was previously resolved to.
+    pub async fn get_canonical_url(&self, url: &str) -> Result<Option<String>> {
+        debug!("Reading canonical URL of {url} from canonical_urls");
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "SELECT canonical_url FROM canonical_urls WHERE url = ?",
+            postgres: "SELECT canonical_url FROM canonical_urls WHERE url = $1",
+            url
+            => |query, pool| query.fetch_optional(pool).await?.map(|row| row.canonical_url)
+        ))
+    }
+
+    pub async fn set_canonical_url(&self, url: &str, canonical_url: &str) -> Result<()> {
+        debug!("Storing canonical URL of {url} in canonical_urls");
+        query_each!(
+            &self.pool,
+            sqlite: "INSERT INTO canonical_urls (url, canonical_url, resolved_at) VALUES (?, ?, datetime('now'))
+            ON CONFLICT(url) DO UPDATE SET canonical_url = excluded.canonical_url, resolved_at = excluded.resolved_at",
+            postgres: "INSERT INTO canonical_urls (url, canonical_url, resolved_at)
+            VALUES ($1, $2, to_char(now() AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS'))
+            ON CONFLICT(url) DO UPDATE SET canonical_url = excluded.canonical_url, resolved_at = excluded.resolved_at",
+            url,
+            canonical_url
+            => |query, pool| {
+                query.execute(pool).await?;
+            }
+        );
+        Ok(())
+    }
+
+    /// Get the number of times an article has been posted.
+    pub async fn get_article_post_count(&self, article: &str) -> Result<i64> {
+        let article = self.normalise_url(article);
+        debug!("Reading post count of {article} from article_post_counts");
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "SELECT count FROM article_post_counts WHERE article = ?",
+            postgres: "SELECT count FROM article_post_counts WHERE article = $1",
+            article
+            => |query, pool| query.fetch_optional(pool).await?.map_or(0, |row| row.count)
+        ))
+    }
+
+    /// Get the number of posts made on the given UTC day.
+    pub async fn get_daily_post_count(&self, day: NaiveDate) -> Result<i64> {
+        let day = day.format("%Y-%m-%d").to_string();
+        debug!("Reading post count of {day} from daily_post_counts");
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "SELECT count FROM daily_post_counts WHERE day = ?",
+            postgres: "SELECT count FROM daily_post_counts WHERE day = $1",
+            day
+            => |query, pool| query.fetch_optional(pool).await?.map_or(0, |row| row.count)
+        ))
+    }
+
+    /// Rewrite every URL in `posted_urls` and `article_post_counts` that was stored before it was normalised.
+    ///
+    /// Returns the number of URLs that were rewritten and how many of them were removed as duplicates of a URL
+    /// that was already stored.
+    pub async fn normalise_stored_urls(&self) -> Result<(u64, u64)> {
+        debug!("Normalising URLs stored in posted_urls");
+        let mut tx = self.begin().await?;
+        let (mut rewritten, mut collapsed) = (0, 0);
+        let urls: Vec<String> = query_each!(
+            &mut tx,
+            sqlite: r#"SELECT url AS "url!" FROM posted_urls ORDER BY ROWID"#,
+            postgres: r#"SELECT url AS "url!" FROM posted_urls ORDER BY id"#
+            => |query, tx| query.fetch_all(&mut **tx).await?.into_iter().map(|row| row.url).collect()
+        );
+        for url in urls {
+            let normalised = self.normalise_url(&url);
+            if normalised == url {
+                continue;
+            }
+            rewritten += 1;
+            let exists = query_each!(
+                &mut tx,
+                sqlite: "SELECT url FROM posted_urls WHERE url = ?",
+                postgres: "SELECT url FROM posted_urls WHERE url = $1",
+                normalised
+                => |query, tx| query.fetch_optional(&mut **tx).await?.is_some()
+            );
+            if exists {
+                // The URL that was stored first is kept as it is the one that was handled first.
+                query_each!(
+                    &mut tx,
+                    sqlite: "DELETE FROM posted_urls WHERE url = ?",
+                    postgres: "DELETE FROM posted_urls WHERE url = $1",
+                    url
+                    => |query, tx| {
+                        query.execute(&mut **tx).await?;
+                    }
+                );
+                collapsed += 1;
+            } else {
+                query_each!(
+                    &mut tx,
+                    sqlite: "UPDATE posted_urls SET url = ? WHERE url = ?",
+                    postgres: "UPDATE posted_urls SET url = $1 WHERE url = $2",
+                    normalised,
+                    url
+                    => |query, tx| {
+                        query.execute(&mut **tx).await?;
+                    }
+                );
+            }
+        }
+
+        let articles: Vec<(String, i64)> = query_each!(
+            &mut tx,
+            sqlite: "SELECT article, count FROM article_post_counts",
+            postgres: "SELECT article, count FROM article_post_counts FOR UPDATE"
+            => |query, tx| query
+                .fetch_all(&mut **tx)
+                .await?
+                .into_iter()
+                .map(|row| (row.article, row.count))
+                .collect()
+        );
+        for (article, count) in articles {
+            let normalised = self.normalise_url(&article);
+            if normalised == article {
+                continue;
+            }
+            query_each!(
+                &mut tx,
+                sqlite: "DELETE FROM article_post_counts WHERE article = ?",
+                postgres: "DELETE FROM article_post_counts WHERE article = $1",
+                article
+                => |query, tx| {
+                    query.execute(&mut **tx).await?;
+                }
+            );
+            query_each!(
+                &mut tx,
+                sqlite: "INSERT INTO article_post_counts (article, count) VALUES (?, ?) ON CONFLICT(article) DO UPDATE SET count = count + excluded.count",
+                postgres: "INSERT INTO article_post_counts (article, count) VALUES ($1, $2)
+                ON CONFLICT(article) DO UPDATE SET count = article_post_counts.count + excluded.count",
+                normalised,
+                count
+                => |query, tx| {
+                    query.execute(&mut **tx).await?;
+                }
+            );
+        }
+        tx.commit().await?;
+        Ok((rewritten, collapsed))
+    }
+
+    /// Remove every URL that was recorded in collect-only mode so that it becomes eligible for posting.
+    pub async fn promote_collected(&self) -> Result<u64> {
+        debug!("Removing collected entries from posted_urls");
+        let status = PostStatus::Collected.as_str();
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "DELETE FROM posted_urls WHERE status = ?",
+            postgres: "DELETE FROM posted_urls WHERE status = $1",
+            status
+            => |query, pool| query.execute(pool).await?.rows_affected()
+        ))
+    }
+
+    pub async fn has_posted_fingerprint(&self, fingerprint: &str) -> Result<bool> {
+        debug!("Checking if {fingerprint} exists in posted_urls table");
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "SELECT url FROM posted_urls WHERE fingerprint = ?",
+            postgres: "SELECT url FROM posted_urls WHERE fingerprint = $1",
+            fingerprint
+            => |query, pool| query.fetch_optional(pool).await?.is_some()
+        ))
+    }
+
+    pub async fn mark_posted_url_deleted(&self, url: &str) -> Result<bool> {
+        let url = self.normalise_url(url);
+        debug!("Marking {url} as deleted in posted_urls");
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "UPDATE posted_urls SET deleted_at = CURRENT_TIMESTAMP WHERE url = ?",
+            postgres: "UPDATE posted_urls SET deleted_at = to_char(now() AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS') WHERE url = $1",
+            url
+            => |query, pool| query.execute(pool).await?.rows_affected()
+        ) > 0)
+    }
+
+    pub async fn get_last_successful_fetch(&self) -> Result<Option<DateTime<Utc>>> {
+        self.get_state(LAST_SUCCESSFUL_FETCH_KEY)
             .await?
-            .is_some())
+            .as_deref()
+            .map(parse_timestamp)
+            .transpose()
+    }
+
+    /// Move the last successful fetch time forward to the given time.
+    ///
+    /// The comparison is done by the database so that a writer holding an older time can never move
+    /// it backwards, returning whether the stored time was changed.
+    pub async fn set_last_successful_fetch(&self, time: DateTime<Utc>) -> Result<bool> {
+        debug!("Advancing {LAST_SUCCESSFUL_FETCH_KEY} in state to {time}");
+        let time = time.to_rfc3339();
+        let rows_affected = query_each!(
+            &self.pool,
+            sqlite: "INSERT INTO state (key, value) VALUES (?, ?) ON CONFLICT(key) DO UPDATE SET value = excluded.value WHERE julianday(excluded.value) > julianday(state.value)",
+            postgres: "INSERT INTO state (key, value) VALUES ($1, $2)
+            ON CONFLICT(key) DO UPDATE SET value = excluded.value WHERE excluded.value::timestamptz > state.value::timestamptz",
+            LAST_SUCCESSFUL_FETCH_KEY,
+            time
+            => |query, pool| query.execute(pool).await?.rows_affected()
+        );
+        Ok(rows_affected != 0)
+    }
+
+    pub async fn get_state(&self, key: &str) -> Result<Option<String>> {
+        debug!("Reading {key} from state");
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "SELECT value FROM state WHERE key = ?",
+            postgres: "SELECT value FROM state WHERE key = $1",
+            key
+            => |query, pool| query.fetch_optional(pool).await?.map(|row| row.value)
+        ))
+    }
+
+    pub async fn set_state(&self, key: &str, value: &str) -> Result<()> {
+        debug!("Storing {key} in state");
+        query_each!(
+            &self.pool,
+            sqlite: "INSERT INTO state (key, value) VALUES (?, ?) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            postgres: "INSERT INTO state (key, value) VALUES ($1, $2) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            key,
+            value
+            => |query, pool| {
+                query.execute(pool).await?;
+            }
+        );
+        Ok(())
+    }
+
+    pub async fn remove_state(&self, key: &str) -> Result<()> {
+        debug!("Removing {key} from state");
+        query_each!(
+            &self.pool,
+            sqlite: "DELETE FROM state WHERE key = ?",
+            postgres: "DELETE FROM state WHERE key = $1",
+            key
+            => |query, pool| {
+                query.execute(pool).await?;
+            }
+        );
+        Ok(())
+    }
+
+    /// Record an attempt at fetching a feed, pruning the oldest attempts past the per-feed limit.
+    pub async fn add_fetch_attempt(&self, feed: &str, attempt: &FetchAttempt) -> Result<()> {
+        debug!("Storing fetch attempt of {feed} in fetch_history");
+        let fetched_at = attempt.fetched_at.to_rfc3339();
+        query_each!(
+            &self.pool,
+            sqlite: "INSERT INTO fetch_history (feed, fetched_at, error_class, error, item_count, duration_ms) VALUES (?, ?, ?, ?, ?, ?)",
+            postgres: "INSERT INTO fetch_history (feed, fetched_at, error_class, error, item_count, duration_ms) VALUES ($1, $2, $3, $4, $5, $6)",
+            feed,
+            fetched_at,
+            attempt.error_class,
+            attempt.error,
+            attempt.item_count,
+            attempt.duration_ms
+            => |query, pool| {
+                query.execute(pool).await?;
+            }
+        );
+        query_each!(
+            &self.pool,
+            sqlite: "DELETE FROM fetch_history WHERE feed = ? AND id <= (SELECT id FROM fetch_history WHERE feed = ? ORDER BY id DESC LIMIT 1 OFFSET ?)",
+            postgres: "DELETE FROM fetch_history WHERE feed = $1 AND id <= (SELECT id FROM fetch_history WHERE feed = $2 ORDER BY id DESC LIMIT 1 OFFSET $3)",
+            feed,
+            feed,
+            FETCH_HISTORY_PER_FEED
+            => |query, pool| {
+                query.execute(pool).await?;
+            }
+        );
+        Ok(())
+    }
+
+    /// Get the most recent attempts at fetching a feed, newest first.
+    pub async fn get_fetch_history(&self, feed: &str, limit: i64) -> Result<Vec<FetchAttempt>> {
+        debug!("Reading fetch attempts of {feed} from fetch_history");
+        query_each!(
+            &self.pool,
+            sqlite: "SELECT fetched_at, error_class, error, item_count, duration_ms FROM fetch_history WHERE feed = ? ORDER BY id DESC LIMIT ?",
+            postgres: "SELECT fetched_at, error_class, error, item_count, duration_ms FROM fetch_history WHERE feed = $1 ORDER BY id DESC LIMIT $2",
+            feed,
+            limit
+            => |query, pool| query
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .map(|row| {
+                    Ok(FetchAttempt {
+                        fetched_at: parse_timestamp(&row.fetched_at)?,
+                        error_class: row.error_class,
+                        error: row.error,
+                        item_count: row.item_count,
+                        duration_ms: row.duration_ms,
+                    })
+                })
+                .collect()
+        )
+    }
+
+    /// Get a summary of every feed that has a fetch attempt recorded.
+    pub async fn get_feed_summaries(&self) -> Result<Vec<FeedSummary>> {
+        debug!("Reading feed summaries from fetch_history");
+        query_each!(
+            &self.pool,
+            sqlite: r#"SELECT
+                feed AS "feed!",
+                (SELECT fetched_at FROM fetch_history h WHERE h.feed = f.feed ORDER BY id DESC LIMIT 1) AS "last_attempt!: String",
+                (SELECT error_class FROM fetch_history h WHERE h.feed = f.feed ORDER BY id DESC LIMIT 1) AS "last_error_class?: String",
+                (SELECT fetched_at FROM fetch_history h WHERE h.feed = f.feed AND error_class IS NULL ORDER BY id DESC LIMIT 1) AS "last_success?: String"
+            FROM fetch_history f GROUP BY feed ORDER BY feed"#,
+            postgres: r#"SELECT
+                f.feed AS "feed!",
+                (SELECT fetched_at FROM fetch_history h WHERE h.feed = f.feed ORDER BY h.id DESC LIMIT 1) AS "last_attempt!: String",
+                (SELECT error_class FROM fetch_history h WHERE h.feed = f.feed ORDER BY h.id DESC LIMIT 1) AS "last_error_class?: String",
+                (SELECT fetched_at FROM fetch_history h WHERE h.feed = f.feed AND h.error_class IS NULL ORDER BY h.id DESC LIMIT 1) AS "last_success?: String"
+            FROM fetch_history f GROUP BY f.feed ORDER BY f.feed"#
+            => |query, pool| query
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .map(|row| {
+                    Ok(FeedSummary {
+                        feed: row.feed,
+                        last_attempt: parse_timestamp(&row.last_attempt)?,
+                        last_error_class: row.last_error_class,
+                        last_success: row.last_success.as_deref().map(parse_timestamp).transpose()?,
+                    })
+                })
+                .collect()
+        )
+    }
+
+    /// Record a failed attempt at publishing a post, returning the number of attempts that have now been made.
+    pub async fn add_failed_post(
+        &self,
+        url: &str,
+        source: &str,
+        source_id: Option<&str>,
+        fingerprint: Option<&str>,
+        payload: &str,
+        error: &str,
+    ) -> Result<i64> {
+        debug!("Storing {url} in failed_posts");
+        let now = Utc::now().to_rfc3339();
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "INSERT INTO failed_posts (url, source, source_id, fingerprint, payload, error, attempts, last_attempt_at) VALUES (?, ?, ?, ?, ?, ?, 1, ?)
+            ON CONFLICT(url) DO UPDATE SET payload = excluded.payload, error = excluded.error, attempts = attempts + 1, last_attempt_at = excluded.last_attempt_at
+            RETURNING attempts",
+            postgres: "INSERT INTO failed_posts (url, source, source_id, fingerprint, payload, error, attempts, last_attempt_at) VALUES ($1, $2, $3, $4, $5, $6, 1, $7)
+            ON CONFLICT(url) DO UPDATE SET payload = excluded.payload, error = excluded.error, attempts = failed_posts.attempts + 1, last_attempt_at = excluded.last_attempt_at
+            RETURNING attempts",
+            url,
+            source,
+            source_id,
+            fingerprint,
+            payload,
+            error,
+            now
+            => |query, pool| query.fetch_one(pool).await?.attempts
+        ))
+    }
+
+    /// Queue a post to be published later without counting it as a failed attempt, such as when it was rate limited.
+    pub async fn defer_post(
+        &self,
+        url: &str,
+        source: &str,
+        source_id: Option<&str>,
+        fingerprint: Option<&str>,
+        payload: &str,
+        reason: &str,
+    ) -> Result<()> {
+        debug!("Deferring {url} in failed_posts");
+        let now = Utc::now().to_rfc3339();
+        query_each!(
+            &self.pool,
+            sqlite: "INSERT INTO failed_posts (url, source, source_id, fingerprint, payload, error, attempts, last_attempt_at) VALUES (?, ?, ?, ?, ?, ?, 0, ?)
+            ON CONFLICT(url) DO UPDATE SET payload = excluded.payload, error = excluded.error, last_attempt_at = excluded.last_attempt_at",
+            postgres: "INSERT INTO failed_posts (url, source, source_id, fingerprint, payload, error, attempts, last_attempt_at) VALUES ($1, $2, $3, $4, $5, $6, 0, $7)
+            ON CONFLICT(url) DO UPDATE SET payload = excluded.payload, error = excluded.error, last_attempt_at = excluded.last_attempt_at",
+            url,
+            source,
+            source_id,
+            fingerprint,
+            payload,
+            reason,
+            now
+            => |query, pool| {
+                query.execute(pool).await?;
+            }
+        );
+        Ok(())
+    }
+
+    pub async fn has_failed_post(&self, url: &str) -> Result<bool> {
+        debug!("Checking if {url} exists in failed_posts table");
+        Ok(query_each!(
+            &self.pool,
+            sqlite: "SELECT url FROM failed_posts WHERE url = ?",
+            postgres: "SELECT url FROM failed_posts WHERE url = $1",
+            url
+            => |query, pool| query.fetch_optional(pool).await?.is_some()
+        ))
+    }
+
+    /// Get every failed post, optionally only those that have been attempted fewer than the given number of times.
+    pub async fn get_failed_posts(&self, max_attempts: Option<i64>) -> Result<Vec<FailedPost>> {
+        debug!("Reading failed_posts");
+        let max_attempts = max_attempts.unwrap_or(i64::MAX);
+        query_each!(
+            &self.pool,
+            sqlite: "SELECT url, source, source_id, fingerprint, payload, error, attempts, last_attempt_at FROM failed_posts WHERE attempts < ? ORDER BY last_attempt_at",
+            postgres: "SELECT url, source, source_id, fingerprint, payload, error, attempts, last_attempt_at FROM failed_posts WHERE attempts < $1 ORDER BY last_attempt_at",
+            max_attempts
+            => |query, pool| query
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .map(|row| {
+                    Ok(FailedPost {
+                        url: row.url,
+                        source: row.source,
+                        source_id: row.source_id,
+                        fingerprint: row.fingerprint,
+                        payload: row.payload,
+                        error: row.error,
+                        attempts: row.attempts,
+                        last_attempt_at: parse_timestamp(&row.last_attempt_at)?,
+                    })
+                })
+                .collect()
+        )
+    }
+
+    pub async fn remove_failed_post(&self, url: &str) -> Result<()> {
+        debug!("Removing {url} from failed_posts");
+        query_each!(
+            &self.pool,
+            sqlite: "DELETE FROM failed_posts WHERE url = ?",
+            postgres: "DELETE FROM failed_posts WHERE url = $1",
+            url
+            => |query, pool| {
+                query.execute(pool).await?;
+            }
+        );
+        Ok(())
+    }
+
+    /// Write a consistent snapshot of the database to a new file, even while another process is writing to it.
+    pub async fn backup(&self, path: &Path) -> Result<()> {
+        let Backend::Sqlite(pool) = &self.pool else {
+            bail!(
+                "only sqlite databases can be backed up: use pg_dump to back up a PostgreSQL database"
+            );
+        };
+        let Some(path) = path.to_str() else {
+            bail!("{} is not valid UTF-8", path.display());
+        };
+        debug!("Backing up the database to {path}");
+        query!("VACUUM INTO ?", path).execute(pool).await?;
+        Ok(())
+    }
+
+    /// Write a consistent snapshot of the sqlite database at `source` to a new file, including anything committed to
+    /// its write-ahead log, without migrating or otherwise changing it.
+    pub async fn copy_sqlite_file(source: &Path, destination: &Path) -> Result<()> {
+        let Some(destination) = destination.to_str() else {
+            bail!("{} is not valid UTF-8", destination.display());
+        };
+        let mut connection =
+            SqliteConnection::connect_with(&SqliteConnectOptions::new().filename(source))
+                .await
+                .with_context(|| format!("failed to open the database at {}", source.display()))?;
+        debug!(
+            "Copying the database at {} to {destination}",
+            source.display()
+        );
+        query!("VACUUM INTO ?", destination)
+            .execute(&mut connection)
+            .await?;
+        connection.close().await?;
+        Ok(())
+    }
+
+    /// Replace everything stored in the database with the contents of a backup made by [`Database::backup`].
+    ///
+    /// A copy of the backup is brought up to date with the current schema and checked for corruption first, and
+    /// nothing is replaced if another process is writing to the database.
+    pub async fn restore(&self, path: &Path) -> Result<()> {
+        let Backend::Sqlite(pool) = &self.pool else {
+            bail!(
+                "only sqlite databases can be restored: use pg_restore to restore a PostgreSQL database"
+            );
+        };
+        let options = pool.connect_options();
+        let snapshot_path = format!("{}.restore", options.get_filename().display());
+        std::fs::copy(path, &snapshot_path)
+            .with_context(|| format!("failed to copy the backup at {}", path.display()))?;
+        let result = Self::restore_snapshot(&options, &snapshot_path).await;
+        std::fs::remove_file(&snapshot_path)?;
+        result
+    }
+
+    async fn restore_snapshot(options: &SqliteConnectOptions, snapshot_path: &str) -> Result<()> {
+        debug!("Checking the backup copied to {snapshot_path}");
+        let snapshot =
+            SqlitePool::connect_with(SqliteConnectOptions::new().filename(snapshot_path))
+                .await
+                .context("failed to open the backup")?;
+        migrate!()
+            .run(&snapshot)
+            .await
+            .context("failed to bring the backup up to date")?;
+        let problems = integrity_problems(&snapshot).await?;
+        snapshot.close().await;
+        if !problems.is_empty() {
+            bail!(
+                "the backup failed its integrity check: {}",
+                problems.join("; ")
+            );
+        }
+
+        debug!("Replacing the database with the backup");
+        // Waiting for another writer would only delay replacing data that it is still using.
+        let mut conn = SqliteConnection::connect_with(
+            &options.clone().busy_timeout(std::time::Duration::ZERO),
+        )
+        .await?;
+        query!("ATTACH DATABASE ? AS snapshot", snapshot_path)
+            .execute(&mut conn)
+            .await?;
+        let mut tx = conn.begin_with("BEGIN IMMEDIATE").await.context(
+            "another process is writing to the database: stop it before restoring a backup",
+        )?;
+        let script = RESTORED_TABLES
+            .map(|table| {
+                format!("DELETE FROM main.{table}; INSERT INTO main.{table} SELECT * FROM snapshot.{table};")
+            })
+            .join("\n");
+        raw_sql(&script).execute(&mut *tx).await?;
+        tx.commit().await?;
+        query!("DETACH DATABASE snapshot")
+            .execute(&mut conn)
+            .await?;
+        conn.close().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_in_memory_urls() {
+        assert!(is_in_memory("sqlite::memory:"));
+        assert!(is_in_memory("sqlite://:memory:"));
+        assert!(is_in_memory("sqlite://db?mode=memory&cache=shared"));
+        assert!(!is_in_memory("sqlite://data/db.sqlite3?mode=rwc"));
+        assert!(!is_in_memory("sqlite://memory.sqlite3"));
+    }
+
+    /// Read the value of a pragma from a connection to a sqlite database.
+    async fn pragma(database: &Database, name: &str) -> String {
+        use sqlx::Row;
+
+        let Backend::Sqlite(pool) = &database.pool else {
+            unreachable!("only sqlite databases have pragmas");
+        };
+        let row = raw_sql(&format!("PRAGMA {name}"))
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        row.try_get::<String, _>(0)
+            .or_else(|_| row.try_get::<i64, _>(0).map(|value| value.to_string()))
+            .unwrap()
+    }
+
+    /// The URL of a sqlite database file in a directory, followed by any extra connection parameters.
+    fn sqlite_url(dir: &Path, params: &str) -> String {
+        format!(
+            "sqlite://{}?mode=rwc{params}",
+            dir.join("db.sqlite3").display()
+        )
+    }
+
+    #[tokio::test]
+    async fn sqlite_databases_use_wal_mode_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::new(&sqlite_url(dir.path(), ""), &[])
+            .await
+            .unwrap();
+        assert_eq!(pragma(&database, "journal_mode").await, "wal");
+        assert_eq!(pragma(&database, "busy_timeout").await, "10000");
+        assert_eq!(pragma(&database, "foreign_keys").await, "1");
+        // Synchronous modes are read back as numbers, where 1 is NORMAL and 2 is FULL.
+        assert_eq!(pragma(&database, "synchronous").await, "1");
+    }
+
+    #[tokio::test]
+    async fn sqlite_defaults_can_be_overridden_by_the_database_url() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::new(
+            &sqlite_url(
+                dir.path(),
+                "&journal_mode=delete&busy_timeout=250&foreign_keys=off&synchronous=full",
+            ),
+            &[],
+        )
+        .await
+        .unwrap();
+        assert_eq!(pragma(&database, "journal_mode").await, "delete");
+        assert_eq!(pragma(&database, "busy_timeout").await, "250");
+        assert_eq!(pragma(&database, "foreign_keys").await, "0");
+        assert_eq!(pragma(&database, "synchronous").await, "2");
+
+        for params in [
+            "&busy_timeout=soon",
+            "&foreign_keys=maybe",
+            "&journal_mode=paper",
+        ] {
+            assert!(
+                Database::new(&sqlite_url(dir.path(), params), &[])
+                    .await
+                    .is_err(),
+                "{params}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn copies_include_changes_still_in_the_write_ahead_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::new(&sqlite_url(dir.path(), ""), &[])
+            .await
+            .unwrap();
+        database
+            .record_posted(
+                "https://example.com/news/1",
+                "test",
+                None,
+                None,
+                PostStatus::Posted,
+            )
+            .await
+            .unwrap();
+        // The database is still open, so the URL hasn't been checkpointed out of the log yet.
+        assert!(dir.path().join("db.sqlite3-wal").metadata().unwrap().len() > 0);
+
+        let copy = dir.path().join("copy.sqlite3");
+        Database::copy_sqlite_file(&dir.path().join("db.sqlite3"), &copy)
+            .await
+            .unwrap();
+        assert!(!dir.path().join("copy.sqlite3-wal").exists());
+        let copy = Database::new(&format!("sqlite://{}", copy.display()), &[])
+            .await
+            .unwrap();
+        assert!(
+            copy.has_posted_url("https://example.com/news/1")
+                .await
+                .unwrap()
+        );
+        assert!(
+            Database::copy_sqlite_file(&dir.path().join("missing.sqlite3"), &dir.path().join("x"))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn separate_pools_can_write_to_the_same_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = sqlite_url(dir.path(), "");
+        // Like a database command run while the bot is in the middle of posting.
+        let (bot, command) = (
+            Database::new(&url, &[]).await.unwrap(),
+            Database::new(&url, &[]).await.unwrap(),
+        );
+        let today = Utc::now().date_naive();
+        let bot_writes = async {
+            for n in 0..50 {
+                let url = format!("https://example.com/bot/{n}");
+                bot.record_posted(&url, "test", None, None, PostStatus::Pending)
+                    .await
+                    .unwrap();
+                bot.mark_published(&url, None, today).await.unwrap();
+            }
+        };
+        let command_writes = async {
+            for n in 0..50 {
+                command
+                    .record_batch(
+                        &[PostedEntry {
+                            url: &format!("https://example.com/command/{n}"),
+                            source: MANUAL_SOURCE,
+                            source_id: None,
+                            fingerprint: None,
+                        }],
+                        PostStatus::Posted,
+                    )
+                    .await
+                    .unwrap();
+            }
+        };
+        tokio::join!(bot_writes, command_writes);
+        assert_eq!(bot.stats().await.unwrap().posted_urls, 100);
+        assert_eq!(command.get_daily_post_count(today).await.unwrap(), 50);
+    }
+
+    #[tokio::test]
+    async fn in_memory_database_is_shared_between_queries() {
+        let database = Database::new_in_memory().await.unwrap();
+        assert!(
+            database
+                .record_posted(
+                    "https://example.com/a",
+                    MANUAL_SOURCE,
+                    None,
+                    None,
+                    PostStatus::Posted
+                )
+                .await
+                .unwrap()
+        );
+        // Every query runs on the connection that the migrations were applied to, so the row is visible.
+        assert!(
+            database
+                .has_posted_url("https://example.com/a")
+                .await
+                .unwrap()
+        );
+        assert_eq!(database.stats().await.unwrap().posted_urls, 1);
+    }
+
+    #[tokio::test]
+    async fn published_records_are_found_until_deleted() {
+        let database = Database::new_in_memory().await.unwrap();
+        let (url, uri) = (
+            "https://example.com/a",
+            "at://did:plc:example/app.bsky.feed.post/a",
+        );
+        database
+            .record_posted(url, MANUAL_SOURCE, None, None, PostStatus::Pending)
+            .await
+            .unwrap();
+        database
+            .mark_published(url, Some((uri, "cid")), Utc::now().date_naive())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            database
+                .get_url_by_record_uri(uri)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some(url)
+        );
+        let later = Utc::now() + Duration::seconds(1);
+        let earlier = Utc::now() - Duration::hours(1);
+        assert_eq!(
+            database.get_published_records_before(later).await.unwrap(),
+            [(url.to_string(), uri.to_string())]
+        );
+        assert!(
+            database
+                .get_published_records_before(earlier)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        database.mark_posted_url_deleted(url).await.unwrap();
+        assert!(
+            database
+                .get_published_records_before(later)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn posted_records_are_stored_with_their_url() {
+        let database = Database::new("sqlite::memory:", &["utm_*".to_string()])
+            .await
+            .unwrap();
+        let (url, uri) = (
+            "https://example.com/a",
+            "at://did:plc:example/app.bsky.feed.post/a",
+        );
+        database
+            .record_posted(url, MANUAL_SOURCE, None, None, PostStatus::Posted)
+            .await
+            .unwrap();
+        assert_eq!(database.get_posted_record_uri(url).await.unwrap(), None);
+
+        // The URL is normalised the same way as when it was recorded.
+        assert!(
+            database
+                .set_posted_record(&format!("{url}?utm_source=rss"), uri, "cid")
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            database
+                .get_posted_record_uri(url)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some(uri)
+        );
+        let posts = database.query_posts(&PostFilter::default()).await.unwrap();
+        assert_eq!(posts[0].record_uri.as_deref(), Some(uri));
+
+        assert!(
+            !database
+                .set_posted_record("https://example.com/unknown", uri, "cid")
+                .await
+                .unwrap()
+        );
+    }
+
+    /// Change when a URL was stored, or forget it like URLs stored before the time was recorded.
+    async fn set_posted_at(database: &Database, url: &str, posted_at: Option<DateTime<Utc>>) {
+        let posted_at = posted_at.map(|time| time.to_rfc3339());
+        match &database.pool {
+            Backend::Sqlite(pool) => {
+                sqlx::query("UPDATE posted_urls SET posted_at = ? WHERE url = ?")
+                    .bind(posted_at)
+                    .bind(url)
+                    .execute(pool)
+                    .await
+                    .unwrap();
+            }
+            Backend::Postgres(pool) => {
+                sqlx::query("UPDATE posted_urls SET posted_at = $1 WHERE url = $2")
+                    .bind(posted_at)
+                    .bind(url)
+                    .execute(pool)
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+
+    /// The URLs returned by [`Database::query_posts`] for a filter.
+    async fn query_urls(database: &Database, filter: PostFilter) -> Vec<String> {
+        database
+            .query_posts(&filter)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|post| post.url)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn posts_are_queried_newest_first_with_filters() {
+        let database = Database::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        for (url, source, source_id, age) in [
+            (
+                "https://example.com/news/old",
+                "nikki:en",
+                Some("nikki:1"),
+                Some(48),
+            ),
+            (
+                "https://example.com/news/new",
+                "nikki:en",
+                Some("nikki:2"),
+                Some(1),
+            ),
+            ("https://example.org/feed/item", "rss:feed", None, Some(12)),
+            ("https://example.com/news/unknown", "unknown", None, None),
+        ] {
+            database
+                .record_posted(url, source, source_id, None, PostStatus::Posted)
+                .await
+                .unwrap();
+            set_posted_at(
+                &database,
+                url,
+                age.map(|hours| now - Duration::hours(hours)),
+            )
+            .await;
+        }
+
+        assert_eq!(
+            query_urls(&database, PostFilter::default()).await,
+            [
+                "https://example.com/news/new",
+                "https://example.org/feed/item",
+                "https://example.com/news/old",
+                "https://example.com/news/unknown",
+            ]
+        );
+        // URLs without a known time are left out once filtering by time.
+        assert_eq!(
+            query_urls(
+                &database,
+                PostFilter {
+                    since: Some(now - Duration::hours(24)),
+                    ..Default::default()
+                }
+            )
+            .await,
+            [
+                "https://example.com/news/new",
+                "https://example.org/feed/item"
+            ]
+        );
+        assert_eq!(
+            query_urls(
+                &database,
+                PostFilter {
+                    before: Some(now - Duration::hours(6)),
+                    ..Default::default()
+                }
+            )
+            .await,
+            [
+                "https://example.org/feed/item",
+                "https://example.com/news/old"
+            ]
+        );
+        assert_eq!(
+            query_urls(
+                &database,
+                PostFilter {
+                    contains: Some("NEWS".to_string()),
+                    limit: Some(2),
+                    ..Default::default()
+                }
+            )
+            .await,
+            [
+                "https://example.com/news/new",
+                "https://example.com/news/old"
+            ]
+        );
+        assert_eq!(
+            query_urls(
+                &database,
+                PostFilter {
+                    prefix: Some("https://example.org/".to_string()),
+                    ..Default::default()
+                }
+            )
+            .await,
+            ["https://example.org/feed/item"]
+        );
+        assert_eq!(
+            query_urls(
+                &database,
+                PostFilter {
+                    source: Some("nikki:en".to_string()),
+                    since: Some(now - Duration::hours(24)),
+                    ..Default::default()
+                }
+            )
+            .await,
+            ["https://example.com/news/new"]
+        );
+        assert_eq!(
+            query_urls(
+                &database,
+                PostFilter {
+                    source_id: Some("nikki:1".to_string()),
+                    ..Default::default()
+                }
+            )
+            .await,
+            ["https://example.com/news/old"]
+        );
+        assert!(
+            query_urls(
+                &database,
+                PostFilter {
+                    contains: Some("missing".to_string()),
+                    ..Default::default()
+                }
+            )
+            .await
+            .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn posted_urls_are_kept_for_the_retention_period() {
+        let database = Database::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        let today = now.date_naive();
+        for (url, age) in [
+            ("https://example.com/old", 200),
+            ("https://example.com/recent", 1),
+        ] {
+            database
+                .record_posted(url, "test", None, None, PostStatus::Pending)
+                .await
+                .unwrap();
+            database.mark_published(url, None, today).await.unwrap();
+            set_posted_at(&database, url, Some(now - Duration::days(age))).await;
+        }
+
+        assert_eq!(
+            database
+                .remove_posts_older_than(Duration::days(365))
+                .await
+                .unwrap(),
+            0
+        );
+        database
+            .remove_old_stored_posts(Duration::days(180))
+            .await
+            .unwrap();
+        assert!(
+            !database
+                .has_posted_url("https://example.com/old")
+                .await
+                .unwrap()
+        );
+        assert!(
+            database
+                .has_posted_url("https://example.com/recent")
+                .await
+                .unwrap()
+        );
+        // The post counts of removed URLs go with them.
+        assert_eq!(
+            database
+                .get_article_post_count("https://example.com/old")
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            database
+                .get_article_post_count("https://example.com/recent")
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn recording_a_url_twice_keeps_the_first_entry() {
+        let database = Database::new_in_memory().await.unwrap();
+        let url = "https://example.com/a";
+        assert!(
+            database
+                .record_posted(url, "nikki:en", None, None, PostStatus::Posted)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !database
+                .record_posted(url, MANUAL_SOURCE, None, None, PostStatus::Collected)
+                .await
+                .unwrap()
+        );
+        let posts = database.query_posts(&PostFilter::default()).await.unwrap();
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].source, "nikki:en");
+        assert_eq!(posts[0].status, "posted");
+
+        // Duplicates within a batch and of URLs already stored are both left out.
+        let entry = |url| PostedEntry {
+            url,
+            source: MANUAL_SOURCE,
+            source_id: None,
+            fingerprint: None,
+        };
+        assert_eq!(
+            database
+                .record_batch(
+                    &[
+                        entry(url),
+                        entry("https://example.com/b"),
+                        entry("https://example.com/b"),
+                    ],
+                    PostStatus::Posted
+                )
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(database.stats().await.unwrap().posted_urls, 2);
+    }
+
+    #[tokio::test]
+    async fn only_one_of_several_concurrent_inserts_is_stored() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!(
+            "sqlite://{}?mode=rwc",
+            dir.path().join("db.sqlite3").display()
+        );
+        let database = Database::new(&url, &[]).await.unwrap();
+        let insert = || {
+            database.record_posted(
+                "https://example.com/a",
+                "test",
+                None,
+                None,
+                PostStatus::Pending,
+            )
+        };
+        let (a, b, c, d) = tokio::join!(insert(), insert(), insert(), insert());
+        assert_eq!(
+            [a, b, c, d]
+                .into_iter()
+                .filter(|inserted| *inserted.as_ref().unwrap())
+                .count(),
+            1
+        );
+    }
+
+    /// Make every insert into a table fail when a column has the given value, such as to fail part way through a
+    /// transaction.
+    async fn reject_inserts(database: &Database, table: &str, column: &str, value: &str) {
+        let Backend::Sqlite(pool) = &database.pool else {
+            unreachable!("tests use sqlite databases");
+        };
+        raw_sql(&format!(
+            "CREATE TRIGGER reject_{table} BEFORE INSERT ON {table} WHEN NEW.{column} = '{value}'
+            BEGIN SELECT RAISE(ABORT, 'rejected'); END"
+        ))
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn batches_that_fail_part_way_store_nothing() {
+        let database = Database::new_in_memory().await.unwrap();
+        reject_inserts(&database, "posted_urls", "url", "https://example.com/bad").await;
+        let entry = |url| PostedEntry {
+            url,
+            source: MANUAL_SOURCE,
+            source_id: None,
+            fingerprint: None,
+        };
+        assert!(
+            database
+                .record_batch(
+                    &[
+                        entry("https://example.com/a"),
+                        entry("https://example.com/bad"),
+                        entry("https://example.com/b"),
+                    ],
+                    PostStatus::Posted
+                )
+                .await
+                .is_err()
+        );
+        assert_eq!(database.stats().await.unwrap().posted_urls, 0);
+
+        assert_eq!(
+            database
+                .record_batch(
+                    &[
+                        entry("https://example.com/a"),
+                        entry("https://example.com/b")
+                    ],
+                    PostStatus::Posted
+                )
+                .await
+                .unwrap(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn removals_that_fail_part_way_remove_nothing() {
+        let database = Database::new_in_memory().await.unwrap();
+        let urls = [
+            "https://example.com/a".to_string(),
+            "https://example.com/bad".to_string(),
+            "https://example.com/b".to_string(),
+        ];
+        for url in &urls {
+            database
+                .record_posted(url, "test", None, None, PostStatus::Posted)
+                .await
+                .unwrap();
+        }
+        let Backend::Sqlite(pool) = &database.pool else {
+            unreachable!("tests use sqlite databases");
+        };
+        raw_sql(
+            "CREATE TRIGGER reject_delete BEFORE DELETE ON posted_urls WHEN OLD.url = 'https://example.com/bad'
+            BEGIN SELECT RAISE(ABORT, 'rejected'); END",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        assert!(database.remove_posted_urls(&urls).await.is_err());
+        assert_eq!(database.stats().await.unwrap().posted_urls, 3);
+
+        raw_sql("DROP TRIGGER reject_delete")
+            .execute(pool)
+            .await
+            .unwrap();
+        // URLs that aren't recorded, including repeats, aren't counted as removed.
+        assert_eq!(
+            database
+                .remove_posted_urls(&[
+                    urls[0].clone(),
+                    urls[0].clone(),
+                    "https://example.com/missing".to_string(),
+                ])
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(database.stats().await.unwrap().posted_urls, 2);
+    }
+
+    #[tokio::test]
+    async fn publishing_that_fails_part_way_changes_nothing() {
+        let database = Database::new_in_memory().await.unwrap();
+        let url = "https://example.com/a";
+        let today = NaiveDate::from_ymd_opt(2025, 3, 31).unwrap();
+        reject_inserts(&database, "daily_post_counts", "day", "2025-03-31").await;
+        // An update to an article that was already posted.
+        database
+            .record_posted(url, "test", None, None, PostStatus::Posted)
+            .await
+            .unwrap();
+        database.seed_posted_hash(url, "first").await.unwrap();
+        database.set_pending_hash(url, "second").await.unwrap();
+        database
+            .set_posted_url_status(url, PostStatus::Pending)
+            .await
+            .unwrap();
+
+        assert!(
+            database
+                .mark_published(url, Some(("at://record", "cid")), today)
+                .await
+                .is_err()
+        );
+        // The post is still pending with nothing counted, rather than half published.
+        assert_eq!(database.get_pending_urls().await.unwrap(), [url]);
+        assert_eq!(database.get_posted_record_uri(url).await.unwrap(), None);
+        assert_eq!(database.get_article_post_count(url).await.unwrap(), 0);
+        database.abandon_pending(url, true).await.unwrap();
+        assert_eq!(
+            database.get_posted_hash(url).await.unwrap().as_deref(),
+            Some("first")
+        );
+    }
+
+    #[tokio::test]
+    async fn posts_are_removed_by_each_matcher() {
+        let database = Database::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        let urls = [
+            ("https://example.com/kr/news/1", "nikki:kr", 48),
+            ("https://example.com/en/news/1", "nikki:en", 1),
+            ("https://example.com/en/news/100%_off", "nikki:en", 1),
+            ("https://example.org/feed/item", "rss:feed", 1),
+        ];
+        for (url, source, age) in urls {
+            database
+                .record_posted(url, source, None, None, PostStatus::Posted)
+                .await
+                .unwrap();
+            set_posted_at(&database, url, Some(now - Duration::hours(age))).await;
+        }
+        let remove = |filter| {
+            let database = &database;
+            async move { database.remove_posts(&filter).await.unwrap() }
+        };
+
+        // Patterns are matched literally rather than as SQL, so wildcards and quotes have no special meaning.
+        for pattern in ["%", "_", "'; DROP TABLE posted_urls; --"] {
+            assert_eq!(
+                remove(PostFilter {
+                    prefix: Some(pattern.to_string()),
+                    ..Default::default()
+                })
+                .await,
+                0
+            );
+        }
+        assert_eq!(
+            remove(PostFilter {
+                contains: Some("100%_OFF".to_string()),
+                ..Default::default()
+            })
+            .await,
+            1
+        );
+        assert_eq!(
+            remove(PostFilter {
+                contains: Some("/KR/NEWS/".to_string()),
+                ..Default::default()
+            })
+            .await,
+            1
+        );
+        assert_eq!(
+            remove(PostFilter {
+                prefix: Some("https://example.org/".to_string()),
+                ..Default::default()
+            })
+            .await,
+            1
+        );
+        assert_eq!(
+            remove(PostFilter {
+                before: Some(now - Duration::hours(24)),
+                ..Default::default()
+            })
+            .await,
+            0
+        );
+        assert_eq!(
+            remove(PostFilter {
+                source: Some("nikki:en".to_string()),
+                ..Default::default()
+            })
+            .await,
+            1
+        );
+        assert_eq!(database.stats().await.unwrap().posted_urls, 0);
+    }
+
+    #[tokio::test]
+    async fn only_the_newest_fetch_attempts_of_each_feed_are_kept() {
+        let database = Database::new_in_memory().await.unwrap();
+        let attempt = |item_count| FetchAttempt {
+            fetched_at: Utc::now(),
+            error_class: None,
+            error: None,
+            item_count,
+            duration_ms: 10,
+        };
+        for item_count in 0..FETCH_HISTORY_PER_FEED + 5 {
+            database
+                .add_fetch_attempt("rss:busy", &attempt(item_count))
+                .await
+                .unwrap();
+        }
+        database
+            .add_fetch_attempt("rss:quiet", &attempt(1))
+            .await
+            .unwrap();
+
+        let history = database
+            .get_fetch_history("rss:busy", FETCH_HISTORY_PER_FEED * 2)
+            .await
+            .unwrap();
+        assert_eq!(history.len() as i64, FETCH_HISTORY_PER_FEED);
+        assert_eq!(history[0].item_count, FETCH_HISTORY_PER_FEED + 4);
+        assert_eq!(history.last().unwrap().item_count, 5);
+        // Pruning one feed leaves the history of others alone.
+        assert_eq!(
+            database
+                .get_fetch_history("rss:quiet", FETCH_HISTORY_PER_FEED)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn feed_summaries_show_the_last_attempt_and_success() {
+        let database = Database::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        let attempt = |age: i64, error_class: Option<&str>| FetchAttempt {
+            fetched_at: now - Duration::hours(age),
+            error_class: error_class.map(str::to_string),
+            error: error_class.map(|class| format!("{class} error")),
+            item_count: 0,
+            duration_ms: 10,
+        };
+        for (feed, attempt) in [
+            ("rss:failing", attempt(3, None)),
+            ("rss:failing", attempt(2, Some("http"))),
+            ("rss:failing", attempt(1, Some("timeout"))),
+            ("rss:ok", attempt(2, Some("http"))),
+            ("rss:ok", attempt(1, None)),
+            ("rss:never", attempt(1, Some("decode"))),
+        ] {
+            database.add_fetch_attempt(feed, &attempt).await.unwrap();
+        }
+
+        let summaries = database.get_feed_summaries().await.unwrap();
+        let summary = |feed: &str| {
+            let summary = summaries
+                .iter()
+                .find(|summary| summary.feed == feed)
+                .unwrap();
+            (
+                summary.last_attempt,
+                summary.last_error_class.as_deref(),
+                summary.last_success,
+            )
+        };
+        let at = |age| now - Duration::hours(age);
+        assert_eq!(
+            summaries
+                .iter()
+                .map(|summary| summary.feed.as_str())
+                .collect::<Vec<_>>(),
+            ["rss:failing", "rss:never", "rss:ok"]
+        );
+        assert_eq!(
+            summary("rss:failing"),
+            (at(1), Some("timeout"), Some(at(3)))
+        );
+        assert_eq!(summary("rss:ok"), (at(1), None, Some(at(1))));
+        assert_eq!(summary("rss:never"), (at(1), Some("decode"), None));
+    }
+
+    #[tokio::test]
+    async fn maintenance_reclaims_removed_space_once_a_week() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::new(&sqlite_url(dir.path(), ""), &[])
+            .await
+            .unwrap();
+        assert!(database.is_maintenance_due().await.unwrap());
+
+        let urls: Vec<String> = (0..2000)
+            .map(|i| format!("https://example.com/{i}/{}", "a".repeat(200)))
+            .collect();
+        let entries: Vec<PostedEntry> = urls
+            .iter()
+            .map(|url| PostedEntry {
+                url,
+                source: "test",
+                source_id: None,
+                fingerprint: None,
+            })
+            .collect();
+        database
+            .record_batch(&entries, PostStatus::Posted)
+            .await
+            .unwrap();
+        database.remove_posted_urls(&urls).await.unwrap();
+
+        let report = database.run_maintenance().await.unwrap();
+        assert_eq!(report.integrity_problems, Some(vec![]));
+        assert!(
+            report.size_after.unwrap() < report.size_before,
+            "{report:?}"
+        );
+        assert!(!database.is_maintenance_due().await.unwrap());
+
+        let last_week = Utc::now() - Duration::days(MAINTENANCE_INTERVAL_DAYS);
+        database
+            .set_state(LAST_MAINTENANCE_KEY, &last_week.to_rfc3339())
+            .await
+            .unwrap();
+        assert!(database.is_maintenance_due().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn corrupt_databases_are_not_vacuumed() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = sqlite_url(dir.path(), "");
+        let database = Database::new(&url, &[]).await.unwrap();
+        database
+            .record_posted(
+                "https://example.com/",
+                "test",
+                None,
+                None,
+                PostStatus::Posted,
+            )
+            .await
+            .unwrap();
+        // Point the index of sources at another column, so that its entries no longer match the table.
+        let Backend::Sqlite(pool) = &database.pool else {
+            unreachable!();
+        };
+        raw_sql(
+            "PRAGMA writable_schema = ON;
+            UPDATE sqlite_schema SET sql = replace(sql, '(source)', '(url)') WHERE name = 'posted_urls_source';",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        database.close().await;
+
+        let database = Database::new(&url, &[]).await.unwrap();
+        let report = database.run_maintenance().await.unwrap();
+        assert!(!report.integrity_problems.unwrap().is_empty());
+        assert_eq!(report.size_after, None);
+        assert!(database.is_maintenance_due().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn stats_count_what_is_stored() {
+        let database = Database::new_in_memory().await.unwrap();
+        let stats = database.stats().await.unwrap();
+        assert_eq!(stats.posted_urls, 0);
+        assert_eq!(stats.oldest_posted_at, None);
+        assert_eq!(stats.newest_posted_at, None);
+        assert_eq!((stats.added_last_24h, stats.added_last_7d), (0, 0));
+        assert_eq!((stats.pending, stats.failed), (0, 0));
+        assert!(stats.size_bytes > 0);
+
+        let now = Utc::now();
+        let oldest = now - Duration::days(30);
+        for (url, status, posted_at) in [
+            ("https://example.com/1", PostStatus::Posted, Some(oldest)),
+            (
+                "https://example.com/2",
+                PostStatus::Posted,
+                Some(now - Duration::days(3)),
+            ),
+            (
+                "https://example.com/3",
+                PostStatus::Posted,
+                Some(now - Duration::hours(1)),
+            ),
+            ("https://example.com/4", PostStatus::Pending, Some(now)),
+            ("https://example.com/5", PostStatus::Skipped, None),
+        ] {
+            database
+                .record_posted(url, "test", None, None, status)
+                .await
+                .unwrap();
+            set_posted_at(&database, url, posted_at).await;
+        }
+        database
+            .add_failed_post("https://example.com/6", "test", None, None, "{}", "error")
+            .await
+            .unwrap();
+
+        let stats = database.stats().await.unwrap();
+        assert_eq!(stats.posted_urls, 5);
+        assert_eq!(
+            stats.oldest_posted_at.map(|time| time.timestamp()),
+            Some(oldest.timestamp())
+        );
+        assert_eq!(
+            stats.newest_posted_at.map(|time| time.timestamp()),
+            Some(now.timestamp())
+        );
+        assert_eq!(stats.added_last_24h, 2);
+        assert_eq!(stats.added_last_7d, 3);
+        assert_eq!(stats.pending, 1);
+        assert_eq!(stats.failed, 1);
+    }
+
+    #[tokio::test]
+    async fn restoring_a_backup_undoes_later_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::new(&sqlite_url(dir.path(), ""), &[])
+            .await
+            .unwrap();
+        for url in ["https://example.com/a", "https://example.com/b"] {
+            database
+                .record_posted(url, "test", None, None, PostStatus::Posted)
+                .await
+                .unwrap();
+        }
+        database.set_state("key", "before").await.unwrap();
+        let backup = dir.path().join("backup.sqlite3");
+        database.backup(&backup).await.unwrap();
+        // Backups are never written over an existing file.
+        assert!(database.backup(&backup).await.is_err());
+
+        database
+            .remove_posted_url("https://example.com/a")
+            .await
+            .unwrap();
+        database
+            .record_posted(
+                "https://example.com/c",
+                "test",
+                None,
+                None,
+                PostStatus::Posted,
+            )
+            .await
+            .unwrap();
+        database.set_state("key", "after").await.unwrap();
+        database
+            .add_failed_post("https://example.com/d", "test", None, None, "{}", "error")
+            .await
+            .unwrap();
+
+        database.restore(&backup).await.unwrap();
+        let urls = query_urls(&database, PostFilter::default()).await;
+        assert_eq!(urls.len(), 2);
+        for url in ["https://example.com/a", "https://example.com/b"] {
+            assert!(urls.contains(&url.to_string()), "{urls:?}");
+        }
+        assert_eq!(
+            database.get_state("key").await.unwrap().as_deref(),
+            Some("before")
+        );
+        assert_eq!(database.stats().await.unwrap().failed, 0);
+        assert!(!dir.path().join("db.sqlite3.restore").exists());
+    }
+
+    #[tokio::test]
+    async fn backups_that_arent_databases_are_not_restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::new(&sqlite_url(dir.path(), ""), &[])
+            .await
+            .unwrap();
+        database
+            .record_posted(
+                "https://example.com/a",
+                "test",
+                None,
+                None,
+                PostStatus::Posted,
+            )
+            .await
+            .unwrap();
+        let backup = dir.path().join("backup.sqlite3");
+        std::fs::write(&backup, "not a database").unwrap();
+
+        assert!(database.restore(&backup).await.is_err());
+        assert!(
+            database
+                .restore(&dir.path().join("missing.sqlite3"))
+                .await
+                .is_err()
+        );
+        assert!(
+            database
+                .has_posted_url("https://example.com/a")
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn urls_are_stored_with_their_source() {
+        let database = Database::new_in_memory().await.unwrap();
+        let Backend::Sqlite(pool) = &database.pool else {
+            unreachable!("tests use sqlite databases");
+        };
+        // Like a URL stored before sources were tracked.
+        sqlx::query("INSERT INTO posted_urls (url, status) VALUES (?, 'posted')")
+            .bind("https://example.com/old")
+            .execute(pool)
+            .await
+            .unwrap();
+        database
+            .record_posted(
+                "https://example.com/rss",
+                "rss:https://example.com/feed.xml",
+                None,
+                None,
+                PostStatus::Posted,
+            )
+            .await
+            .unwrap();
+
+        let posts = database.query_posts(&PostFilter::default()).await.unwrap();
+        let sources: Vec<(&str, &str)> = posts
+            .iter()
+            .map(|post| (post.url.as_str(), post.source.as_str()))
+            .collect();
+        assert!(sources.contains(&("https://example.com/old", "unknown")));
+        assert!(sources.contains(&(
+            "https://example.com/rss",
+            "rss:https://example.com/feed.xml"
+        )));
+        assert_eq!(
+            query_urls(
+                &database,
+                PostFilter {
+                    source: Some("unknown".to_string()),
+                    ..Default::default()
+                }
+            )
+            .await,
+            ["https://example.com/old"]
+        );
+
+        // Failed posts keep their source for when they are recorded after being retried.
+        database
+            .add_failed_post(
+                "https://example.com/failed",
+                "nikki:en",
+                None,
+                None,
+                "{}",
+                "error",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            database.get_failed_posts(None).await.unwrap()[0].source,
+            "nikki:en"
+        );
+    }
+
+    #[tokio::test]
+    async fn promoting_collected_urls_keeps_skipped_urls() {
+        let database = Database::new_in_memory().await.unwrap();
+        for (url, status) in [
+            ("https://example.com/collected", PostStatus::Collected),
+            ("https://example.com/skipped", PostStatus::Skipped),
+            ("https://example.com/posted", PostStatus::Posted),
+        ] {
+            database
+                .record_posted(url, MANUAL_SOURCE, None, None, status)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(database.promote_collected().await.unwrap(), 1);
+        assert!(
+            !database
+                .has_posted_url("https://example.com/collected")
+                .await
+                .unwrap()
+        );
+        assert!(
+            database
+                .has_posted_url("https://example.com/skipped")
+                .await
+                .unwrap()
+        );
+        assert!(
+            database
+                .has_posted_url("https://example.com/posted")
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn content_hashes_change_only_once_an_update_is_published() {
+        let database = Database::new_in_memory().await.unwrap();
+        let url = "https://example.com/a";
+        let today = Utc::now().date_naive();
+        database
+            .record_posted(url, MANUAL_SOURCE, None, None, PostStatus::Pending)
+            .await
+            .unwrap();
+        database.mark_published(url, None, today).await.unwrap();
+        assert_eq!(database.get_posted_hash(url).await.unwrap(), None);
+
+        database.seed_posted_hash(url, "first").await.unwrap();
+        database.seed_posted_hash(url, "second").await.unwrap();
+        assert_eq!(
+            database.get_posted_hash(url).await.unwrap().as_deref(),
+            Some("first")
+        );
+
+        // An update that fails to publish leaves the posted hash alone, so the edit is found again.
+        database.set_pending_hash(url, "second").await.unwrap();
+        database
+            .set_posted_url_status(url, PostStatus::Pending)
+            .await
+            .unwrap();
+        database
+            .set_posted_url_status(url, PostStatus::Posted)
+            .await
+            .unwrap();
+        assert_eq!(
+            database.get_posted_hash(url).await.unwrap().as_deref(),
+            Some("first")
+        );
+
+        database.mark_published(url, None, today).await.unwrap();
+        assert_eq!(
+            database.get_posted_hash(url).await.unwrap().as_deref(),
+            Some("second")
+        );
+        assert_eq!(database.get_article_post_count(url).await.unwrap(), 2);
+
+        database.set_pending_hash(url, "third").await.unwrap();
+        database.apply_pending_hash(url).await.unwrap();
+        assert_eq!(
+            database.get_posted_hash(url).await.unwrap().as_deref(),
+            Some("third")
+        );
+    }
+
+    #[tokio::test]
+    async fn the_last_successful_fetch_only_moves_forward() {
+        let database = Database::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        assert!(database.set_last_successful_fetch(now).await.unwrap());
+        assert!(
+            !database
+                .set_last_successful_fetch(now - Duration::hours(1))
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            database.get_last_successful_fetch().await.unwrap(),
+            Some(now)
+        );
+        assert!(
+            database
+                .set_last_successful_fetch(now + Duration::hours(1))
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn daily_post_counts_start_again_each_day() {
+        let database = Database::new_in_memory().await.unwrap();
+        let today = NaiveDate::from_ymd_opt(2025, 3, 31).unwrap();
+        let tomorrow = today.succ_opt().unwrap();
+        for (url, day) in [
+            ("https://example.com/1", today),
+            ("https://example.com/2", today),
+            ("https://example.com/3", tomorrow),
+        ] {
+            database
+                .record_posted(url, "test", None, None, PostStatus::Pending)
+                .await
+                .unwrap();
+            database.mark_published(url, None, day).await.unwrap();
+        }
+        assert_eq!(database.get_daily_post_count(today).await.unwrap(), 2);
+        assert_eq!(database.get_daily_post_count(tomorrow).await.unwrap(), 1);
+        assert_eq!(
+            database
+                .get_daily_post_count(tomorrow.succ_opt().unwrap())
+                .await
+                .unwrap(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn urls_stored_before_normalising_are_rewritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!(
+            "sqlite://{}?mode=rwc",
+            dir.path().join("db.sqlite3").display()
+        );
+        let today = Utc::now().date_naive();
+        {
+            // Tracking parameters were kept before they were stripped.
+            let database = Database::new(&url, &[]).await.unwrap();
+            for url in [
+                "https://example.com/news/1",
+                "https://example.com/news/1?utm_source=rss",
+                "https://example.com/news/2?utm_source=rss",
+            ] {
+                database
+                    .record_posted(url, "test", None, None, PostStatus::Pending)
+                    .await
+                    .unwrap();
+                database.mark_published(url, None, today).await.unwrap();
+            }
+        }
+        let database = Database::new(&url, &["utm_*".to_string()]).await.unwrap();
+        assert_eq!(database.normalise_stored_urls().await.unwrap(), (2, 1));
+        assert_eq!(database.stats().await.unwrap().posted_urls, 2);
+        assert!(
+            database
+                .has_posted_url("https://example.com/news/2")
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            database
+                .get_article_post_count("https://example.com/news/1")
+                .await
+                .unwrap(),
+            2
+        );
+        // Running it again has nothing left to rewrite.
+        assert_eq!(database.normalise_stored_urls().await.unwrap(), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn daily_post_counts_survive_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!(
+            "sqlite://{}?mode=rwc",
+            dir.path().join("db.sqlite3").display()
+        );
+        let today = Utc::now().date_naive();
+        {
+            let database = Database::new(&url, &[]).await.unwrap();
+            database
+                .record_posted(
+                    "https://example.com",
+                    "test",
+                    None,
+                    None,
+                    PostStatus::Pending,
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                database
+                    .mark_published("https://example.com", None, today)
+                    .await
+                    .unwrap(),
+                1
+            );
+        }
+        let database = Database::new(&url, &[]).await.unwrap();
+        assert_eq!(database.get_daily_post_count(today).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn pending_urls_count_as_posted_until_resolved() {
+        let database = Database::new_in_memory().await.unwrap();
+        let url = "https://example.com/news/1";
+        database
+            .record_posted(url, "test", None, None, PostStatus::Pending)
+            .await
+            .unwrap();
+        // Stopping before the post is marked as published leaves it pending rather than posting it twice.
+        assert!(database.has_posted_url(url).await.unwrap());
+        assert_eq!(database.get_pending_urls().await.unwrap(), [url]);
+
+        database
+            .mark_published(url, None, Utc::now().date_naive())
+            .await
+            .unwrap();
+        assert!(database.get_pending_urls().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn abandoning_a_pending_update_keeps_it_posted() {
+        let database = Database::new_in_memory().await.unwrap();
+        let url = "https://example.com/news/1";
+        database
+            .record_posted(url, "test", None, None, PostStatus::Posted)
+            .await
+            .unwrap();
+        database.seed_posted_hash(url, "original").await.unwrap();
+        database.set_pending_hash(url, "edited").await.unwrap();
+        database
+            .set_posted_url_status(url, PostStatus::Pending)
+            .await
+            .unwrap();
+
+        database.abandon_pending(url, true).await.unwrap();
+        assert!(database.has_posted_url(url).await.unwrap());
+        assert!(database.get_pending_urls().await.unwrap().is_empty());
+        assert_eq!(
+            database.get_posted_hash(url).await.unwrap().as_deref(),
+            Some("original")
+        );
+    }
+
+    #[tokio::test]
+    async fn abandoning_a_pending_post_forgets_it() {
+        let database = Database::new_in_memory().await.unwrap();
+        let url = "https://example.com/news/1";
+        database
+            .record_posted(url, "test", None, None, PostStatus::Pending)
+            .await
+            .unwrap();
+        database.abandon_pending(url, false).await.unwrap();
+        assert!(!database.has_posted_url(url).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn failed_posts_count_their_attempts() {
+        let database = Database::new_in_memory().await.unwrap();
+        let url = "https://example.com/news/1";
+        for attempts in 1..=3 {
+            assert_eq!(
+                database
+                    .add_failed_post(
+                        url,
+                        "test",
+                        Some("1"),
+                        None,
+                        &format!("{{\"attempt\":{attempts}}}"),
+                        &format!("error {attempts}"),
+                    )
+                    .await
+                    .unwrap(),
+                attempts
+            );
+        }
+
+        let failed = database.get_failed_posts(None).await.unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].attempts, 3);
+        assert_eq!(failed[0].source_id.as_deref(), Some("1"));
+        // Each attempt replaces the post and error of the one before it.
+        assert_eq!(failed[0].payload, "{\"attempt\":3}");
+        assert_eq!(failed[0].error, "error 3");
+    }
+
+    #[tokio::test]
+    async fn deferred_posts_dont_count_as_attempts() {
+        let database = Database::new_in_memory().await.unwrap();
+        let failed_url = "https://example.com/news/1";
+        let deferred_url = "https://example.com/news/2";
+        database
+            .add_failed_post(failed_url, "test", None, None, "{}", "server error")
+            .await
+            .unwrap();
+        database
+            .defer_post(failed_url, "test", None, None, "{}", "rate limited")
+            .await
+            .unwrap();
+        database
+            .defer_post(deferred_url, "test", None, None, "{}", "rate limited")
+            .await
+            .unwrap();
+
+        let failed = database.get_failed_posts(None).await.unwrap();
+        let attempts = |url: &str| {
+            let post = failed.iter().find(|post| post.url == url).unwrap();
+            (post.attempts, post.error.as_str())
+        };
+        assert_eq!(attempts(failed_url), (1, "rate limited"));
+        assert_eq!(attempts(deferred_url), (0, "rate limited"));
+        // A deferred post fails for the first time once it is retried.
+        assert_eq!(
+            database
+                .add_failed_post(deferred_url, "test", None, None, "{}", "server error")
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_posts_are_retried_until_they_use_up_their_attempts() {
+        let database = Database::new_in_memory().await.unwrap();
+        for (url, attempts) in [
+            ("https://example.com/news/1", 1),
+            ("https://example.com/news/2", 2),
+            ("https://example.com/news/3", 3),
+        ] {
+            for _ in 0..attempts {
+                database
+                    .add_failed_post(url, "test", None, None, "{}", "server error")
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let urls = |failed: Vec<FailedPost>| {
+            let mut urls = failed.into_iter().map(|post| post.url).collect::<Vec<_>>();
+            urls.sort();
+            urls
+        };
+        assert_eq!(
+            urls(database.get_failed_posts(None).await.unwrap()).len(),
+            3
+        );
+        assert_eq!(
+            urls(database.get_failed_posts(Some(3)).await.unwrap()),
+            ["https://example.com/news/1", "https://example.com/news/2"]
+        );
+        assert_eq!(
+            urls(database.get_failed_posts(Some(1)).await.unwrap()),
+            Vec::<String>::new()
+        );
+
+        database
+            .remove_failed_post("https://example.com/news/1")
+            .await
+            .unwrap();
+        assert!(
+            !database
+                .has_failed_post("https://example.com/news/1")
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            urls(database.get_failed_posts(Some(3)).await.unwrap()),
+            ["https://example.com/news/2"]
+        );
+    }
+
+    /// The environment variable holding the URL of a PostgreSQL server to run the PostgreSQL tests against,
+    /// which are skipped when it isn't set.
+    const TEST_POSTGRES_URL_VAR: &str = "WHIMSKY_TEST_POSTGRES_URL";
+
+    /// An empty PostgreSQL database created for a single test, which is dropped again by [`TestPostgres::drop`].
+    struct TestPostgres {
+        database: Database,
+        server: PgPool,
+        name: String,
+    }
+
+    impl TestPostgres {
+        /// Create a database for a test on the server from [`TEST_POSTGRES_URL_VAR`], or `None` if it isn't set.
+        async fn new(test: &str) -> Option<Self> {
+            let Ok(server_url) = std::env::var(TEST_POSTGRES_URL_VAR) else {
+                eprintln!("Skipping as {TEST_POSTGRES_URL_VAR} isn't set");
+                return None;
+            };
+            let server = PgPool::connect(&server_url).await.unwrap();
+            let name = format!("whimsky_test_{test}_{}", std::process::id());
+            raw_sql(&format!("DROP DATABASE IF EXISTS {name} WITH (FORCE)"))
+                .execute(&server)
+                .await
+                .unwrap();
+            raw_sql(&format!("CREATE DATABASE {name}"))
+                .execute(&server)
+                .await
+                .unwrap();
+            let mut url = url::Url::parse(&server_url).unwrap();
+            url.set_path(&name);
+            let database = Database::new(url.as_str(), &["utm_*".to_string()])
+                .await
+                .unwrap();
+            Some(Self {
+                database,
+                server,
+                name,
+            })
+        }
+
+        async fn drop(self) {
+            self.database.close().await;
+            raw_sql(&format!("DROP DATABASE {} WITH (FORCE)", self.name))
+                .execute(&self.server)
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn postgres_records_each_url_once() {
+        let Some(postgres) = TestPostgres::new("record").await else {
+            return;
+        };
+        let database = &postgres.database;
+        let url = "https://example.com/a";
+        assert!(
+            database
+                .record_posted(url, "nikki:en", Some("1"), None, PostStatus::Posted)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !database
+                .record_posted(
+                    &format!("{url}?utm_source=rss"),
+                    MANUAL_SOURCE,
+                    None,
+                    None,
+                    PostStatus::Collected
+                )
+                .await
+                .unwrap()
+        );
+        assert!(database.has_posted_url(url).await.unwrap());
+        assert!(database.has_posted_source_id("1").await.unwrap());
+
+        let entry = |url| PostedEntry {
+            url,
+            source: MANUAL_SOURCE,
+            source_id: None,
+            fingerprint: Some("fingerprint"),
+        };
+        assert_eq!(
+            database
+                .record_batch(
+                    &[
+                        entry(url),
+                        entry("https://example.com/b"),
+                        entry("https://example.com/b"),
+                    ],
+                    PostStatus::Collected
+                )
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(
+            database
+                .has_posted_fingerprint("fingerprint")
+                .await
+                .unwrap()
+        );
+        assert_eq!(database.promote_collected().await.unwrap(), 1);
+        assert_eq!(database.stats().await.unwrap().posted_urls, 1);
+        postgres.drop().await;
+    }
+
+    #[tokio::test]
+    async fn postgres_marks_posts_as_published() {
+        let Some(postgres) = TestPostgres::new("publish").await else {
+            return;
+        };
+        let database = &postgres.database;
+        let (url, uri) = (
+            "https://example.com/a",
+            "at://did:plc:example/app.bsky.feed.post/a",
+        );
+        let today = Utc::now().date_naive();
+        database
+            .record_posted(url, "test", None, None, PostStatus::Pending)
+            .await
+            .unwrap();
+        assert_eq!(database.get_pending_urls().await.unwrap(), [url]);
+        assert_eq!(
+            database
+                .mark_published(url, Some((uri, "cid")), today)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(database.get_pending_urls().await.unwrap().is_empty());
+        assert_eq!(
+            database
+                .get_posted_record_uri(url)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some(uri)
+        );
+        assert_eq!(
+            database
+                .get_url_by_record_uri(uri)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some(url)
+        );
+
+        // Publishing an update moves the pending content hash over and counts the article again.
+        database.seed_posted_hash(url, "first").await.unwrap();
+        database.set_pending_hash(url, "second").await.unwrap();
+        assert_eq!(database.mark_published(url, None, today).await.unwrap(), 2);
+        assert_eq!(
+            database.get_posted_hash(url).await.unwrap().as_deref(),
+            Some("second")
+        );
+        assert_eq!(database.get_article_post_count(url).await.unwrap(), 2);
+        assert_eq!(database.get_daily_post_count(today).await.unwrap(), 2);
+
+        set_posted_at(database, url, Some(Utc::now() - Duration::days(200))).await;
+        database
+            .remove_old_stored_posts(Duration::days(180))
+            .await
+            .unwrap();
+        assert!(!database.has_posted_url(url).await.unwrap());
+        assert_eq!(database.get_article_post_count(url).await.unwrap(), 0);
+        postgres.drop().await;
+    }
+
+    #[tokio::test]
+    async fn postgres_queries_posts_with_filters() {
+        let Some(postgres) = TestPostgres::new("query").await else {
+            return;
+        };
+        let database = &postgres.database;
+        let now = Utc::now();
+        for (url, age) in [
+            ("https://example.com/news/old", Some(48)),
+            ("https://example.com/news/new", Some(1)),
+            ("https://example.org/feed/item", Some(12)),
+            ("https://example.com/news/unknown", None),
+        ] {
+            database
+                .record_posted(url, "test", None, None, PostStatus::Posted)
+                .await
+                .unwrap();
+            set_posted_at(database, url, age.map(|hours| now - Duration::hours(hours))).await;
+        }
+
+        assert_eq!(
+            query_urls(database, PostFilter::default()).await,
+            [
+                "https://example.com/news/new",
+                "https://example.org/feed/item",
+                "https://example.com/news/old",
+                "https://example.com/news/unknown",
+            ]
+        );
+        assert_eq!(
+            query_urls(
+                database,
+                PostFilter {
+                    since: Some(now - Duration::hours(24)),
+                    contains: Some("NEWS".to_string()),
+                    ..Default::default()
+                }
+            )
+            .await,
+            ["https://example.com/news/new"]
+        );
+        assert_eq!(
+            query_urls(
+                database,
+                PostFilter {
+                    prefix: Some("https://example.com/".to_string()),
+                    limit: Some(2),
+                    ..Default::default()
+                }
+            )
+            .await,
+            [
+                "https://example.com/news/new",
+                "https://example.com/news/old"
+            ]
+        );
+        assert_eq!(
+            database
+                .remove_posts(&PostFilter {
+                    before: Some(now - Duration::hours(6)),
+                    ..Default::default()
+                })
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(database.stats().await.unwrap().posted_urls, 2);
+        postgres.drop().await;
+    }
+
+    #[tokio::test]
+    async fn postgres_queues_failed_posts() {
+        let Some(postgres) = TestPostgres::new("failed").await else {
+            return;
+        };
+        let database = &postgres.database;
+        let url = "https://example.com/a";
+        for attempts in 1..=2 {
+            assert_eq!(
+                database
+                    .add_failed_post(url, "test", None, None, "{}", "rate limited")
+                    .await
+                    .unwrap(),
+                attempts
+            );
+        }
+        database
+            .defer_post(
+                "https://example.com/b",
+                "test",
+                Some("2"),
+                None,
+                "{}",
+                "daily limit reached",
+            )
+            .await
+            .unwrap();
+        assert!(database.has_failed_post(url).await.unwrap());
+
+        let failed = database.get_failed_posts(None).await.unwrap();
+        assert_eq!(failed.len(), 2);
+        let deferred = failed
+            .iter()
+            .find(|post| post.url == "https://example.com/b")
+            .unwrap();
+        assert_eq!(deferred.attempts, 0);
+        assert_eq!(deferred.source_id.as_deref(), Some("2"));
+        // Posts that have used up their attempts are no longer returned to be retried.
+        let retried = database.get_failed_posts(Some(2)).await.unwrap();
+        assert_eq!(retried.len(), 1);
+        assert_eq!(retried[0].url, "https://example.com/b");
+
+        database.remove_failed_post(url).await.unwrap();
+        assert!(!database.has_failed_post(url).await.unwrap());
+        assert_eq!(database.stats().await.unwrap().failed, 1);
+        postgres.drop().await;
+    }
+
+    #[tokio::test]
+    async fn postgres_state_only_moves_forward() {
+        let Some(postgres) = TestPostgres::new("state").await else {
+            return;
+        };
+        let database = &postgres.database;
+        let now = Utc::now();
+        assert!(database.set_last_successful_fetch(now).await.unwrap());
+        assert!(
+            !database
+                .set_last_successful_fetch(now - Duration::hours(1))
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            database.get_last_successful_fetch().await.unwrap(),
+            Some(now)
+        );
+        assert!(
+            database
+                .set_last_successful_fetch(now + Duration::hours(1))
+                .await
+                .unwrap()
+        );
+
+        database.set_state("key", "first").await.unwrap();
+        database.set_state("key", "second").await.unwrap();
+        assert_eq!(
+            database.get_state("key").await.unwrap().as_deref(),
+            Some("second")
+        );
+        database.remove_state("key").await.unwrap();
+        assert_eq!(database.get_state("key").await.unwrap(), None);
+        postgres.drop().await;
     }
 }
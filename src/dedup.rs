@@ -0,0 +1,178 @@
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+
+/// Normalise a title for comparison by lowercasing it, removing punctuation and collapsing whitespace.
+///
+/// This is the canonical form used anywhere titles are compared so that they are always compared the same way.
+pub fn normalise_title(title: &str) -> String {
+    title
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Build a fingerprint for an article from its normalised title and publish date.
+///
+/// Articles that are republished under a new id will usually share the same fingerprint.
+pub fn fingerprint(title: &str, publish_time: DateTime<Utc>) -> String {
+    format!(
+        "{}|{}",
+        normalise_title(title),
+        publish_time.date_naive().format("%Y-%m-%d")
+    )
+}
+
+/// Build a hash of the content of an article that changes whenever it is edited.
+pub fn content_hash(title: &str, r#abstract: &str, cover: &str) -> String {
+    format!(
+        "{:x}",
+        Sha256::digest(format!("{title}\0{abstract}\0{cover}"))
+    )
+}
+
+/// The query parameters removed from URLs by default, which are only used to track where visitors came from.
+pub const DEFAULT_STRIPPED_URL_PARAMS: &str = "utm_*,fbclid,gclid";
+
+/// Normalise a URL so that addresses of the same page compare equal.
+///
+/// The scheme and host are lowercased, default ports, fragments and trailing slashes are removed, and so are
+/// query parameters matching `stripped_params`. A parameter ending in `*` matches any parameter starting with the
+/// rest of it. Values that can't be parsed as a URL are only trimmed.
+pub fn normalise_url(url: &str, stripped_params: &[String]) -> String {
+    let Ok(mut parsed) = Url::parse(url.trim()) else {
+        return url.trim().to_string();
+    };
+    if parsed.cannot_be_a_base() {
+        return parsed.into();
+    }
+    parsed.set_fragment(None);
+
+    // The query is filtered as written instead of re-encoded so that the remaining parameters are left untouched.
+    let query = parsed.query().map(|query| {
+        query
+            .split('&')
+            .filter(|pair| {
+                let name = pair.split('=').next().unwrap_or_default();
+                !pair.is_empty() && !is_stripped_param(name, stripped_params)
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    });
+    parsed.set_query(query.as_deref().filter(|query| !query.is_empty()));
+
+    let path = parsed.path().trim_end_matches('/').to_string();
+    if !path.is_empty() {
+        parsed.set_path(&path);
+    }
+    parsed.into()
+}
+
+fn is_stripped_param(name: &str, stripped_params: &[String]) -> bool {
+    let name = name.to_ascii_lowercase();
+    stripped_params.iter().any(|param| {
+        let param = param.to_ascii_lowercase();
+        match param.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == param,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn stripped() -> Vec<String> {
+        DEFAULT_STRIPPED_URL_PARAMS
+            .split(',')
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn urls_of_the_same_page_are_normalised_alike() {
+        let expected = "https://example.com/en/news/123";
+        for url in [
+            "https://example.com/en/news/123",
+            "https://example.com/en/news/123/",
+            " HTTPS://Example.COM:443/en/news/123#top",
+            "https://example.com/en/news/123?utm_source=rss&utm_medium=feed",
+            "https://example.com/en/news/123?fbclid=abc&gclid=def",
+        ] {
+            assert_eq!(normalise_url(url, &stripped()), expected, "{url}");
+        }
+    }
+
+    #[test]
+    fn only_tracking_params_are_removed_from_queries() {
+        assert_eq!(
+            normalise_url(
+                "https://example.com/news?id=1&UTM_Source=rss&page=2",
+                &stripped()
+            ),
+            "https://example.com/news?id=1&page=2"
+        );
+        // Parameters are only removed when they're listed.
+        assert_eq!(
+            normalise_url("https://example.com/news?utm_source=rss", &[]),
+            "https://example.com/news?utm_source=rss"
+        );
+        assert_eq!(
+            normalise_url("https://example.com/news?ref=rss", &["ref".to_string()]),
+            "https://example.com/news"
+        );
+    }
+
+    #[test]
+    fn paths_and_ports_that_differ_are_kept() {
+        assert_eq!(
+            normalise_url("http://example.com:8080/News/", &stripped()),
+            "http://example.com:8080/News"
+        );
+        assert_eq!(
+            normalise_url("https://example.com/", &stripped()),
+            "https://example.com/"
+        );
+        assert_eq!(normalise_url(" not a url ", &stripped()), "not a url");
+    }
+
+    #[test]
+    fn fingerprints_ignore_case_punctuation_and_time_of_day() {
+        let morning = Utc.with_ymd_and_hms(2026, 3, 1, 8, 0, 0).unwrap();
+        let evening = Utc.with_ymd_and_hms(2026, 3, 1, 20, 0, 0).unwrap();
+        assert_eq!(
+            fingerprint("New Outfit: \"Starlit Dream\"!", morning),
+            fingerprint("new outfit starlit dream", evening)
+        );
+        assert_ne!(
+            fingerprint("New outfit", morning),
+            fingerprint("New outfit", morning + chrono::Duration::days(1))
+        );
+    }
+
+    #[test]
+    fn content_hashes_change_with_any_part_of_the_content() {
+        let hash = content_hash("Title", "Abstract", "https://example.com/cover.png");
+        assert_eq!(
+            hash,
+            content_hash("Title", "Abstract", "https://example.com/cover.png")
+        );
+        assert_ne!(
+            hash,
+            content_hash("Title!", "Abstract", "https://example.com/cover.png")
+        );
+        assert_ne!(
+            hash,
+            content_hash("Title", "Abstract.", "https://example.com/cover.png")
+        );
+        assert_ne!(hash, content_hash("Title", "Abstract", ""));
+        // Parts can't be moved between fields without changing the hash.
+        assert_ne!(content_hash("ab", "c", ""), content_hash("a", "bc", ""));
+    }
+}
@@ -1,16 +1,37 @@
-use crate::database::Database;
-use anyhow::Result;
+use crate::canonical::CanonicalResolver;
+use crate::database::{Database, PostStatus};
+use crate::dedup;
+use crate::html;
+use crate::source::{self, BoxFuture, FUTURE_PUBLISH_SKEW, PostSource, SourcePost};
+use anyhow::{Context, Result};
 use chrono::{DateTime, Duration, Utc};
-use reqwest::Url;
+use regex::Regex;
+use reqwest::{
+    StatusCode, Url,
+    header::{ETAG, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+};
 use serde::Deserialize;
-use tracing::debug;
+use std::{collections::HashSet, num::NonZeroUsize};
+use tracing::{debug, info, warn};
 
 pub struct NikkiNewsFetcher<'a> {
+    client: reqwest::Client,
     filter_date: chrono::DateTime<Utc>,
     database: &'a Database,
     backdate_duration: Duration,
     news_url: Url,
     locale: String,
+    fingerprint_dedup: bool,
+    processed_until: DateTime<Utc>,
+    /// The `ETag` and `Last-Modified` headers of the previous response, sent so that an unchanged feed isn't
+    /// downloaded again.
+    validators: Option<(Option<HeaderValue>, Option<HeaderValue>)>,
+    api: NewsApi,
+    filter: NewsFilter,
+    /// The prefix added to the title of edited articles that are posted again, when they should be.
+    update_title_prefix: Option<String>,
+    /// Resolves the canonical URL of articles to store them under, when they should be.
+    canonical: Option<CanonicalResolver>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -22,7 +43,8 @@ pub struct NikkiNewsResponse {
 #[allow(dead_code)]
 pub struct NikkiNewsData {
     pub total: usize,
-    pub data: Vec<NikkiNewsDataInner>,
+    /// Kept unparsed so that a single malformed item doesn't prevent the rest from being parsed.
+    pub data: Vec<serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -33,80 +55,1100 @@ pub struct NikkiNewsDataInner {
     pub section: usize,
     // pub info: Option<String>, // Not actually sure the inner type of this yet
     pub publish_time: DateTime<Utc>,
-    pub cover: Url,
+    #[serde(default)]
+    pub cover: Option<Url>,
     pub r#abstract: String,
 }
 
-pub struct NikkiNewsPost {
-    pub url: Url,
-    pub title: String,
-    pub publish_time: DateTime<Utc>,
-    pub cover: Url,
-    pub r#abstract: String,
+/// A page of news from the news API, leaving out any items that couldn't be parsed.
+struct NikkiNewsPage {
+    total: usize,
+    /// The number of items on the page, including any that couldn't be parsed.
+    len: usize,
+    items: Vec<NikkiNewsDataInner>,
 }
 
-impl<'a> NikkiNewsFetcher<'a> {
-    fn make_news_url(locale: &str, limit: usize) -> Url {
+/// The maximum number of bytes of a response that are logged when it can't be parsed.
+const MAX_LOGGED_BODY_BYTES: usize = 2048;
+
+/// Parse a page of news, skipping individual items that can't be parsed with a warning.
+///
+/// When the page as a whole can't be parsed the start of the response is logged to help diagnose changes to the API.
+fn parse_news_page(url: &Url, body: &[u8]) -> Result<NikkiNewsPage> {
+    let response: NikkiNewsResponse =
+        serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_slice(body))
+            .inspect_err(|err| {
+                warn!(
+                    "Failed to parse news from {url} at '{}': {} - response began with: {}",
+                    err.path(),
+                    err.inner(),
+                    String::from_utf8_lossy(&body[..body.len().min(MAX_LOGGED_BODY_BYTES)])
+                );
+            })
+            .with_context(|| format!("failed to parse news from {url}"))?;
+    let len = response.data.data.len();
+    let items = response
+        .data
+        .data
+        .into_iter()
+        .filter_map(|value| {
+            let id = value
+                .get("id")
+                .map_or("without an id".to_string(), |id| id.to_string());
+            serde_path_to_error::deserialize::<_, NikkiNewsDataInner>(value)
+                .inspect_err(|err| {
+                    warn!(
+                        "Skipping news item {id} from {url} that couldn't be parsed at '{}': {}",
+                        err.path(),
+                        err.inner()
+                    );
+                })
+                .ok()
+        })
+        .collect();
+    Ok(NikkiNewsPage {
+        total: response.data.total,
+        len,
+        items,
+    })
+}
+
+/// Which sections of news are fetched, by their numeric id.
+#[derive(Debug, Clone, Default)]
+pub enum SectionFilter {
+    #[default]
+    All,
+    Include(Vec<usize>),
+    Exclude(Vec<usize>),
+}
+
+impl SectionFilter {
+    fn allows(&self, section: usize) -> bool {
+        match self {
+            Self::All => true,
+            Self::Include(sections) => sections.contains(&section),
+            Self::Exclude(sections) => !sections.contains(&section),
+        }
+    }
+}
+
+/// Which news is posted, applied before news is checked against the database.
+#[derive(Debug, Clone, Default)]
+pub struct NewsFilter {
+    pub sections: SectionFilter,
+    /// News must match at least one of these in its title or abstract, when any are given.
+    pub include: Vec<Regex>,
+    /// News matching any of these in its title or abstract is skipped.
+    pub exclude: Vec<Regex>,
+}
+
+impl NewsFilter {
+    /// Get why news with the given title and abstract is filtered out by its content, if it is.
+    pub fn content_filter_reason(&self, title: &str, r#abstract: &str) -> Option<String> {
+        let matches = |regex: &Regex| regex.is_match(title) || regex.is_match(r#abstract);
+        if let Some(regex) = self.exclude.iter().find(|regex| matches(regex)) {
+            return Some(format!("it matches the exclude filter '{regex}'"));
+        }
+        if !self.include.is_empty() && !self.include.iter().any(matches) {
+            return Some("it doesn't match any include filter".to_string());
+        }
+        None
+    }
+}
+
+/// The maximum number of pages of news fetched at once, in case the API never returns a page past the filter date.
+const MAX_NEWS_PAGES: usize = 10;
+
+/// The languages that news from each locale is written in.
+const LOCALE_LANGUAGES: [(&str, &str); 3] = [("en", "en"), ("ja", "ja"), ("kr", "ko")];
+
+/// Get the language that news from a known locale is written in.
+pub fn locale_language(locale: &str) -> Option<&'static str> {
+    LOCALE_LANGUAGES
+        .iter()
+        .find(|(known, _)| *known == locale)
+        .map(|(_, language)| *language)
+}
+
+/// The default base URL of both the news API and news articles.
+pub const DEFAULT_NEWS_BASE_URL: &str = "https://infinitynikki.infoldgames.com";
+
+/// Where news is fetched from and how much of it is requested at a time.
+#[derive(Debug, Clone)]
+pub struct NewsApi {
+    /// The base URL that the news API is requested from.
+    pub base_url: Url,
+    /// The base URL that links to news articles are built from.
+    pub article_base_url: Url,
+    pub page_size: NonZeroUsize,
+}
+
+impl NewsApi {
+    fn news_url(&self, locale: &str, offset: usize) -> Url {
         Url::parse(&format!(
-            "https://infinitynikki.infoldgames.com/api/news?offset=0&limit={}&locale={}",
-            limit, locale
+            "{}/api/news?offset={}&limit={}&locale={}",
+            self.base_url.as_str().trim_end_matches('/'),
+            offset,
+            self.page_size,
+            locale
         ))
         .unwrap()
     }
 
-    pub fn new(locale: String, database: &'a Database, feed_backdate: Duration) -> Self {
-        let news_url = Self::make_news_url(&locale, 20);
+    /// Fetch the first page of news for a locale, leaving out any items that couldn't be parsed.
+    pub async fn fetch_latest(
+        &self,
+        client: &reqwest::Client,
+        locale: &str,
+    ) -> Result<Vec<NikkiNewsDataInner>> {
+        let url = self.news_url(locale, 0);
+        let body = client
+            .get(url.clone())
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(parse_news_page(&url, &body)?.items)
+    }
+
+    fn article_url(&self, locale: &str, id: usize) -> Result<Url> {
+        Ok(Url::parse(&format!(
+            "{}/{}/news/{}",
+            self.article_base_url.as_str().trim_end_matches('/'),
+            locale,
+            id
+        ))?)
+    }
+}
+
+impl<'a> NikkiNewsFetcher<'a> {
+    pub fn new(
+        client: reqwest::Client,
+        locale: String,
+        database: &'a Database,
+        feed_backdate: Duration,
+        fingerprint_dedup: bool,
+        api: NewsApi,
+        filter: NewsFilter,
+    ) -> Self {
+        let news_url = api.news_url(&locale, 0);
         let filter_date = Utc::now() - feed_backdate;
         debug!(
             "Initializing news fetcher for {news_url} with starting filter date of {filter_date}"
         );
 
         Self {
+            client,
             database,
             news_url,
             filter_date,
             locale,
             backdate_duration: feed_backdate,
+            fingerprint_dedup,
+            processed_until: Utc::now(),
+            validators: None,
+            api,
+            filter,
+            update_title_prefix: None,
+            canonical: None,
         }
     }
 
-    pub fn get_news_url(&self) -> &Url {
-        &self.news_url
+    /// Move the filter date back to the given time of the last successful fetch so that news posted while the
+    /// bot wasn't running is not missed, going back no further than the maximum catchup duration.
+    pub fn catch_up_from(&mut self, last_successful_fetch: DateTime<Utc>, max_catchup: Duration) {
+        let filter_date = self
+            .filter_date
+            .min(last_successful_fetch)
+            .max(Utc::now() - max_catchup);
+        if filter_date < self.filter_date {
+            info!("Catching up on news posted since {filter_date}");
+            self.filter_date = filter_date;
+        }
     }
 
-    pub async fn fetch_unposted(&mut self) -> Result<Vec<NikkiNewsPost>> {
-        let mut content = reqwest::get(self.news_url.as_str())
-            .await?
-            .json::<NikkiNewsResponse>()
-            .await?;
-        content.data.data.dedup_by_key(|k| k.id);
-        content.data.data.sort_by_key(|k| k.id);
-        content.data.data.reverse();
+    /// Post articles again with the given prefix added to their title when they are edited after being posted.
+    pub fn post_updates(&mut self, title_prefix: String) {
+        self.update_title_prefix = Some(title_prefix);
+    }
+
+    /// Store articles under their canonical URL so that they are only posted once when linked from other sources.
+    pub fn resolve_canonical(&mut self, resolver: CanonicalResolver) {
+        self.canonical = Some(resolver);
+    }
+
+    /// Fetch news posts that haven't been posted yet, newest first.
+    ///
+    /// When a limit is given the newest posts past it are deferred, and the filter date will not advance
+    /// past them so that they are returned again by the next fetch.
+    async fn fetch(&mut self, limit: Option<usize>) -> Result<Vec<SourcePost>> {
+        let mut request = self.client.get(self.news_url.as_str());
+        if let Some((etag, last_modified)) = &self.validators {
+            if let Some(etag) = etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            debug!(
+                "News at {} has not changed since the last fetch",
+                self.news_url
+            );
+            let now = Utc::now();
+            self.processed_until = now;
+            self.filter_date = now - self.backdate_duration;
+            return Ok(vec![]);
+        }
+        let response = response.error_for_status()?;
+        let validators = (
+            response.headers().get(ETAG).cloned(),
+            response.headers().get(LAST_MODIFIED).cloned(),
+        );
+        let mut page = parse_news_page(&self.news_url, &response.bytes().await?)?;
+
+        // Keep fetching older pages until one reaches past the filter date, so that nothing inside of the
+        // window is missed when there is more news in it than fits on a single page.
+        let mut items = vec![];
+        let mut offset = 0;
+        let mut pages = 1;
+        loop {
+            let reached_filter_date = page
+                .items
+                .iter()
+                .any(|item| item.publish_time <= self.filter_date);
+            offset += page.len;
+            items.append(&mut page.items);
+            if reached_filter_date || page.len == 0 || offset >= page.total {
+                break;
+            }
+            if pages >= MAX_NEWS_PAGES {
+                warn!(
+                    "Stopped fetching news from {} after {MAX_NEWS_PAGES} pages without reaching the filter date",
+                    self.news_url
+                );
+                break;
+            }
+            let url = self.api.news_url(&self.locale, offset);
+            debug!("Fetching the next page of news from {url}");
+            let body = self
+                .client
+                .get(url.clone())
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?;
+            page = parse_news_page(&url, &body)?;
+            pages += 1;
+        }
+        // Sorted before deduplicating as only adjacent duplicates are removed, such as those left at the
+        // boundary of pages when news is published while paging.
+        items.sort_by_key(|k| k.id);
+        items.dedup_by_key(|k| k.id);
+        items.reverse();
 
         let mut posts = vec![];
-        for item in content.data.data {
+        let mut links = HashSet::new();
+        let mut fingerprints = HashSet::new();
+        let mut has_future_news = false;
+        let publish_limit = Utc::now() + FUTURE_PUBLISH_SKEW;
+        for item in items {
             // Only count posts that are after the filter date.
             if item.publish_time <= self.filter_date {
                 continue;
             }
+            // Pre-staged news is left until it's published. The filter date never advances past the current
+            // time so it's picked up by a later fetch.
+            if item.publish_time > publish_limit {
+                debug!(
+                    "Skipping news {} '{}' until it's published at {}",
+                    item.id, item.title, item.publish_time
+                );
+                has_future_news = true;
+                continue;
+            }
 
-            let link = Url::parse(&format!(
-                "https://infinitynikki.infoldgames.com/{}/news/{}",
-                self.locale, item.id
-            ))?;
-            if self.database.has_posted_url(link.as_str()).await? {
+            if !self.filter.sections.allows(item.section) {
+                debug!(
+                    "Skipping news {} '{}' as its section {} is filtered out",
+                    item.id, item.title, item.section
+                );
                 continue;
             }
+            let title = html::to_plain_text(&item.title);
+            let r#abstract = html::to_plain_text(&item.r#abstract);
+            // Filtered news isn't recorded so that it's picked up if the filters are loosened later.
+            if let Some(reason) = self.filter.content_filter_reason(&title, &r#abstract) {
+                info!("Skipping news {} '{title}' as {reason}", item.id);
+                continue;
+            }
+
+            let link = self.api.article_url(&self.locale, item.id)?;
+            let key = match &self.canonical {
+                Some(canonical) => canonical.resolve(self.database, &link).await?,
+                None => link.clone(),
+            };
+            if !links.insert(key.clone()) {
+                debug!("Skipping '{link}' as it was already returned in this fetch");
+                continue;
+            }
+            let source_id = format!("nikki:{}", item.id);
+            let content_hash = dedup::content_hash(
+                &title,
+                &r#abstract,
+                item.cover.as_ref().map_or("", Url::as_str),
+            );
+            // Entries stored before source ids were recorded can only be found by their URL.
+            let posted = match self.database.has_posted_source_id(&source_id).await? {
+                true => true,
+                false if self.database.has_posted_url(key.as_str()).await? => {
+                    self.database
+                        .set_posted_source_id(key.as_str(), &source_id)
+                        .await?;
+                    true
+                }
+                false => false,
+            };
+            // Posts that failed to publish are retried from the failed posts queue instead.
+            if self.database.has_failed_post(key.as_str()).await? {
+                continue;
+            }
+            if posted {
+                match self.database.get_posted_hash(key.as_str()).await? {
+                    // Articles posted before their content was tracked have their hash stored the first time they
+                    // are seen.
+                    None => {
+                        self.database
+                            .seed_posted_hash(key.as_str(), &content_hash)
+                            .await?;
+                        continue;
+                    }
+                    Some(posted_hash) if posted_hash == content_hash => continue,
+                    Some(_) => {}
+                }
+                // The hash of the edit is only stored once its update is published, so an edit that isn't posted
+                // is found again by the next fetch.
+                let Some(prefix) = &self.update_title_prefix else {
+                    debug!(
+                        "Skipping '{link}' which was edited after being posted: see --post-updates"
+                    );
+                    continue;
+                };
+                info!("'{link}' was edited after being posted: posting it again as an update");
+                self.database
+                    .set_pending_hash(key.as_str(), &content_hash)
+                    .await?;
+                posts.push(SourcePost {
+                    summary: r#abstract,
+                    thumbnail: item.cover,
+                    published: item.publish_time,
+                    title: format!("{prefix}{title}"),
+                    url: link,
+                    key,
+                    source_id: Some(source_id),
+                    section: Some(item.section),
+                    fingerprint: dedup::fingerprint(&item.title, item.publish_time),
+                    is_update: true,
+                });
+                continue;
+            }
+
+            // Catch articles that were republished under a new id.
+            let fingerprint = dedup::fingerprint(&item.title, item.publish_time);
+            if self.fingerprint_dedup {
+                if self.database.has_posted_fingerprint(&fingerprint).await? {
+                    info!(
+                        "Skipping '{link}' as it matches the fingerprint of an already posted article"
+                    );
+                    self.database
+                        .record_posted(
+                            key.as_str(),
+                            &self.name(),
+                            Some(&source_id),
+                            Some(&fingerprint),
+                            PostStatus::Posted,
+                        )
+                        .await?;
+                    continue;
+                }
+                if !fingerprints.insert(fingerprint.clone()) {
+                    info!(
+                        "Skipping '{link}' as it matches the fingerprint of another article in this fetch"
+                    );
+                    continue;
+                }
+            }
 
-            posts.push(NikkiNewsPost {
-                r#abstract: item.r#abstract.trim().to_string(),
-                cover: item.cover,
-                publish_time: item.publish_time,
-                title: item.title.trim().to_string(),
+            posts.push(SourcePost {
+                summary: r#abstract,
+                thumbnail: item.cover,
+                published: item.publish_time,
+                title,
                 url: link,
+                key,
+                source_id: Some(source_id),
+                section: Some(item.section),
+                fingerprint,
+                is_update: false,
             });
         }
-        self.filter_date = Utc::now() - self.backdate_duration;
+
+        let now = Utc::now();
+        match limit {
+            Some(limit) if posts.len() > limit => {
+                // Defer the newest posts so that a backlog is drained in the order it was published.
+                let deferred: Vec<_> = posts.drain(..posts.len() - limit).collect();
+                debug!("Deferring {} posts until the next fetch", deferred.len());
+                let oldest_deferred = deferred
+                    .iter()
+                    .map(|post| post.published)
+                    .min()
+                    .expect("deferred posts should never be empty");
+                self.processed_until = now.min(oldest_deferred - Duration::nanoseconds(1));
+            }
+            _ => self.processed_until = now,
+        }
+        // An unchanged response can only be skipped when nothing from it was deferred to the next fetch or
+        // is waiting to be published.
+        self.validators = match self.processed_until == now
+            && !has_future_news
+            && (validators.0.is_some() || validators.1.is_some())
+        {
+            true => Some(validators),
+            false => None,
+        };
+        self.filter_date = (now - self.backdate_duration).min(self.processed_until);
         Ok(posts)
     }
 }
+
+impl PostSource for NikkiNewsFetcher<'_> {
+    fn describe(&self) -> String {
+        self.news_url.to_string()
+    }
+
+    fn name(&self) -> String {
+        format!("nikki:{}", self.locale)
+    }
+
+    fn locale(&self) -> Option<&str> {
+        Some(&self.locale)
+    }
+
+    fn languages(&self) -> Option<&[String]> {
+        None
+    }
+
+    fn processed_until(&self) -> DateTime<Utc> {
+        self.processed_until
+    }
+
+    fn fetch_unposted(&mut self, limit: Option<usize>) -> BoxFuture<'_, Result<Vec<SourcePost>>> {
+        Box::pin(self.fetch(limit))
+    }
+
+    fn error_class(&self, err: &anyhow::Error) -> &'static str {
+        match err
+            .downcast_ref::<serde_path_to_error::Error<serde_json::Error>>()
+            .is_some()
+        {
+            true => "decode",
+            false => source::error_class(err),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::database::PostFilter;
+    use axum::{
+        Json, Router, extract::RawQuery, http::HeaderMap, response::IntoResponse, routing::get,
+    };
+    use serde_json::{Value, json};
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    /// News served by [`spawn_news`], newest first, which can be changed between fetches.
+    pub(crate) type News = Arc<Mutex<Vec<Value>>>;
+
+    pub(crate) fn news_item(id: usize, publish_time: DateTime<Utc>) -> Value {
+        json!({
+            "id": id,
+            "title": format!("News {id}"),
+            "section": 1,
+            "publish_time": publish_time.to_rfc3339(),
+            "abstract": format!("The abstract of news {id}"),
+        })
+    }
+
+    /// Serve news from a mock of the news API on a random local port, paged by the requested offset and limit.
+    pub(crate) async fn spawn_news(news: News) -> Url {
+        spawn_recorded_news(news).await.0
+    }
+
+    /// Serve news like [`spawn_news`], recording the offset of every page requested.
+    async fn spawn_recorded_news(news: News) -> (Url, Arc<Mutex<Vec<usize>>>) {
+        let offsets = Arc::new(Mutex::new(vec![]));
+        let recorder = offsets.clone();
+        let router = Router::new().route(
+            "/api/news",
+            get(move |RawQuery(query): RawQuery| async move {
+                let query = query.unwrap_or_default();
+                let param = |name: &str| -> usize {
+                    query
+                        .split('&')
+                        .find_map(|param| param.strip_prefix(&format!("{name}=")))
+                        .unwrap()
+                        .parse()
+                        .unwrap()
+                };
+                recorder.lock().unwrap().push(param("offset"));
+                let news = news.lock().unwrap();
+                let page: Vec<_> = news
+                    .iter()
+                    .skip(param("offset"))
+                    .take(param("limit"))
+                    .cloned()
+                    .collect();
+                Json(json!({ "data": { "total": news.len(), "data": page } }))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        (url, offsets)
+    }
+
+    fn fetcher(url: Url, database: &Database, backdate: Duration) -> NikkiNewsFetcher<'_> {
+        NikkiNewsFetcher::new(
+            reqwest::Client::new(),
+            "en".into(),
+            database,
+            backdate,
+            false,
+            NewsApi {
+                base_url: url,
+                article_base_url: Url::parse("https://example.com").unwrap(),
+                page_size: NonZeroUsize::new(10).unwrap(),
+            },
+            NewsFilter::default(),
+        )
+    }
+
+    fn ids(posts: &[SourcePost]) -> Vec<String> {
+        posts
+            .iter()
+            .map(|post| post.source_id.clone().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn news_published_while_stopped_is_caught_up_on() {
+        let database = Database::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        let news = Arc::new(Mutex::new(vec![
+            news_item(2, now - Duration::hours(1)),
+            news_item(1, now - Duration::hours(10)),
+        ]));
+        let url = spawn_news(news).await;
+        database
+            .set_last_successful_fetch(now - Duration::hours(12))
+            .await
+            .unwrap();
+
+        let mut backdated = fetcher(url.clone(), &database, Duration::hours(3));
+        assert_eq!(ids(&backdated.fetch(None).await.unwrap()), ["nikki:2"]);
+
+        // A restart picks up from the last successful fetch rather than the backdate.
+        let last_successful_fetch = database.get_last_successful_fetch().await.unwrap().unwrap();
+        let mut restarted = fetcher(url, &database, Duration::hours(3));
+        restarted.catch_up_from(last_successful_fetch, Duration::hours(48));
+        assert_eq!(
+            ids(&restarted.fetch(None).await.unwrap()),
+            ["nikki:2", "nikki:1"]
+        );
+    }
+
+    #[tokio::test]
+    async fn catching_up_goes_back_no_further_than_the_max_catchup() {
+        let database = Database::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        let news = Arc::new(Mutex::new(vec![
+            news_item(2, now - Duration::hours(1)),
+            news_item(1, now - Duration::hours(10)),
+        ]));
+        let mut restarted = fetcher(spawn_news(news).await, &database, Duration::hours(3));
+        restarted.catch_up_from(now - Duration::days(30), Duration::hours(5));
+        assert_eq!(ids(&restarted.fetch(None).await.unwrap()), ["nikki:2"]);
+    }
+
+    #[tokio::test]
+    async fn news_past_the_first_page_is_found() {
+        let database = Database::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        let news = (1..=25)
+            .rev()
+            .map(|id| news_item(id, now - Duration::minutes(26 - id as i64)))
+            .collect();
+        let mut fetcher = fetcher(
+            spawn_news(Arc::new(Mutex::new(news))).await,
+            &database,
+            Duration::hours(1),
+        );
+        let expected: Vec<_> = (1..=25).rev().map(|id| format!("nikki:{id}")).collect();
+        assert_eq!(ids(&fetcher.fetch(None).await.unwrap()), expected);
+    }
+
+    #[tokio::test]
+    async fn the_latest_news_is_fetched_without_a_database() {
+        let news = (1..=25).rev().map(|id| news_item(id, Utc::now())).collect();
+        let url = spawn_news(Arc::new(Mutex::new(news))).await;
+        let api = NewsApi {
+            base_url: url.clone(),
+            article_base_url: url,
+            page_size: NonZeroUsize::new(10).unwrap(),
+        };
+        let items = api
+            .fetch_latest(&reqwest::Client::new(), "en")
+            .await
+            .unwrap();
+        // Only the first page is fetched.
+        let ids: Vec<_> = items.iter().map(|item| item.id).collect();
+        assert_eq!(ids, (16..=25).rev().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn paging_stops_at_the_filter_date() {
+        let database = Database::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        let news = (1..=40)
+            .rev()
+            .map(|id| news_item(id, now - Duration::hours(41 - id as i64)))
+            .collect();
+        let (url, offsets) = spawn_recorded_news(Arc::new(Mutex::new(news))).await;
+        // The third newest item is already past the filter date, so the second page is never requested.
+        let mut recent = fetcher(url.clone(), &database, Duration::minutes(150));
+        assert_eq!(
+            ids(&recent.fetch(None).await.unwrap()),
+            ["nikki:40", "nikki:39"]
+        );
+        assert_eq!(*offsets.lock().unwrap(), [0]);
+
+        offsets.lock().unwrap().clear();
+        let mut backdated = fetcher(url, &database, Duration::hours(25));
+        let expected: Vec<_> = (17..=40).rev().map(|id| format!("nikki:{id}")).collect();
+        assert_eq!(ids(&backdated.fetch(None).await.unwrap()), expected);
+        assert_eq!(*offsets.lock().unwrap(), [0, 10, 20]);
+    }
+
+    #[tokio::test]
+    async fn paging_stops_after_the_maximum_number_of_pages() {
+        let database = Database::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        let news = (1..=150)
+            .rev()
+            .map(|id| news_item(id, now - Duration::seconds(151 - id as i64)))
+            .collect();
+        let (url, offsets) = spawn_recorded_news(Arc::new(Mutex::new(news))).await;
+        let mut fetcher = fetcher(url, &database, Duration::hours(1));
+        assert_eq!(
+            fetcher.fetch(None).await.unwrap().len(),
+            MAX_NEWS_PAGES * 10
+        );
+        assert_eq!(offsets.lock().unwrap().len(), MAX_NEWS_PAGES);
+    }
+
+    #[tokio::test]
+    async fn duplicate_ids_are_only_returned_once() {
+        let database = Database::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        let item = |id: usize| news_item(id, now - Duration::minutes(10 - id as i64));
+        // Duplicates that aren't next to each other, including one on the next page.
+        let mut news = vec![item(4), item(2), item(4), item(3), item(2), item(1)];
+        news.extend((0..4).map(|_| item(1)));
+        news.push(item(3));
+        let mut fetcher = fetcher(
+            spawn_news(Arc::new(Mutex::new(news))).await,
+            &database,
+            Duration::hours(1),
+        );
+        assert_eq!(
+            ids(&fetcher.fetch(None).await.unwrap()),
+            ["nikki:4", "nikki:3", "nikki:2", "nikki:1"]
+        );
+    }
+
+    #[tokio::test]
+    async fn unchanged_news_is_not_fetched_again() {
+        let database = Database::new_in_memory().await.unwrap();
+        let news = json!({ "data": { "total": 1, "data": [news_item(1, Utc::now())] } });
+        // Each request's If-None-Match header, answered with 304 Not Modified once it matches the ETag.
+        let validators = Arc::new(Mutex::new(vec![]));
+        let recorder = validators.clone();
+        let router = Router::new().route(
+            "/api/news",
+            get(move |headers: HeaderMap| async move {
+                let validator = headers
+                    .get(IF_NONE_MATCH)
+                    .map(|value| value.to_str().unwrap().to_string());
+                recorder.lock().unwrap().push(validator.clone());
+                match validator.as_deref() {
+                    Some("\"v1\"") => StatusCode::NOT_MODIFIED.into_response(),
+                    _ => ([(ETAG, "\"v1\"")], Json(news.clone())).into_response(),
+                }
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let mut fetcher = fetcher(url, &database, Duration::hours(1));
+        let posts = fetcher.fetch(None).await.unwrap();
+        assert_eq!(ids(&posts), ["nikki:1"]);
+        publish(&database, &posts).await;
+        let processed_until = fetcher.processed_until();
+
+        // Not having changed counts as a successful fetch of nothing new rather than an error.
+        assert!(fetcher.fetch(None).await.unwrap().is_empty());
+        assert_eq!(
+            *validators.lock().unwrap(),
+            [None, Some("\"v1\"".to_string())]
+        );
+        assert!(fetcher.processed_until() > processed_until);
+    }
+
+    #[test]
+    fn malformed_news_items_are_skipped() {
+        let url = Url::parse("https://example.com/api/news").unwrap();
+        let mut malformed = news_item(2, Utc::now());
+        malformed["publish_time"] = json!("yesterday");
+        let mut with_cover = news_item(3, Utc::now());
+        with_cover["cover"] = json!("https://example.com/cover.png");
+        with_cover["added_later"] = json!({ "anything": true });
+        let body = json!({
+            "data": {
+                "total": 4,
+                "data": [with_cover, malformed, news_item(1, Utc::now()), { "title": "No id" }],
+            }
+        });
+
+        let page = parse_news_page(&url, body.to_string().as_bytes()).unwrap();
+        assert_eq!(page.total, 4);
+        assert_eq!(page.len, 4);
+        let items: Vec<_> = page.items.iter().map(|item| item.id).collect();
+        assert_eq!(items, [3, 1]);
+        assert_eq!(
+            page.items[0].cover.as_ref().map(Url::as_str),
+            Some("https://example.com/cover.png")
+        );
+        assert_eq!(page.items[1].cover, None);
+    }
+
+    #[tokio::test]
+    async fn responses_that_cant_be_parsed_are_decode_errors() {
+        let url = Url::parse("https://example.com/api/news").unwrap();
+        let Err(err) = parse_news_page(&url, br#"{"data": {"total": "many", "data": []}}"#) else {
+            panic!("a total that isn't a number should fail to parse");
+        };
+        let database = Database::new_in_memory().await.unwrap();
+        let fetcher = fetcher(url, &database, Duration::hours(1));
+        assert_eq!(fetcher.error_class(&err), "decode");
+        assert!(format!("{err:#}").contains("data.total"), "{err:#}");
+    }
+
+    #[tokio::test]
+    async fn news_published_in_the_future_is_picked_up_once_current() {
+        let database = Database::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        let news = Arc::new(Mutex::new(vec![
+            news_item(3, now + Duration::hours(1)),
+            news_item(2, now + FUTURE_PUBLISH_SKEW + Duration::seconds(1)),
+            news_item(1, now + FUTURE_PUBLISH_SKEW - Duration::seconds(5)),
+        ]));
+        let mut fetcher = fetcher(spawn_news(news).await, &database, Duration::zero());
+        // News within the allowed skew is posted, but anything past it waits.
+        let posts = fetcher.fetch(None).await.unwrap();
+        assert_eq!(ids(&posts), ["nikki:1"]);
+        publish(&database, &posts).await;
+        assert!(fetcher.processed_until() <= Utc::now());
+
+        tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+        assert_eq!(ids(&fetcher.fetch(None).await.unwrap()), ["nikki:2"]);
+    }
+
+    #[tokio::test]
+    async fn news_is_found_by_its_id_or_by_the_url_of_legacy_entries() {
+        let database = Database::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        // Stored before source ids were recorded, so it can only be found by its URL.
+        database
+            .record_posted(
+                "https://example.com/en/news/1",
+                "nikki:en",
+                None,
+                None,
+                PostStatus::Posted,
+            )
+            .await
+            .unwrap();
+        let news = Arc::new(Mutex::new(vec![
+            news_item(2, now - Duration::minutes(5)),
+            news_item(1, now - Duration::minutes(10)),
+        ]));
+        let mut fetcher = fetcher(spawn_news(news).await, &database, Duration::hours(1));
+        let posts = fetcher.fetch(None).await.unwrap();
+        assert_eq!(ids(&posts), ["nikki:2"]);
+        assert!(database.has_posted_source_id("nikki:1").await.unwrap());
+        publish(&database, &posts).await;
+
+        // Once the id is stored the news is still found when the links to it change.
+        fetcher.api.article_base_url = Url::parse("https://moved.example.com").unwrap();
+        assert!(fetcher.fetch(None).await.unwrap().is_empty());
+    }
+
+    /// Store news returned by a fetch as posted, like the start command does once it's published.
+    async fn publish(database: &Database, posts: &[SourcePost]) {
+        for post in posts {
+            database
+                .record_posted(
+                    post.key.as_str(),
+                    "nikki:en",
+                    post.source_id.as_deref(),
+                    Some(&post.fingerprint),
+                    PostStatus::Pending,
+                )
+                .await
+                .unwrap();
+            database
+                .mark_published(post.key.as_str(), None, Utc::now().date_naive())
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Serve a single item of news that has been posted, returning the fetcher and a way to edit the news.
+    async fn posted_news(database: &Database) -> (NikkiNewsFetcher<'_>, News) {
+        let news = Arc::new(Mutex::new(vec![news_item(
+            1,
+            Utc::now() - Duration::minutes(5),
+        )]));
+        let mut fetcher = fetcher(spawn_news(news.clone()).await, database, Duration::hours(1));
+        let posts = fetcher.fetch(None).await.unwrap();
+        assert_eq!(ids(&posts), ["nikki:1"]);
+        assert!(!posts[0].is_update);
+        publish(database, &posts).await;
+        // The first fetch after posting stores the hash of the news as it was posted.
+        assert!(fetcher.fetch(None).await.unwrap().is_empty());
+        (fetcher, news)
+    }
+
+    #[tokio::test]
+    async fn republished_news_is_recorded_under_the_source_of_its_locale() {
+        let database = Database::new_in_memory().await.unwrap();
+        let published = Utc::now() - Duration::minutes(5);
+        let news = Arc::new(Mutex::new(vec![news_item(1, published)]));
+        let mut fetcher = NikkiNewsFetcher {
+            fingerprint_dedup: true,
+            ..fetcher(
+                spawn_news(news.clone()).await,
+                &database,
+                Duration::hours(1),
+            )
+        };
+        publish(&database, &fetcher.fetch(None).await.unwrap()).await;
+
+        // The same article under a new id is skipped, but still recorded so it isn't checked again.
+        let mut republished = news_item(2, published);
+        republished["title"] = json!("News 1");
+        *news.lock().unwrap() = vec![republished];
+        assert!(fetcher.fetch(None).await.unwrap().is_empty());
+        let posts = database
+            .query_posts(&PostFilter {
+                source: Some("nikki:en".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(posts.len(), 2);
+        assert!(posts.iter().all(|post| post.source == "nikki:en"));
+    }
+
+    #[tokio::test]
+    async fn unchanged_news_is_only_posted_once() {
+        let database = Database::new_in_memory().await.unwrap();
+        let (mut fetcher, _) = posted_news(&database).await;
+        fetcher.post_updates("[Updated] ".into());
+        assert!(fetcher.fetch(None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn edited_news_is_skipped_unless_updates_are_posted() {
+        let database = Database::new_in_memory().await.unwrap();
+        let (mut fetcher, news) = posted_news(&database).await;
+        news.lock().unwrap()[0]["title"] = json!("News 1 (fixed)");
+        assert!(fetcher.fetch(None).await.unwrap().is_empty());
+
+        fetcher.post_updates("[Updated] ".into());
+        let posts = fetcher.fetch(None).await.unwrap();
+        assert_eq!(ids(&posts), ["nikki:1"]);
+        assert!(posts[0].is_update);
+        assert_eq!(posts[0].title, "[Updated] News 1 (fixed)");
+        // The edit is found again until its update is published.
+        assert_eq!(ids(&fetcher.fetch(None).await.unwrap()), ["nikki:1"]);
+        publish(&database, &posts).await;
+        assert!(fetcher.fetch(None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn refused_updates_are_not_found_again() {
+        let database = Database::new_in_memory().await.unwrap();
+        let (mut fetcher, news) = posted_news(&database).await;
+        fetcher.post_updates("[Updated] ".into());
+        news.lock().unwrap()[0]["title"] = json!("News 1 (fixed)");
+        let posts = fetcher.fetch(None).await.unwrap();
+        assert_eq!(ids(&posts), ["nikki:1"]);
+        database
+            .apply_pending_hash(posts[0].key.as_str())
+            .await
+            .unwrap();
+        assert!(fetcher.fetch(None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn updates_waiting_to_be_retried_are_not_posted_again() {
+        let database = Database::new_in_memory().await.unwrap();
+        let (mut fetcher, news) = posted_news(&database).await;
+        fetcher.post_updates("[Updated] ".into());
+        news.lock().unwrap()[0]["title"] = json!("News 1 (fixed)");
+        let posts = fetcher.fetch(None).await.unwrap();
+        assert!(posts[0].is_update);
+        database
+            .add_failed_post(
+                posts[0].key.as_str(),
+                "nikki:en",
+                posts[0].source_id.as_deref(),
+                Some(&posts[0].fingerprint),
+                "{}",
+                "failed to post",
+            )
+            .await
+            .unwrap();
+        assert!(fetcher.fetch(None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_backlog_is_drained_in_order_across_limited_fetches() {
+        let database = Database::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        let news = (1..=30)
+            .rev()
+            .map(|id| news_item(id, now - Duration::hours(31 - id as i64)))
+            .collect();
+        let url = spawn_news(Arc::new(Mutex::new(news))).await;
+        let mut fetcher = fetcher(url, &database, Duration::hours(3));
+        fetcher.catch_up_from(now - Duration::hours(31), Duration::hours(48));
+
+        let mut batches = vec![];
+        for _ in 0..10 {
+            let posts = fetcher.fetch(Some(5)).await.unwrap();
+            for post in &posts {
+                database
+                    .record_posted(
+                        post.key.as_str(),
+                        "nikki:en",
+                        post.source_id.as_deref(),
+                        Some(&post.fingerprint),
+                        PostStatus::Posted,
+                    )
+                    .await
+                    .unwrap();
+            }
+            database
+                .set_last_successful_fetch(fetcher.processed_until())
+                .await
+                .unwrap();
+            batches.push(ids(&posts));
+        }
+        // The oldest news is posted first, with each fetch returning its posts newest first.
+        let mut expected: Vec<Vec<_>> = (0..6)
+            .map(|batch| {
+                (batch * 5 + 1..=batch * 5 + 5)
+                    .rev()
+                    .map(|id| format!("nikki:{id}"))
+                    .collect()
+            })
+            .collect();
+        expected.resize(10, vec![]);
+        assert_eq!(batches, expected);
+    }
+
+    /// Fetch and post news a few items at a time until there is none left like the start command does, returning
+    /// the source ids of the news this drain was the one to post.
+    async fn drain(url: Url, database: &Database, since: DateTime<Utc>) -> Vec<String> {
+        let mut fetcher = fetcher(url, database, Duration::hours(3));
+        fetcher.catch_up_from(since, Duration::hours(48));
+        let mut posted = vec![];
+        loop {
+            let posts = fetcher.fetch(Some(3)).await.unwrap();
+            if posts.is_empty() {
+                return posted;
+            }
+            for post in posts {
+                // Only the first drain to store a post goes on to post it.
+                if database
+                    .record_posted(
+                        post.key.as_str(),
+                        "nikki:en",
+                        post.source_id.as_deref(),
+                        Some(&post.fingerprint),
+                        PostStatus::Pending,
+                    )
+                    .await
+                    .unwrap()
+                {
+                    database
+                        .mark_published(post.key.as_str(), None, Utc::now().date_naive())
+                        .await
+                        .unwrap();
+                    posted.push(post.source_id.unwrap());
+                }
+            }
+            let processed_until = fetcher.processed_until();
+            database
+                .set_last_successful_fetch(processed_until)
+                .await
+                .unwrap();
+            // Other drains may have moved it further, but never back. Times are compared to the millisecond.
+            let stored = database.get_last_successful_fetch().await.unwrap().unwrap();
+            assert!(stored >= processed_until - Duration::milliseconds(1));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_drains_post_every_item_exactly_once() {
+        let database: &'static Database =
+            Box::leak(Box::new(Database::new_in_memory().await.unwrap()));
+        let now = Utc::now();
+        let news = (1..=60)
+            .rev()
+            .map(|id| news_item(id, now - Duration::minutes(1900 - id as i64 * 30)))
+            .collect();
+        let url = spawn_news(Arc::new(Mutex::new(news))).await;
+
+        let drains: Vec<_> = (0..4)
+            .map(|_| tokio::spawn(drain(url.clone(), database, now - Duration::hours(32))))
+            .collect();
+        let mut posted = vec![];
+        for drain in drains {
+            posted.append(&mut drain.await.unwrap());
+        }
+        posted.sort_by_key(|id| id["nikki:".len()..].parse::<usize>().unwrap());
+        let expected: Vec<_> = (1..=60).map(|id| format!("nikki:{id}")).collect();
+        assert_eq!(posted, expected);
+    }
+}
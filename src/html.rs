@@ -0,0 +1,139 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+static TAG_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+static ENTITY_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap());
+static META_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)<meta\s[^>]*>").unwrap());
+static LINK_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)<link\s[^>]*>").unwrap());
+static ATTRIBUTE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"([a-zA-Z:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap()
+});
+
+/// Convert text that may contain HTML markup into plain text.
+///
+/// Tags are removed, common named and numeric entities are decoded and runs of whitespace are collapsed.
+pub fn to_plain_text(text: &str) -> String {
+    let text = TAG_REGEX.replace_all(text, " ");
+    let text = ENTITY_REGEX.replace_all(&text, |caps: &regex::Captures| {
+        let entity = &caps[1];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => match entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => entity
+                    .strip_prefix('#')
+                    .and_then(|dec| dec.parse().ok())
+                    .and_then(char::from_u32),
+            },
+        };
+        decoded.map_or_else(|| caps[0].to_string(), String::from)
+    });
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Get the content of the first `<meta>` tag in a HTML document with a `property` or `name` matching the given one,
+/// such as `og:image`.
+pub fn meta_content(document: &str, property: &str) -> Option<String> {
+    META_REGEX.find_iter(document).find_map(|tag| {
+        let attributes = attributes(tag.as_str());
+        let matches_property = attributes.iter().any(|(name, value)| {
+            matches!(name.as_str(), "property" | "name") && value.eq_ignore_ascii_case(property)
+        });
+        attributes
+            .into_iter()
+            .find(|(name, _)| name == "content")
+            .map(|(_, content)| to_plain_text(&content))
+            .filter(|content| matches_property && !content.is_empty())
+    })
+}
+
+/// Get the `href` of the first `<link>` tag in a HTML document with the given `rel`, such as `canonical`.
+pub fn link_href(document: &str, rel: &str) -> Option<String> {
+    LINK_REGEX.find_iter(document).find_map(|tag| {
+        let attributes = attributes(tag.as_str());
+        let matches_rel = attributes.iter().any(|(name, value)| {
+            name == "rel"
+                && value
+                    .split_whitespace()
+                    .any(|value| value.eq_ignore_ascii_case(rel))
+        });
+        attributes
+            .into_iter()
+            .find(|(name, _)| name == "href")
+            .map(|(_, href)| to_plain_text(&href))
+            .filter(|href| matches_rel && !href.is_empty())
+    })
+}
+
+/// Get the attributes of a single tag as lowercased names and their unquoted values.
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    ATTRIBUTE_REGEX
+        .captures_iter(tag)
+        .map(|caps| {
+            let value = caps
+                .get(2)
+                .or_else(|| caps.get(3))
+                .or_else(|| caps.get(4))
+                .map_or("", |value| value.as_str());
+            (caps[1].to_ascii_lowercase(), value.to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markup_is_stripped_and_whitespace_collapsed() {
+        assert_eq!(
+            to_plain_text("<p>New <b>outfits</b>\n  are<br/>here</p>"),
+            "New outfits are here"
+        );
+        assert_eq!(to_plain_text("No markup"), "No markup");
+    }
+
+    #[test]
+    fn entities_are_decoded() {
+        assert_eq!(
+            to_plain_text("Nikki &amp; Momo &lt;3 &quot;Miraland&quot;&nbsp;&apos;26"),
+            "Nikki & Momo <3 \"Miraland\" '26"
+        );
+        assert_eq!(to_plain_text("&#9733; &#x2728; &#X1F338;"), "★ ✨ 🌸");
+        // Entities that aren't known or aren't valid characters are left alone.
+        assert_eq!(to_plain_text("&copy; &#xD800;"), "&copy; &#xD800;");
+    }
+
+    #[test]
+    fn decoded_entities_are_not_treated_as_markup() {
+        assert_eq!(to_plain_text("&lt;b&gt;bold&lt;/b&gt;"), "<b>bold</b>");
+    }
+
+    #[test]
+    fn meta_content_and_link_hrefs_are_found_by_name() {
+        let document = r#"<head>
+            <meta name="description" content="Ignored">
+            <META property="og:image" content='https://example.com/a.png?x=1&amp;y=2'>
+            <link rel="alternate canonical" href=https://example.com/news/1>
+        </head>"#;
+        assert_eq!(
+            meta_content(document, "OG:IMAGE").as_deref(),
+            Some("https://example.com/a.png?x=1&y=2")
+        );
+        assert_eq!(meta_content(document, "og:title"), None);
+        assert_eq!(
+            link_href(document, "canonical").as_deref(),
+            Some("https://example.com/news/1")
+        );
+        assert_eq!(link_href(document, "icon"), None);
+    }
+}
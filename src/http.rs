@@ -0,0 +1,92 @@
+use anyhow::Result;
+use std::time::Duration;
+
+/// The user agent sent with every HTTP request.
+const USER_AGENT: &str = concat!("whimsky/", env!("CARGO_PKG_VERSION"));
+
+/// The default time in seconds to wait for a connection or a response before an HTTP request fails.
+pub const DEFAULT_HTTP_TIMEOUT_SECONDS: u64 = 30;
+/// How long to wait for an article page when reading metadata from it.
+pub const PAGE_TIMEOUT: Duration = Duration::from_secs(10);
+/// The maximum number of bytes of an article page that are read when looking for metadata.
+pub const MAX_PAGE_BYTES: usize = 1024 * 1024;
+
+/// Build the HTTP client shared by everything that makes requests, so that connections are pooled and a
+/// server that stops responding can't stall the bot indefinitely.
+pub fn build_client(timeout: Duration) -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(timeout)
+        .read_timeout(timeout)
+        .build()?)
+}
+
+/// Read the body of a response up to the given number of bytes, discarding the rest.
+pub async fn read_limited(mut response: reqwest::Response, max_bytes: usize) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= max_bytes {
+            body.truncate(max_bytes);
+            break;
+        }
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, http::HeaderMap, routing::get};
+    use std::time::Instant;
+    use tokio::net::TcpListener;
+
+    async fn spawn(router: Router) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        url
+    }
+
+    #[tokio::test]
+    async fn requests_to_servers_that_never_respond_time_out() {
+        // Connections are accepted by the listener's backlog but nothing is ever written back.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let client = build_client(Duration::from_millis(200)).unwrap();
+
+        let started = Instant::now();
+        let err = client.get(url).send().await.unwrap_err();
+        assert!(err.is_timeout(), "{err}");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn requests_identify_whimsky() {
+        let url =
+            spawn(Router::new().route(
+                "/",
+                get(|headers: HeaderMap| async move {
+                    headers["user-agent"].to_str().unwrap().to_string()
+                }),
+            ))
+            .await;
+        let client = build_client(Duration::from_secs(5)).unwrap();
+        let user_agent = client.get(url).send().await.unwrap().text().await.unwrap();
+        assert_eq!(user_agent, USER_AGENT);
+        assert!(user_agent.starts_with("whimsky/"));
+    }
+
+    #[tokio::test]
+    async fn bodies_are_read_up_to_the_limit() {
+        let url = spawn(Router::new().route("/", get(|| async { "a".repeat(100_000) }))).await;
+        let client = build_client(Duration::from_secs(5)).unwrap();
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(read_limited(response, 10).await.unwrap(), b"aaaaaaaaaa");
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(
+            read_limited(response, 1_000_000).await.unwrap().len(),
+            100_000
+        );
+    }
+}
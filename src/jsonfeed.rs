@@ -0,0 +1,138 @@
+use crate::html;
+use crate::rss::FeedEntry;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde::Deserialize;
+
+/// A feed in the JSON Feed format described at <https://www.jsonfeed.org/version/1.1/>.
+#[derive(Debug, Deserialize)]
+struct JsonFeed {
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonFeedItem {
+    id: serde_json::Value,
+    url: Option<String>,
+    title: Option<String>,
+    summary: Option<String>,
+    content_text: Option<String>,
+    content_html: Option<String>,
+    image: Option<String>,
+    banner_image: Option<String>,
+    date_published: Option<DateTime<Utc>>,
+    date_modified: Option<DateTime<Utc>>,
+}
+
+/// Parse the items of a JSON Feed, resolving any relative URLs against the URL of the feed.
+pub fn parse(body: &[u8], feed_url: &Url) -> Result<Vec<FeedEntry>> {
+    let feed: JsonFeed =
+        serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_slice(body))
+            .with_context(|| format!("failed to parse JSON Feed from {feed_url}"))?;
+    Ok(feed
+        .items
+        .into_iter()
+        .map(|item| {
+            // Ids should be strings, but some feeds use numbers.
+            let id = match item.id {
+                serde_json::Value::String(id) => id,
+                id => id.to_string(),
+            };
+            FeedEntry {
+                title: item
+                    .title
+                    .as_deref()
+                    .map(html::to_plain_text)
+                    .unwrap_or_default(),
+                link: match item.url {
+                    Some(url) => feed_url.join(&url).ok(),
+                    None => FeedEntry::link_from_id(&id),
+                },
+                published: item.date_published.or(item.date_modified),
+                summary: item
+                    .summary
+                    .or(item.content_text)
+                    .or(item.content_html)
+                    .as_deref()
+                    .map(html::to_plain_text)
+                    .unwrap_or_default(),
+                thumbnail: item
+                    .image
+                    .or(item.banner_image)
+                    .and_then(|image| feed_url.join(&image).ok()),
+                id,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = include_str!("../tests/fixtures/feed.json");
+
+    #[test]
+    fn parses_items_with_fallbacks() {
+        let feed_url = Url::parse("https://example.com/feed.json").unwrap();
+        let entries = parse(FEED.as_bytes(), &feed_url).unwrap();
+        let entries = entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.id.as_str(),
+                    entry.title.as_str(),
+                    entry.link.as_ref().map(Url::as_str),
+                    entry.published.map(|published| published.to_rfc3339()),
+                    entry.summary.as_str(),
+                    entry.thumbnail.as_ref().map(Url::as_str),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                (
+                    "1",
+                    "Published with an image",
+                    Some("https://example.com/news/1"),
+                    Some("2025-04-01T12:00:00+00:00".to_string()),
+                    "The first item.",
+                    Some("https://example.com/images/1.png"),
+                ),
+                (
+                    "2",
+                    "Only modified",
+                    Some("https://example.com/news/2"),
+                    Some("2025-04-02T12:00:00+00:00".to_string()),
+                    "The second item.",
+                    Some("https://example.com/images/2-banner.png"),
+                ),
+                (
+                    "https://example.com/news/3",
+                    "Permalink in the id",
+                    Some("https://example.com/news/3"),
+                    Some("2025-04-03T03:00:00+00:00".to_string()),
+                    "",
+                    None,
+                ),
+                ("urn:uuid:4", "", None, None, "No link or title", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_feeds_without_items() {
+        let feed_url = Url::parse("https://example.com/feed.json").unwrap();
+        let feed = br#"{"version": "https://jsonfeed.org/version/1.1"}"#;
+        let Err(err) = parse(feed, &feed_url) else {
+            panic!("a feed without items should fail to parse");
+        };
+        assert!(
+            format!("{err:#}")
+                .contains("failed to parse JSON Feed from https://example.com/feed.json"),
+            "{err:#}"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use tracing::debug;
+use whatlang::Lang;
+
+/// Detect the language of a piece of text, returning its ISO-639-1 code.
+///
+/// Returns `None` when the language can't be detected reliably, such as when the text is too short.
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let info = whatlang::detect(text)?;
+    let code = iso_639_1(info.lang());
+    match info.is_reliable() {
+        true => {
+            debug!(
+                "Detected language as {code} with a confidence of {:.2}",
+                info.confidence()
+            );
+            Some(code)
+        }
+        false => {
+            debug!(
+                "Detected language as {code} with a confidence of {:.2}, which is too low to use",
+                info.confidence()
+            );
+            None
+        }
+    }
+}
+
+/// Convert a detected language into the ISO-639-1 code that Bluesky expects.
+fn iso_639_1(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Epo => "eo",
+        Lang::Eng => "en",
+        Lang::Rus => "ru",
+        Lang::Cmn => "zh",
+        Lang::Spa => "es",
+        Lang::Por => "pt",
+        Lang::Ita => "it",
+        Lang::Ben => "bn",
+        Lang::Fra => "fr",
+        Lang::Deu => "de",
+        Lang::Ukr => "uk",
+        Lang::Kat => "ka",
+        Lang::Ara => "ar",
+        Lang::Hin => "hi",
+        Lang::Jpn => "ja",
+        Lang::Heb => "he",
+        Lang::Yid => "yi",
+        Lang::Pol => "pl",
+        Lang::Amh => "am",
+        Lang::Jav => "jv",
+        Lang::Kor => "ko",
+        Lang::Nob => "nb",
+        Lang::Dan => "da",
+        Lang::Swe => "sv",
+        Lang::Fin => "fi",
+        Lang::Tur => "tr",
+        Lang::Nld => "nl",
+        Lang::Hun => "hu",
+        Lang::Ces => "cs",
+        Lang::Ell => "el",
+        Lang::Bul => "bg",
+        Lang::Bel => "be",
+        Lang::Mar => "mr",
+        Lang::Kan => "kn",
+        Lang::Ron => "ro",
+        Lang::Slv => "sl",
+        Lang::Hrv => "hr",
+        Lang::Srp => "sr",
+        Lang::Mkd => "mk",
+        Lang::Lit => "lt",
+        Lang::Lav => "lv",
+        Lang::Est => "et",
+        Lang::Tam => "ta",
+        Lang::Vie => "vi",
+        Lang::Urd => "ur",
+        Lang::Tha => "th",
+        Lang::Guj => "gu",
+        Lang::Uzb => "uz",
+        Lang::Pan => "pa",
+        Lang::Aze => "az",
+        Lang::Ind => "id",
+        Lang::Tel => "te",
+        Lang::Pes => "fa",
+        Lang::Mal => "ml",
+        Lang::Ori => "or",
+        Lang::Mya => "my",
+        Lang::Nep => "ne",
+        Lang::Sin => "si",
+        Lang::Khm => "km",
+        Lang::Tuk => "tk",
+        Lang::Aka => "ak",
+        Lang::Zul => "zu",
+        Lang::Sna => "sn",
+        Lang::Afr => "af",
+        Lang::Lat => "la",
+        Lang::Slk => "sk",
+        Lang::Cat => "ca",
+        Lang::Tgl => "tl",
+        Lang::Hye => "hy",
+        Lang::Cym => "cy",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bsky_sdk::api::types::string::Language;
+    use std::str::FromStr;
+
+    #[test]
+    fn detects_english_japanese_and_korean() {
+        assert_eq!(
+            detect_language(
+                "New outfits are coming to Miraland\nThe next event brings a brand new set of outfits for Nikki to collect."
+            ),
+            Some("en")
+        );
+        assert_eq!(
+            detect_language(
+                "ミラランドに新しいコーデが登場\n次のイベントでは、ニキが集められる新しいコーデが登場します。"
+            ),
+            Some("ja")
+        );
+        assert_eq!(
+            detect_language(
+                "미라랜드에 새로운 코디가 등장합니다\n다음 이벤트에서 니키가 모을 수 있는 새로운 코디가 등장합니다."
+            ),
+            Some("ko")
+        );
+    }
+
+    #[test]
+    fn text_too_short_to_detect_falls_back() {
+        assert_eq!(detect_language(""), None);
+        assert_eq!(detect_language("v1.2"), None);
+    }
+
+    #[test]
+    fn detected_languages_are_valid_post_languages() {
+        for lang in Lang::all() {
+            let code = iso_639_1(*lang);
+            assert!(Language::from_str(code).is_ok(), "{lang:?} -> {code}");
+        }
+    }
+}
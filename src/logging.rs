@@ -0,0 +1,108 @@
+use clap::ValueEnum;
+use tracing_subscriber::EnvFilter;
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// A JSON object per line including the fields of the spans the event was logged in, such as for shipping
+    /// to a log aggregator.
+    Json,
+}
+
+/// The most verbose level of log lines that are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    fn directive(&self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        }
+    }
+}
+
+/// Dependencies that log every query or connection, which are limited to warnings even at more verbose levels.
+const QUIETED_DEPENDENCIES: [&str; 4] = ["sqlx", "hyper", "hyper_util", "h2"];
+
+/// Choose which log lines are written, using the directives of `RUST_LOG` instead of the log level when they are
+/// set and valid.
+fn filter(level: LogLevel, rust_log: Option<&str>) -> EnvFilter {
+    rust_log
+        .and_then(|directives| EnvFilter::try_new(directives).ok())
+        .unwrap_or_else(|| {
+            let dependency_level = level.min(LogLevel::Warn).directive();
+            let directives = [level.directive().to_string()]
+                .into_iter()
+                .chain(
+                    QUIETED_DEPENDENCIES
+                        .iter()
+                        .map(|dependency| format!("{dependency}={dependency_level}")),
+                )
+                .collect::<Vec<_>>();
+            EnvFilter::new(directives.join(","))
+        })
+}
+
+/// Install the global subscriber that writes logs to stdout.
+///
+/// `RUST_LOG` takes priority over the log level when it is set, so that any filter can be used.
+pub fn init(format: LogFormat, level: LogLevel) {
+    let rust_log = std::env::var(EnvFilter::DEFAULT_ENV).ok();
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter(level, rust_log.as_deref()))
+        .with_thread_ids(true);
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::level_filters::LevelFilter;
+
+    #[test]
+    fn dependencies_are_quieter_than_the_log_level() {
+        let filter = filter(LogLevel::Debug, None);
+        assert_eq!(filter.max_level_hint(), Some(LevelFilter::DEBUG));
+        let directives = filter.to_string();
+        for dependency in ["sqlx=warn", "hyper=warn", "h2=warn"] {
+            assert!(directives.contains(dependency), "{directives}");
+        }
+        // Dependencies are never more verbose than the log level.
+        assert_eq!(
+            super::filter(LogLevel::Error, None).max_level_hint(),
+            Some(LevelFilter::ERROR)
+        );
+    }
+
+    #[test]
+    fn rust_log_replaces_the_log_level_unless_invalid() {
+        let filter = filter(LogLevel::Error, Some("whimsky=trace"));
+        assert_eq!(filter.max_level_hint(), Some(LevelFilter::TRACE));
+        assert!(!filter.to_string().contains("sqlx"));
+        assert_eq!(
+            super::filter(LogLevel::Warn, Some("whimsky=nonsense")).max_level_hint(),
+            Some(LevelFilter::WARN)
+        );
+    }
+}
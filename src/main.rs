@@ -1,21 +1,40 @@
+mod boilerplate;
 mod bsky;
+mod canonical;
 mod commands;
+mod config;
 mod database;
+mod dedup;
 mod fetcher;
+mod html;
+mod http;
+mod jsonfeed;
+mod language;
+mod logging;
+mod quiet_hours;
+mod ratelimit;
+mod refresh;
+mod rss;
+mod secret;
+mod server;
+mod shutdown;
+mod source;
+mod template;
+mod thumbnail;
+mod webhook;
 
 use anyhow::Result;
-use clap::Parser;
-use commands::CommandRoot;
 use dotenvy::dotenv;
-use tracing_subscriber::EnvFilter;
+use tracing::warn;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv().ok();
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or(EnvFilter::new("info")))
-        .with_thread_ids(true)
-        .init();
+    let (command, warnings) = config::parse_args()?;
+    logging::init(command.log_format(), command.log_level());
+    for warning in &warnings {
+        warn!("{warning}");
+    }
 
-    CommandRoot::parse().run().await
+    command.run().await
 }
@@ -0,0 +1,104 @@
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use std::{fmt::Display, str::FromStr};
+
+/// A daily window of time during which news should be held instead of posted.
+#[derive(Debug, Clone, Copy)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl FromStr for QuietHours {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("'{s}' is not in the format HH:MM-HH:MM"))?;
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|_| format!("'{time}' is not a valid time in the format HH:MM"))
+        };
+        Ok(Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+impl Display for QuietHours {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+/// Whether the given time falls within the quiet hours window in the given timezone.
+///
+/// Windows where the start is after the end are treated as crossing midnight.
+pub fn is_within_quiet_hours(now: DateTime<Utc>, window: &QuietHours, tz: Tz) -> bool {
+    let time = now.with_timezone(&tz).time();
+    match window.start <= window.end {
+        true => time >= window.start && time < window.end,
+        false => time >= window.start || time < window.end,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 15, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn parses_and_displays_windows() {
+        let window: QuietHours = " 22:00 - 07:30 ".parse().unwrap();
+        assert_eq!(window.to_string(), "22:00-07:30");
+        assert!("22:00".parse::<QuietHours>().is_err());
+        assert!("25:00-07:00".parse::<QuietHours>().is_err());
+        assert!("10pm-7am".parse::<QuietHours>().is_err());
+    }
+
+    #[test]
+    fn windows_within_a_day() {
+        let window: QuietHours = "09:00-17:00".parse().unwrap();
+        assert!(!is_within_quiet_hours(at(8, 59), &window, Tz::UTC));
+        assert!(is_within_quiet_hours(at(9, 0), &window, Tz::UTC));
+        assert!(is_within_quiet_hours(at(16, 59), &window, Tz::UTC));
+        assert!(!is_within_quiet_hours(at(17, 0), &window, Tz::UTC));
+    }
+
+    #[test]
+    fn windows_crossing_midnight() {
+        let window: QuietHours = "22:00-07:00".parse().unwrap();
+        assert!(is_within_quiet_hours(at(23, 30), &window, Tz::UTC));
+        assert!(is_within_quiet_hours(at(0, 0), &window, Tz::UTC));
+        assert!(is_within_quiet_hours(at(6, 59), &window, Tz::UTC));
+        assert!(!is_within_quiet_hours(at(7, 0), &window, Tz::UTC));
+        assert!(!is_within_quiet_hours(at(21, 59), &window, Tz::UTC));
+    }
+
+    #[test]
+    fn windows_are_in_their_timezone() {
+        // 22:00 UTC is 07:00 the next day in Tokyo, which is UTC+9 without daylight saving.
+        let window: QuietHours = "22:00-07:00".parse().unwrap();
+        assert!(!is_within_quiet_hours(at(22, 0), &window, Tz::Asia__Tokyo));
+        assert!(is_within_quiet_hours(at(21, 59), &window, Tz::Asia__Tokyo));
+        assert!(is_within_quiet_hours(at(13, 0), &window, Tz::Asia__Tokyo));
+        assert!(!is_within_quiet_hours(at(12, 59), &window, Tz::Asia__Tokyo));
+    }
+
+    #[test]
+    fn empty_windows_are_never_quiet() {
+        let window: QuietHours = "12:00-12:00".parse().unwrap();
+        assert!(!is_within_quiet_hours(at(12, 0), &window, Tz::UTC));
+    }
+}
@@ -0,0 +1,250 @@
+use bsky_sdk::api::xrpc::{
+    HttpClient, XrpcClient,
+    http::{HeaderMap, Request, Response},
+};
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, Mutex};
+use tracing::debug;
+
+/// The amount of remaining requests below which requests will start being spread out until the limit resets.
+const LOW_REMAINING_THRESHOLD: u64 = 10;
+
+/// Rate limit information reported by the service in the headers of a response.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub remaining: u64,
+    pub reset: DateTime<Utc>,
+}
+
+impl RateLimit {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.parse().ok() };
+        Some(Self {
+            remaining: header("ratelimit-remaining")?,
+            reset: DateTime::from_timestamp(header("ratelimit-reset")? as i64, 0)?,
+        })
+    }
+
+    /// Build a rate limit from the `retry-after` header of a rate limited response, for services that
+    /// don't send the `ratelimit-*` headers.
+    fn from_retry_after(headers: &HeaderMap) -> Option<Self> {
+        let seconds: i64 = headers.get("retry-after")?.to_str().ok()?.parse().ok()?;
+        Some(Self {
+            remaining: 0,
+            reset: Utc::now() + chrono::Duration::seconds(seconds),
+        })
+    }
+
+    /// How long to wait before making another request so that the limit is not hit.
+    ///
+    /// Requests are spread evenly across the time until the limit resets once only a few remain.
+    pub fn pacing_delay(&self, now: DateTime<Utc>) -> Option<std::time::Duration> {
+        if self.remaining >= LOW_REMAINING_THRESHOLD || self.reset <= now {
+            return None;
+        }
+        let until_reset = (self.reset - now).to_std().ok()?;
+        Some(match self.remaining {
+            0 => until_reset,
+            remaining => until_reset / remaining as u32,
+        })
+    }
+}
+
+/// An XRPC client that records the rate limit headers of every response it receives.
+///
+/// Services that don't send rate limit headers are handled the same as any other client.
+#[derive(Clone)]
+pub struct RateLimitedClient {
+    base_uri: String,
+    client: reqwest::Client,
+    rate_limit: Arc<Mutex<Option<RateLimit>>>,
+}
+
+impl RateLimitedClient {
+    pub fn new(base_uri: impl AsRef<str>, client: reqwest::Client) -> Self {
+        Self {
+            base_uri: base_uri.as_ref().into(),
+            client,
+            rate_limit: Arc::default(),
+        }
+    }
+
+    /// Get the rate limit reported by the most recent response that included one.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        *self.rate_limit.lock().unwrap()
+    }
+}
+
+impl HttpClient for RateLimitedClient {
+    async fn send_http(
+        &self,
+        request: Request<Vec<u8>>,
+    ) -> Result<Response<Vec<u8>>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        let response = self.client.execute(request.try_into()?).await?;
+        let rate_limit = RateLimit::from_headers(response.headers()).or_else(|| {
+            match response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                true => RateLimit::from_retry_after(response.headers()),
+                false => None,
+            }
+        });
+        if let Some(rate_limit) = rate_limit {
+            debug!(
+                "Rate limit for {}: {} remaining, resets at {}",
+                response.url().path(),
+                rate_limit.remaining,
+                rate_limit.reset
+            );
+            *self.rate_limit.lock().unwrap() = Some(rate_limit);
+        }
+
+        let mut builder = Response::builder().status(response.status());
+        for (k, v) in response.headers() {
+            builder = builder.header(k, v);
+        }
+        builder
+            .body(response.bytes().await?.to_vec())
+            .map_err(Into::into)
+    }
+}
+
+impl XrpcClient for RateLimitedClient {
+    fn base_uri(&self) -> String {
+        self.base_uri.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, http::StatusCode, routing::get};
+    use chrono::Duration;
+    use tokio::net::TcpListener;
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn rate_limits_are_read_from_headers() {
+        let reset = Utc::now() + Duration::minutes(5);
+        let rate_limit = RateLimit::from_headers(&headers(&[
+            ("ratelimit-remaining", "42"),
+            ("ratelimit-reset", &reset.timestamp().to_string()),
+        ]))
+        .unwrap();
+        assert_eq!(rate_limit.remaining, 42);
+        assert_eq!(rate_limit.reset.timestamp(), reset.timestamp());
+
+        for pairs in [
+            &[][..],
+            &[("ratelimit-remaining", "42")],
+            &[("ratelimit-reset", "1700000000")],
+            &[
+                ("ratelimit-remaining", "many"),
+                ("ratelimit-reset", "1700000000"),
+            ],
+            &[
+                ("ratelimit-remaining", "-1"),
+                ("ratelimit-reset", "1700000000"),
+            ],
+            &[("ratelimit-remaining", "42"), ("ratelimit-reset", "soon")],
+        ] {
+            assert!(
+                RateLimit::from_headers(&headers(pairs)).is_none(),
+                "{pairs:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn retry_after_is_read_as_a_limit_with_nothing_remaining() {
+        let before = Utc::now();
+        let rate_limit = RateLimit::from_retry_after(&headers(&[("retry-after", "60")])).unwrap();
+        assert_eq!(rate_limit.remaining, 0);
+        assert!(rate_limit.reset >= before + Duration::seconds(60));
+        assert!(rate_limit.reset <= Utc::now() + Duration::seconds(60));
+
+        // Only a number of seconds is understood, rather than an HTTP date.
+        for value in ["Wed, 21 Oct 2015 07:28:00 GMT", "soon"] {
+            assert!(RateLimit::from_retry_after(&headers(&[("retry-after", value)])).is_none());
+        }
+        assert!(RateLimit::from_retry_after(&HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn requests_are_only_paced_once_few_remain() {
+        let now = Utc::now();
+        let limit = |remaining, reset| RateLimit { remaining, reset };
+        let minute = std::time::Duration::from_secs(60);
+        assert_eq!(
+            limit(LOW_REMAINING_THRESHOLD, now + Duration::minutes(1)).pacing_delay(now),
+            None
+        );
+        assert_eq!(
+            limit(4, now + Duration::minutes(1)).pacing_delay(now),
+            Some(minute / 4)
+        );
+        assert_eq!(
+            limit(0, now + Duration::minutes(1)).pacing_delay(now),
+            Some(minute)
+        );
+        // A limit that has already reset has nothing left to wait for.
+        for reset in [now, now - Duration::minutes(1)] {
+            assert_eq!(limit(0, reset).pacing_delay(now), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn the_latest_rate_limit_is_kept_from_responses() {
+        let reset = (Utc::now() + Duration::minutes(5)).timestamp().to_string();
+        let router = Router::new()
+            .route(
+                "/limited",
+                get(move || async move {
+                    [
+                        ("ratelimit-remaining", "3".to_string()),
+                        ("ratelimit-reset", reset),
+                    ]
+                }),
+            )
+            .route(
+                "/throttled",
+                get(|| async { (StatusCode::TOO_MANY_REQUESTS, [("retry-after", "120")]) }),
+            )
+            .route("/unlimited", get(|| async { "ok" }))
+            .route(
+                "/failed",
+                get(|| async { (StatusCode::SERVICE_UNAVAILABLE, [("retry-after", "120")]) }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_uri = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        let client = RateLimitedClient::new(&base_uri, reqwest::Client::new());
+        let send = |path: &str| {
+            let request = Request::builder()
+                .uri(format!("{base_uri}{path}"))
+                .body(vec![])
+                .unwrap();
+            let client = client.clone();
+            async move { client.send_http(request).await.unwrap().status() }
+        };
+
+        assert_eq!(send("/unlimited").await, 200);
+        assert!(client.rate_limit().is_none());
+        send("/limited").await;
+        assert_eq!(client.rate_limit().unwrap().remaining, 3);
+        // Responses without a rate limit don't forget the last one.
+        send("/unlimited").await;
+        assert_eq!(client.rate_limit().unwrap().remaining, 3);
+        // Retry-After is only read from rate limited responses.
+        assert_eq!(send("/failed").await, 503);
+        assert_eq!(client.rate_limit().unwrap().remaining, 3);
+        assert_eq!(send("/throttled").await, 429);
+        let rate_limit = client.rate_limit().unwrap();
+        assert_eq!(rate_limit.remaining, 0);
+        assert!(rate_limit.reset > Utc::now() + Duration::seconds(100));
+    }
+}
@@ -0,0 +1,33 @@
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Create a notifier that is notified whenever the process is asked to refresh immediately with SIGHUP or SIGUSR1.
+///
+/// A signal received while nothing is waiting is remembered, so it is never dropped during an active cycle.
+pub fn refresh_notify() -> Arc<Notify> {
+    let notify = Arc::new(Notify::new());
+    let signal_notify = notify.clone();
+    tokio::spawn(async move {
+        wait_for_signals(&signal_notify).await;
+    });
+    notify
+}
+
+#[cfg(unix)]
+async fn wait_for_signals(notify: &Notify) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut sighup = signal(SignalKind::hangup()).expect("failed to install SIGHUP handler");
+    let mut sigusr1 =
+        signal(SignalKind::user_defined1()).expect("failed to install SIGUSR1 handler");
+    loop {
+        tokio::select! {
+            _ = sighup.recv() => {},
+            _ = sigusr1.recv() => {},
+        }
+        notify.notify_one();
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signals(_notify: &Notify) {}
@@ -0,0 +1,857 @@
+use crate::canonical::CanonicalResolver;
+use crate::database::Database;
+use crate::dedup;
+use crate::fetcher::NewsFilter;
+use crate::html;
+use crate::http;
+use crate::jsonfeed;
+use crate::source::{self, BoxFuture, FUTURE_PUBLISH_SKEW, PostSource, SourcePost};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+use feed_rs::model::Entry;
+use reqwest::{
+    StatusCode, Url,
+    header::{CONTENT_TYPE, ETAG, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+};
+use std::collections::HashSet;
+use tracing::{debug, info, warn};
+
+/// The format of a feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum FeedFormat {
+    /// JSON Feed when the feed is served as `application/feed+json`, otherwise RSS or Atom.
+    #[default]
+    Auto,
+    /// RSS or Atom.
+    Rss,
+    /// JSON Feed.
+    Json,
+}
+
+/// An entry of a feed in any format.
+pub struct FeedEntry {
+    pub id: String,
+    pub title: String,
+    pub link: Option<Url>,
+    pub published: Option<DateTime<Utc>>,
+    pub summary: String,
+    pub thumbnail: Option<Url>,
+}
+
+impl FeedEntry {
+    /// Use the id of an entry as its link when it is a web URL, as some feeds put the permalink there.
+    pub fn link_from_id(id: &str) -> Option<Url> {
+        Url::parse(id)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+    }
+}
+
+/// The `ETag` and `Last-Modified` headers of a response, sent with the next request for the same feed.
+pub type Validators = (Option<HeaderValue>, Option<HeaderValue>);
+
+/// Download a feed and parse its entries in whichever format it is in.
+pub async fn fetch_entries(
+    client: &reqwest::Client,
+    feed_url: &Url,
+    format: FeedFormat,
+) -> Result<Vec<FeedEntry>> {
+    Ok(fetch_entries_if_modified(client, feed_url, format, None)
+        .await?
+        .map(|(entries, _)| entries)
+        .unwrap_or_default())
+}
+
+/// Download a feed and parse its entries unless it hasn't changed since the response the given validators are
+/// from, returning the validators of the new response alongside its entries.
+pub async fn fetch_entries_if_modified(
+    client: &reqwest::Client,
+    feed_url: &Url,
+    format: FeedFormat,
+    validators: Option<&Validators>,
+) -> Result<Option<(Vec<FeedEntry>, Validators)>> {
+    let mut request = client.get(feed_url.clone());
+    if let Some((etag, last_modified)) = validators {
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let response = request.send().await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    let response = response.error_for_status()?;
+    let validators = (
+        response.headers().get(ETAG).cloned(),
+        response.headers().get(LAST_MODIFIED).cloned(),
+    );
+    let is_json_feed = match format {
+        FeedFormat::Auto => response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(|content_type| content_type.starts_with("application/feed+json")),
+        FeedFormat::Rss => false,
+        FeedFormat::Json => true,
+    };
+    let body = response.bytes().await?;
+    let entries = match is_json_feed {
+        true => jsonfeed::parse(&body, feed_url)?,
+        false => parse_rss(&body, feed_url)?,
+    };
+    Ok(Some((entries, validators)))
+}
+
+/// Parse the entries of an RSS or Atom feed.
+fn parse_rss(body: &[u8], feed_url: &Url) -> Result<Vec<FeedEntry>> {
+    Ok(feed_rs::parser::parse(body)
+        .with_context(|| format!("failed to parse feed from {feed_url}"))?
+        .entries
+        .into_iter()
+        .map(|entry| rss_entry(feed_url, entry))
+        .collect())
+}
+
+/// Convert an RSS or Atom entry, falling back to the date it was updated when it has no published date.
+fn rss_entry(feed_url: &Url, entry: Entry) -> FeedEntry {
+    FeedEntry {
+        title: entry
+            .title
+            .as_ref()
+            .map(|title| html::to_plain_text(&title.content))
+            .unwrap_or_default(),
+        link: match entry.links.first() {
+            Some(link) => feed_url.join(&link.href).ok(),
+            None => FeedEntry::link_from_id(&entry.id),
+        },
+        // Plenty of Atom feeds only set when an entry was last updated.
+        published: entry.published.or(entry.updated),
+        summary: entry
+            .summary
+            .as_ref()
+            .map(|summary| summary.content.as_str())
+            .or_else(|| {
+                entry
+                    .content
+                    .as_ref()
+                    .and_then(|content| content.body.as_deref())
+            })
+            .map(html::to_plain_text)
+            .unwrap_or_default(),
+        thumbnail: entry_thumbnail(feed_url, &entry),
+        id: entry.id,
+    }
+}
+
+/// Get the URL of the first image attached to an entry with `media:thumbnail` or `media:content` tags.
+fn entry_thumbnail(feed_url: &Url, entry: &Entry) -> Option<Url> {
+    entry.media.iter().find_map(|media| {
+        media
+            .thumbnails
+            .first()
+            .and_then(|thumbnail| feed_url.join(&thumbnail.image.uri).ok())
+            .or_else(|| {
+                media
+                    .content
+                    .iter()
+                    .filter(|content| {
+                        content
+                            .content_type
+                            .as_ref()
+                            .is_none_or(|ty| ty.to_string().starts_with("image/"))
+                    })
+                    .find_map(|content| content.url.clone())
+            })
+    })
+}
+
+/// An RSS, Atom or JSON Feed to post entries from and how its entries are posted.
+#[derive(Debug, Clone)]
+pub struct RssFeed {
+    pub url: Url,
+    pub format: FeedFormat,
+    /// The languages that entries are posted under, overriding --post-languages when not empty.
+    pub languages: Vec<String>,
+    /// How far back the feed is checked for entries that haven't been posted, overriding --news-backdate-hours.
+    pub backdate: Option<Duration>,
+}
+
+/// Fetches posts from an RSS, Atom or JSON Feed.
+pub struct RssHandler<'a> {
+    client: reqwest::Client,
+    feed_url: Url,
+    format: FeedFormat,
+    languages: Vec<String>,
+    database: &'a Database,
+    backdate_duration: Duration,
+    filter_date: DateTime<Utc>,
+    processed_until: DateTime<Utc>,
+    /// Whether the Open Graph metadata of articles is read when an entry has no media to use as a thumbnail.
+    fetch_og_image: bool,
+    /// Resolves the canonical URL of entries to store them under, when they should be.
+    canonical: Option<CanonicalResolver>,
+    /// Which entries are posted by their title and summary. Feeds have no sections, so those filters are ignored.
+    filter: NewsFilter,
+    /// The validators of the previous response, sent so that an unchanged feed isn't downloaded again.
+    validators: Option<Validators>,
+}
+
+impl<'a> RssHandler<'a> {
+    pub fn new(
+        client: reqwest::Client,
+        feed: RssFeed,
+        database: &'a Database,
+        default_backdate: Duration,
+    ) -> Self {
+        let feed_backdate = feed.backdate.unwrap_or(default_backdate);
+        let filter_date = Utc::now() - feed_backdate;
+        info!(
+            "Initializing RSS handler for {} with starting filter date of {filter_date}",
+            feed.url
+        );
+        Self {
+            client,
+            feed_url: feed.url,
+            format: feed.format,
+            languages: feed.languages,
+            database,
+            backdate_duration: feed_backdate,
+            filter_date,
+            processed_until: Utc::now(),
+            fetch_og_image: false,
+            canonical: None,
+            filter: NewsFilter::default(),
+            validators: None,
+        }
+    }
+
+    /// Move the filter date back to the given time of the last successful fetch so that entries published while
+    /// the bot wasn't running are not missed, going back no further than the maximum catchup duration.
+    pub fn catch_up_from(&mut self, last_successful_fetch: DateTime<Utc>, max_catchup: Duration) {
+        let filter_date = self
+            .filter_date
+            .min(last_successful_fetch)
+            .max(Utc::now() - max_catchup);
+        if filter_date < self.filter_date {
+            info!(
+                "Catching up on entries from {} published since {filter_date}",
+                self.feed_url
+            );
+            self.filter_date = filter_date;
+        }
+    }
+
+    /// Read the thumbnail and description of articles from their Open Graph metadata when an entry has no media.
+    pub fn fetch_og_image(&mut self) {
+        self.fetch_og_image = true;
+    }
+
+    /// Store entries under their canonical URL so that they are only posted once when linked from other sources.
+    pub fn resolve_canonical(&mut self, resolver: CanonicalResolver) {
+        self.canonical = Some(resolver);
+    }
+
+    /// Only post entries whose title and summary pass the include and exclude filters.
+    pub fn filter(&mut self, filter: NewsFilter) {
+        self.filter = filter;
+    }
+
+    /// Read the `og:image` and `og:description` of an article page.
+    async fn fetch_open_graph(&self, url: &Url) -> Result<(Option<Url>, Option<String>)> {
+        let response = self
+            .client
+            .get(url.clone())
+            .timeout(http::PAGE_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?;
+        // Metadata is in the head of the page, so there's no need to download all of a large one.
+        let body = http::read_limited(response, http::MAX_PAGE_BYTES).await?;
+        let document = String::from_utf8_lossy(&body);
+        Ok((
+            html::meta_content(&document, "og:image").and_then(|image| url.join(&image).ok()),
+            html::meta_content(&document, "og:description"),
+        ))
+    }
+
+    /// Fetch feed entries that haven't been posted yet, newest first.
+    ///
+    /// When a limit is given the newest posts past it are deferred, and the filter date will not advance
+    /// past them so that they are returned again by the next fetch.
+    async fn fetch(&mut self, limit: Option<usize>) -> Result<Vec<SourcePost>> {
+        let Some((entries, validators)) = fetch_entries_if_modified(
+            &self.client,
+            &self.feed_url,
+            self.format,
+            self.validators.as_ref(),
+        )
+        .await?
+        else {
+            debug!(
+                "Feed at {} has not changed since the last fetch",
+                self.feed_url
+            );
+            let now = Utc::now();
+            self.processed_until = now;
+            self.filter_date = now - self.backdate_duration;
+            return Ok(vec![]);
+        };
+
+        let mut posts = vec![];
+        let mut has_future_entries = false;
+        let mut links = HashSet::new();
+        let publish_limit = Utc::now() + FUTURE_PUBLISH_SKEW;
+        for entry in entries {
+            let title = entry.title;
+            let Some(published) = entry.published else {
+                debug!(
+                    "Skipping entry '{title}' from {} as it has no published or updated date",
+                    self.feed_url
+                );
+                continue;
+            };
+            let Some(link) = entry.link else {
+                debug!(
+                    "Skipping entry '{title}' from {} as it has no link and its id '{}' isn't a URL",
+                    self.feed_url, entry.id
+                );
+                continue;
+            };
+            if published <= self.filter_date {
+                continue;
+            }
+            if published > publish_limit {
+                debug!("Skipping '{link}' until it's published at {published}");
+                has_future_entries = true;
+                continue;
+            }
+            // Filtered entries aren't recorded so that they're picked up if the filters are loosened later.
+            if let Some(reason) = self.filter.content_filter_reason(&title, &entry.summary) {
+                info!(
+                    "Skipping entry '{title}' from {} as {reason}",
+                    self.feed_url
+                );
+                continue;
+            }
+            let key = match &self.canonical {
+                Some(canonical) => canonical.resolve(self.database, &link).await?,
+                None => link.clone(),
+            };
+            if !links.insert(key.clone())
+                || self.database.has_posted_url(key.as_str()).await?
+                || self.database.has_failed_post(key.as_str()).await?
+            {
+                continue;
+            }
+
+            let mut summary = entry.summary;
+            let mut thumbnail = entry.thumbnail;
+            if thumbnail.is_none() && self.fetch_og_image {
+                // The metadata only makes the post look nicer, so failing to get it never stops the post.
+                match self.fetch_open_graph(&link).await {
+                    Ok((image, description)) => {
+                        thumbnail = image;
+                        if summary.is_empty() {
+                            summary = description.unwrap_or_default();
+                        }
+                    }
+                    Err(err) => warn!("Failed to read Open Graph metadata of '{link}': {err:#}"),
+                }
+            }
+            posts.push(SourcePost {
+                url: link,
+                key,
+                source_id: None,
+                section: None,
+                fingerprint: dedup::fingerprint(&title, published),
+                title,
+                summary,
+                published,
+                thumbnail,
+                is_update: false,
+            });
+        }
+        posts.sort_by_key(|post| std::cmp::Reverse(post.published));
+
+        let now = Utc::now();
+        match limit {
+            Some(limit) if posts.len() > limit => {
+                // Defer the newest posts so that a backlog is drained in the order it was published.
+                let deferred: Vec<_> = posts.drain(..posts.len() - limit).collect();
+                debug!("Deferring {} posts until the next fetch", deferred.len());
+                let oldest_deferred = deferred
+                    .iter()
+                    .map(|post| post.published)
+                    .min()
+                    .expect("deferred posts should never be empty");
+                self.processed_until = now.min(oldest_deferred - Duration::nanoseconds(1));
+            }
+            _ => self.processed_until = now,
+        }
+        // An unchanged feed can only be skipped when nothing from it was deferred to the next fetch or is
+        // waiting to be published.
+        self.validators = match self.processed_until == now
+            && !has_future_entries
+            && (validators.0.is_some() || validators.1.is_some())
+        {
+            true => Some(validators),
+            false => None,
+        };
+        self.filter_date = (now - self.backdate_duration).min(self.processed_until);
+        Ok(posts)
+    }
+}
+
+impl PostSource for RssHandler<'_> {
+    fn describe(&self) -> String {
+        self.feed_url.to_string()
+    }
+
+    fn name(&self) -> String {
+        format!("rss:{}", self.feed_url)
+    }
+
+    fn locale(&self) -> Option<&str> {
+        None
+    }
+
+    fn languages(&self) -> Option<&[String]> {
+        match self.languages.is_empty() {
+            true => None,
+            false => Some(&self.languages),
+        }
+    }
+
+    fn processed_until(&self) -> DateTime<Utc> {
+        self.processed_until
+    }
+
+    fn fetch_unposted(&mut self, limit: Option<usize>) -> BoxFuture<'_, Result<Vec<SourcePost>>> {
+        Box::pin(self.fetch(limit))
+    }
+
+    fn error_class(&self, err: &anyhow::Error) -> &'static str {
+        match err
+            .downcast_ref::<feed_rs::parser::ParseFeedError>()
+            .is_some()
+        {
+            true => "decode",
+            false => source::error_class(err),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::database::PostStatus;
+    use crate::fetcher::SectionFilter;
+    use axum::{
+        Router,
+        http::{HeaderMap, Uri},
+        response::IntoResponse,
+        routing::get,
+    };
+    use regex::Regex;
+    use std::sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    };
+    use tokio::net::TcpListener;
+
+    const ATOM: &str = include_str!("../tests/fixtures/atom.xml");
+    const ETAG_VALUE: &str = "\"v1\"";
+
+    fn feed_url() -> Url {
+        Url::parse("https://example.com/feed.xml").unwrap()
+    }
+
+    /// Serve the Atom fixture with an `ETag` on a random local port, answering requests that send it back with
+    /// 304 Not Modified and counting how many requests were answered that way.
+    pub(crate) async fn spawn_feed() -> (Url, Arc<AtomicUsize>) {
+        let not_modified = Arc::new(AtomicUsize::new(0));
+        let counter = not_modified.clone();
+        let router = Router::new().route(
+            "/feed.xml",
+            get(move |headers: HeaderMap| async move {
+                if headers
+                    .get(IF_NONE_MATCH)
+                    .is_some_and(|etag| etag == ETAG_VALUE)
+                {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    return StatusCode::NOT_MODIFIED.into_response();
+                }
+                (
+                    [(CONTENT_TYPE, "application/atom+xml"), (ETAG, ETAG_VALUE)],
+                    ATOM,
+                )
+                    .into_response()
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/feed.xml",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        (url, not_modified)
+    }
+
+    fn handler<'a>(url: Url, database: &'a Database) -> RssHandler<'a> {
+        RssHandler::new(
+            reqwest::Client::new(),
+            RssFeed {
+                url,
+                format: FeedFormat::Auto,
+                languages: vec![],
+                backdate: None,
+            },
+            database,
+            // The fixture's entries are from a fixed date, so look back far enough to always include them.
+            Duration::days(365 * 100),
+        )
+    }
+
+    #[test]
+    fn falls_back_to_updated_dates_and_ids() {
+        let entries = parse_rss(ATOM.as_bytes(), &feed_url()).unwrap();
+        let entries = entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.title.as_str(),
+                    entry.link.as_ref().map(Url::as_str),
+                    entry.published.map(|published| published.to_rfc3339()),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                (
+                    "Published with a link",
+                    Some("https://example.com/news/1"),
+                    Some("2025-04-01T12:00:00+00:00".to_string())
+                ),
+                (
+                    "Only updated",
+                    Some("https://example.com/news/2"),
+                    Some("2025-04-02T12:00:00+00:00".to_string())
+                ),
+                (
+                    "Permalink in the id",
+                    Some("https://example.com/news/3"),
+                    Some("2025-04-03T12:00:00+00:00".to_string())
+                ),
+                (
+                    "No link",
+                    None,
+                    Some("2025-04-03T12:00:00+00:00".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn strips_html_from_summaries() {
+        let entries = parse_rss(ATOM.as_bytes(), &feed_url()).unwrap();
+        assert_eq!(entries[0].summary, "The first entry.");
+    }
+
+    #[test]
+    fn only_uses_web_urls_as_links() {
+        assert!(FeedEntry::link_from_id("https://example.com/news/1").is_some());
+        assert!(FeedEntry::link_from_id("urn:uuid:1").is_none());
+        assert!(FeedEntry::link_from_id("tag:example.com,2025:1").is_none());
+    }
+
+    #[tokio::test]
+    async fn skips_entries_without_a_link() {
+        let (url, _) = spawn_feed().await;
+        let database = Database::new_in_memory().await.unwrap();
+        let posts = handler(url, &database).fetch(None).await.unwrap();
+        // The first entry's relative link is resolved against the mock server rather than example.com.
+        let paths = posts.iter().map(|post| post.url.path()).collect::<Vec<_>>();
+        assert_eq!(paths, ["/news/3", "/news/2", "/news/1"]);
+    }
+
+    #[tokio::test]
+    async fn feeds_are_fetched_without_a_database() {
+        let (url, _) = spawn_feed().await;
+        let client = reqwest::Client::new();
+        let entries = fetch_entries(&client, &url, FeedFormat::Auto)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 4);
+        assert!(
+            fetch_entries(&client, &url.join("missing.xml").unwrap(), FeedFormat::Auto)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_validators_from_the_previous_response() {
+        let (url, not_modified) = spawn_feed().await;
+        let database = Database::new_in_memory().await.unwrap();
+        let mut handler = handler(url, &database);
+        assert_eq!(handler.fetch(None).await.unwrap().len(), 3);
+        assert_eq!(not_modified.load(Ordering::SeqCst), 0);
+        assert!(handler.fetch(None).await.unwrap().is_empty());
+        assert_eq!(not_modified.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn does_not_send_validators_after_deferring_entries() {
+        let (url, not_modified) = spawn_feed().await;
+        let database = Database::new_in_memory().await.unwrap();
+        let mut handler = handler(url, &database);
+        assert_eq!(handler.fetch(Some(1)).await.unwrap().len(), 1);
+        assert_eq!(handler.fetch(Some(1)).await.unwrap().len(), 1);
+        assert_eq!(not_modified.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn entries_published_in_the_future_are_picked_up_once_current() {
+        let entry = |id: usize, published: DateTime<Utc>| {
+            format!(
+                "<entry><title>Entry {id}</title><id>https://example.com/news/{id}</id><published>{}</published></entry>",
+                published.to_rfc3339()
+            )
+        };
+        let now = Utc::now();
+        let feed = format!(
+            r#"<?xml version="1.0" encoding="utf-8"?><feed xmlns="http://www.w3.org/2005/Atom"><title>Example News</title><id>https://example.com/</id><updated>{}</updated>{}{}{}</feed>"#,
+            now.to_rfc3339(),
+            entry(3, now + Duration::hours(1)),
+            entry(2, now + FUTURE_PUBLISH_SKEW + Duration::seconds(1)),
+            entry(1, now + FUTURE_PUBLISH_SKEW - Duration::seconds(5)),
+        );
+        let router = Router::new().route(
+            "/feed.xml",
+            get(move || async move { ([(CONTENT_TYPE, "application/atom+xml")], feed) }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/feed.xml",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let database = Database::new_in_memory().await.unwrap();
+        let mut handler = RssHandler::new(
+            reqwest::Client::new(),
+            RssFeed {
+                url,
+                format: FeedFormat::Auto,
+                languages: vec![],
+                backdate: None,
+            },
+            &database,
+            Duration::zero(),
+        );
+        let titles =
+            |posts: Vec<SourcePost>| posts.into_iter().map(|post| post.title).collect::<Vec<_>>();
+        let posts = handler.fetch(None).await.unwrap();
+        for post in &posts {
+            database
+                .record_posted(
+                    post.key.as_str(),
+                    "rss",
+                    None,
+                    Some(&post.fingerprint),
+                    PostStatus::Posted,
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(titles(posts), ["Entry 1"]);
+
+        tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+        assert_eq!(titles(handler.fetch(None).await.unwrap()), ["Entry 2"]);
+    }
+
+    const MEDIA: &str = include_str!("../tests/fixtures/media.xml");
+
+    /// Serve the media fixture alongside an article page with Open Graph metadata on a random local port,
+    /// recording the paths of every article page requested.
+    async fn spawn_media_feed() -> (Url, Arc<Mutex<Vec<String>>>) {
+        let requested = Arc::new(Mutex::new(vec![]));
+        let recorder = requested.clone();
+        let router = Router::new()
+            .route("/feed.xml", get(|| async { MEDIA }))
+            .route(
+                "/articles/{article}",
+                get(move |uri: Uri| async move {
+                    recorder.lock().unwrap().push(uri.path().to_string());
+                    match uri.path() {
+                        "/articles/open-graph" => (
+                            StatusCode::OK,
+                            r#"<html><head>
+                                <meta property="og:image" content="/images/cover.png">
+                                <meta property="og:description" content="From the article page.">
+                            </head></html>"#,
+                        ),
+                        _ => (StatusCode::NOT_FOUND, ""),
+                    }
+                }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/feed.xml",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        (url, requested)
+    }
+
+    /// The title, thumbnail and summary of each post, oldest first.
+    fn embeds(posts: &[SourcePost]) -> Vec<(&str, Option<String>, &str)> {
+        posts
+            .iter()
+            .rev()
+            .map(|post| {
+                (
+                    post.title.as_str(),
+                    post.thumbnail
+                        .as_ref()
+                        .map(|thumbnail| thumbnail.path().to_string()),
+                    post.summary.as_str(),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn thumbnails_fall_back_from_media_to_open_graph() {
+        let (url, requested) = spawn_media_feed().await;
+        let database = Database::new_in_memory().await.unwrap();
+        let mut handler = handler(url, &database);
+        handler.fetch_og_image();
+        let posts = handler.fetch(None).await.unwrap();
+        assert_eq!(
+            embeds(&posts),
+            [
+                (
+                    "Media thumbnail",
+                    Some("/thumbnail.png".to_string()),
+                    "Has a media thumbnail."
+                ),
+                (
+                    "Media content",
+                    Some("/content.jpg".to_string()),
+                    "Has an image as media content."
+                ),
+                (
+                    "Open Graph",
+                    Some("/images/cover.png".to_string()),
+                    "From the article page."
+                ),
+                // A page that can't be read doesn't stop the entry being posted.
+                ("Missing page", None, "The article page can't be found."),
+            ]
+        );
+        // Pages are only read for entries without media.
+        let mut requested = requested.lock().unwrap().clone();
+        requested.sort();
+        assert_eq!(requested, ["/articles/missing", "/articles/open-graph"]);
+    }
+
+    #[tokio::test]
+    async fn article_pages_are_only_read_when_asked_to() {
+        let (url, requested) = spawn_media_feed().await;
+        let database = Database::new_in_memory().await.unwrap();
+        let posts = handler(url, &database).fetch(None).await.unwrap();
+        assert_eq!(embeds(&posts)[2], ("Open Graph", None, ""));
+        assert!(requested.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn json_feeds_are_detected_by_content_type() {
+        const JSON_FEED: &str = include_str!("../tests/fixtures/feed.json");
+        let router = Router::new()
+            .route(
+                "/feed.json",
+                get(|| async { ([(CONTENT_TYPE, "application/feed+json")], JSON_FEED) }),
+            )
+            .route(
+                "/untyped.json",
+                get(|| async { ([(CONTENT_TYPE, "application/json")], JSON_FEED) }),
+            )
+            .route("/feed.xml", get(|| async { ATOM }));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        let client = reqwest::Client::new();
+        let titles = |entries: Vec<FeedEntry>| {
+            entries
+                .into_iter()
+                .map(|entry| entry.title)
+                .take(1)
+                .collect::<Vec<_>>()
+        };
+
+        let feed = base.join("feed.json").unwrap();
+        let entries = fetch_entries(&client, &feed, FeedFormat::Auto)
+            .await
+            .unwrap();
+        assert_eq!(titles(entries), ["Published with an image"]);
+        // Feeds served without the JSON Feed content type need the format to be given.
+        let untyped = base.join("untyped.json").unwrap();
+        assert!(
+            fetch_entries(&client, &untyped, FeedFormat::Auto)
+                .await
+                .is_err()
+        );
+        let entries = fetch_entries(&client, &untyped, FeedFormat::Json)
+            .await
+            .unwrap();
+        assert_eq!(titles(entries), ["Published with an image"]);
+        assert!(
+            fetch_entries(&client, &feed, FeedFormat::Rss)
+                .await
+                .is_err()
+        );
+
+        let atom = base.join("feed.xml").unwrap();
+        let entries = fetch_entries(&client, &atom, FeedFormat::Auto)
+            .await
+            .unwrap();
+        assert_eq!(titles(entries), ["Published with a link"]);
+    }
+
+    #[tokio::test]
+    async fn filters_entries_by_title_and_summary() {
+        let (url, _) = spawn_feed().await;
+        let database = Database::new_in_memory().await.unwrap();
+        let mut handler = handler(url, &database);
+        handler.filter(NewsFilter {
+            sections: SectionFilter::All,
+            include: vec![],
+            exclude: vec![Regex::new("(?i)maintenance").unwrap()],
+        });
+        let posts = handler.fetch(None).await.unwrap();
+        let titles = posts
+            .iter()
+            .map(|post| post.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Permalink in the id", "Published with a link"]);
+
+        handler.filter(NewsFilter {
+            sections: SectionFilter::All,
+            include: vec![Regex::new("(?i)third").unwrap()],
+            exclude: vec![],
+        });
+        handler.validators = None;
+        let posts = handler.fetch(None).await.unwrap();
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].title, "Permalink in the id");
+    }
+}
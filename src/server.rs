@@ -0,0 +1,364 @@
+use anyhow::Result;
+use axum::{
+    Json, Router,
+    extract::State,
+    http::{StatusCode, header},
+    response::IntoResponse,
+    routing::get,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, RwLock},
+};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+/// The number of cycles in a row that can fail to fetch anything before `/healthz` reports the bot as unhealthy.
+pub const DEFAULT_UNHEALTHY_AFTER_FAILED_CYCLES: u64 = 3;
+
+/// A summary of the bot's activity, updated by the start loop and served over HTTP.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunStatus {
+    pub started_at: DateTime<Utc>,
+    pub ready: bool,
+    pub cycles: u64,
+    pub posts: u64,
+    pub fetch_failures: u64,
+    /// The number of times fetching failed, by the name of the source.
+    pub source_fetch_failures: BTreeMap<String, u64>,
+    pub post_failures: u64,
+    /// The number of posts in a row that failed to be published.
+    pub consecutive_post_failures: u64,
+    /// The number of posts refused as their article had already been posted too many times.
+    pub capped_posts: u64,
+    pub consecutive_failures: u64,
+    /// The number of cycles in a row where no source could be fetched.
+    pub consecutive_failed_cycles: u64,
+    #[serde(skip)]
+    pub unhealthy_after_failed_cycles: u64,
+    pub uploaded_blob_bytes: u64,
+    /// The number of URLs recorded in the database, as of the end of the last cycle.
+    pub posted_urls: Option<i64>,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_post: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+impl Default for RunStatus {
+    fn default() -> Self {
+        Self {
+            started_at: Utc::now(),
+            ready: false,
+            cycles: 0,
+            posts: 0,
+            fetch_failures: 0,
+            source_fetch_failures: BTreeMap::new(),
+            post_failures: 0,
+            consecutive_post_failures: 0,
+            capped_posts: 0,
+            consecutive_failures: 0,
+            consecutive_failed_cycles: 0,
+            unhealthy_after_failed_cycles: DEFAULT_UNHEALTHY_AFTER_FAILED_CYCLES,
+            uploaded_blob_bytes: 0,
+            posted_urls: None,
+            last_success: None,
+            last_post: None,
+            last_error: None,
+        }
+    }
+}
+
+impl RunStatus {
+    /// Whether the bot has fetched something recently enough to be considered working.
+    pub fn is_healthy(&self) -> bool {
+        self.consecutive_failed_cycles < self.unhealthy_after_failed_cycles
+    }
+
+    /// Count a post as published.
+    pub fn record_post(&mut self) {
+        self.posts += 1;
+        self.consecutive_post_failures = 0;
+        self.last_post = Some(Utc::now());
+    }
+
+    /// Count a failure to publish a post.
+    pub fn record_post_failure(&mut self, error: String) {
+        self.post_failures += 1;
+        self.consecutive_post_failures += 1;
+        self.last_error = Some(error);
+    }
+
+    /// Count a post refused by the limit on how many times an article can be posted.
+    pub fn record_capped_post(&mut self) {
+        self.capped_posts += 1;
+    }
+
+    /// Count a failure to fetch from a source.
+    pub fn record_fetch_failure(&mut self, source: &str, error: String) {
+        self.fetch_failures += 1;
+        *self
+            .source_fetch_failures
+            .entry(source.to_string())
+            .or_default() += 1;
+        self.consecutive_failures += 1;
+        self.last_error = Some(error);
+    }
+}
+
+pub type SharedRunStatus = Arc<RwLock<RunStatus>>;
+
+pub fn router(status: SharedRunStatus) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/status", get(status_json))
+        .route("/metrics", get(metrics))
+        .with_state(status)
+}
+
+/// Serve the HTTP endpoints on the given listener until the shutdown token is cancelled.
+pub async fn serve(
+    listener: TcpListener,
+    status: SharedRunStatus,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    info!("Serving HTTP endpoints on {}", listener.local_addr()?);
+    axum::serve(listener, router(status))
+        .with_graceful_shutdown(async move { shutdown.cancelled().await })
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct Health {
+    healthy: bool,
+    last_success: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    cycles: u64,
+    posts: u64,
+    consecutive_failed_cycles: u64,
+}
+
+async fn healthz(State(status): State<SharedRunStatus>) -> impl IntoResponse {
+    let status = status.read().unwrap();
+    let health = Health {
+        healthy: status.is_healthy(),
+        last_success: status.last_success,
+        last_error: status.last_error.clone(),
+        cycles: status.cycles,
+        posts: status.posts,
+        consecutive_failed_cycles: status.consecutive_failed_cycles,
+    };
+    match health.healthy {
+        true => (StatusCode::OK, Json(health)),
+        false => (StatusCode::SERVICE_UNAVAILABLE, Json(health)),
+    }
+}
+
+async fn readyz(State(status): State<SharedRunStatus>) -> impl IntoResponse {
+    match status.read().unwrap().ready {
+        true => (StatusCode::OK, "ready"),
+        false => (StatusCode::SERVICE_UNAVAILABLE, "not ready"),
+    }
+}
+
+async fn status_json(State(status): State<SharedRunStatus>) -> Json<RunStatus> {
+    Json(status.read().unwrap().clone())
+}
+
+async fn metrics(State(status): State<SharedRunStatus>) -> impl IntoResponse {
+    let status = status.read().unwrap().clone();
+    let now = Utc::now();
+    // Before anything has happened, time is counted from when the bot started.
+    let seconds_since = |time: Option<DateTime<Utc>>| {
+        (now - time.unwrap_or(status.started_at)).num_milliseconds() as f64 / 1000.0
+    };
+    let unlabelled = |value: f64| vec![(String::new(), value)];
+    let mut metrics = vec![
+        (
+            "whimsky_cycles_total",
+            "counter",
+            "Number of times news has been checked.",
+            unlabelled(status.cycles as f64),
+        ),
+        (
+            "whimsky_posts_total",
+            "counter",
+            "Number of posts published.",
+            unlabelled(status.posts as f64),
+        ),
+        (
+            "whimsky_post_failures_total",
+            "counter",
+            "Number of times publishing a post failed.",
+            unlabelled(status.post_failures as f64),
+        ),
+        (
+            "whimsky_capped_posts_total",
+            "counter",
+            "Number of posts refused as their article had already been posted the maximum number of times.",
+            unlabelled(status.capped_posts as f64),
+        ),
+        (
+            "whimsky_fetch_failures_total",
+            "counter",
+            "Number of times fetching news failed.",
+            unlabelled(status.fetch_failures as f64),
+        ),
+        (
+            "whimsky_source_fetch_failures_total",
+            "counter",
+            "Number of times fetching news failed, by source.",
+            status
+                .source_fetch_failures
+                .iter()
+                .map(|(source, count)| {
+                    (
+                        format!("{{source=\"{}\"}}", escape_label(source)),
+                        *count as f64,
+                    )
+                })
+                .collect(),
+        ),
+        (
+            "whimsky_uploaded_blob_bytes_total",
+            "counter",
+            "Number of bytes uploaded to Bluesky as images.",
+            unlabelled(status.uploaded_blob_bytes as f64),
+        ),
+        (
+            "whimsky_last_success_timestamp_seconds",
+            "gauge",
+            "Unix timestamp of the last time news was fetched successfully.",
+            unlabelled(status.last_success.map_or(0.0, |t| t.timestamp() as f64)),
+        ),
+        (
+            "whimsky_seconds_since_last_success",
+            "gauge",
+            "Seconds since news was last fetched successfully, or since starting if it hasn't been.",
+            unlabelled(seconds_since(status.last_success)),
+        ),
+        (
+            "whimsky_seconds_since_last_post",
+            "gauge",
+            "Seconds since a post was last published, or since starting if one hasn't been.",
+            unlabelled(seconds_since(status.last_post)),
+        ),
+    ];
+    if let Some(posted_urls) = status.posted_urls {
+        metrics.push((
+            "whimsky_posted_urls",
+            "gauge",
+            "Number of URLs recorded in the database.",
+            unlabelled(posted_urls as f64),
+        ));
+    }
+
+    let mut body = String::new();
+    for (name, kind, help, samples) in metrics {
+        let _ = writeln!(body, "# HELP {name} {help}\n# TYPE {name} {kind}");
+        for (labels, value) in samples {
+            let _ = writeln!(body, "{name}{labels} {value}");
+        }
+    }
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+/// Escape a label value for the Prometheus text format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Serve the endpoints on a random local port, returning the base URL to request them from.
+    pub(crate) async fn spawn(status: SharedRunStatus) -> (String, CancellationToken) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let shutdown = CancellationToken::new();
+        tokio::spawn(serve(listener, status, shutdown.clone()));
+        (url, shutdown)
+    }
+
+    #[test]
+    fn posts_reset_consecutive_post_failures() {
+        let mut status = RunStatus::default();
+        status.record_post_failure("first".to_string());
+        status.record_post_failure("second".to_string());
+        assert_eq!(status.consecutive_post_failures, 2);
+        assert_eq!(status.last_error.as_deref(), Some("second"));
+        status.record_post();
+        assert_eq!(
+            (
+                status.posts,
+                status.post_failures,
+                status.consecutive_post_failures
+            ),
+            (1, 2, 0)
+        );
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(
+            escape_label("rss:https://a/\"feed\"\\\n"),
+            "rss:https://a/\\\"feed\\\"\\\\\\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn metrics_include_counters_by_source() {
+        let status = SharedRunStatus::default();
+        {
+            let mut status = status.write().unwrap();
+            status.record_fetch_failure("nikki:en", "timed out".to_string());
+            status.record_fetch_failure("nikki:en", "timed out".to_string());
+            status.record_fetch_failure("rss:https://example.com/feed", "404".to_string());
+            status.record_capped_post();
+            status.posted_urls = Some(12);
+        }
+        let (url, shutdown) = spawn(status).await;
+        let body = reqwest::get(format!("{url}/metrics"))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        shutdown.cancel();
+
+        let lines: Vec<&str> = body.lines().collect();
+        assert!(lines.contains(&"whimsky_fetch_failures_total 3"));
+        assert!(lines.contains(&"whimsky_source_fetch_failures_total{source=\"nikki:en\"} 2"));
+        assert!(lines.contains(
+            &"whimsky_source_fetch_failures_total{source=\"rss:https://example.com/feed\"} 1"
+        ));
+        assert!(lines.contains(&"whimsky_capped_posts_total 1"));
+        assert!(lines.contains(&"whimsky_posted_urls 12"));
+        assert!(lines.contains(&"# TYPE whimsky_posts_total counter"));
+    }
+
+    #[tokio::test]
+    async fn healthz_fails_after_too_many_failed_cycles() {
+        let status = SharedRunStatus::default();
+        let (url, shutdown) = spawn(status.clone()).await;
+        let healthz = || async {
+            reqwest::get(format!("{url}/healthz"))
+                .await
+                .unwrap()
+                .status()
+        };
+        assert_eq!(healthz().await, StatusCode::OK);
+        status.write().unwrap().consecutive_failed_cycles = DEFAULT_UNHEALTHY_AFTER_FAILED_CYCLES;
+        assert_eq!(healthz().await, StatusCode::SERVICE_UNAVAILABLE);
+        shutdown.cancel();
+    }
+}
@@ -0,0 +1,410 @@
+use crate::canonical::CanonicalResolver;
+use crate::database::{Database, PostStatus, PostedEntry};
+use crate::fetcher::{NewsApi, NewsFilter, NikkiNewsFetcher, SectionFilter};
+use crate::rss::{RssFeed, RssHandler};
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use reqwest::Url;
+use std::{future::Future, num::NonZeroUsize, pin::Pin};
+use tracing::info;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// How far into the future posts can be published and still be posted, allowing for clock differences.
+pub const FUTURE_PUBLISH_SKEW: Duration = Duration::minutes(5);
+
+/// A post fetched from a source of news, ready to be published.
+pub struct SourcePost {
+    /// The URL that the post links to.
+    pub url: Url,
+    /// The URL that the post is stored under to tell whether it has been posted, which is its canonical URL when
+    /// it is resolved and otherwise the same as `url`.
+    pub key: Url,
+    /// The id of the item that the source gave it, such as `nikki:123`, which stays the same if its URL changes.
+    pub source_id: Option<String>,
+    /// The numeric id of the section of news that the post is from, when the source has sections.
+    pub section: Option<usize>,
+    pub title: String,
+    pub summary: String,
+    pub published: DateTime<Utc>,
+    pub thumbnail: Option<Url>,
+    /// Identifies the same article when it is republished under a different URL.
+    pub fingerprint: String,
+    /// Whether this is an update to an article that was already posted and has since been edited.
+    pub is_update: bool,
+}
+
+/// A source of news that can be checked for posts that haven't been published yet.
+pub trait PostSource {
+    /// Describe where posts come from, such as the URL of a feed. Also used to key the source's fetch history.
+    fn describe(&self) -> String;
+
+    /// Name the source in the `source` column of the URLs it posts, such as `nikki:en` or `rss:<feed url>`.
+    fn name(&self) -> String;
+
+    /// The locale of the source's posts, used to pick the languages they are posted under.
+    fn locale(&self) -> Option<&str>;
+
+    /// The languages the source's posts are written in when they are known, taking priority over its locale.
+    fn languages(&self) -> Option<&[String]>;
+
+    /// Get the time before which every post has been returned by a fetch, accounting for deferred posts.
+    fn processed_until(&self) -> DateTime<Utc>;
+
+    /// Fetch posts that haven't been published yet, newest first.
+    ///
+    /// When a limit is given the newest posts past it are deferred so that they are returned again by the next fetch.
+    fn fetch_unposted(&mut self, limit: Option<usize>) -> BoxFuture<'_, Result<Vec<SourcePost>>>;
+
+    /// Classify an error returned by a fetch into a short, stable name suitable for grouping failures.
+    fn error_class(&self, err: &anyhow::Error) -> &'static str {
+        error_class(err)
+    }
+}
+
+/// Classify an error that any source could return, such as a failed request, into a short, stable name.
+pub fn error_class(err: &anyhow::Error) -> &'static str {
+    match err.downcast_ref::<reqwest::Error>() {
+        Some(err) if err.is_timeout() => "timeout",
+        Some(err) if err.is_connect() => "connect",
+        Some(err) if err.is_status() => "status",
+        Some(err) if err.is_decode() => "decode",
+        Some(_) => "request",
+        None if err.downcast_ref::<sqlx::Error>().is_some() => "database",
+        None => "other",
+    }
+}
+
+/// How the sources that news is posted from are built.
+#[derive(Debug, Clone)]
+pub struct SourceConfig {
+    /// The locales that news is fetched in, each as its own source.
+    pub news_locales: Vec<String>,
+    pub news_api_base_url: Url,
+    /// The base URL that links to news articles are built from, which is the API's when not given.
+    pub news_article_base_url: Option<Url>,
+    pub news_fetch_limit: NonZeroUsize,
+    pub news_include_sections: Option<Vec<usize>>,
+    pub news_exclude_sections: Option<Vec<usize>>,
+    pub include_regex: Vec<Regex>,
+    pub exclude_regex: Vec<Regex>,
+    pub rss_feeds: Vec<RssFeed>,
+    /// How far back posts are looked for when a source is first fetched.
+    pub backdate: Duration,
+    /// The time of the last successful fetch and the furthest back to catch up to from it, when catching up.
+    pub catch_up: Option<(DateTime<Utc>, Duration)>,
+    pub fingerprint_dedup: bool,
+    pub resolve_canonical: bool,
+    /// The prefix of the title of updates to edited articles, when they are posted.
+    pub update_title_prefix: Option<String>,
+    pub fetch_og_image: bool,
+    /// Whether every post the sources currently return is recorded as skipped once they are built.
+    pub skip_backfill: bool,
+}
+
+/// Build every source that news is posted from.
+pub async fn build_sources<'a>(
+    client: reqwest::Client,
+    database: &'a Database,
+    config: SourceConfig,
+) -> Result<Vec<Box<dyn PostSource + 'a>>> {
+    let news_filter = NewsFilter {
+        sections: match (config.news_include_sections, config.news_exclude_sections) {
+            (Some(sections), _) => SectionFilter::Include(sections),
+            (_, Some(sections)) => SectionFilter::Exclude(sections),
+            (None, None) => SectionFilter::All,
+        },
+        include: config.include_regex,
+        exclude: config.exclude_regex,
+    };
+    let news_api = NewsApi {
+        article_base_url: config
+            .news_article_base_url
+            .unwrap_or_else(|| config.news_api_base_url.clone()),
+        base_url: config.news_api_base_url,
+        page_size: config.news_fetch_limit,
+    };
+    let canonical = config
+        .resolve_canonical
+        .then(|| CanonicalResolver::new(client.clone()));
+    let mut sources: Vec<Box<dyn PostSource + 'a>> =
+        Vec::with_capacity(config.news_locales.len() + config.rss_feeds.len());
+    for locale in config.news_locales {
+        let mut news_fetcher = NikkiNewsFetcher::new(
+            client.clone(),
+            locale,
+            database,
+            config.backdate,
+            config.fingerprint_dedup,
+            news_api.clone(),
+            news_filter.clone(),
+        );
+        if let Some((last_successful_fetch, max_catchup)) = config.catch_up {
+            news_fetcher.catch_up_from(last_successful_fetch, max_catchup);
+        }
+        if let Some(canonical) = &canonical {
+            news_fetcher.resolve_canonical(canonical.clone());
+        }
+        if config.skip_backfill {
+            skip_backfill(&mut news_fetcher, database).await?;
+        }
+        if let Some(title_prefix) = &config.update_title_prefix {
+            news_fetcher.post_updates(title_prefix.clone());
+        }
+        sources.push(Box::new(news_fetcher));
+    }
+    let mut rss_feed_urls = Vec::new();
+    for rss_feed in config.rss_feeds {
+        if rss_feed_urls.contains(&rss_feed.url) {
+            bail!("the feed '{}' was provided more than once", rss_feed.url);
+        }
+        rss_feed_urls.push(rss_feed.url.clone());
+        let mut rss_handler = RssHandler::new(client.clone(), rss_feed, database, config.backdate);
+        if let Some((last_successful_fetch, max_catchup)) = config.catch_up {
+            rss_handler.catch_up_from(last_successful_fetch, max_catchup);
+        }
+        if let Some(canonical) = &canonical {
+            rss_handler.resolve_canonical(canonical.clone());
+        }
+        if config.skip_backfill {
+            skip_backfill(&mut rss_handler, database).await?;
+        }
+        if config.fetch_og_image {
+            rss_handler.fetch_og_image();
+        }
+        rss_handler.filter(NewsFilter {
+            sections: SectionFilter::All,
+            include: news_filter.include.clone(),
+            exclude: news_filter.exclude.clone(),
+        });
+        sources.push(Box::new(rss_handler));
+    }
+    Ok(sources)
+}
+
+/// Record every post a source would currently return as skipped so that only posts published from now on are posted.
+///
+/// Skipped posts aren't removed by `database promote-collected`, unlike those recorded in collect-only mode.
+async fn skip_backfill(source: &mut dyn PostSource, database: &Database) -> Result<()> {
+    let posts = source
+        .fetch_unposted(None)
+        .await
+        .with_context(|| format!("failed to fetch {} to skip backfill", source.describe()))?;
+    let source_name = source.name();
+    for post in &posts {
+        info!("Skipping backfill of '{}'", post.url);
+    }
+    let entries = posts
+        .iter()
+        .map(|post| PostedEntry {
+            url: post.key.as_str(),
+            source: &source_name,
+            source_id: post.source_id.as_deref(),
+            fingerprint: Some(&post.fingerprint),
+        })
+        .collect::<Vec<_>>();
+    let stored = database.record_batch(&entries, PostStatus::Skipped).await?;
+    info!(
+        "Skipping backfill of {} posts from {}: {stored} newly recorded, {} already recorded",
+        entries.len(),
+        source.describe(),
+        entries.len() as u64 - stored
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::DEFAULT_NEWS_BASE_URL;
+    use crate::rss::FeedFormat;
+
+    fn config() -> SourceConfig {
+        SourceConfig {
+            news_locales: vec!["en".to_string()],
+            news_api_base_url: Url::parse(DEFAULT_NEWS_BASE_URL).unwrap(),
+            news_article_base_url: None,
+            news_fetch_limit: NonZeroUsize::new(10).unwrap(),
+            news_include_sections: None,
+            news_exclude_sections: None,
+            include_regex: vec![],
+            exclude_regex: vec![],
+            rss_feeds: vec![],
+            backdate: Duration::hours(1),
+            catch_up: None,
+            fingerprint_dedup: false,
+            resolve_canonical: false,
+            update_title_prefix: None,
+            fetch_og_image: false,
+            skip_backfill: false,
+        }
+    }
+
+    fn feed(url: &str) -> RssFeed {
+        RssFeed {
+            url: Url::parse(url).unwrap(),
+            format: FeedFormat::Auto,
+            languages: vec![],
+            backdate: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn builds_a_source_per_locale_and_feed() {
+        let database = Database::new_in_memory().await.unwrap();
+        let sources = build_sources(
+            reqwest::Client::new(),
+            &database,
+            SourceConfig {
+                news_locales: vec!["en".to_string(), "ja".to_string()],
+                rss_feeds: vec![feed("https://example.com/feed.xml")],
+                ..config()
+            },
+        )
+        .await
+        .unwrap();
+        let names = sources
+            .iter()
+            .map(|source| source.name())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["nikki:en", "nikki:ja", "rss:https://example.com/feed.xml"]
+        );
+    }
+
+    /// A source that always returns the same posts.
+    struct FixedSource(Vec<&'static str>);
+
+    impl PostSource for FixedSource {
+        fn describe(&self) -> String {
+            "fixed posts".to_string()
+        }
+
+        fn name(&self) -> String {
+            "fixed".to_string()
+        }
+
+        fn locale(&self) -> Option<&str> {
+            None
+        }
+
+        fn languages(&self) -> Option<&[String]> {
+            None
+        }
+
+        fn processed_until(&self) -> DateTime<Utc> {
+            Utc::now()
+        }
+
+        fn fetch_unposted(&mut self, _: Option<usize>) -> BoxFuture<'_, Result<Vec<SourcePost>>> {
+            let posts = self
+                .0
+                .iter()
+                .map(|url| SourcePost {
+                    url: Url::parse(url).unwrap(),
+                    key: Url::parse(url).unwrap(),
+                    source_id: None,
+                    section: None,
+                    title: "Title".to_string(),
+                    summary: String::new(),
+                    published: Utc::now(),
+                    thumbnail: None,
+                    fingerprint: url.to_string(),
+                    is_update: false,
+                })
+                .collect();
+            Box::pin(async move { Ok(posts) })
+        }
+    }
+
+    #[tokio::test]
+    async fn skipped_backfill_is_recorded_under_the_source_name() {
+        let database = Database::new_in_memory().await.unwrap();
+        database
+            .record_posted(
+                "https://example.com/1",
+                "manual",
+                None,
+                None,
+                PostStatus::Posted,
+            )
+            .await
+            .unwrap();
+        let mut source = FixedSource(vec!["https://example.com/1", "https://example.com/2"]);
+        skip_backfill(&mut source, &database).await.unwrap();
+
+        let posts = database.query_posts(&Default::default()).await.unwrap();
+        let posts: Vec<(&str, &str, &str)> = posts
+            .iter()
+            .map(|post| {
+                (
+                    post.url.as_str(),
+                    post.source.as_str(),
+                    post.status.as_str(),
+                )
+            })
+            .collect();
+        // URLs that were already recorded are left as they were.
+        assert!(posts.contains(&("https://example.com/1", "manual", "posted")));
+        assert!(posts.contains(&("https://example.com/2", "fixed", "skipped")));
+    }
+
+    #[tokio::test]
+    async fn rejects_a_feed_provided_more_than_once() {
+        let database = Database::new_in_memory().await.unwrap();
+        let err = build_sources(
+            reqwest::Client::new(),
+            &database,
+            SourceConfig {
+                rss_feeds: vec![
+                    feed("https://example.com/feed.xml"),
+                    feed("https://example.com/feed.xml"),
+                ],
+                ..config()
+            },
+        )
+        .await
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "the feed 'https://example.com/feed.xml' was provided more than once"
+        );
+    }
+
+    #[tokio::test]
+    async fn classifies_errors_by_source() {
+        let database = Database::new_in_memory().await.unwrap();
+        let sources = build_sources(
+            reqwest::Client::new(),
+            &database,
+            SourceConfig {
+                rss_feeds: vec![feed("https://example.com/feed.xml")],
+                ..config()
+            },
+        )
+        .await
+        .unwrap();
+        let (news, rss) = (&sources[0], &sources[1]);
+
+        let json_err = serde_path_to_error::deserialize::<_, usize>(
+            &mut serde_json::Deserializer::from_str("\"text\""),
+        )
+        .unwrap_err();
+        let json_err = anyhow::Error::from(json_err).context("failed to parse news");
+        assert_eq!(news.error_class(&json_err), "decode");
+        assert_eq!(rss.error_class(&json_err), "other");
+
+        let feed_err = feed_rs::parser::parse(&b"not a feed"[..]).unwrap_err();
+        let feed_err = anyhow::Error::from(feed_err).context("failed to parse feed");
+        assert_eq!(rss.error_class(&feed_err), "decode");
+        assert_eq!(news.error_class(&feed_err), "other");
+
+        let database_err = anyhow::Error::from(sqlx::Error::RowNotFound);
+        assert_eq!(news.error_class(&database_err), "database");
+        assert_eq!(rss.error_class(&database_err), "database");
+        assert_eq!(error_class(&anyhow::anyhow!("failed")), "other");
+    }
+}
@@ -0,0 +1,514 @@
+use chrono::{DateTime, Utc};
+use regex::{Regex, RegexBuilder};
+use std::{fmt::Display, str::FromStr};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Characters that are treated as separating a placeholder from the text before it.
+const SEPARATORS: [char; 6] = ['-', '|', ':', '·', '–', '—'];
+
+/// A value that can be substituted into a post text template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeholder {
+    Title,
+    Url,
+    Abstract,
+    PublishDate,
+}
+
+impl Placeholder {
+    const ALL: [Self; 4] = [Self::Title, Self::Url, Self::Abstract, Self::PublishDate];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Title => "title",
+            Self::Url => "url",
+            Self::Abstract => "abstract",
+            Self::PublishDate => "publish_date",
+        }
+    }
+
+    /// Whether the value may be shortened to keep a post within the length limit.
+    fn is_truncatable(&self) -> bool {
+        matches!(self, Self::Title | Self::Abstract)
+    }
+}
+
+/// A value that can be substituted into the text of a downtime notice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NoticePlaceholder {
+    Hours,
+    Since,
+}
+
+impl NoticePlaceholder {
+    const ALL: [Self; 2] = [Self::Hours, Self::Since];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Hours => "hours",
+            Self::Since => "since",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment<P = Placeholder> {
+    Literal(String),
+    Placeholder(P),
+}
+
+/// The values of a news post to render a template with.
+pub struct TemplateValues<'a> {
+    pub title: &'a str,
+    pub url: &'a str,
+    pub r#abstract: &'a str,
+    pub publish_time: DateTime<Utc>,
+}
+
+impl TemplateValues<'_> {
+    fn get(&self, placeholder: Placeholder) -> String {
+        match placeholder {
+            Placeholder::Title => self.title.to_string(),
+            Placeholder::Url => self.url.to_string(),
+            Placeholder::Abstract => self.r#abstract.to_string(),
+            Placeholder::PublishDate => self.publish_time.format("%Y-%m-%d").to_string(),
+        }
+    }
+}
+
+/// What a post must match for a [`PrefixRule`] to apply to it.
+#[derive(Debug, Clone)]
+enum PrefixMatcher {
+    /// News from the section with the given numeric id.
+    Section(usize),
+    /// Posts with a title matching the regular expression, ignoring case.
+    Title(Regex),
+}
+
+/// A rule adding a prefix to the text of posts that match it, such as `section:3=🎀 ` or `title~Maintenance=🔧 `.
+///
+/// The prefix is everything after the first `=`, including any trailing whitespace.
+#[derive(Debug, Clone)]
+pub struct PrefixRule {
+    matcher: PrefixMatcher,
+    pub prefix: String,
+}
+
+impl FromStr for PrefixRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rule = s.trim_start();
+        let invalid = |reason: String| format!("'{rule}' is not a valid prefix rule: {reason}");
+        let (matcher, prefix) = rule.split_once('=').ok_or_else(|| {
+            invalid("expected 'section:<id>=<prefix>' or 'title~<pattern>=<prefix>'".into())
+        })?;
+        if prefix.is_empty() {
+            return Err(invalid("the prefix can't be empty".into()));
+        }
+        let matcher = match (
+            matcher.strip_prefix("section:"),
+            matcher.strip_prefix("title~"),
+        ) {
+            (Some(section), _) => PrefixMatcher::Section(
+                section
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid(format!("'{section}' is not a valid numeric id")))?,
+            ),
+            (_, Some(pattern)) if !pattern.is_empty() => PrefixMatcher::Title(
+                RegexBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|err| invalid(err.to_string()))?,
+            ),
+            _ => {
+                return Err(invalid(
+                    "expected 'section:<id>=<prefix>' or 'title~<pattern>=<prefix>'".into(),
+                ));
+            }
+        };
+        Ok(Self {
+            matcher,
+            prefix: prefix.to_string(),
+        })
+    }
+}
+
+impl PrefixRule {
+    pub fn matches(&self, section: Option<usize>, title: &str) -> bool {
+        match &self.matcher {
+            PrefixMatcher::Section(id) => section == Some(*id),
+            PrefixMatcher::Title(pattern) => pattern.is_match(title),
+        }
+    }
+}
+
+/// A template for the text of a post such as `{title} - {url}`.
+///
+/// Supports the `{title}`, `{url}`, `{abstract}` and `{publish_date}` placeholders, `{{` and `}}` for literal
+/// braces and `\n` for a new line.
+#[derive(Debug, Clone)]
+pub struct PostTemplate {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl FromStr for PostTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            source: s.to_string(),
+            segments: parse_segments(s, &Placeholder::ALL, Placeholder::name)?,
+        })
+    }
+}
+
+/// Split a template into literal text and the placeholders it uses, failing on any placeholder that isn't one of
+/// the given ones.
+fn parse_segments<P: Copy>(
+    s: &str,
+    placeholders: &[P],
+    name: fn(&P) -> &'static str,
+) -> Result<Vec<Segment<P>>, String> {
+    let mut segments = vec![];
+    let mut literal = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '\\' if chars.peek() == Some(&'n') => {
+                chars.next();
+                literal.push('\n');
+            }
+            '{' => {
+                let mut placeholder_name = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    placeholder_name.push(c);
+                }
+                if !closed {
+                    return Err(format!(
+                        "unclosed '{{' in '{{{placeholder_name}' (use '{{{{' for a literal brace)"
+                    ));
+                }
+                let placeholder = placeholders
+                    .iter()
+                    .find(|p| name(p) == placeholder_name)
+                    .ok_or_else(|| {
+                        format!(
+                            "'{{{placeholder_name}}}' is not a known placeholder (expected one of {})",
+                            placeholders
+                                .iter()
+                                .map(|p| format!("{{{}}}", name(p)))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    })?;
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Placeholder(*placeholder));
+            }
+            '}' => return Err("'}' must be escaped as '}}' outside of a placeholder".into()),
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+impl Display for PostTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl PostTemplate {
+    pub fn contains(&self, placeholder: Placeholder) -> bool {
+        self.segments
+            .iter()
+            .any(|s| matches!(s, Segment::Placeholder(p) if *p == placeholder))
+    }
+
+    /// Get a copy of this template with every use of a placeholder removed, along with any separator such as
+    /// `" - "` that came directly before it.
+    pub fn without(&self, placeholder: Placeholder) -> Self {
+        let mut segments: Vec<Segment> = vec![];
+        for segment in &self.segments {
+            match segment {
+                Segment::Placeholder(p) if *p == placeholder => {
+                    if let Some(Segment::Literal(text)) = segments.last()
+                        && text
+                            .chars()
+                            .all(|c| c.is_whitespace() || SEPARATORS.contains(&c))
+                    {
+                        segments.pop();
+                    }
+                }
+                segment => segments.push(segment.clone()),
+            }
+        }
+        Self {
+            source: self.source.clone(),
+            segments,
+        }
+    }
+
+    /// Render the template, shortening the title and then the abstract with an ellipsis so that the result
+    /// fits within the given number of graphemes. Every other value is always kept whole.
+    pub fn render(&self, values: &TemplateValues, max_graphemes: usize) -> String {
+        let count = |placeholder: Placeholder| {
+            self.segments
+                .iter()
+                .filter(|s| matches!(s, Segment::Placeholder(p) if *p == placeholder))
+                .count()
+        };
+        let fixed: usize = self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.graphemes(true).count(),
+                Segment::Placeholder(p) if p.is_truncatable() => 0,
+                Segment::Placeholder(p) => values.get(*p).graphemes(true).count(),
+            })
+            .sum();
+
+        // The title takes priority over the abstract for whatever room is left.
+        let mut available = max_graphemes.saturating_sub(fixed);
+        let mut limits = vec![];
+        for placeholder in [Placeholder::Title, Placeholder::Abstract] {
+            let occurrences = count(placeholder);
+            if occurrences == 0 {
+                continue;
+            }
+            let limit = values
+                .get(placeholder)
+                .graphemes(true)
+                .count()
+                .min(available / occurrences);
+            available -= limit * occurrences;
+            limits.push((placeholder, limit));
+        }
+
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Placeholder(p) => match limits.iter().find(|(l, _)| l == p) {
+                    Some((_, limit)) => truncate_graphemes(&values.get(*p), *limit),
+                    None => values.get(*p),
+                },
+            })
+            .collect()
+    }
+}
+
+/// A template for the text of the post announcing that the bot was offline, such as
+/// `Back after {hours} hours offline`.
+///
+/// Supports the `{hours}` and `{since}` placeholders, `{{` and `}}` for literal braces and `\n` for a new line.
+#[derive(Debug, Clone)]
+pub struct NoticeTemplate {
+    source: String,
+    segments: Vec<Segment<NoticePlaceholder>>,
+}
+
+impl FromStr for NoticeTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            source: s.to_string(),
+            segments: parse_segments(s, &NoticePlaceholder::ALL, NoticePlaceholder::name)?,
+        })
+    }
+}
+
+impl Display for NoticeTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl NoticeTemplate {
+    /// Render the template for a bot that has been offline since the given time.
+    pub fn render(&self, since: DateTime<Utc>, now: DateTime<Utc>) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Placeholder(NoticePlaceholder::Hours) => {
+                    (now - since).num_hours().to_string()
+                }
+                Segment::Placeholder(NoticePlaceholder::Since) => {
+                    since.format("%Y-%m-%d %H:%M UTC").to_string()
+                }
+            })
+            .collect()
+    }
+}
+
+/// Shorten text to at most the given number of graphemes, ending it with an ellipsis when anything was removed.
+pub fn truncate_graphemes(text: &str, max_graphemes: usize) -> String {
+    if text.graphemes(true).count() <= max_graphemes {
+        return text.to_string();
+    }
+    if max_graphemes <= 1 {
+        return String::new();
+    }
+    let truncated: String = text.graphemes(true).take(max_graphemes - 1).collect();
+    format!("{}…", truncated.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn values<'a>(title: &'a str, r#abstract: &'a str) -> TemplateValues<'a> {
+        TemplateValues {
+            title,
+            url: "https://example.com/news/1",
+            r#abstract,
+            publish_time: Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn parses_placeholders_escapes_and_new_lines() {
+        let template: PostTemplate = "{{{title}}}\\n{publish_date}: {url}".parse().unwrap();
+        assert_eq!(
+            template.render(&values("Title", ""), 300),
+            "{Title}\n2026-03-01: https://example.com/news/1"
+        );
+        assert!(template.contains(Placeholder::Url));
+        assert!(!template.contains(Placeholder::Abstract));
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        let err = "{title} {link}".parse::<PostTemplate>().unwrap_err();
+        assert!(err.contains("'{link}' is not a known placeholder"), "{err}");
+    }
+
+    #[test]
+    fn rejects_unclosed_placeholders() {
+        let err = "{title".parse::<PostTemplate>().unwrap_err();
+        assert!(err.starts_with("unclosed '{' in '{title'"), "{err}");
+        assert!("{title} - {".parse::<PostTemplate>().is_err());
+    }
+
+    #[test]
+    fn rejects_unescaped_closing_braces() {
+        assert!("{title} }".parse::<PostTemplate>().is_err());
+        assert!("{title} }}".parse::<PostTemplate>().is_ok());
+    }
+
+    #[test]
+    fn removing_a_placeholder_removes_the_separator_before_it() {
+        let template: PostTemplate = "{title} - {url}".parse().unwrap();
+        let without = template.without(Placeholder::Url);
+        assert_eq!(without.render(&values("Title", ""), 300), "Title");
+        assert!(!without.contains(Placeholder::Url));
+
+        let template: PostTemplate = "{title}: read more at {url}".parse().unwrap();
+        assert_eq!(
+            template
+                .without(Placeholder::Url)
+                .render(&values("Title", ""), 300),
+            "Title: read more at "
+        );
+    }
+
+    #[test]
+    fn truncation_never_splits_graphemes() {
+        let family = "👨\u{200d}👩\u{200d}👧";
+        assert_eq!(
+            truncate_graphemes(&family.repeat(3), 2),
+            format!("{family}…")
+        );
+        assert_eq!(truncate_graphemes(&family.repeat(3), 3), family.repeat(3));
+        assert_eq!(truncate_graphemes("インフィニティニキ", 5), "インフィ…");
+        assert_eq!(truncate_graphemes("インフィニティニキ", 1), "");
+    }
+
+    #[test]
+    fn titles_are_truncated_before_abstracts() {
+        let template: PostTemplate = "{title}\n{abstract}".parse().unwrap();
+        assert_eq!(
+            template.render(&values("ニキの新しい衣装", "A long abstract"), 12),
+            "ニキの新しい衣装\nA…"
+        );
+        assert_eq!(
+            template.render(&values("ニキの新しい衣装", "A long abstract"), 6),
+            "ニキの新…\n"
+        );
+    }
+
+    #[test]
+    fn parses_prefix_rules() {
+        let rule: PrefixRule = "section:3=🎀 ".parse().unwrap();
+        assert_eq!(rule.prefix, "🎀 ");
+        assert!(rule.matches(Some(3), "Anything"));
+        assert!(!rule.matches(Some(4), "Anything"));
+        assert!(!rule.matches(None, "Anything"));
+
+        let rule: PrefixRule = "title~maintenance|downtime=🔧 ".parse().unwrap();
+        assert_eq!(rule.prefix, "🔧 ");
+        assert!(rule.matches(None, "Scheduled Maintenance Notice"));
+        assert!(rule.matches(Some(3), "Server DOWNTIME"));
+        assert!(!rule.matches(None, "New outfits"));
+
+        // Only the first '=' separates the prefix.
+        let rule: PrefixRule = "title~event=[=] ".parse().unwrap();
+        assert_eq!(rule.prefix, "[=] ");
+    }
+
+    #[test]
+    fn rejects_invalid_prefix_rules() {
+        for (rule, reason) in [
+            ("section:3", "expected 'section:<id>=<prefix>'"),
+            ("section:3=", "the prefix can't be empty"),
+            ("section:three=🎀", "'three' is not a valid numeric id"),
+            ("title~=🔧", "expected 'section:<id>=<prefix>'"),
+            ("title~(=🔧", "regex parse error"),
+            ("author:nikki=🎀", "expected 'section:<id>=<prefix>'"),
+        ] {
+            let err = rule.parse::<PrefixRule>().unwrap_err();
+            assert!(
+                err.starts_with(&format!("'{rule}' is not a valid prefix rule: ")),
+                "{err}"
+            );
+            assert!(err.contains(reason), "{err}");
+        }
+    }
+
+    #[test]
+    fn renders_notices() {
+        let template: NoticeTemplate = "Back after {hours}h, offline since {since}"
+            .parse()
+            .unwrap();
+        let since = Utc.with_ymd_and_hms(2026, 3, 1, 6, 30, 0).unwrap();
+        let now = Utc.with_ymd_and_hms(2026, 3, 2, 8, 0, 0).unwrap();
+        assert_eq!(
+            template.render(since, now),
+            "Back after 25h, offline since 2026-03-01 06:30 UTC"
+        );
+        assert!("{title}".parse::<NoticeTemplate>().is_err());
+    }
+}
@@ -0,0 +1,341 @@
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use image::{
+    DynamicImage, ImageFormat, ImageReader, codecs::jpeg::JpegEncoder, imageops::FilterType,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    io::Cursor,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+use tracing::{debug, info, warn};
+
+/// The default maximum size in bytes of an uploaded image, leaving headroom under the blob limit of most services.
+pub const DEFAULT_MAX_THUMBNAIL_BYTES: usize = 950_000;
+/// The qualities that images are encoded at in turn until one fits within the size limit.
+const ENCODE_QUALITIES: [u8; 3] = [85, 70, 55];
+/// The number of times an image is halved in size when it can't be made to fit within the size limit otherwise.
+const MAX_DOWNSCALES: u32 = 3;
+
+/// The filter used to resize images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ThumbnailFilter {
+    /// Nearest-neighbour, which is the fastest but makes images look blocky.
+    Nearest,
+    /// Linear filtering.
+    Triangle,
+    /// Lanczos with a window of 3, which is the slowest but gives the sharpest results.
+    Lanczos3,
+}
+
+impl From<ThumbnailFilter> for FilterType {
+    fn from(filter: ThumbnailFilter) -> Self {
+        match filter {
+            ThumbnailFilter::Nearest => FilterType::Nearest,
+            ThumbnailFilter::Triangle => FilterType::Triangle,
+            ThumbnailFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// The format that images are encoded in before being uploaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ThumbnailFormat {
+    Webp,
+    Jpeg,
+    Png,
+}
+
+impl From<ThumbnailFormat> for ImageFormat {
+    fn from(format: ThumbnailFormat) -> Self {
+        match format {
+            ThumbnailFormat::Webp => ImageFormat::WebP,
+            ThumbnailFormat::Jpeg => ImageFormat::Jpeg,
+            ThumbnailFormat::Png => ImageFormat::Png,
+        }
+    }
+}
+
+/// The name of the directory inside of the data path that processed images are cached in.
+pub const THUMBNAIL_CACHE_DIR: &str = "thumb-cache";
+/// How long processed images are kept in the cache for.
+pub const THUMBNAIL_CACHE_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How images are processed before being uploaded.
+#[derive(Debug, Clone)]
+pub struct ImageOptions {
+    /// The width that images are resized to fit within.
+    pub max_width: u32,
+    /// The height that images are resized to fit within.
+    pub max_height: u32,
+    pub filter: ThumbnailFilter,
+    pub format: ThumbnailFormat,
+    /// The maximum size in bytes of an image, which is shrunk until it fits.
+    pub max_bytes: usize,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            max_width: 960,
+            max_height: 540,
+            filter: ThumbnailFilter::Lanczos3,
+            format: ThumbnailFormat::Webp,
+            max_bytes: DEFAULT_MAX_THUMBNAIL_BYTES,
+        }
+    }
+}
+
+/// Decode an image, resize it to fit within the configured dimensions while preserving its aspect ratio and
+/// encode it so that it fits within the configured size.
+///
+/// Returns the encoded image alongside its final dimensions.
+pub fn process_thumbnail(bytes: &[u8], options: &ImageOptions) -> Result<(Vec<u8>, (u32, u32))> {
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()?
+        .decode()
+        .context("image data could not be decoded")?
+        .resize(options.max_width, options.max_height, options.filter.into());
+    encode_within(&image, options.format.into(), options.max_bytes)
+}
+
+/// Encode an image so that it fits within the given size in bytes, first by lowering the quality and then
+/// by halving its dimensions, returning the encoded image alongside its final dimensions.
+///
+/// Fails when the image can't be made to fit after [`MAX_DOWNSCALES`] downscales.
+fn encode_within(
+    image: &DynamicImage,
+    format: ImageFormat,
+    max_bytes: usize,
+) -> Result<(Vec<u8>, (u32, u32))> {
+    // Only JPEG has a quality to lower, so other formats are encoded once for each size.
+    let qualities = match format {
+        ImageFormat::Jpeg => &ENCODE_QUALITIES[..],
+        _ => &ENCODE_QUALITIES[..1],
+    };
+    let mut scaled = image.clone();
+    for downscale in 0..=MAX_DOWNSCALES {
+        if downscale > 0 {
+            scaled = scaled.resize(
+                (scaled.width() / 2).max(1),
+                (scaled.height() / 2).max(1),
+                FilterType::Triangle,
+            );
+        }
+        for quality in qualities {
+            let mut buf: Vec<u8> = vec![];
+            match format {
+                ImageFormat::Jpeg => {
+                    scaled.write_with_encoder(JpegEncoder::new_with_quality(&mut buf, *quality))?
+                }
+                format => scaled.write_to(&mut Cursor::new(&mut buf), format)?,
+            }
+            if buf.len() <= max_bytes {
+                return Ok((buf, (scaled.width(), scaled.height())));
+            }
+            debug!(
+                "Encoded {}x{} image at quality {quality} is {} bytes which is over the limit of {max_bytes}",
+                scaled.width(),
+                scaled.height(),
+                buf.len()
+            );
+        }
+    }
+    bail!("image couldn't be shrunk to fit within {max_bytes} bytes")
+}
+
+/// An on-disk cache of processed images, keyed by a hash of their source URL and the options they were processed with.
+///
+/// Avoids downloading and encoding the same image again when a post is retried or a cover is shared between locales.
+pub struct ThumbnailCache {
+    dir: PathBuf,
+}
+
+impl ThumbnailCache {
+    pub fn new(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create thumbnail cache at {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    fn entry_path(&self, url: &str, options: &ImageOptions) -> PathBuf {
+        let key = format!(
+            "{url}|{}x{}|{:?}|{:?}|{}",
+            options.max_width,
+            options.max_height,
+            options.filter,
+            options.format,
+            options.max_bytes
+        );
+        self.dir.join(format!("{:x}", Sha256::digest(key)))
+    }
+
+    /// Get a processed image from the cache alongside its dimensions.
+    pub fn get(&self, url: &str, options: &ImageOptions) -> Option<(Vec<u8>, (u32, u32))> {
+        let bytes = fs::read(self.entry_path(url, options)).ok()?;
+        let dimensions = ImageReader::new(Cursor::new(&bytes))
+            .with_guessed_format()
+            .ok()?
+            .into_dimensions()
+            .ok()?;
+        debug!("Using cached image for '{url}'");
+        Some((bytes, dimensions))
+    }
+
+    /// Store a processed image in the cache. Failures are only logged as the cache is an optimisation.
+    ///
+    /// The image is written to a temporary file that is then renamed into place, so that a crash or another process
+    /// reading the cache never sees a partially written image.
+    pub fn put(&self, url: &str, options: &ImageOptions, bytes: &[u8]) {
+        let path = self.entry_path(url, options);
+        let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+        if let Err(err) = fs::write(&temp_path, bytes).and_then(|()| fs::rename(&temp_path, &path))
+        {
+            warn!("Failed to cache image for '{url}': {err}");
+            let _ = fs::remove_file(&temp_path);
+        }
+    }
+
+    /// Remove every cached image that was stored longer ago than the given age.
+    ///
+    /// Images that can't be read or removed are logged and skipped so that the rest are still cleaned up, with only
+    /// failing to read the cache directory itself being returned.
+    pub fn remove_older_than(&self, max_age: Duration) -> Result<()> {
+        let now = SystemTime::now();
+        let mut removed = 0;
+        let entries = fs::read_dir(&self.dir)
+            .with_context(|| format!("failed to read thumbnail cache at {}", self.dir.display()))?;
+        for entry in entries {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(err) => {
+                    warn!("Failed to read an entry of the thumbnail cache: {err}");
+                    continue;
+                }
+            };
+            let modified = match fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+                Ok(modified) => modified,
+                Err(err) => {
+                    warn!("Failed to read cached image {}: {err}", path.display());
+                    continue;
+                }
+            };
+            if now.duration_since(modified).unwrap_or_default() > max_age {
+                match fs::remove_file(&path) {
+                    Ok(()) => removed += 1,
+                    Err(err) => warn!("Failed to remove expired image {}: {err}", path.display()),
+                }
+            }
+        }
+        if removed > 0 {
+            info!("Removed {removed} expired images from the thumbnail cache");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// An image of noise, which compresses poorly enough to need shrinking to fit small size limits.
+    fn noise(width: u32, height: u32) -> DynamicImage {
+        let mut state: u32 = 0x2545_f491;
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |_, _| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let [r, g, b, _] = state.to_le_bytes();
+            Rgb([r, g, b])
+        }))
+    }
+
+    fn png(image: &DynamicImage) -> Vec<u8> {
+        let mut bytes = vec![];
+        image
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn large_images_are_shrunk_to_fit_the_size_limit() {
+        let image = noise(1000, 800);
+        let (bytes, (width, height)) = encode_within(&image, ImageFormat::Jpeg, 30_000).unwrap();
+        assert!(bytes.len() <= 30_000, "{} bytes", bytes.len());
+        // Lowering the quality alone isn't enough for noise, so the image is also downscaled.
+        assert!(width < 1000 && height < 800, "{width}x{height}");
+        assert_eq!(width * 4, height * 5);
+        assert!(
+            image::load_from_memory_with_format(&bytes, ImageFormat::Jpeg).is_ok(),
+            "the shrunk image should still decode"
+        );
+    }
+
+    #[test]
+    fn images_that_cant_be_shrunk_enough_are_rejected() {
+        let err = encode_within(&noise(64, 64), ImageFormat::Png, 10).unwrap_err();
+        assert!(err.to_string().contains("within 10 bytes"), "{err}");
+    }
+
+    #[test]
+    fn images_are_resized_to_fit_the_configured_dimensions() {
+        let options = ImageOptions {
+            max_width: 100,
+            max_height: 100,
+            format: ThumbnailFormat::Jpeg,
+            ..Default::default()
+        };
+        let (_, dimensions) = process_thumbnail(&png(&noise(400, 200)), &options).unwrap();
+        assert_eq!(dimensions, (100, 50));
+        assert!(process_thumbnail(b"not an image", &options).is_err());
+    }
+
+    #[test]
+    fn caches_images_by_url_and_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ThumbnailCache::new(dir.path().join("thumb-cache")).unwrap();
+        let options = ImageOptions::default();
+        let bytes = png(&noise(8, 4));
+
+        assert!(cache.get("https://example.com/a.png", &options).is_none());
+        cache.put("https://example.com/a.png", &options, &bytes);
+        assert_eq!(
+            cache.get("https://example.com/a.png", &options),
+            Some((bytes, (8, 4)))
+        );
+        assert!(cache.get("https://example.com/b.png", &options).is_none());
+        let other_options = ImageOptions {
+            max_width: 100,
+            ..Default::default()
+        };
+        assert!(
+            cache
+                .get("https://example.com/a.png", &other_options)
+                .is_none()
+        );
+        // Only the finished image is left behind, without the temporary file it was written to.
+        assert_eq!(fs::read_dir(&cache.dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn removes_expired_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ThumbnailCache::new(dir.path().to_path_buf()).unwrap();
+        let options = ImageOptions::default();
+        cache.put("https://example.com/a.png", &options, &png(&noise(2, 2)));
+        // Anything the sweep can't remove, such as a directory, is skipped rather than stopping it.
+        fs::create_dir(dir.path().join("not-an-image")).unwrap();
+
+        cache.remove_older_than(THUMBNAIL_CACHE_MAX_AGE).unwrap();
+        assert!(cache.get("https://example.com/a.png", &options).is_some());
+
+        std::thread::sleep(Duration::from_millis(20));
+        cache.remove_older_than(Duration::from_millis(10)).unwrap();
+        assert!(cache.get("https://example.com/a.png", &options).is_none());
+        assert!(dir.path().join("not-an-image").is_dir());
+    }
+}
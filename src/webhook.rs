@@ -0,0 +1,235 @@
+use crate::template::truncate_graphemes;
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+use reqwest::Url;
+use serde_json::{Value, json};
+use std::{collections::HashMap, fmt, sync::Mutex};
+use tracing::{info, warn};
+
+/// The longest error summary sent, keeping messages within the length limits of chat services.
+const MAX_SUMMARY_GRAPHEMES: usize = 1500;
+
+/// The shape of the JSON body posted to a webhook.
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum WebhookFormat {
+    /// A Discord webhook message.
+    Discord,
+    /// A Slack incoming webhook message.
+    Slack,
+    /// A JSON object with separate `bot`, `error`, `summary` and `timestamp` fields.
+    #[default]
+    GenericJson,
+}
+
+/// A kind of sustained failure, which is notified about at most once per cooldown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    FetchFailures,
+    PostFailures,
+    Authentication,
+    /// Posts refused by --max-posts-per-article.
+    CappedPosts,
+}
+
+impl ErrorClass {
+    fn id(&self) -> &'static str {
+        match self {
+            Self::FetchFailures => "fetch_failures",
+            Self::PostFailures => "post_failures",
+            Self::Authentication => "authentication",
+            Self::CappedPosts => "capped_posts",
+        }
+    }
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::FetchFailures => "News is failing to be fetched",
+            Self::PostFailures => "Posts are failing to be published",
+            Self::Authentication => "Logging in to Bluesky failed",
+            Self::CappedPosts => "An article was refused for being posted too many times",
+        })
+    }
+}
+
+/// Posts a message to a webhook when the bot is persistently failing.
+#[derive(Debug)]
+pub struct ErrorNotifier {
+    http: reqwest::Client,
+    url: Url,
+    format: WebhookFormat,
+    /// The name of the bot that messages are sent on behalf of, such as its account's handle.
+    bot: String,
+    cooldown: Duration,
+    last_sent: Mutex<HashMap<ErrorClass, DateTime<Utc>>>,
+}
+
+impl ErrorNotifier {
+    pub fn new(
+        http: reqwest::Client,
+        url: Url,
+        format: WebhookFormat,
+        bot: String,
+        cooldown: Duration,
+    ) -> Self {
+        Self {
+            http,
+            url,
+            format,
+            bot,
+            cooldown,
+            last_sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Send a message about an error unless one about the same class of error was sent within the cooldown.
+    ///
+    /// Failing to deliver the message is only logged, so that it can't stop the bot.
+    pub async fn notify(&self, class: ErrorClass, summary: &str) {
+        let now = Utc::now();
+        {
+            let mut last_sent = self.last_sent.lock().unwrap();
+            if last_sent
+                .get(&class)
+                .is_some_and(|sent| now - *sent < self.cooldown)
+            {
+                return;
+            }
+            last_sent.insert(class, now);
+        }
+
+        let body = payload(self.format, &self.bot, class, summary, now);
+        match self
+            .http
+            .post(self.url.clone())
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+        {
+            Ok(_) => info!("Sent an error notification to the webhook: {class}"),
+            // The URL often contains a token, so it is left out of the error.
+            Err(err) => warn!(
+                "Failed to send an error notification to the webhook: {}",
+                err.without_url()
+            ),
+        }
+    }
+}
+
+/// Build the JSON body of a webhook message in the given format.
+pub fn payload(
+    format: WebhookFormat,
+    bot: &str,
+    class: ErrorClass,
+    summary: &str,
+    time: DateTime<Utc>,
+) -> Value {
+    let summary = truncate_graphemes(summary, MAX_SUMMARY_GRAPHEMES);
+    let timestamp = time.to_rfc3339();
+    let text = format!("**{bot}**: {class} ({timestamp})\n{summary}");
+    match format {
+        WebhookFormat::Discord => json!({ "content": text }),
+        // Slack uses single asterisks for bold.
+        WebhookFormat::Slack => json!({ "text": text.replacen("**", "*", 2) }),
+        WebhookFormat::GenericJson => json!({
+            "bot": bot,
+            "error": class.id(),
+            "summary": summary,
+            "timestamp": timestamp,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, routing::post};
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+
+    /// Accept webhook messages on a random local port, recording their bodies.
+    async fn spawn_webhook() -> (Url, Arc<Mutex<Vec<Value>>>) {
+        let received = Arc::new(Mutex::new(vec![]));
+        let recorder = received.clone();
+        let router = Router::new().route(
+            "/webhook",
+            post(move |Json(body): Json<Value>| async move {
+                recorder.lock().unwrap().push(body);
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/webhook",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        (url, received)
+    }
+
+    #[test]
+    fn generic_json_payloads_name_the_class_of_error() {
+        let time = Utc::now();
+        let body = payload(
+            WebhookFormat::GenericJson,
+            "whimsky.test",
+            ErrorClass::CappedPosts,
+            "Refusing to post",
+            time,
+        );
+        assert_eq!(
+            body,
+            json!({
+                "bot": "whimsky.test",
+                "error": "capped_posts",
+                "summary": "Refusing to post",
+                "timestamp": time.to_rfc3339(),
+            })
+        );
+    }
+
+    #[test]
+    fn slack_payloads_use_single_asterisks_for_bold() {
+        let body = payload(
+            WebhookFormat::Slack,
+            "whimsky.test",
+            ErrorClass::Authentication,
+            "Bad password",
+            Utc::now(),
+        );
+        assert!(
+            body["text"]
+                .as_str()
+                .unwrap()
+                .starts_with("*whimsky.test*: ")
+        );
+    }
+
+    #[tokio::test]
+    async fn each_class_of_error_is_notified_once_per_cooldown() {
+        let (url, received) = spawn_webhook().await;
+        let notifier = ErrorNotifier::new(
+            reqwest::Client::new(),
+            url,
+            WebhookFormat::GenericJson,
+            "whimsky.test".into(),
+            Duration::hours(1),
+        );
+        notifier
+            .notify(ErrorClass::CappedPosts, "Refusing to post 'a'")
+            .await;
+        notifier
+            .notify(ErrorClass::CappedPosts, "Refusing to post 'b'")
+            .await;
+        notifier
+            .notify(ErrorClass::PostFailures, "3 posts failed")
+            .await;
+
+        let received = received.lock().unwrap();
+        let errors: Vec<_> = received.iter().map(|body| &body["error"]).collect();
+        assert_eq!(errors, ["capped_posts", "post_failures"]);
+        assert_eq!(received[0]["summary"], "Refusing to post 'a'");
+    }
+}